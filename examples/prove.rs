@@ -1,4 +1,4 @@
-use plonky2_por::{prove_from_data, get_ledger_values_from_file, prove_from_file};
+use plonky2_por::{prove_from_data, get_ledger_values_from_file, prove_from_file, write_to_file, SerializationFormat};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Example: Generating Global Proof ===");
@@ -7,10 +7,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Method 1: Generate from file");
     let (final_proof, merkle_tree, nonces) = prove_from_file("private_ledger.json", None)?;
     
-    // Save to files
-    std::fs::write("final_proof.json", serde_json::to_string(&final_proof)?)?;
-    std::fs::write("merkle_tree.json", serde_json::to_string(&merkle_tree)?)?;
-    std::fs::write("private_nonces.json", serde_json::to_string(&nonces)?)?;
+    // Save to files (streamed into buffered files, the merkle tree can be huge)
+    // NOTE: passing Some(output_dir) to prove_from_file writes the same files
+    write_to_file("final_proof.json", &final_proof, SerializationFormat::Json)?;
+    write_to_file("merkle_tree.json", &merkle_tree, SerializationFormat::Json)?;
+    write_to_file("private_nonces.json", &nonces, SerializationFormat::Json)?;
     println!("Files saved: final_proof.json, merkle_tree.json, private_nonces.json");
     
     // Method 2: Generate from data
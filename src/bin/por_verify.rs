@@ -0,0 +1,238 @@
+// standalone auditor tool: only verifies proofs (no prover, daemon, inclusion server or custom allocator)
+// it uses the same circuits as the main binary, so it verifies the proofs produced by it bit-for-bit
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use plonky2_por::core::verifier::{
+    check_prover_version, verify_inclusion_files, verify_proof_only, verify_root, verify_root_streaming,
+};
+use plonky2_por::utils::logger::{print_header, set_verbosity, Verbosity};
+use plonky2_por::utils::util::{calculate_with_decimals, format_amount, format_timestamp, set_display_precision};
+use plonky2_por::{
+    bytes_to_hex, format_error, hex_to_bytes, log_error, log_info, log_success, read_from_file, root_circuit_digest,
+    FinalProof, MerkleTree, SerializationFormat, VerifyOptions, DEFAULT_MAX_TREE_DEPTH,
+};
+use regex::Regex;
+use std::time::Instant;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about = "OtterSec plonky2 proof of reserves verifier", long_about = None)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Commands,
+
+    /// Serialization format of the proof files to read (final_proof.<ext> and merkle_tree.<ext>)
+    #[clap(long, global = true, value_enum, default_value_t = SerializationFormat::Json)]
+    format: SerializationFormat,
+
+    /// Only print errors
+    #[clap(long, short, global = true)]
+    quiet: bool,
+
+    /// Number of decimals of the printed amounts (display only, the verification always uses the exact values)
+    #[clap(long, global = true)]
+    display_precision: Option<u32>,
+
+    /// Continue when the proof was generated by a different prover version (the circuits may not match)
+    #[clap(long, global = true)]
+    ignore_version: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Commands {
+    /// Verifies the global proof
+    Verify(VerifyArgs),
+    /// Verifies the inclusion proofs (inclusion_proof_*) in the current directory
+    VerifyInclusion,
+    /// Prints the information of the final proof without verifying it
+    Info,
+}
+
+#[derive(Args, Debug, Clone)]
+struct VerifyArgs {
+    /// Skip rebuilding the root circuit (trusts the circuit data inside the proof file)
+    #[clap(long)]
+    skip_rebuild_circuit: bool,
+
+    /// Trusted root circuit digest (hex), checked first and used instead of rebuilding the root circuit
+    #[clap(long)]
+    expected_digest: Option<String>,
+
+    /// Also check that the batch reserves recorded in the merkle tree add up to the proven reserves
+    #[clap(long)]
+    deep_check: bool,
+
+    /// Only verify the proof and the reserves, without the merkle tree (merkle_tree.<ext> is not read)
+    #[clap(long, conflicts_with = "deep_check")]
+    proof_only: bool,
+
+    /// Verify the merkle tree while it is read instead of deserializing it first (constant memory, for huge trees)
+    #[clap(long, conflicts_with = "proof_only")]
+    stream: bool,
+
+    /// Reject proofs whose merkle tree is deeper than this (protects against malformed proof files)
+    #[clap(long, default_value_t = DEFAULT_MAX_TREE_DEPTH)]
+    max_tree_depth: usize,
+
+    /// Only display the reserves of these assets (e.g. BTC,ETH), the whole proof is still verified
+    #[clap(long, value_delimiter = ',')]
+    assets: Option<Vec<String>>,
+}
+
+impl VerifyArgs {
+    fn options(&self) -> Result<VerifyOptions> {
+        let expected_digest = match &self.expected_digest {
+            Some(digest) => {
+                let digest = hex_to_bytes(digest).context(format_error("Invalid expected digest"))?;
+                if digest.len() != 32 {
+                    return Err(anyhow::anyhow!(format_error("The expected digest must be 32 bytes (64 hex characters)")));
+                }
+                Some(digest)
+            }
+            None => None,
+        };
+
+        Ok(VerifyOptions {
+            rebuild_circuit: !self.skip_rebuild_circuit,
+            deep_check: self.deep_check,
+            max_tree_depth: self.max_tree_depth,
+            expected_digest,
+            assets: self.assets.clone(),
+            ..VerifyOptions::default()
+        })
+    }
+}
+
+fn read_final_proof(ext: &str, ignore_version: bool) -> Result<FinalProof> {
+    let final_proof = FinalProof::read(&format!("final_proof.{ext}"))
+        .context(format_error(&format!("Failed to read final_proof.{ext}")))?;
+
+    // the circuit sizes are chosen when proving (stored in the final proof), only check if they are valid
+    if let Err(e) = final_proof.prover_config().validate() {
+        log_error!("{}", e);
+    }
+    check_prover_version(&final_proof, ignore_version)?;
+
+    Ok(final_proof)
+}
+
+fn print_info(final_proof: &FinalProof) -> Result<()> {
+    let config = final_proof.prover_config();
+
+    println!("======================");
+    println!("Prover version: {}", final_proof.prover_version);
+    println!("Proof generation date: {}", format_timestamp(final_proof.timestamp).unwrap());
+    println!("Proof generation timestamp (ms): {}", final_proof.timestamp);
+    println!("Batch size: {}", config.batch_size);
+    println!("Recursive size: {}", config.recursive_size);
+    println!("Merkle tree depth: {}", final_proof.tree_depth);
+    println!("Root hash (hex): {}", bytes_to_hex(&final_proof.root_hash()));
+    println!("Root circuit digest (hex, not verified): {}", bytes_to_hex(&root_circuit_digest(final_proof)?));
+    println!("Proof commitment (hex): {}", bytes_to_hex(&final_proof.commitment()));
+
+    // annotations are added after proving (annotate subcommand), only the commitment signature can be verified
+    let annotations = &final_proof.annotations;
+    if let Some(exchange_id) = &annotations.exchange_id {
+        println!("Exchange id (not verified): {exchange_id}");
+    }
+    if let Some(anchor_url) = &annotations.anchor_url {
+        println!("Commitment anchor (not verified): {anchor_url}");
+    }
+    final_proof.verify_annotations().context(format_error("Invalid annotations"))?;
+    if let (Some(signature), Some(public_key)) = (&annotations.signature, &annotations.public_key) {
+        println!("Commitment signature (hex, valid): {signature}");
+        println!("Commitment signed by (ed25519 public key, compare it with the key published by the exchange): {public_key}");
+    }
+
+    println!("\n-----Assets ({})-----", final_proof.asset_names.len());
+    for (i, asset_name) in final_proof.asset_names.iter().enumerate() {
+        let decimals = &final_proof.asset_decimals[i];
+        let price = calculate_with_decimals(final_proof.asset_prices[i].try_into()?, decimals.usdt_decimals);
+        println!(
+            "{asset_name}: US$ {} (usdt_decimals: {}, balance_decimals: {})",
+            format_amount(&price),
+            decimals.usdt_decimals,
+            decimals.balance_decimals
+        );
+    }
+    println!("======================");
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let global_timer = Instant::now();
+
+    let cli = Cli::parse();
+    let ext = cli.format.extension();
+
+    if cli.quiet {
+        set_verbosity(Verbosity::Quiet);
+    }
+    set_display_precision(cli.display_precision);
+
+    print_header();
+
+    match &cli.command {
+        Commands::Verify(args) => {
+            log_info!("Verifying the proof of reserves...");
+            let final_proof = read_final_proof(ext, cli.ignore_version)?;
+
+            // parse the options (e.g. the expected digest) before the (slow) verification
+            let options = args.options()?;
+            if let Some(assets) = &options.assets {
+                final_proof.selected_asset_order(assets).context(format_error("Invalid asset filter"))?;
+            }
+            if args.proof_only {
+                verify_proof_only(&final_proof, &options).context(format_error("Failed to verify the proof"))?;
+            } else if args.stream {
+                verify_root_streaming(&final_proof, &format!("merkle_tree.{ext}"), &options)
+                    .context(format_error("Failed to verify the proof"))?;
+            } else {
+                let merkle_tree: MerkleTree = read_from_file(&format!("merkle_tree.{ext}")).context(format_error(
+                    &format!("Failed to read merkle_tree.{ext} (use --proof-only to verify the proof without it)"),
+                ))?;
+                verify_root(final_proof, merkle_tree, &options);
+            }
+        }
+        Commands::VerifyInclusion => {
+            let final_proof = read_final_proof(ext, cli.ignore_version)?;
+
+            let re = Regex::new(r"^inclusion_proof_.*\.(json|bin|msgpack)$").context(format_error("Failed to create regex"))?;
+            let entries = std::fs::read_dir(".").context(format_error("Failed to read current directory"))?;
+
+            let mut files = entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|filename| re.is_match(filename))
+                .collect::<Vec<_>>();
+            files.sort();
+
+            if files.is_empty() {
+                return Err(anyhow::anyhow!(format_error("No inclusion_proof_* files found in the current directory")));
+            }
+
+            // the global proof is verified once, the inclusion proofs are verified in parallel
+            let report = verify_inclusion_files(&final_proof, &files).context(format_error("Failed to verify the global proof"))?;
+
+            println!();
+            if let Some((filename, error)) = &report.first_failure {
+                return Err(anyhow::anyhow!(format_error(&format!(
+                    "{} of {} inclusion proofs are not valid (first failing file: {filename}: {error})",
+                    report.invalid,
+                    files.len()
+                ))));
+            }
+            log_success!("All {} inclusion proofs are valid!", report.valid);
+        }
+        Commands::Info => {
+            let final_proof = read_final_proof(ext, cli.ignore_version)?;
+            print_info(&final_proof)?;
+            return Ok(());
+        }
+    }
+
+    log_success!("Finished in {:?}!", global_timer.elapsed());
+
+    Ok(())
+}
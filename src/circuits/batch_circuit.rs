@@ -23,15 +23,26 @@ pub struct BatchCircuit {
     asset_prices_target: Vec<Target>,
     account_targets: Vec<Account>,
     leaf_hashes: Vec<HashOutTarget>,
+    batch_size: usize,
     pub circuit_data: CircuitData<F, C, D>,
 }
 
 impl BatchCircuit {
     pub fn new(asset_count: usize) -> BatchCircuit {
+        Self::new_with_batch_size(asset_count, BATCH_SIZE)
+    }
+
+    // the balances range checks are sized for BATCH_SIZE accounts, so batch_size must not be larger (see ProverConfig::validate)
+    pub fn new_with_batch_size(asset_count: usize, batch_size: usize) -> BatchCircuit {
+        assert!(
+            batch_size > 0 && batch_size <= BATCH_SIZE,
+            "The batch size must be between 1 and BATCH_SIZE"
+        );
+
         let config = BATCH_CIRCUIT_CONFIG;
         let mut builder = CircuitBuilder::<F, D>::new(config);
 
-        // create a circuit that takes BATCH_SIZE inputs and check these constraints
+        // create a circuit that takes batch_size inputs and check these constraints
         // --> Calculate account equity (sum of "asset * price")
         // --> Constraint account equity non-negativity
         // --> Calculate sum of all assets of all accounts
@@ -41,7 +52,7 @@ impl BatchCircuit {
         let asset_prices_target = builder.add_virtual_targets(asset_count);
 
         // create targets for each leaf
-        for _ in 0..BATCH_SIZE {
+        for _ in 0..batch_size {
             let asset_balances = builder.add_virtual_targets(asset_count);
 
             let account = Account {
@@ -66,14 +77,16 @@ impl BatchCircuit {
             builder.assert_bool(is_negative);
 
 
-            // CONSTRAINT: check if not overflowing
+            // CONSTRAINT: check if not overflowing (or underflowing)
             // this is a faster way to check if not overflowing
-            // we check if a single balance is not higher than MAX_ACCOUNT_BALANCE
+            // we check if the absolute value of a single balance is not higher than MAX_ACCOUNT_BALANCE
             // MAX_ACCOUNT_BALANCE is calculated based on the number of users in a batch circuit
-            // and the max possible integer value (we use 2^62)
-            let _ = account.asset_balances.iter().map(|balance| {
-                builder.range_check(*balance, MAX_ACCOUNT_BALANCE_BITS);
-            });
+            // and the max possible integer value ((ORDER - 1) / 2), the range check accepts up to MAX_RANGE_CHECKED_BALANCE
+            // the check is signed since negative balances (liabilities) are allowed: without the lower bound,
+            // the sum of many large negative balances could wrap around into the positive half of the field
+            for balance in &account.asset_balances {
+                range_check_signed(&mut builder, *balance, MAX_ACCOUNT_BALANCE_BITS);
+            }
         }
 
         
@@ -85,11 +98,17 @@ impl BatchCircuit {
             for account in &accounts {
                 sum = builder.add(account.asset_balances[i], sum);
             }
+
+            // CONSTRAINT: check if the batch total is not overflowing (positive) or underflowing (negative)
+            // mirrors the recursive circuit overflow check, the total must be in [-2^MAX_BATCH_TOTAL_BITS, 2^MAX_BATCH_TOTAL_BITS)
+            // which is always the case if the balances range checks hold (it is cheap, a single check per asset)
+            range_check_signed(&mut builder, sum, MAX_BATCH_TOTAL_BITS);
+
             builder.connect(sum, *total_value);
         }
 
         // leaf hashes to calculate root hash
-        let leaf_hashes = builder.add_virtual_hashes(BATCH_SIZE);
+        let leaf_hashes = builder.add_virtual_hashes(batch_size);
 
         // calculate root hash by concatenating all leaf hashes
         let concat_hashes = leaf_hashes.iter().fold(Vec::new(), |mut acc, hash| {
@@ -103,7 +122,7 @@ impl BatchCircuit {
         let root_hash = builder.hash_n_to_hash_no_pad::<H>(concat_hashes);
 
         // register the public inputs
-        builder.register_public_inputs(&total_asset_values); // sum of all assets of BATCH_SIZE accounts
+        builder.register_public_inputs(&total_asset_values); // sum of all assets of batch_size accounts
         builder.register_public_inputs(&asset_prices_target);
         builder.register_public_inputs(&root_hash.elements);
 
@@ -113,6 +132,7 @@ impl BatchCircuit {
             asset_prices_target,
             leaf_hashes,
             account_targets: accounts,
+            batch_size,
             circuit_data: circuit,
         }
     }
@@ -123,12 +143,49 @@ impl BatchCircuit {
         accounts: &[Vec<i64>],
         leaf_hashes: &[HashOut<F>],
     ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        // check if the balances are in the range accepted by the circuit (a clear error instead of a failed witness generation)
+        let min_balance = -(1i64 << MAX_ACCOUNT_BALANCE_BITS);
+        let max_balance = MAX_RANGE_CHECKED_BALANCE as i64;
+        for account in accounts {
+            if let Some(balance) = account.iter().find(|&&b| b < min_balance || b > max_balance) {
+                return Err(anyhow::anyhow!(
+                    "Account balance {} is out of the supported range [{}, {}]",
+                    balance,
+                    min_balance,
+                    max_balance
+                ));
+            }
+        }
+
+        let pw = self.witness(asset_prices, accounts, leaf_hashes)?;
+
+        let mut timing = TimingTree::new("prove", Level::Trace);
+        let proof = prove::<F, C, D>(
+            &self.circuit_data.prover_only,
+            &self.circuit_data.common,
+            pw,
+            &mut timing,
+        )?;
+
+        timing.print();
+
+        Ok(proof)
+    }
+
+    // the witness of a batch, without the range check of prove_batch_circuit: an out of range balance is only
+    // rejected by the circuit constraints (the tests use it to check them)
+    pub fn witness(
+        &self,
+        asset_prices: &[u64],
+        accounts: &[Vec<i64>],
+        leaf_hashes: &[HashOut<F>],
+    ) -> Result<PartialWitness<F>> {
         let mut pw = PartialWitness::<F>::new();
 
-        // check if accounts length is equal to BATCH_SIZE
+        // check if accounts length is equal to the batch size
         assert!(
-            accounts.len() == BATCH_SIZE,
-            "The number of accounts must be equal to BATCH_SIZE"
+            accounts.len() == self.batch_size,
+            "The number of accounts must be equal to the batch size"
         );
 
         // convert the asset prices to Numeric Field
@@ -161,17 +218,7 @@ impl BatchCircuit {
             pw.set_hash_target(*leaf_hash, leaf_hashes[i])?;
         }
 
-        let mut timing = TimingTree::new("prove", Level::Trace);
-        let proof = prove::<F, C, D>(
-            &self.circuit_data.prover_only,
-            &self.circuit_data.common,
-            pw,
-            &mut timing,
-        )?;
-
-        timing.print();
-
-        Ok(proof)
+        Ok(pw)
     }
 
     pub fn prove_empty(&self, asset_prices: &[u64]) -> ProofWithPublicInputs<F, C, D> {
@@ -209,6 +256,11 @@ impl BatchCircuit {
         proof
     }
 
+    // number of accounts proven by each batch proof
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
     // Verify a proof
     pub fn verify_batch_circuit(
         &self,
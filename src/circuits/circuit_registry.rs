@@ -23,10 +23,8 @@ pub struct CircuitRegistry{
 
 
 impl CircuitRegistry {
-    pub fn new(batch_circuit: BatchCircuit, asset_prices: &[u64]) -> Self {
-
-        let empty_batch_proof = batch_circuit.prove_empty(asset_prices);
-
+    // the empty batch proof is generated by the caller, so it can be proved in parallel with the batch proofs
+    pub fn new(batch_circuit: BatchCircuit, empty_batch_proof: ProofWithPublicInputs<F, C, D>) -> Self {
         CircuitRegistry {
             batch_circuit: BatchCircuitEntry {
                 circuit: batch_circuit,
@@ -62,17 +60,19 @@ impl CircuitRegistry {
         None
     }
 
-    pub fn add_recursive_circuit(&mut self, circuit: RecursiveCircuit, depth: usize) {
-
+    // the empty proof is generated by the caller (in parallel with the proofs of the level), None for the root
+    pub fn add_recursive_circuit(
+        &mut self,
+        circuit: RecursiveCircuit,
+        depth: usize,
+        empty_proof: Option<ProofWithPublicInputs<F, C, D>>,
+    ) {
         let digest = circuit.circuit_data.verifier_only.circuit_digest;
 
-        if depth == 1 { // dont need to prove empty for root
-            self.recursive_circuits.insert(digest, RecursiveCircuitEntry { circuit, empty_proof: None, depth });
-            return;
-        }
+        // dont need to prove empty for root
+        debug_assert!(depth != 1 || empty_proof.is_none());
 
-        let empty_proof = circuit.prove_empty(self);
-        self.recursive_circuits.insert(digest, RecursiveCircuitEntry { circuit, empty_proof: Some(empty_proof), depth });
+        self.recursive_circuits.insert(digest, RecursiveCircuitEntry { circuit, empty_proof, depth });
     }
 
     pub fn get_recursive_circuit_by_depth(&self, depth: usize) -> Option<&RecursiveCircuitEntry> {
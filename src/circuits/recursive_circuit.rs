@@ -30,18 +30,19 @@ impl RecursiveCircuit {
     pub fn new(
         inner_circuit: &CircuitData<F, C, D>,
         asset_count: usize,
+        recursive_size: usize,
     ) -> RecursiveCircuit {
         let config = RECURSIVE_CIRCUIT_CONFIG;
         let mut builder = CircuitBuilder::<F, D>::new(config);
 
-        // create a circuit that takes RECURSIVE_SIZE (n) inputs (inner_circuit proofs) and check these constraints
+        // create a circuit that takes recursive_size (n) inputs (inner_circuit proofs) and check these constraints
         // --> Verify n proofs
         // --> Calculate sum of all inner_circuit balances (maybe store in 2 64bit targets)
         // --> Check if no overflow
 
         // create targets for batch proofs (input)
         let mut inner_targets = Vec::new();
-        for _ in 0..RECURSIVE_SIZE {
+        for _ in 0..recursive_size {
             let proof_target = builder.add_virtual_proof_with_pis(&inner_circuit.common);
             let verify_target = builder
                 .add_virtual_verifier_data(inner_circuit.common.config.fri_config.cap_height);
@@ -98,7 +99,7 @@ impl RecursiveCircuit {
             .to_vec();
 
         // iterate through all circuits to verify if the asset prices are the same
-        for inner_data in inner_targets.iter().take(RECURSIVE_SIZE) {
+        for inner_data in inner_targets.iter() {
             let inner_asset_prices = inner_data.proof_target.public_inputs
                 [RecursiveCircuit::get_asset_prices_offset(asset_count)]
                 .to_vec();
@@ -113,7 +114,7 @@ impl RecursiveCircuit {
 
         // iterate through proofs to create the hashes
         let mut concat_hashes = Vec::new();
-        for inner_data in inner_targets.iter().take(RECURSIVE_SIZE) {
+        for inner_data in inner_targets.iter() {
             let hash_elements = inner_data.proof_target.public_inputs
                 [RecursiveCircuit::get_root_hash_offset(asset_count)]
                 .to_vec();
@@ -135,6 +136,11 @@ impl RecursiveCircuit {
         }
     }
 
+    // number of inner proofs verified by the circuit
+    pub fn recursive_size(&self) -> usize {
+        self.inner_circuit_targets.len()
+    }
+
     pub fn prove_recursive_circuit(
         &self,
         subproofs: Vec<ProofWithPublicInputs<F, C, D>>,
@@ -165,10 +171,14 @@ impl RecursiveCircuit {
 
     pub fn prove_empty(
         &self,
-        circuit_registry: &mut CircuitRegistry,
+        circuit_registry: &CircuitRegistry,
     ) -> ProofWithPublicInputs<F, C, D> {        
         let current_digest = self.circuit_data.verifier_only.circuit_digest;
 
+        // NOTE: each recursive level has a distinct circuit (and digest) since it verifies proofs of the level below,
+        // so an empty proof can't be shared between levels: it is proved once per level and stored in the registry,
+        // and the levels above reuse it through get_empty_proof (keyed by the inner digest) for padding
+
         // see if it is already in the registry and return
         let cached_proof = circuit_registry.get_empty_proof(current_digest);
         if let Some(proof) = cached_proof {
@@ -182,7 +192,7 @@ impl RecursiveCircuit {
         let inner_empty_proof = circuit_registry.get_empty_proof(inner_digest).unwrap();
 
         // create and return a new proof with the empty proof as input
-        self.prove_recursive_circuit(vec![inner_empty_proof.clone(); RECURSIVE_SIZE])
+        self.prove_recursive_circuit(vec![inner_empty_proof.clone(); self.recursive_size()])
     }
 
 
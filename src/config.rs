@@ -3,17 +3,89 @@ use plonky2::field::types::Field64;
 
 // change size of each circuits here
 pub const BATCH_SIZE: usize = 512;
+// default recursion arity (number of inner proofs verified by each recursive circuit)
+// it can be changed per proof (e.g. a larger arity for small ledgers reduces the tree depth), it is stored in the final proof
 pub const RECURSIVE_SIZE: usize = 8;
+pub const MIN_RECURSIVE_SIZE: usize = 2;
+// largest recursion arity, each recursive circuit verifies this many inner proofs, so the recursive_size of an untrusted
+// proof file must be bounded before its circuits are rebuilt (far above the arities that pay off for small ledgers)
+pub const MAX_RECURSIVE_SIZE: usize = 64;
 
 pub const D: usize = 2;
+
+// size in bytes of the merkle tree hashes (4 field elements)
+pub const HASH_BYTES: usize = 32;
+
+// user hashes are converted to field elements in chunks of this many hex characters (see user_hash_elements)
+// it is also the longest chunk always below the field order (60 bits, 16 hex characters could exceed it and be reduced)
+pub const USER_HASH_CHUNK_HEX_LEN: usize = 15;
+
 pub type C = PoseidonGoldilocksConfig;
 pub type F = <C as GenericConfig<D>>::F;
 pub type H = <C as GenericConfig<D>>::Hasher;
 
 // max possible integer value for a single account balance
-// this is used to make overflow check faster
+// this is used to make overflow check faster: the sum of BATCH_SIZE balances can't exceed (ORDER - 1) / 2 (the largest positive value)
 pub const MAX_ACCOUNT_BALANCE: u64 = (F::ORDER - 1) / 2 / BATCH_SIZE as u64;
+// the balances are range checked with this number of bits, so the largest accepted balance is 2^bits - 1
+// the bits MUST be rounded down (ilog2 is floor(log2)), rounding up would accept balances above MAX_ACCOUNT_BALANCE
 pub const MAX_ACCOUNT_BALANCE_BITS: usize = MAX_ACCOUNT_BALANCE.ilog2() as usize;
+// largest balance accepted by the range check (2^53 - 1 with BATCH_SIZE = 512)
+pub const MAX_RANGE_CHECKED_BALANCE: u64 = (1u64 << MAX_ACCOUNT_BALANCE_BITS) - 1;
+
+// balances may be negative (liabilities), so they are range checked in [-2^bits, 2^bits)
+// the total of a batch is then in [-BATCH_SIZE * 2^bits, BATCH_SIZE * 2^bits), which is range checked with this number of bits
+pub const MAX_BATCH_TOTAL_BITS: usize = MAX_ACCOUNT_BALANCE_BITS + BATCH_SIZE.next_power_of_two().ilog2() as usize;
+
+// the overflow (and underflow) guarantee must hold for any BATCH_SIZE, checked at compile time
+const _: () = assert!(MAX_RANGE_CHECKED_BALANCE <= MAX_ACCOUNT_BALANCE);
+const _: () = assert!(
+    (MAX_RANGE_CHECKED_BALANCE as u128) * (BATCH_SIZE as u128) <= ((F::ORDER - 1) / 2) as u128
+);
+const _: () = assert!((1u64 << MAX_BATCH_TOTAL_BITS) <= (F::ORDER - 1) / 2);
+
+// default bounds for the (scaled by usdt_decimals) asset prices, checked before proving
+// a zero price makes an asset contribute nothing to the account equity (hiding negative balances)
+// and an absurdly large price is most likely a price feed bug, the bounds don't prevent the equity overflow (see MAX_ACCOUNT_EQUITY)
+pub const DEFAULT_MIN_ASSET_PRICE: u64 = 1;
+pub const DEFAULT_MAX_ASSET_PRICE: u64 = u32::MAX as u64;
+
+// the batch circuit computes the equity of every leaf (sum of balance * price) in the field, so its absolute value must
+// not exceed the largest positive value or it wraps around. with balances up to MAX_RANGE_CHECKED_BALANCE, only prices
+// below 2^(63 - MAX_ACCOUNT_BALANCE_BITS) / asset count would guarantee it, so the exact equity of every leaf is checked
+// before proving instead (see Ledger::check_equity_range)
+pub const MAX_ACCOUNT_EQUITY: u64 = (F::ORDER - 1) / 2;
+
+// bounds of the ledger timestamp (milliseconds), it is what the proof attests the reserves at
+// --> not before 2020-01-01 (a zero or seconds timestamp would render close to the Unix epoch)
+// --> not more than a day in the future (clock skew between the ledger export and the prover)
+pub const MIN_LEDGER_TIMESTAMP: u64 = 1_577_836_800_000;
+pub const MAX_LEDGER_TIMESTAMP_SKEW: u64 = 24 * 60 * 60 * 1000;
+
+// number of leading user hash characters used to group the inclusion proofs in bundles
+// (prove-inclusion --all --layout bundles)
+pub const DEFAULT_GROUP_PREFIX_LEN: usize = 3;
+
+// number of inclusion proof bundles serialized, compressed and written at the same time (prove-inclusion --all
+// --layout bundles), it bounds the bundles held in memory independently of the number of proving threads
+pub const DEFAULT_MAX_BUNDLE_WRITERS: usize = 4;
+
+// largest tree depth accepted by the verifier, the root circuit rebuild builds one circuit per level, so a proof file
+// with an absurd tree_depth would otherwise allocate circuits until it runs out of memory
+// --> 2^32 accounts (far above any exchange) in batches of a single account with the smallest arity (2) give
+//     32 recursive levels, plus the batch and leaf levels
+pub const DEFAULT_MAX_TREE_DEPTH: usize = 32 + 2;
+
+// number of random users whose inclusion proofs are generated and verified by prove --verify
+pub const DEFAULT_VERIFY_SAMPLE_SIZE: usize = 64;
+
+// directory the inclusion proofs are written to (--all always uses it, see prove-inclusion --out-dir)
+pub const DEFAULT_INCLUSION_PROOFS_DIR: &str = "inclusion_proofs";
+
+// version of the schema of the serialized merkle trees and inclusion proofs, bumped on every change of their fields
+// files written before it was recorded have no version and are read as LEGACY_FORMAT_VERSION
+pub const CURRENT_FORMAT_VERSION: u32 = 2;
+pub const LEGACY_FORMAT_VERSION: u32 = 1;
 
 // batch circuit config
 pub const BATCH_CIRCUIT_CONFIG: CircuitConfig = CircuitConfig {
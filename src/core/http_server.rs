@@ -0,0 +1,119 @@
+use crate::*;
+use crate::utils::util::{is_padding_hash, is_valid_user_hash};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+fn respond(request: Request, status: u16, body: String) {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(content_type);
+
+    if let Err(e) = request.respond(response) {
+        log_error!("Failed to send HTTP response: {}", e);
+    }
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn handle_request(
+    request: Request,
+    hash_index: &HashMap<String, usize>,
+    merkle_tree: &MerkleTree,
+    nonces: &[u64],
+    ledger: &Ledger,
+) {
+    // the query string (e.g. a cache buster) is not part of the route
+    let path = request.url().split(['?', '#']).next().unwrap_or_default();
+
+    // only GET /inclusion/{user_hash} is supported
+    let Some(user_hash) = path.strip_prefix("/inclusion/") else {
+        return respond(request, 404, error_body("Not found"));
+    };
+    if *request.method() != Method::Get {
+        return respond(request, 405, error_body("Only GET is supported"));
+    }
+    if !is_valid_user_hash(user_hash) {
+        return respond(request, 400, error_body("The user hash must be a lowercase hex string"));
+    }
+
+    // padding accounts are not real users
+    if is_padding_hash(user_hash) {
+        return respond(request, 400, error_body("Padding hashes cannot be proven"));
+    }
+    if !hash_index.contains_key(user_hash) {
+        return respond(request, 404, error_body("User hash not found in ledger"));
+    }
+
+    // the user is in the ledger, so a failure here is a server error (e.g. a merkle tree that doesn't match the ledger)
+    let user_hash = user_hash.to_string();
+    match prove_user_inclusion_by_hash_indexed(user_hash, hash_index, merkle_tree, nonces, ledger) {
+        Ok(inclusion_proof) => match serde_json::to_string(&inclusion_proof) {
+            Ok(json) => respond(request, 200, json),
+            Err(e) => respond(request, 500, error_body(&e.to_string())),
+        },
+        Err(e) => {
+            log_error!("Failed to serve {}: {}", path, e);
+            respond(request, 500, error_body(&e.to_string()))
+        }
+    }
+}
+
+// serve inclusion proofs over HTTP: GET /inclusion/{user_hash} returns the InclusionProof as JSON (the query string is
+// ignored), 404 if the hash is not in the ledger (or for another path), 405 for another method, 400 for a hash that is
+// not hex or a padding hash, 500 if the proof can't be generated
+pub fn serve_http(
+    addr: &str,
+    merkle_tree: MerkleTree,
+    nonces: Vec<u64>,
+    ledger: Ledger,
+) -> Result<()> {
+    check_user_nonces_count(&nonces, &ledger)?;
+
+    let server = Server::http(addr)
+        .map_err(|e| anyhow::anyhow!("Failed to start HTTP server on {addr}: {e}"))?;
+    log_success!("HTTP server listening on: {}", addr);
+
+    // build the hash -> index map once so each lookup is O(1)
+    let hash_index = Arc::new(ledger.build_index());
+    let server = Arc::new(server);
+    let merkle_tree = Arc::new(merkle_tree);
+    let nonces = Arc::new(nonces);
+    let ledger = Arc::new(ledger);
+
+    // one worker per CPU, each one pulling requests from the shared server
+    let workers = (0..rayon::current_num_threads())
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let hash_index = Arc::clone(&hash_index);
+            let merkle_tree = Arc::clone(&merkle_tree);
+            let nonces = Arc::clone(&nonces);
+            let ledger = Arc::clone(&ledger);
+
+            thread::spawn(move || {
+                loop {
+                    match server.recv() {
+                        Ok(request) => {
+                            handle_request(request, &hash_index, &merkle_tree, &nonces, &ledger)
+                        }
+                        Err(e) => {
+                            log_error!("Failed to receive HTTP request: {}", e);
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    Ok(())
+}
@@ -1,5 +1,12 @@
+#[cfg(feature = "prover")]
 pub mod prover;
 pub mod verifier;
 
-#[cfg(target_family = "unix")]
-pub mod server;
\ No newline at end of file
+#[cfg(all(feature = "prover", target_family = "unix"))]
+pub mod server;
+
+#[cfg(feature = "http-server")]
+pub mod http_server;
+
+#[cfg(feature = "fixtures")]
+pub mod test_vector;
\ No newline at end of file
@@ -1,21 +1,25 @@
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Instant;
 
+use crate::core::verifier::verify_inclusion_against_root;
 use crate::types::*;
 use crate::utils::logger::*;
 use crate::{
     circuits::batch_circuit::BatchCircuit,
     circuits::circuit_registry::CircuitRegistry,
     circuits::recursive_circuit::RecursiveCircuit,
-    merkle_tree::{MerkleTree, Node},
+    custom_serializer::format::{
+        from_bytes, is_storage_full, write_atomic_with_retry, write_to_file_with_retry, SerializationFormat,
+    },
+    merkle_tree::{CompactMerkleTree, MerkleTree, Node},
     utils::util::*,
-    config::{BATCH_SIZE, RECURSIVE_SIZE, F, C, D},
+    config::{F, C, D, DEFAULT_GROUP_PREFIX_LEN},
     *,
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
 use plonky2::util::serialization::DefaultGateSerializer;
 use plonky2::hash::hash_types::HashOut;
 use plonky2::plonk::proof::ProofWithPublicInputs;
@@ -23,62 +27,64 @@ use plonky2::plonk::circuit_data::VerifierCircuitData;
 use plonky2::plonk::config::GenericHashOut;
 use zstd;
 
+// inner_level is the digest of the inner recursive circuit and the merkle depth of this level, None for the level that
+// proves the batch proofs
 fn prove_recursively(
-    inner_circuit_digest: Option<HashOut<F>>,
+    inner_level: Option<(HashOut<F>, usize)>,
     asset_count: usize,
     mut inner_proofs: Vec<ProofWithPublicInputs<F, C, D>>,
     mut merkle_tree: MerkleTree,
-    mut merkle_depth: Option<usize>,
+    recursive_size: usize,
     circuit_registry: &mut CircuitRegistry,
     progress: &mut ProveProgress,
 ) -> (ProofWithPublicInputs<F, C, D>, MerkleTree) {
+    let _span = log_span!("prove_recursive_level");
+
     // show the progress bar
     progress.print_progress_bar();
 
     // get the inner circuit
-    let inner_circuit;
-    if let Some(inner_circuit_digest) = inner_circuit_digest {
+    let (inner_circuit, merkle_depth) = match inner_level {
         // inner circuit is a recursive circuit if the digest is not None
-        inner_circuit = &circuit_registry
-            .get_recursive_circuit(inner_circuit_digest)
-            .unwrap()
-            .circuit
-            .circuit_data;
-    } else {
+        Some((inner_circuit_digest, merkle_depth)) => (
+            &circuit_registry
+                .get_recursive_circuit(inner_circuit_digest)
+                .unwrap()
+                .circuit
+                .circuit_data,
+            merkle_depth,
+        ),
         // otherwise it is the batch circuit
-        inner_circuit = &circuit_registry.get_batch_circuit().circuit_data;
-    }
-
-    if merkle_depth.is_none() {
-        merkle_depth = Some(merkle_tree.depth - 2); // last depth are the leafs (account hashes) and second to last are batch circuit hashes
-    }
+        // last depth are the leafs (account hashes) and second to last are batch circuit hashes
+        None => (&circuit_registry.get_batch_circuit().circuit_data, merkle_tree.depth - 2),
+    };
 
     let build_circuit_time = Instant::now();
     // build the recursive circuit
-    let recursive_circuit = RecursiveCircuit::new(inner_circuit, asset_count);
+    let recursive_circuit = RecursiveCircuit::new(inner_circuit, asset_count, recursive_size);
     progress.update_recursive_circuit_progress();
 
     // BENCHMARK DEBUG
-    if cfg!(debug_assertions) {
+    if show_timings() {
         let elapsed = build_circuit_time.elapsed();
         progress.clear_bar();
         log_warning!(
             "Recursive circuit at depth {} build time: {:?}",
-            merkle_depth.unwrap(),
+            merkle_depth,
             elapsed
         );
         progress.print_progress_bar();
     }
 
-    // pad the inner proofs to have a multiple of RECURSIVE_SIZE
+    // pad the inner proofs to have a multiple of recursive_size
     let empty_proof = circuit_registry
         .get_empty_proof(inner_circuit.verifier_only.circuit_digest)
         .unwrap();
-    pad_recursive_proofs(&mut inner_proofs, empty_proof);
+    pad_recursive_proofs(&mut inner_proofs, empty_proof, recursive_size);
 
     // add the padded ones to the merkle tree (in the last depth)
     let mut count = 0;
-    for node in merkle_tree.get_nodes_from_depth(merkle_depth.unwrap() + 1) {
+    for node in merkle_tree.get_nodes_from_depth(merkle_depth + 1) {
         if node.hash().is_some() {
             count += 1;
             continue; // already populated
@@ -96,36 +102,69 @@ fn prove_recursively(
         count += 1;
     }
 
-    // chunk inner circuits in groups of RECURSIVE_SIZE
-    let subproofs = inner_proofs.chunks(RECURSIVE_SIZE);
-
-    // prove all chunks
-    let mut recursive_proofs = Vec::new();
-
-    for chunk in subproofs {
-        let timer = Instant::now();
-
-        let proof = recursive_circuit.prove_recursive_circuit(chunk.to_vec());
-        recursive_proofs.push(proof);
+    // chunk inner circuits in groups of recursive_size and prove all chunks in parallel (they are independent)
+    // the empty proof of this level (used to pad the next level) only depends on the inner empty proof,
+    // so it is proved at the same time (the root level doesn't need one)
+    let is_root = merkle_depth == 1;
+    let registry: &CircuitRegistry = circuit_registry;
+
+    let (recursive_proofs, empty_proof) = {
+        let progress_lock = Mutex::new(&mut *progress);
+
+        rayon::join(
+            || {
+                inner_proofs
+                    .par_chunks(recursive_size)
+                    .map(|chunk| {
+                        let timer = Instant::now();
+
+                        let proof = recursive_circuit.prove_recursive_circuit(chunk.to_vec());
+
+                        let mut progress = progress_lock.lock().unwrap();
+                        if show_timings() {
+                            // BENCHMARK DEBUG
+                            let elapsed = timer.elapsed();
+                            progress.clear_bar();
+                            log_warning!("Recursive proof time: {:?}", elapsed);
+                            progress.print_progress_bar();
+                        }
+
+                        // update progress
+                        progress.update_recursive_progress();
+
+                        proof
+                    })
+                    .collect::<Vec<_>>()
+            },
+            || {
+                (!is_root).then(|| {
+                    let timer = Instant::now();
+                    (recursive_circuit.prove_empty(registry), timer.elapsed())
+                })
+            },
+        )
+    };
 
-        if cfg!(debug_assertions) {
-            // BENCHMARK DEBUG
-            let elapsed = timer.elapsed();
+    // BENCHMARK DEBUG
+    let empty_proof = empty_proof.map(|(empty_proof, elapsed)| {
+        if show_timings() {
             progress.clear_bar();
-            log_warning!("Recursive proof time: {:?}", elapsed);
+            log_warning!(
+                "Empty proof at depth {} time: {:?}",
+                merkle_depth,
+                elapsed
+            );
             progress.print_progress_bar();
         }
+        empty_proof
+    });
 
-        // update progress
-        progress.update_recursive_progress();
-    }
-
-    // add the recursive circuit to the registry (only if it is not the root circuit)
+    // add the recursive circuit to the registry
     let inner_circuit_digest = recursive_circuit.circuit_data.verifier_only.circuit_digest;
-    circuit_registry.add_recursive_circuit(recursive_circuit, merkle_depth.unwrap());
+    circuit_registry.add_recursive_circuit(recursive_circuit, merkle_depth, empty_proof);
 
     // get the nodes from the merkle tree at the current depth
-    let nodes = &mut merkle_tree.get_nodes_from_depth(merkle_depth.unwrap());
+    let nodes = &mut merkle_tree.get_nodes_from_depth(merkle_depth);
 
     // set the nodes hashes and proofs
     let mut count = 0;
@@ -149,11 +188,11 @@ fn prove_recursively(
     if recursive_proofs.len() > 1 {
         // prove the recursive circuit with the recursive proofs
         prove_recursively(
-            Some(inner_circuit_digest),
+            Some((inner_circuit_digest, merkle_depth - 1)),
             asset_count,
             recursive_proofs,
             merkle_tree,
-            Some(merkle_depth.unwrap() - 1),
+            recursive_size,
             circuit_registry,
             progress,
         )
@@ -162,73 +201,157 @@ fn prove_recursively(
     }
 }
 
-pub fn prove_global(mut ledger: Ledger) -> Result<(FinalProof, MerkleTree, Vec<u64>)> {
+// append the non-user leaves to the ledger accounts, in the same order they are proven
+// returns the number of real leaves (before the padding accounts), recorded in the merkle tree
+pub(crate) fn prepare_leaf_accounts(ledger: &mut Ledger, batch_size: usize) -> Result<usize> {
     let asset_count = ledger.asset_names.len();
 
-    // pad accounts to have a multiple of BATCH_SIZE
+    // the batch circuit expects exactly asset_count balances per account
+    ledger.validate_accounts()?;
+
+    // the reserved leafs take the hash length of the user accounts
+    if ledger.hashes.is_empty() {
+        return Err(anyhow::anyhow!("The ledger has no accounts, there is nothing to prove"));
+    }
+    let hash_nibbles = ledger.hashes[0].len();
+
+    // add the dust aggregate as an extra leaf after the user accounts so the dust accounts are summed into the proven reserves
+    // it uses a reserved hash and the dust accounts are not individually inclusion-provable
+    if let Some(dust) = &ledger.dust {
+        ledger.hashes.push(dust_aggregate_hash(hash_nibbles));
+        ledger.account_balances.push(dust.balances.clone());
+    }
+
+    // add the reserve buffer as an extra leaf after the user accounts so it is summed into the proven reserves
+    // it uses a reserved hash and is not a user account, so no inclusion proof is generated for it
+    if ledger.reserve_buffer.iter().any(|balance| *balance != 0) {
+        ledger.hashes.push(reserve_buffer_hash(hash_nibbles));
+        ledger.account_balances.push(ledger.reserve_buffer.clone());
+    }
+
+    let real_leaf_count = ledger.hashes.len();
+
+    // the equity of every leaf (the dust aggregate and reserve buffer included) is computed in the batch circuit
+    ledger.check_equity_range()?;
+
+    // pad accounts to have a multiple of batch_size
     pad_accounts(
         &mut ledger.account_balances,
         &mut ledger.hashes,
         asset_count,
-        BATCH_SIZE,
+        batch_size,
     )?;
 
-    let mut progress = ProveProgress::new(ledger.account_balances.len() / BATCH_SIZE);
+    Ok(real_leaf_count)
+}
+
+pub fn prove_global(ledger: Ledger) -> Result<(FinalProof, MerkleTree, Vec<u64>)> {
+    prove_global_with_config(ledger, &ProverConfig::default())
+}
+
+// prove the ledger with recursive circuits verifying recursive_size inner proofs each
+// a larger arity reduces the tree depth (fewer recursive levels), the arity is stored in the final proof
+pub fn prove_global_with_arity(
+    ledger: Ledger,
+    recursive_size: usize,
+) -> Result<(FinalProof, MerkleTree, Vec<u64>)> {
+    prove_global_with_config(
+        ledger,
+        &ProverConfig {
+            recursive_size,
+            ..ProverConfig::default()
+        },
+    )
+}
+
+// prove the ledger with the circuit sizes of the config (e.g. ProverConfig::testing() for fast tests)
+// both sizes are stored in the final proof
+pub fn prove_global_with_config(
+    mut ledger: Ledger,
+    config: &ProverConfig,
+) -> Result<(FinalProof, MerkleTree, Vec<u64>)> {
+    let _span = log_span!("prove_global");
+    let asset_count = ledger.asset_names.len();
+
+    config.validate()?;
+    let ProverConfig { batch_size, recursive_size } = *config;
+
+    let real_leaf_count = prepare_leaf_accounts(&mut ledger, batch_size)?;
+
+    let mut progress = ProveProgress::new(ledger.account_balances.len() / batch_size, recursive_size);
 
     // create the batch circuit
     log_info!("Creating batch circuit and proving all accounts...");
     progress.print_progress_bar();
 
-    let batch_circuit = BatchCircuit::new(asset_count);
+    let batch_circuit = BatchCircuit::new_with_batch_size(asset_count, batch_size);
     let mut batch_proofs = Vec::new();
 
     let mut merkle_leafs = Vec::new();
     let mut account_nonces = Vec::new();
 
-    // split the account into chunks of BATCH_SIZE and prove all chunks
-    let mut count = 0;
-    for chunk in ledger.account_balances.chunks(BATCH_SIZE) {
-        let circuit_ref = &batch_circuit;
-        let batch_time = Instant::now();
-
-        // calculate each account hash (leafs)
-        let mut leaf_hashes = Vec::new();
-        for i in 0..chunk.len() {
-            let userhash = ledger.hashes[count * BATCH_SIZE + i].clone();
-            let balances = chunk[i].clone();
-
-            // generate a random nonce as security against brute force attacks to discover user balances
-            // MAKE SURE THIS ITERATION IS NOT PARALLELIZED, OTHERWISE THE NONCES VECTOR
-            // WILL NOT BE ORDERED CORRECTLY
-            let nonce = rand::random::<u64>();
-            account_nonces.push(nonce);
-
-            let hash = hash_account(&balances, userhash, nonce);
-            leaf_hashes.push(hash);
-        }
+    // the empty batch proof (used to pad the batch level) is independent of the accounts,
+    // so it is proved in parallel with the batch proofs
+    let ((empty_batch_proof, empty_batch_elapsed), batch_result) = rayon::join(
+        || {
+            let timer = Instant::now();
+            (batch_circuit.prove_empty(&ledger.asset_prices), timer.elapsed())
+        },
+        || {
+            // split the account into chunks of batch_size and prove all chunks
+            let mut count = 0;
+            for chunk in ledger.account_balances.chunks(batch_size) {
+                let circuit_ref = &batch_circuit;
+                let batch_time = Instant::now();
+
+                // calculate each account hash (leafs)
+                let mut leaf_hashes = Vec::new();
+                for i in 0..chunk.len() {
+                    let userhash = ledger.hashes[count * batch_size + i].clone();
+                    let balances = chunk[i].clone();
+
+                    // generate a random nonce as security against brute force attacks to discover user balances
+                    // MAKE SURE THIS ITERATION IS NOT PARALLELIZED, OTHERWISE THE NONCES VECTOR
+                    // WILL NOT BE ORDERED CORRECTLY
+                    // NOTE: a nonce is generated for every leaf (users, then dust aggregate and reserve buffer, then
+                    // padding accounts), so nonces[i] always belongs to the i-th leaf and nonces.len() is exactly the
+                    // leaf count padded to a multiple of the batch size (see check_nonces_count)
+                    // the nonce is a canonical field element (see random_nonce)
+                    let nonce = random_nonce();
+                    account_nonces.push(nonce);
+
+                    let hash = hash_account(&balances, userhash, nonce);
+                    leaf_hashes.push(hash);
+                }
 
-        let proof = circuit_ref
-            .prove_batch_circuit(&ledger.asset_prices, chunk, &leaf_hashes)
-            .unwrap();
+                let proof = circuit_ref.prove_batch_circuit(&ledger.asset_prices, chunk, &leaf_hashes)?;
 
-        // add to the merkle tree leafs
-        merkle_leafs.push(leaf_hashes);
+                // add to the merkle tree leafs
+                merkle_leafs.push(leaf_hashes);
 
-        // update progress
-        progress.update_batch_progress();
+                // update progress
+                progress.update_batch_progress();
 
-        if cfg!(debug_assertions) {
-            let elapsed = batch_time.elapsed();
-            progress.clear_bar();
-            log_warning!("Batch {} took {:?}", count, elapsed);
-            progress.print_progress_bar();
-        }
+                if show_timings() {
+                    let elapsed = batch_time.elapsed();
+                    progress.clear_bar();
+                    log_warning!("Batch {} took {:?}", count, elapsed);
+                    progress.print_progress_bar();
+                }
 
-        batch_proofs.push(proof);
-        count += 1;
-    }
+                batch_proofs.push(proof);
+                count += 1;
+            }
+
+            Ok::<(), anyhow::Error>(())
+        },
+    );
     progress.clear_bar(); // need to clear the progress bar to print information
+    batch_result?;
     log_success!("Proved all batch circuits successfully!");
+    if show_timings() {
+        log_warning!("Empty batch proof time: {:?}", empty_batch_elapsed);
+    }
     progress.print_progress_bar();
 
     // create the merkle tree leaf nodes
@@ -241,11 +364,19 @@ pub fn prove_global(mut ledger: Ledger) -> Result<(FinalProof, MerkleTree, Vec<u
     }
 
     // create all the merkle tree structure (and populate the leafs)
-    let mut merkle_tree = MerkleTree::new_from_leafs(leaf_nodes, 1, true);
+    let mut merkle_tree =
+        MerkleTree::new_from_leafs_with_config(leaf_nodes, 1, true, config).with_real_leaf_count(real_leaf_count);
+
+    // record the reserves proven by each batch for the deep check of the verifier
+    // (the batch public inputs have the same layout as the recursive ones)
+    merkle_tree.batch_reserves = batch_proofs
+        .iter()
+        .map(|proof| ProofPublicInputs::new(proof, asset_count).final_balances())
+        .collect();
 
     // create the circuit registry
     let batch_circuit_digest = batch_circuit.circuit_data.verifier_only.circuit_digest;
-    let mut circuit_registry = CircuitRegistry::new(batch_circuit, &ledger.asset_prices);
+    let mut circuit_registry = CircuitRegistry::new(batch_circuit, empty_batch_proof);
 
     // populate the batch nodes
     let batch_nodes = merkle_tree.get_nodes_from_depth(merkle_tree.depth - 1);
@@ -294,7 +425,7 @@ pub fn prove_global(mut ledger: Ledger) -> Result<(FinalProof, MerkleTree, Vec<u
         asset_count,
         batch_proofs,
         merkle_tree,
-        None,
+        recursive_size,
         &mut circuit_registry,
         &mut progress,
     );
@@ -316,9 +447,10 @@ pub fn prove_global(mut ledger: Ledger) -> Result<(FinalProof, MerkleTree, Vec<u
         .clone();
 
     let final_proof = FinalProof {
+        format_version: CURRENT_FORMAT_VERSION,
         proof: root_proof,
-        batch_size: BATCH_SIZE,
-        recursive_size: RECURSIVE_SIZE,
+        batch_size,
+        recursive_size,
         asset_prices: asset_prices.clone(),
         asset_names: ledger.asset_names.clone(),
         asset_decimals: ledger.asset_decimals.clone(),
@@ -328,6 +460,10 @@ pub fn prove_global(mut ledger: Ledger) -> Result<(FinalProof, MerkleTree, Vec<u
             .unwrap(),
         timestamp: ledger.timestamp,
         prover_version: format!("v{}", env!("CARGO_PKG_VERSION")),
+        reserve_buffer: ledger.reserve_buffer.clone(),
+        dust: ledger.dust.clone(),
+        annotations: ProofAnnotations::default(),
+        verifier_data_digest: None,
     };
 
     log_success!("Created final proof successfully!");
@@ -342,6 +478,15 @@ pub fn prove_user_inclusion(
     merkle_tree: &MerkleTree,
     ledger: &Ledger,
 ) -> Result<InclusionProof> {
+    // padding leafs are not accounts (only checked when the tree records its real leaf count, the fallback walks the tree)
+    if let Some(real_leaf_count) = merkle_tree.real_leaf_count.filter(|count| user_index >= *count) {
+        return Err(anyhow::anyhow!(
+            "Leaf {} is a padding leaf (the merkle tree has {} real leafs)",
+            user_index,
+            real_leaf_count
+        ));
+    }
+
     let user_balances = ledger.account_balances[user_index].clone();
 
     let user_node_path = merkle_tree.get_nth_leaf_path(user_index).unwrap();
@@ -349,6 +494,7 @@ pub fn prove_user_inclusion(
     let merkle_proof = merkle_tree.prove_inclusion(user_node_path);
 
     let inclusion_proof = InclusionProof {
+        format_version: CURRENT_FORMAT_VERSION,
         user_hash,
         user_balances: user_balances.clone(),
         merkle_proof,
@@ -359,12 +505,315 @@ pub fn prove_user_inclusion(
     Ok(inclusion_proof)
 }
 
+// rebuild the merkle tree of a global proof from the ledger and nonces, without proving (disaster recovery)
+// every hash of the tree is the same hash computed by the circuits, so only the hashing is re-run:
+// --> leafs: account hashes (balances, user hash and nonce)
+// --> batch and recursive nodes: hash of the concatenated children hashes
+// --> padding nodes: root hash of the empty proofs (empty batch leafs are zero hashes, recursive empty proofs are built from the inner ones)
+pub fn rebuild_merkle_tree(mut ledger: Ledger, nonces: &[u64], config: &ProverConfig) -> Result<MerkleTree> {
+    let _span = log_span!("rebuild_merkle_tree");
+
+    config.validate()?;
+    check_count(nonces.len(), leaf_nonces_count(&ledger, config.batch_size), LEAF_NONCES_LABEL)?;
+    let real_leaf_count = prepare_leaf_accounts(&mut ledger, config.batch_size)?;
+    let leaf_nodes = leaf_hashes(&ledger, nonces)?
+        .into_iter()
+        .map(|hash| Node::new(Some(hash)))
+        .collect::<Vec<_>>();
+
+    let mut merkle_tree =
+        MerkleTree::new_from_leafs_with_config(leaf_nodes, 1, true, config).with_real_leaf_count(real_leaf_count);
+
+    // the batch reserves are the sums the batch circuits compute (range checked, so they fit in an i64)
+    let asset_count = ledger.asset_names.len();
+    merkle_tree.batch_reserves = ledger
+        .account_balances
+        .chunks(config.batch_size)
+        .map(|chunk| {
+            (0..asset_count)
+                .map(|asset| chunk.iter().map(|balances| balances[asset]).sum())
+                .collect()
+        })
+        .collect();
+
+    // populate the nodes bottom-up, starting from the batch level
+    let mut padding_hash = HashOut::<F>::default().to_bytes();
+    let mut padding_arity = config.batch_size;
+
+    for depth in (1..merkle_tree.depth).rev() {
+        // root hash of the empty proof at this depth
+        padding_hash = hash_n_subhashes::<F, D>(&vec![padding_hash; padding_arity]).to_bytes();
+        padding_arity = config.recursive_size;
+
+        merkle_tree
+            .get_nodes_from_depth(depth)
+            .into_par_iter()
+            .for_each(|node| {
+                let hash = match node.children() {
+                    Some(children) => {
+                        let children_hashes = children
+                            .iter()
+                            .map(|child| child.hash().clone().unwrap())
+                            .collect::<Vec<_>>();
+                        hash_n_subhashes::<F, D>(&children_hashes).to_bytes()
+                    }
+                    None => padding_hash.clone(),
+                };
+                node.set_hash(hash);
+            });
+    }
+
+    Ok(merkle_tree)
+}
+
+// check if the root hash of a (rebuilt) merkle tree is the root hash proven in the final proof
+pub fn check_merkle_root(final_proof: &FinalProof, merkle_tree: &MerkleTree) -> Result<()> {
+    if merkle_tree.root.hash().as_ref() != Some(&final_proof.root_hash()) {
+        return Err(anyhow::anyhow!(
+            "Merkle tree root hash does not match the final proof. Make sure the ledger and nonces files belong to this proof"
+        ));
+    }
+    Ok(())
+}
+
+// calculates the leaf hashes (same as prove_global) of a ledger with its non-user and padding leaves already added
+// (see prepare_leaf_accounts), the nonces must have one nonce per leaf
+fn leaf_hashes(ledger: &Ledger, nonces: &[u64]) -> Result<Vec<Vec<u8>>> {
+    check_count(nonces.len(), ledger.hashes.len(), LEAF_NONCES_LABEL)?;
+
+    for nonce in nonces {
+        check_nonce(*nonce)?;
+    }
+
+    Ok(ledger
+        .account_balances
+        .par_iter()
+        .zip(ledger.hashes.par_iter())
+        .zip(nonces.par_iter())
+        .map(|((balances, userhash), nonce)| hash_account(balances, userhash.clone(), *nonce).to_bytes())
+        .collect())
+}
+
+// generates the inclusion proof of a single user without the serialized merkle tree: the tree is rebuilt from the
+// ledger and nonces with the low-memory CompactMerkleTree (only flat hash levels, no nodes or batch reserves) and its
+// root is checked against the published root before the path of the user is extracted
+// the nonces must be positional (one per leaf, including the padding leaves), the ledger must have the proof dust applied
+pub fn prove_user_inclusion_compact(
+    user_hash: String,
+    mut ledger: Ledger,
+    nonces: &[u64],
+    config: &ProverConfig,
+    published_root: &[u8],
+) -> Result<InclusionProof> {
+    let _span = log_span!("prove_user_inclusion_compact");
+
+    config.validate()?;
+    check_count(nonces.len(), leaf_nonces_count(&ledger, config.batch_size), LEAF_NONCES_LABEL)?;
+
+    let user_index = ledger
+        .hashes
+        .iter()
+        .position(|hash| *hash == user_hash)
+        .ok_or_else(|| anyhow::anyhow!("User hash not found in ledger"))?;
+    let user_balances = ledger.account_balances[user_index].clone();
+    let nonce = nonces[user_index];
+
+    prepare_leaf_accounts(&mut ledger, config.batch_size)?;
+    let merkle_tree = CompactMerkleTree::new(&leaf_hashes(&ledger, nonces)?, config)?;
+    drop(ledger);
+
+    let root_hash = merkle_tree.root_hash();
+    if root_hash != published_root {
+        return Err(anyhow::anyhow!(
+            "The rebuilt root hash {} does not match the published root hash {}. Make sure the ledger and nonces belong to this proof",
+            bytes_to_hex(&root_hash),
+            bytes_to_hex(published_root)
+        ));
+    }
+
+    Ok(InclusionProof {
+        format_version: CURRENT_FORMAT_VERSION,
+        user_hash,
+        user_balances,
+        merkle_proof: merkle_tree.merkle_proof(user_index)?,
+        root_hash,
+        nonce,
+    })
+}
+
+// binds each user hash to its nonce (the nonces of the dust aggregate, reserve buffer and padding leaves come after
+// the users and are left out, they are only needed to rebuild the merkle tree)
+// a duplicate user hash is rejected, its nonces would silently collapse into one entry
+pub fn nonces_by_hash(user_hashes: &[String], nonces: &[u64]) -> Result<HashMap<String, u64>> {
+    if nonces.len() < user_hashes.len() {
+        return Err(anyhow::anyhow!(
+            "Nonces length mismatch: found {} nonces for {} accounts",
+            nonces.len(),
+            user_hashes.len()
+        ));
+    }
+
+    check_unique_user_hashes(user_hashes)?;
+    Ok(user_hashes.iter().cloned().zip(nonces.iter().copied()).collect())
+}
+
+// nonces_by_hash needs one entry per user, `prove --nonces-by-hash` checks it before proving instead of after hours of work
+pub fn check_unique_user_hashes(user_hashes: &[String]) -> Result<()> {
+    let mut seen = HashSet::with_capacity(user_hashes.len());
+    for user_hash in user_hashes {
+        if !seen.insert(user_hash.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Duplicate user hash {user_hash}, the nonces can't be keyed by user hash"
+            ));
+        }
+    }
+    Ok(())
+}
+
+// reads a nonces file in either form (see AccountNonces), the keyed form is tried first: a positional file can't be
+// read as a map (json and msgpack are typed, and in bincode the first nonce would be read as a huge string length)
+pub fn read_nonces(path: &str) -> Result<AccountNonces> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+
+    if let Ok(nonces) = from_bytes::<HashMap<String, u64>>(&bytes) {
+        return Ok(AccountNonces::ByHash(nonces));
+    }
+    let nonces: Vec<u64> = from_bytes(&bytes).with_context(|| format!("Failed to deserialize {path}"))?;
+
+    Ok(AccountNonces::Positional(nonces))
+}
+
+// checks the nonces against the inclusion inputs and returns the nonce of each user, in the order of the ledger hashes
+// positional nonces must have one nonce per leaf (see check_nonces_count), the nonces of the dust aggregate, reserve
+// buffer and padding leaves are dropped, so both forms resolve to the same user nonces (see check_user_nonces_count)
+pub fn resolve_nonces(nonces: AccountNoncesRef<'_>, ledger: &Ledger, merkle_tree: &MerkleTree) -> Result<Vec<u64>> {
+    match nonces {
+        AccountNoncesRef::Positional(nonces) => {
+            check_nonces_count(nonces, ledger, merkle_tree)?;
+            Ok(nonces[..ledger.hashes.len()].to_vec())
+        }
+        AccountNoncesRef::ByHash(nonces) => {
+            check_count(nonces.len(), ledger.hashes.len(), KEYED_NONCES_LABEL)?;
+            ledger.hashes.iter().map(|hash| keyed_nonce(nonces, hash)).collect()
+        }
+    }
+}
+
+// same checks as resolve_nonces, but only the nonce of the user at user_index is read (e.g. for a single inclusion proof)
+pub fn resolve_user_nonce(
+    nonces: AccountNoncesRef<'_>,
+    user_index: usize,
+    ledger: &Ledger,
+    merkle_tree: &MerkleTree,
+) -> Result<u64> {
+    if user_index >= ledger.hashes.len() {
+        return Err(anyhow::anyhow!(
+            "User index {} out of bounds (ledger has {} accounts)",
+            user_index,
+            ledger.hashes.len()
+        ));
+    }
+
+    match nonces {
+        AccountNoncesRef::Positional(nonces) => {
+            check_nonces_count(nonces, ledger, merkle_tree)?;
+            Ok(nonces[user_index])
+        }
+        AccountNoncesRef::ByHash(nonces) => {
+            check_count(nonces.len(), ledger.hashes.len(), KEYED_NONCES_LABEL)?;
+            keyed_nonce(nonces, &ledger.hashes[user_index])
+        }
+    }
+}
+
+const LEAF_NONCES_LABEL: &str = "one per leaf, including the dust aggregate, reserve buffer and padding";
+// the keyed nonces are only stored for the users of the ledger (with the proof dust already applied)
+const KEYED_NONCES_LABEL: &str = "one per user hash";
+
+// every nonce count is exact, a stale nonces file (from a previous ledger) would otherwise produce inclusion proofs
+// failing with a root hash mismatch, the label describes what the expected count is made of
+fn check_count(found: usize, expected: usize, label: &str) -> Result<()> {
+    if found != expected {
+        return Err(anyhow::anyhow!(
+            "Nonces length mismatch: found {found} nonces, expected {expected} ({label}). The nonces are probably from another ledger"
+        ));
+    }
+    Ok(())
+}
+
+fn keyed_nonce(nonces: &HashMap<String, u64>, user_hash: &str) -> Result<u64> {
+    nonces
+        .get(user_hash)
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("No nonce found for user hash {user_hash}"))
+}
+
+// the inclusion provers (and servers) only prove users, so they are given exactly one nonce per user, in the order of
+// the ledger hashes (see resolve_nonces), the nonces of the other leaves are only needed to rebuild the merkle tree
+pub fn check_user_nonces_count(nonces: &[u64], ledger: &Ledger) -> Result<()> {
+    check_count(nonces.len(), ledger.hashes.len(), "one per user, as resolved by resolve_nonces")
+}
+
+// the nonces file has exactly one nonce per merkle tree leaf (users, dust aggregate, reserve buffer and padding
+// accounts, see prepare_leaf_accounts), so its length is fully determined by the ledger and the batch size
+// checked once when loading the inclusion inputs (the ledger must already have the proof dust applied)
+pub fn check_nonces_count(nonces: &[u64], ledger: &Ledger, merkle_tree: &MerkleTree) -> Result<()> {
+    let batch_size = *merkle_tree
+        .level_arities()
+        .last()
+        .ok_or_else(|| anyhow::anyhow!("The merkle tree has no batch level"))?;
+
+    check_count(nonces.len(), leaf_nonces_count(ledger, batch_size), LEAF_NONCES_LABEL)
+}
+
+// one nonce per leaf, padded to a multiple of the batch size
+fn leaf_nonces_count(ledger: &Ledger, batch_size: usize) -> usize {
+    ledger.leaf_count().div_ceil(batch_size) * batch_size
+}
+
+// maps the leafs committed in the merkle tree back to the user hashes of the ledger, for membership audits
+// the leafs are in ledger order (see prepare_leaf_accounts), so the first ones are the user accounts and the dust
+// aggregate, reserve buffer and padding leafs after them are skipped (the ledger must already have the proof dust applied)
+// every user leaf is recomputed from the balances, user hash and nonce of the ledger and must be the leaf of the tree,
+// so a listed user hash is really committed (the nonces are in ledger order, e.g. from resolve_nonces)
+pub fn committed_user_hashes(merkle_tree: &MerkleTree, ledger: &Ledger, nonces: &[u64]) -> Result<Vec<(String, Vec<u8>)>> {
+    check_user_nonces_count(nonces, ledger)?;
+
+    let leaf_hashes = merkle_tree.leaf_hashes();
+    if leaf_hashes.len() < ledger.hashes.len() {
+        return Err(anyhow::anyhow!(
+            "The merkle tree has {} leafs for the {} accounts of the ledger, the ledger doesn't belong to this tree",
+            leaf_hashes.len(),
+            ledger.hashes.len()
+        ));
+    }
+
+    ledger
+        .hashes
+        .par_iter()
+        .zip(ledger.account_balances.par_iter())
+        .zip(nonces.par_iter())
+        .zip(leaf_hashes.into_par_iter())
+        .enumerate()
+        .map(|(index, (((user_hash, balances), nonce), leaf_hash))| {
+            if compute_leaf_hash(balances, user_hash, *nonce)? != leaf_hash {
+                return Err(anyhow::anyhow!(
+                    "The leaf {index} of the merkle tree is not the leaf of user {user_hash}, the ledger or nonces don't belong to this tree"
+                ));
+            }
+            Ok((user_hash.clone(), leaf_hash))
+        })
+        .collect()
+}
+
 pub fn prove_user_inclusion_by_hash(
     user_hash: String,
     merkle_tree: &MerkleTree,
     nonces: &[u64],
     ledger: &Ledger,
 ) -> Result<InclusionProof> {
+    check_user_nonces_count(nonces, ledger)?;
+
     // get the user index from the hash
     let user_index = ledger.hashes.iter().position(|x| *x == user_hash);
     if user_index.is_none() {
@@ -377,26 +826,95 @@ pub fn prove_user_inclusion_by_hash(
     prove_user_inclusion(user_index, user_hash, user_nonce, merkle_tree, ledger)
 }
 
+// self-check right after proving: generates the inclusion proofs of a random sample of users and verifies them against
+// the trusted root (the root of the final proof), failing on the first one that doesn't verify
+// returns the number of checked users (the whole ledger if it has less accounts than the sample size)
+pub fn check_inclusion_sample(
+    trusted_root: &[u8],
+    merkle_tree: &MerkleTree,
+    nonces: &[u64],
+    ledger: &Ledger,
+    sample_size: usize,
+) -> Result<usize> {
+    check_user_nonces_count(nonces, ledger)?;
+
+    let account_count = ledger.hashes.len();
+    let indices = rand::seq::index::sample(&mut rand::rng(), account_count, sample_size.min(account_count));
+
+    for index in indices.iter() {
+        let inclusion_proof = prove_user_inclusion(index, ledger.hashes[index].clone(), nonces[index], merkle_tree, ledger)?;
+        verify_inclusion_against_root(&inclusion_proof, trusted_root).map_err(|e| {
+            anyhow::anyhow!("The inclusion proof of user {} does not verify: {e}", ledger.hashes[index])
+        })?;
+    }
+
+    Ok(indices.len())
+}
+
+// same as prove_user_inclusion_by_hash, but looks the user up in a prebuilt hash -> index map (see Ledger::build_index)
+pub fn prove_user_inclusion_by_hash_indexed(
+    user_hash: String,
+    hash_index: &HashMap<String, usize>,
+    merkle_tree: &MerkleTree,
+    nonces: &[u64],
+    ledger: &Ledger,
+) -> Result<InclusionProof> {
+    check_user_nonces_count(nonces, ledger)?;
+
+    let user_index = *hash_index
+        .get(&user_hash)
+        .ok_or_else(|| anyhow::anyhow!("User hash not found in ledger"))?;
+
+    let user_nonce = nonces[user_index];
+
+    prove_user_inclusion(user_index, user_hash, user_nonce, merkle_tree, ledger)
+}
+
 // Create inclusion proofs for all users using parallel processing
-// Process hashes in batches by their first 3 characters to reduce memory usage
+// the bundles are grouped by the first group_prefix_len characters of the user hashes, a longer prefix produces more
+// (and smaller) bundles, the prefix length is stored in the bundle index so consumers find the bundle of a user hash
+// the bundles are serialized, compressed and written by max_writers dedicated threads fed through a bounded queue:
+// the proving threads wait when max_writers proved bundles are already queued, so about num_threads + 2 * max_writers
+// bundles (and the json and compression buffers of the ones being written) are held in memory at most
+// the bundles and their index are written to out_dir (created if missing)
 pub fn prove_inclusion_all_batched(
     ledger: &Ledger,
     merkle_tree: &MerkleTree,
     nonces: Vec<u64>,
+    group_prefix_len: usize,
+    max_writers: usize,
+    max_write_retries: u32,
+    out_dir: &str,
 ) -> Result<()> {
+    let _span = log_span!("prove_inclusion_all_batched");
     let total_hashes = ledger.hashes.len();
     let num_cpus = rayon::current_num_threads();
 
+    if group_prefix_len == 0 {
+        return Err(anyhow::anyhow!("The group prefix length must be at least 1"));
+    }
+    if max_writers == 0 {
+        return Err(anyhow::anyhow!("The max number of bundle writers must be at least 1"));
+    }
+    check_user_nonces_count(&nonces, ledger)?;
+    let mut bundle_index = BundleIndex {
+        group_prefix_len,
+        bundle_count: 0,
+        proof_count: total_hashes,
+    };
+
     log_info!(
-        "Processing {} hashes in batches grouped by first 3 characters using {} threads...",
+        "Processing {} hashes in batches grouped by first {} characters using {} threads and {} writers...",
         total_hashes,
-        num_cpus
+        group_prefix_len,
+        num_cpus,
+        max_writers
     );
 
-    // Group hashes by their first 3 characters (keeping original approach)
+    // Group hashes by their first group_prefix_len characters
     let mut groups: HashMap<String, Vec<(usize, &String)>> = HashMap::new();
     for (index, userhash) in ledger.hashes.iter().enumerate() {
-        let prefix = userhash.chars().take(3).collect::<String>();
+        let prefix = bundle_index.group_prefix(userhash);
         groups
             .entry(prefix)
             .or_insert_with(Vec::new)
@@ -404,22 +922,89 @@ pub fn prove_inclusion_all_batched(
     }
 
     let total_groups = groups.len();
-    let processed_groups = Arc::new(std::sync::atomic::AtomicUsize::new(0));
-    let processed_hashes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
 
     log_info!(
-        "Created {} groups based on first 3 characters",
-        total_groups
+        "Created {} groups based on first {} characters",
+        total_groups,
+        group_prefix_len
     );
 
-    // Create inclusion_proofs directory if it doesn't exist
-    std::fs::create_dir_all("inclusion_proofs")?;
+    // Create the output directory if it doesn't exist
+    std::fs::create_dir_all(out_dir).with_context(|| format!("Failed to create the {out_dir} directory"))?;
+    let out_path = std::path::Path::new(out_dir);
+
+    // the index of a previous run is removed before the first bundle is written, so a failed run (or one with another
+    // group prefix length) never leaves an index describing bundles it didn't write
+    let index_path = out_path.join(BundleIndex::FILE_NAME).to_string_lossy().into_owned();
+    match std::fs::remove_file(&index_path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(anyhow::anyhow!("Failed to remove the previous bundle index {index_path}: {e}")),
+    }
+
+    // serializes, compresses and writes the bundle of a group
+    let written_groups = std::sync::atomic::AtomicUsize::new(0);
+    let write_bundle = |prefix: &str, inclusion_proofs_map: &HashMap<String, InclusionProof>| -> Result<()> {
+        let bundle_filename = out_path.join(bundle_index.bundle_filename(prefix)).to_string_lossy().into_owned();
+        let bundle_json = serde_json::to_string(inclusion_proofs_map)?;
+
+        // Compress the JSON data using zstd with optimal settings for speed
+        let compressed_data = zstd::encode_all(bundle_json.as_bytes(), 3)?; // Level 3 = good speed/compression balance
+
+        // written atomically, so a failed run only leaves complete bundles behind
+        write_atomic_with_retry(&bundle_filename, &compressed_data, max_write_retries)?;
+        written_groups.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Ok(())
+    };
+
+    // the bar is updated for every proved hash (not every group) so it moves smoothly with a few large groups
+    // when the output is not a terminal it prints plain progress lines instead
+    let progress = Mutex::new(ProveInclusionProgress::new(total_hashes));
+    progress.lock().unwrap().print_progress_bar();
+
+    // proved bundles waiting to be written: (prefix, proofs by user hash)
+    // the queue only bounds the bundles waiting for a writer: besides them, each writer holds the bundle it writes and
+    // each proving thread the group it proves (or the proved bundle it can't queue yet), so up to about
+    // num_threads + 2 * max_writers bundles are in memory at once
+    let (sender, receiver) = std::sync::mpsc::sync_channel::<(String, HashMap<String, InclusionProof>)>(max_writers);
+    let receiver = Mutex::new(receiver);
+    let writers_failed = std::sync::atomic::AtomicBool::new(false);
+
+    let processing_result: Result<()> = std::thread::scope(|scope| {
+        // the writers are plain threads (not rayon workers), so they always drain the queue while the provers wait
+        let writers = (0..max_writers)
+            .map(|_| {
+                scope.spawn(|| -> Result<()> {
+                    let mut result = Ok(());
+
+                    // the queue is closed when all the groups are proved
+                    loop {
+                        let message = receiver.lock().unwrap().recv();
+                        let Ok((prefix, inclusion_proofs_map)) = message else {
+                            return result;
+                        };
+
+                        // after an error the queued bundles are only drained, so the provers never wait on a full queue
+                        if result.is_ok() {
+                            result = write_bundle(&prefix, &inclusion_proofs_map);
+                            if result.is_err() {
+                                writers_failed.store(true, std::sync::atomic::Ordering::Relaxed);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Process groups in parallel, each group's hashes are also proved in parallel
+        // the sender is moved in, so the queue is closed when the proving is done (or failed)
+        let proving_result = groups.par_iter().try_for_each_with(sender, |sender, (prefix, group)| -> Result<()> {
+            // stop proving once a bundle failed to be written
+            if writers_failed.load(std::sync::atomic::Ordering::Relaxed) {
+                return Err(anyhow::anyhow!("Stopped proving after an inclusion proof bundle failed to be written"));
+            }
 
-    // Process groups in parallel with optimized performance
-    let processing_result: Result<()> = groups.par_iter().try_for_each(
-        |(prefix, group)| -> Result<()> {
-            // Process this group's hashes in parallel and collect as HashMap<hash, proof>
-            let group_result: Result<HashMap<String, InclusionProof>> = group
+            let inclusion_proofs_map = group
                 .par_iter()
                 .map(|(index, userhash)| -> Result<(String, InclusionProof)> {
                     let inclusion_proof = prove_user_inclusion(
@@ -429,51 +1014,49 @@ pub fn prove_inclusion_all_batched(
                         merkle_tree,
                         ledger,
                     )?;
+                    progress.lock().unwrap().update_progress(1);
 
                     Ok(((*userhash).clone(), inclusion_proof))
                 })
-                .collect();
-
-            // Handle the group result
-            match group_result {
-                Ok(inclusion_proofs_map) => {
-                    // Write the group to file immediately as a compressed object
-                    let bundle_filename =
-                        format!("inclusion_proofs/inclusion_proofs_{prefix}.json.zst");
-                    let bundle_json = serde_json::to_string(&inclusion_proofs_map)?;
-
-                    // Compress the JSON data using zstd with optimal settings for speed
-                    let compressed_data = zstd::encode_all(bundle_json.as_bytes(), 3)?; // Level 3 = good speed/compression balance
-                    std::fs::write(&bundle_filename, compressed_data)?;
-
-                    // Update counters atomically (much faster than mutex)
-                    let completed_groups =
-                        processed_groups.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
-                    let completed_hashes = processed_hashes
-                        .fetch_add(group.len(), std::sync::atomic::Ordering::Relaxed)
-                        + group.len();
-
-                    // Only log progress every 10 groups or at completion to reduce I/O overhead
-                    if completed_groups % 10 == 0 || completed_groups == total_groups {
-                        log_success!(
-                            "Completed group '{}' ({}/{} groups, {}/{} hashes) - compressed to {}",
-                            prefix,
-                            completed_groups,
-                            total_groups,
-                            completed_hashes,
-                            total_hashes,
-                            bundle_filename
-                        );
-                    }
+                .collect::<Result<HashMap<String, InclusionProof>>>()?;
 
-                    Ok(())
-                }
-                Err(e) => Err(e),
-            }
-        },
-    );
+            // blocks while the queue is full (backpressure)
+            sender
+                .send((prefix.clone(), inclusion_proofs_map))
+                .map_err(|_| anyhow::anyhow!("The inclusion proof bundle writers stopped"))
+        });
+
+        // a writer error is more useful than the "stopped proving" error it causes
+        for writer in writers {
+            writer.join().expect("Inclusion proof bundle writer panicked")?;
+        }
+        proving_result
+    });
+
+    progress.lock().unwrap().clear_bar();
 
-    processing_result?;
+    // the raw io error of a full disk doesn't say what was already written
+    let out_of_space = |error: anyhow::Error| {
+        if !is_storage_full(&error) {
+            return error;
+        }
+        error.context(format!(
+            "Out of disk space after {}/{} groups, the completed groups are in {out_dir} (the bundle index is only written when all the groups are). Free some space and run it again",
+            written_groups.load(std::sync::atomic::Ordering::Relaxed),
+            total_groups
+        ))
+    };
+    processing_result.map_err(out_of_space)?;
+
+    // the index is written last (and the one of a previous run was removed first), so its presence means every bundle
+    // of this run was written
+    bundle_index.bundle_count = total_groups;
+    write_atomic_with_retry(
+        &index_path,
+        &serde_json::to_vec(&bundle_index)?,
+        max_write_retries,
+    )
+    .map_err(out_of_space)?;
 
     log_success!(
         "Successfully processed all {} groups with {} total inclusion proofs!",
@@ -483,12 +1066,91 @@ pub fn prove_inclusion_all_batched(
     Ok(())
 }
 
+// reads the index of the inclusion proof bundles in a directory
+// bundles written before the index existed were always grouped by DEFAULT_GROUP_PREFIX_LEN characters
+pub fn read_bundle_index(bundles_dir: &str) -> Result<BundleIndex> {
+    let index_path = std::path::Path::new(bundles_dir).join(BundleIndex::FILE_NAME);
+    if !index_path.exists() {
+        return Ok(BundleIndex {
+            group_prefix_len: DEFAULT_GROUP_PREFIX_LEN,
+            bundle_count: 0,
+            proof_count: 0,
+        });
+    }
+
+    let index = std::fs::read(&index_path)?;
+    Ok(serde_json::from_slice(&index)?)
+}
+
+// looks up the inclusion proof of a user hash in the bundles written by prove_inclusion_all_batched
+pub fn read_bundled_inclusion_proof(bundles_dir: &str, user_hash: &str) -> Result<InclusionProof> {
+    let bundle_index = read_bundle_index(bundles_dir)?;
+    let bundle_path = std::path::Path::new(bundles_dir).join(bundle_index.bundle_filename(user_hash));
+
+    let compressed = std::fs::read(&bundle_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read the bundle {}: {e}", bundle_path.display()))?;
+    let mut bundle: HashMap<String, InclusionProof> = serde_json::from_slice(&zstd::decode_all(compressed.as_slice())?)?;
+
+    bundle
+        .remove(user_hash)
+        .ok_or_else(|| anyhow::anyhow!("User hash not found in the bundle {}", bundle_path.display()))
+}
+
 // Create inclusion proofs for all users using parallel processing
+// max_inflight bounds the number of proofs held in memory at the same time (None means one per rayon thread)
+// it is clamped to the size of the rayon pool, since more proving threads than cores would only add threads and memory
+// the proofs are written to out_dir (created if missing)
 pub fn prove_inclusion_all(
     ledger: &Ledger,
     merkle_tree: &MerkleTree,
     nonces: Vec<u64>,
+    format: SerializationFormat,
+    max_write_retries: u32,
+    max_inflight: Option<usize>,
+    out_dir: &str,
 ) -> Result<()> {
+    let _span = log_span!("prove_inclusion_all");
+
+    // bounded mode: prove in a dedicated pool of at most max_inflight threads (and no more than the rayon pool)
+    // each thread holds a single proof (and its serialized bytes) at a time, so the peak memory doesn't depend on the core count
+    let bounded_pool = match max_inflight {
+        Some(0) => return Err(anyhow::anyhow!("The max number of in-flight proofs must be at least 1")),
+        Some(max_inflight) if max_inflight >= rayon::current_num_threads() => None,
+        Some(max_inflight) => Some(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(max_inflight)
+                .build()
+                .map_err(|e| anyhow::anyhow!("Failed to create the inclusion proving thread pool: {e}"))?,
+        ),
+        None => None,
+    };
+
+    std::fs::create_dir_all(out_dir).with_context(|| format!("Failed to create the {out_dir} directory"))?;
+
+    // the default sink writes each proof to its own file
+    let write_proof = |inclusion_proof: InclusionProof| -> Result<()> {
+        let inclusion_filename = std::path::Path::new(out_dir)
+            .join(format!("inclusion_proof_{}.{}", inclusion_proof.user_hash, format.extension()))
+            .to_string_lossy()
+            .into_owned();
+        // transient write errors are retried, permanent ones are propagated
+        write_to_file_with_retry(&inclusion_filename, &inclusion_proof, format, max_write_retries)
+    };
+
+    // the parallel iterators of prove_inclusion_all_with run in the pool they are called from
+    match &bounded_pool {
+        Some(pool) => pool.install(|| prove_inclusion_all_with(ledger, merkle_tree, &nonces, write_proof)),
+        None => prove_inclusion_all_with(ledger, merkle_tree, &nonces, write_proof),
+    }
+}
+
+// generates the inclusion proofs of all users in parallel and hands each one to the sink (e.g. a queue or a database)
+// instead of writing it to disk, the sink is called from the proving threads and the first error stops the proving
+pub fn prove_inclusion_all_with<S>(ledger: &Ledger, merkle_tree: &MerkleTree, nonces: &[u64], sink: S) -> Result<()>
+where
+    S: Fn(InclusionProof) -> Result<()> + Sync,
+{
+    check_user_nonces_count(nonces, ledger)?;
     let total_hashes = ledger.hashes.len();
 
     // Wrap the mutable progress state in Arc<Mutex> to allow safe shared access
@@ -504,18 +1166,14 @@ pub fn prove_inclusion_all(
     // Use rayon's parallel iterator `par_iter()`
     // `try_for_each` is used because the closure returns a Result.
     // If any iteration returns an Err, try_for_each stops and returns that Err.
-    let processing_result: Result<()> = ledger
+    let processing_result = ledger
         .hashes
         .par_iter() // Convert the iterator into a parallel iterator
         .enumerate()
         .try_for_each(|(index, userhash)| {
             // The closure executed for each item in parallel
-            let inclusion_proof =
-                prove_user_inclusion(index, userhash.clone(), nonces[index], merkle_tree, ledger)?;
-
-            let inclusion_filename = format!("inclusion_proofs/inclusion_proof_{userhash}.json");
-            let inclusion_proof_json = serde_json::to_string(&inclusion_proof)?; // Propagate serialization errors
-            std::fs::write(inclusion_filename, inclusion_proof_json)?; // Propagate file writing errors
+            let inclusion_proof = prove_user_inclusion(index, userhash.clone(), nonces[index], merkle_tree, ledger)?;
+            sink(inclusion_proof)?;
 
             // Update the progress bar: Safely access the shared progress object
             {
@@ -1,20 +1,272 @@
 use crate::*;
 use anyhow::{Context, Result};
 use interprocess::local_socket::{prelude::*, GenericFilePath, ListenerOptions, Name};
+use std::collections::{BTreeMap, HashMap};
 use std::io::{BufRead, BufReader, Write};
-use std::path::Path;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub const SOCKET_PATH: &str = "/tmp/por.sock";
 
-fn handle_client(
-    stream: &interprocess::local_socket::Stream,
-    merkle_tree: &MerkleTree,
-    nonces: &[u64],
-    ledger: &Ledger,
-) -> Result<()> {
+// default max number of inclusion requests processed at the same time
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
+// default number of serialized inclusion proofs kept in memory by the server (0 disables the cache)
+pub const DEFAULT_CACHE_SIZE: usize = 1024;
+
+// response sent instead of a file path when the server is at max capacity
+pub const SERVER_BUSY_RESPONSE: &str = "BUSY";
+
+// response sent instead of a file path when the requested hash is not a user of the ledger
+pub const SERVER_NOT_FOUND_RESPONSE: &str = "NOT_FOUND";
+
+// prefix of the response sent when the inclusion proof could not be generated: ERROR <message>
+pub const SERVER_ERROR_RESPONSE: &str = "ERROR";
+
+// reserved request to check if the server is alive (user hashes are hex, so it never collides with one)
+// the response is a single line:
+// STATUS timestamp=<proof timestamp ms> accounts=<account count> uptime_secs=<uptime> cache_hits=<hits> cache_misses=<misses>
+pub const STATUS_REQUEST: &str = "STATUS";
+
+// information about the loaded proof returned by the STATUS request
+pub struct ServerStatus {
+    timestamp: u64,
+    account_count: usize,
+    started: Instant,
+}
+
+impl ServerStatus {
+    pub fn new(ledger: &Ledger) -> Self {
+        ServerStatus {
+            timestamp: ledger.timestamp,
+            account_count: ledger.hashes.len(),
+            started: Instant::now(),
+        }
+    }
+
+    fn response(&self, cache: &ProofCache) -> String {
+        format!(
+            "{STATUS_REQUEST} timestamp={} accounts={} uptime_secs={} cache_hits={} cache_misses={}",
+            self.timestamp,
+            self.account_count,
+            self.started.elapsed().as_secs(),
+            cache.hits(),
+            cache.misses()
+        )
+    }
+}
+
+// bounded LRU cache of serialized inclusion proofs keyed by user hash (shared by all clients)
+// the merkle tree and the ledger never change while the server runs, so a cached proof never goes stale
+pub struct ProofCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    // user hash -> (proof json, last use)
+    proofs: HashMap<String, (Arc<String>, u64)>,
+    // last use -> user hash, the first entry is the least recently used
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl ProofCache {
+    pub fn new(capacity: usize) -> Self {
+        ProofCache {
+            capacity,
+            entries: Mutex::new(CacheEntries::default()),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    // returns the cached proof of the user (marking it as the most recently used) and counts the hit or miss
+    pub fn get(&self, hash: &str) -> Option<Arc<String>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        let CacheEntries { proofs, recency, .. } = &mut *entries;
+        let Some((proof, last_use)) = proofs.get_mut(hash) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        recency.remove(last_use);
+        recency.insert(clock, hash.to_string());
+        *last_use = clock;
+
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(Arc::clone(proof))
+    }
+
+    // evicts the least recently used proof if the cache is full
+    pub fn insert(&self, hash: String, proof: Arc<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        let CacheEntries { proofs, recency, .. } = &mut *entries;
+        if let Some((_, last_use)) = proofs.insert(hash.clone(), (proof, clock)) {
+            recency.remove(&last_use);
+        }
+        recency.insert(clock, hash);
+
+        while proofs.len() > self.capacity {
+            let Some((_, evicted)) = recency.pop_first() else { break };
+            proofs.remove(&evicted);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).proofs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+// limits the number of inclusion requests being processed at the same time (shared by all clients)
+// requests above the limit are rejected with SERVER_BUSY_RESPONSE instead of being queued unboundedly
+pub struct ConcurrencyLimiter {
+    in_flight: AtomicUsize,
+    max_in_flight: usize,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(max_in_flight: usize) -> Self {
+        ConcurrencyLimiter {
+            in_flight: AtomicUsize::new(0),
+            max_in_flight,
+        }
+    }
+
+    // returns None if the server is busy, the slot is released when the guard is dropped
+    pub fn try_acquire(&self) -> Option<InFlightGuard<'_>> {
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_flight| {
+                (in_flight < self.max_in_flight).then_some(in_flight + 1)
+            })
+            .ok()
+            .map(|_| InFlightGuard { limiter: self })
+    }
+}
+
+pub struct InFlightGuard<'a> {
+    limiter: &'a ConcurrencyLimiter,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// everything the clients of a server share (one per server, behind an Arc)
+struct ServerState {
+    out_dir: PathBuf,
+    // user hash -> index, built once so each lookup is O(1)
+    hash_index: HashMap<String, usize>,
+    merkle_tree: MerkleTree,
+    nonces: Vec<u64>,
+    ledger: Ledger,
+    limiter: ConcurrencyLimiter,
+    status: ServerStatus,
+    cache: ProofCache,
+}
+
+impl ServerState {
+    // checks the server inputs and creates the inclusion proofs directory (out_dir) if missing
+    fn new(
+        merkle_tree: MerkleTree,
+        nonces: Vec<u64>,
+        ledger: Ledger,
+        max_in_flight: usize,
+        cache_size: usize,
+        out_dir: &str,
+    ) -> Result<Self> {
+        if max_in_flight == 0 {
+            return Err(anyhow::anyhow!("The max number of in-flight requests must be at least 1"));
+        }
+        check_user_nonces_count(&nonces, &ledger)?;
+
+        Ok(ServerState {
+            out_dir: absolute_out_dir(out_dir)?,
+            hash_index: ledger.build_index(),
+            merkle_tree,
+            nonces,
+            status: ServerStatus::new(&ledger),
+            ledger,
+            limiter: ConcurrencyLimiter::new(max_in_flight),
+            cache: ProofCache::new(cache_size),
+        })
+    }
+}
+
+// handle a single request line (user hash) and return the response line (inclusion proof file path)
+// unknown hashes get SERVER_NOT_FOUND_RESPONSE, the other errors are returned (see error_response)
+fn handle_request(hash: &str, state: &ServerState) -> Result<String> {
+    if !state.hash_index.contains_key(hash) {
+        return Ok(SERVER_NOT_FOUND_RESPONSE.to_string());
+    }
+
+    // repeated requests skip the tree walk and the serialization
+    let inclusion_proof_json = match state.cache.get(hash) {
+        Some(inclusion_proof_json) => inclusion_proof_json,
+        None => {
+            // prove inclusion with the received hash
+            let inclusion_proof = prove_user_inclusion_by_hash_indexed(
+                hash.to_string(),
+                &state.hash_index,
+                &state.merkle_tree,
+                &state.nonces,
+                &state.ledger,
+            )?;
+            let inclusion_proof_json = Arc::new(serde_json::to_string(&inclusion_proof)?); // Propagate serialization errors
+            state.cache.insert(hash.to_string(), Arc::clone(&inclusion_proof_json));
+            inclusion_proof_json
+        }
+    };
+
+    // write the proof into the file and send the file path back to the client
+    // (also on a cache hit, the client may have moved the previous file)
+    let proof_path = state
+        .out_dir
+        .join(format!("inclusion_proof_{hash}.json"))
+        .display()
+        .to_string();
+    log_info!("Writing inclusion proof to: {proof_path}");
+    std::fs::write(proof_path.clone(), inclusion_proof_json.as_bytes())?; // Propagate file writing errors
+
+    Ok(proof_path)
+}
+
+// the error is logged on the server and sent back to the client, so it doesn't just see the connection closing
+fn error_response(hash: &str, error: &anyhow::Error) -> String {
+    log_error!("Failed to prove the inclusion of {}: {}", hash, error);
+    format!("{SERVER_ERROR_RESPONSE} {}", error.to_string().replace('\n', " "))
+}
+
+fn handle_client(stream: &interprocess::local_socket::Stream, state: &ServerState) -> Result<()> {
     let mut reader = BufReader::new(stream);
     let mut writer = stream;
 
@@ -26,25 +278,26 @@ fn handle_client(
                 break;
             }
             Ok(_) => {
-                // prove inclusion with the received hash
                 let hash = buffer.trim(); // Remove newline character
 
-                let inclusion_proof =
-                    prove_user_inclusion_by_hash(hash.to_string(), merkle_tree, nonces, ledger)?;
+                // status requests are answered right away (they don't count as in flight)
+                if hash == STATUS_REQUEST {
+                    writer
+                        .write_all(format!("{}\n", state.status.response(&state.cache)).as_bytes())
+                        .context("Failed to write to client")?;
+                    continue;
+                }
 
-                // write the proof into the file and send the file path back to the client
-                let proof_path = format!(
-                    "{}/inclusion_proofs/inclusion_proof_{}.json",
-                    std::env::current_dir()?.display(),
-                    hash
-                );
-                println!("Writing inclusion proof to: {proof_path}");
-                let inclusion_proof_json = serde_json::to_string(&inclusion_proof)?; // Propagate serialization errors
-                std::fs::write(proof_path.clone(), inclusion_proof_json)?; // Propagate file writing errors
+                // reject the request if there are too many requests in flight
+                // the in-flight slot is released before the response is written, so a slow reader doesn't hold it
+                let response = match state.limiter.try_acquire() {
+                    Some(_guard) => handle_request(hash, state).unwrap_or_else(|e| error_response(hash, &e)),
+                    None => SERVER_BUSY_RESPONSE.to_string(),
+                };
 
-                // Send the file path back to the client with a newline
+                // Send the file path (or the error) back to the client with a newline
                 writer
-                    .write_all(format!("{proof_path}\n").as_bytes())
+                    .write_all(format!("{response}\n").as_bytes())
                     .context("Failed to write to client")?;
             }
             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
@@ -62,11 +315,18 @@ fn handle_client(
     Ok(())
 }
 
+// the inclusion proofs are written to out_dir (created if missing), the responses are their absolute paths
+// up to cache_size serialized proofs are kept in memory for repeated requests (see ProofCache)
 pub fn create_local_server(
     merkle_tree: MerkleTree,
     nonces: Vec<u64>,
     ledger: Ledger,
+    max_in_flight: usize,
+    cache_size: usize,
+    out_dir: &str,
 ) -> Result<()> {
+    let state = Arc::new(ServerState::new(merkle_tree, nonces, ledger, max_in_flight, cache_size, out_dir)?);
+
     let socket_name: Name<'_> = SOCKET_PATH.to_fs_name::<GenericFilePath>()?;
 
     // This is important because bind will fail if the file already exists.
@@ -76,10 +336,6 @@ pub fn create_local_server(
         log_info!("Removed existing socket file: {}", SOCKET_PATH);
     }
 
-    let merkle_tree = Arc::new(merkle_tree);
-    let nonces = Arc::new(nonces);
-    let ledger = Arc::new(ledger);
-
     let listener_options = ListenerOptions::new().name(socket_name);
 
     let listener = match listener_options.create_sync() {
@@ -112,12 +368,10 @@ pub fn create_local_server(
             Ok(stream) => {
                 // Spawn a new thread to handle each client.
                 // For a production daemon, consider using a thread pool or async runtime.
-                let merkle_tree = Arc::clone(&merkle_tree);
-                let nonces = Arc::clone(&nonces);
-                let ledger = Arc::clone(&ledger);
+                let state = Arc::clone(&state);
 
                 thread::spawn(move || {
-                    if let Err(e) = handle_client(&stream, &merkle_tree, &nonces, &ledger) {
+                    if let Err(e) = handle_client(&stream, &state) {
                         log_error!("Client handler error: {}", e);
                     }
                 });
@@ -137,6 +391,113 @@ pub fn create_local_server(
     Ok(())
 }
 
+// async variant of create_local_server: same line protocol, but each client is served by a tokio task
+// instead of an OS thread, so it scales to many more concurrent clients with lower memory
+#[cfg(feature = "async-server")]
+pub fn create_local_server_async(
+    merkle_tree: MerkleTree,
+    nonces: Vec<u64>,
+    ledger: Ledger,
+    max_in_flight: usize,
+    cache_size: usize,
+    out_dir: &str,
+) -> Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+    use tokio::net::UnixListener;
+
+    let state = Arc::new(ServerState::new(merkle_tree, nonces, ledger, max_in_flight, cache_size, out_dir)?);
+
+    // This is important because bind will fail if the file already exists.
+    if Path::new(SOCKET_PATH).exists() {
+        std::fs::remove_file(SOCKET_PATH)
+            .with_context(|| format!("Failed to remove existing socket file: {SOCKET_PATH}"))?;
+        log_info!("Removed existing socket file: {}", SOCKET_PATH);
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create tokio runtime")?;
+
+    runtime.block_on(async move {
+        let listener = UnixListener::bind(SOCKET_PATH)
+            .with_context(|| format!("Failed to create listener for socket: {SOCKET_PATH}"))?;
+        log_success!("Async server listening on socket: {}", SOCKET_PATH);
+
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log_error!("Failed to accept incoming connection: {}", e);
+                    continue;
+                }
+            };
+
+            let state = Arc::clone(&state);
+
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = tokio::io::BufReader::new(reader).lines();
+
+                loop {
+                    let hash = match lines.next_line().await {
+                        Ok(Some(line)) => line.trim().to_string(),
+                        Ok(None) => break,
+                        Err(e) => {
+                            log_error!("Error reading from client: {}", e);
+                            break;
+                        }
+                    };
+
+                    // status requests are answered right away (they don't count as in flight)
+                    if hash == STATUS_REQUEST {
+                        if let Err(e) = writer.write_all(format!("{}\n", state.status.response(&state.cache)).as_bytes()).await {
+                            log_error!("Failed to write to client: {}", e);
+                            break;
+                        }
+                        continue;
+                    }
+
+                    // reject the request if there are too many requests in flight
+                    // the in-flight slot is released before the response is written (and awaited), so a slow reader
+                    // doesn't hold it
+                    let response = match state.limiter.try_acquire() {
+                        Some(_guard) => {
+                            // proving and writing the file is blocking work, keep it off the async workers
+                            let state = Arc::clone(&state);
+                            let request_hash = hash.clone();
+                            let result = tokio::task::spawn_blocking(move || {
+                                handle_request(&request_hash, &state).unwrap_or_else(|e| error_response(&request_hash, &e))
+                            })
+                            .await;
+
+                            // a panicking request is answered with an error like any other failure, the connection
+                            // keeps being served
+                            result.unwrap_or_else(|e| {
+                                error_response(&hash, &anyhow::anyhow!("The inclusion proof request panicked: {e}"))
+                            })
+                        }
+                        None => SERVER_BUSY_RESPONSE.to_string(),
+                    };
+
+                    // Send the file path (or the error) back to the client with a newline
+                    if let Err(e) = writer.write_all(format!("{response}\n").as_bytes()).await {
+                        log_error!("Failed to write to client: {}", e);
+                        break;
+                    }
+                }
+            });
+        }
+    })
+}
+
+// creates the inclusion proofs directory if missing and returns its absolute path
+// (the client expects an absolute path, and the daemon may not share the client working directory)
+fn absolute_out_dir(out_dir: &str) -> Result<PathBuf> {
+    std::fs::create_dir_all(out_dir).with_context(|| format!("Failed to create the {out_dir} directory"))?;
+    std::fs::canonicalize(out_dir).with_context(|| format!("Failed to resolve the {out_dir} directory"))
+}
+
 pub fn send_hash_to_server(hash: &str) -> Result<()> {
     // 1. Create a connection to the server.
     let socket_name: Name<'_> = SOCKET_PATH.to_fs_name::<GenericFilePath>()?;
@@ -162,6 +523,16 @@ The prover server probably stopped running. If you want to prove without the ser
         .read_line(&mut buffer)
         .with_context(|| format_error("Failed to read response from server"))?;
 
+    if buffer.trim() == SERVER_BUSY_RESPONSE {
+        return Err(anyhow::anyhow!("The prover server is busy, try again later"));
+    }
+    if buffer.trim() == SERVER_NOT_FOUND_RESPONSE {
+        return Err(anyhow::anyhow!("User hash {hash} not found in the ledger loaded by the prover server"));
+    }
+    if let Some(error) = buffer.trim().strip_prefix(SERVER_ERROR_RESPONSE) {
+        return Err(anyhow::anyhow!("The prover server failed to generate the inclusion proof: {}", error.trim()));
+    }
+
     if !buffer.starts_with("/") {
         // should be a file path
         return Err(anyhow::anyhow!("Invalid response from server: {}", buffer));
@@ -172,3 +543,31 @@ The prover server probably stopped running. If you want to prove without the ser
 
     Ok(())
 }
+
+// query the status of the running server, returns None if it is not running
+pub fn query_server_status() -> Result<Option<String>> {
+    if !Path::new(SOCKET_PATH).exists() {
+        return Ok(None);
+    }
+
+    // a stale socket file (the server stopped running) can't be connected to
+    let socket_name: Name<'_> = SOCKET_PATH.to_fs_name::<GenericFilePath>()?;
+    let Ok(mut stream) = interprocess::local_socket::Stream::connect(socket_name) else {
+        return Ok(None);
+    };
+
+    stream
+        .write_all(format!("{STATUS_REQUEST}\n").as_bytes())
+        .context("Failed to send status request to server")?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut buffer = String::new();
+    reader
+        .read_line(&mut buffer)
+        .with_context(|| format_error("Failed to read response from server"))?;
+
+    match buffer.trim().strip_prefix(STATUS_REQUEST) {
+        Some(status) => Ok(Some(status.trim().to_string())),
+        None => Err(anyhow::anyhow!("Invalid response from server: {}", buffer)),
+    }
+}
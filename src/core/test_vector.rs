@@ -0,0 +1,143 @@
+// deterministic test vector guarding the leaf hashing, the merkle tree construction and the serializers
+// only built with the fixtures feature (gen-fixture command and tests/test_vector.rs), nothing here is used to prove
+
+use anyhow::Result;
+use plonky2::field::types::Field64;
+use serde::{Deserialize, Serialize};
+
+use crate::config::F;
+use crate::core::prover::{prepare_leaf_accounts, prove_user_inclusion, rebuild_merkle_tree};
+use crate::custom_serializer::format::{to_bytes, SerializationFormat};
+use crate::log_span;
+use crate::types::{Ledger, LedgerDecimals, ProverConfig};
+use crate::utils::util::{bytes_to_hex, compute_leaf_hash};
+
+// seed of the nonces of the committed test vector (see generate_test_vector) and where it is committed
+pub const TEST_VECTOR_NONCE_SEED: u64 = 0x706f725f76656374;
+pub const TEST_VECTOR_FILE: &str = "tests/fixtures/test_vector.json";
+
+// deterministic test vector (see generate_test_vector): the inputs (a small ledger, the nonce seed and the circuit sizes)
+// and the outputs they must hash and serialize to, committed in TEST_VECTOR_FILE so any change of the outputs fails the tests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestVector {
+    pub nonce_seed: u64,
+    pub batch_size: usize,
+    pub recursive_size: usize,
+    pub asset_names: Vec<String>,
+    pub asset_prices: Vec<u64>,
+    pub asset_decimals: Vec<LedgerDecimals>,
+    pub user_hashes: Vec<String>,
+    pub account_balances: Vec<Vec<i64>>,
+    pub timestamp: u64,
+    // nonce of every leaf (users, then padding accounts), derived from the seed (see seeded_nonces)
+    pub nonces: Vec<u64>,
+    // the hashes are hex
+    pub root_hash: String,
+    pub sample_user_hash: String,
+    pub sample_leaf_hash: String,
+    // the inclusion proof of the sample user as written in each serialization format (the binary formats in hex)
+    pub inclusion_proof: serde_json::Value,
+    pub inclusion_proof_bincode: String,
+    pub inclusion_proof_msgpack: String,
+}
+
+impl TestVector {
+    // the ledger of the inputs
+    pub fn ledger(&self) -> Ledger {
+        Ledger {
+            asset_names: self.asset_names.clone(),
+            hashes: self.user_hashes.clone(),
+            account_balances: self.account_balances.clone(),
+            asset_prices: self.asset_prices.clone(),
+            asset_decimals: self.asset_decimals.clone(),
+            reserve_buffer: Vec::new(),
+            dust: None,
+            timestamp: self.timestamp,
+        }
+    }
+
+    pub fn config(&self) -> ProverConfig {
+        ProverConfig {
+            batch_size: self.batch_size,
+            recursive_size: self.recursive_size,
+        }
+    }
+}
+
+// deterministic leaf nonces derived from a seed (splitmix64, the values outside the field are skipped so they are canonical)
+// they are predictable, so they are only meant for reproducible test vectors, NEVER for a published proof
+pub fn seeded_nonces(seed: u64, count: usize) -> Vec<u64> {
+    let mut state = seed;
+    let mut nonces = Vec::with_capacity(count);
+    while nonces.len() < count {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        if z < F::ORDER {
+            nonces.push(z);
+        }
+    }
+    nonces
+}
+
+// the small fixed ledger of the committed test vector: 6 users with 3 assets, so the second batch of
+// ProverConfig::testing (4 accounts) is padded and the padding leaves are covered too
+pub fn test_vector_ledger() -> Ledger {
+    let user_count = 6;
+
+    Ledger {
+        asset_names: vec!["BTC".to_string(), "ETH".to_string(), "USDT".to_string()],
+        hashes: (1..=user_count).map(|i| format!("{:064x}", i * 0x1111)).collect(),
+        account_balances: (1..=user_count as i64).map(|i| vec![i * 1500, i * 42000, i * 1_000_000 - 250_000]).collect(),
+        asset_prices: vec![6000000, 200000, 100],
+        asset_decimals: vec![
+            LedgerDecimals { usdt_decimals: 2, balance_decimals: 4 },
+            LedgerDecimals { usdt_decimals: 2, balance_decimals: 4 },
+            LedgerDecimals { usdt_decimals: 2, balance_decimals: 4 },
+        ],
+        reserve_buffer: Vec::new(),
+        dust: None,
+        timestamp: 1746488437000,
+    }
+}
+
+// hashes a ledger into a merkle tree with nonces derived from nonce_seed, without proving (see rebuild_merkle_tree), and
+// records the root hash and the leaf hash and inclusion proof (in every serialization format) of the first user
+// any change of hash_account, hash_n_subhashes, the tree construction or the serializers changes the outputs
+pub fn generate_test_vector(ledger: Ledger, nonce_seed: u64, config: &ProverConfig) -> Result<TestVector> {
+    let _span = log_span!("generate_test_vector");
+
+    let Some(sample_user_hash) = ledger.hashes.first().cloned() else {
+        return Err(anyhow::anyhow!("The test vector ledger has no users"));
+    };
+
+    // one nonce per leaf, including the padding accounts
+    let mut leaf_accounts = ledger.clone();
+    prepare_leaf_accounts(&mut leaf_accounts, config.batch_size)?;
+    let nonces = seeded_nonces(nonce_seed, leaf_accounts.hashes.len());
+
+    let merkle_tree = rebuild_merkle_tree(ledger.clone(), &nonces, config)?;
+    let inclusion_proof = prove_user_inclusion(0, sample_user_hash.clone(), nonces[0], &merkle_tree, &ledger)?;
+    let sample_leaf_hash = compute_leaf_hash(&ledger.account_balances[0], &sample_user_hash, nonces[0])?;
+
+    Ok(TestVector {
+        nonce_seed,
+        batch_size: config.batch_size,
+        recursive_size: config.recursive_size,
+        asset_names: ledger.asset_names,
+        asset_prices: ledger.asset_prices,
+        asset_decimals: ledger.asset_decimals,
+        user_hashes: ledger.hashes,
+        account_balances: ledger.account_balances,
+        timestamp: ledger.timestamp,
+        nonces,
+        root_hash: bytes_to_hex(&merkle_tree.root.hash().clone().unwrap()),
+        sample_user_hash,
+        sample_leaf_hash: bytes_to_hex(&sample_leaf_hash),
+        inclusion_proof: serde_json::to_value(&inclusion_proof)?,
+        inclusion_proof_bincode: bytes_to_hex(&to_bytes(&inclusion_proof, SerializationFormat::Bincode)?),
+        inclusion_proof_msgpack: bytes_to_hex(&to_bytes(&inclusion_proof, SerializationFormat::Msgpack)?),
+    })
+}
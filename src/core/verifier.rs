@@ -2,22 +2,33 @@ use crate::circuits::batch_circuit::BatchCircuit;
 use crate::config::*;
 use crate::log_warning;
 use crate::utils::logger::*;
-use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree::{MerkleTree, StreamedMerkleTree, VerifyingTreeSeed};
 use crate::types::*;
 use crate::circuits::recursive_circuit::RecursiveCircuit;
-use crate::utils::util::calculate_with_decimals;
-use crate::utils::util::{hash_account, pis_to_hash_bytes, format_timestamp};
-use crate::{log_info, log_success};
-use plonky2::field::types::PrimeField64;
-use plonky2::plonk::config::GenericHashOut;
+use crate::utils::util::{calculate_with_decimals, format_amount, unscale_price};
+use crate::utils::util::{
+    bytes_to_hex, check_balance_range, check_unique_asset_names, compute_leaf_hash, format_timestamp,
+    verifier_data_digest,
+};
+use crate::{log_error, log_info, log_span, log_success};
+use crate::custom_serializer::format::{from_reader_seed, read_from_file};
+use anyhow::{Context, Result};
+use bigdecimal::BigDecimal;
+use rayon::prelude::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use plonky2::{
-    plonk::circuit_data::{CircuitData, VerifierCircuitData},
+    plonk::circuit_data::{CircuitData, CommonCircuitData, VerifierCircuitData},
+    plonk::proof::ProofWithPublicInputs,
     util::serialization::DefaultGateSerializer,
 };
 
-fn rebuild_root_circuit(asset_count: usize, depth: usize) -> RecursiveCircuit {
+// NOTE: the circuits can't be built in parallel, each recursive circuit verifies proofs of the previous level,
+// so it is built from the previous circuit's common data (batch -> depth n-2 -> ... -> root)
+fn rebuild_root_circuit(asset_count: usize, depth: usize, config: &ProverConfig) -> RecursiveCircuit {
     // create the batch circuit
-    let batch_circuit = BatchCircuit::new(asset_count);
+    let batch_circuit = BatchCircuit::new_with_batch_size(asset_count, config.batch_size);
 
     let mut inner_circuit: CircuitData<F, C, D> = batch_circuit.circuit_data;
     let mut root_circuit: Option<RecursiveCircuit> = None;
@@ -25,7 +36,7 @@ fn rebuild_root_circuit(asset_count: usize, depth: usize) -> RecursiveCircuit {
     // depth - 1 because we already calculated the batch circuit (which is a depth)
     for i in 0..depth - 1 {
         // create the recursive circuit
-        let recursive_circuit = RecursiveCircuit::new(&inner_circuit, asset_count);
+        let recursive_circuit = RecursiveCircuit::new(&inner_circuit, asset_count, config.recursive_size);
 
         // set the root circuit if last depth
         if i == depth - 2 {
@@ -40,11 +51,223 @@ fn rebuild_root_circuit(asset_count: usize, depth: usize) -> RecursiveCircuit {
     root_circuit.unwrap()
 }
 
+// the tree depth of an untrusted proof file sets the number of circuits built by rebuild_root_circuit
+// it must have the leaves, batch and root levels, and is capped (DoS hardening, see DEFAULT_MAX_TREE_DEPTH)
+pub fn check_tree_depth(tree_depth: usize, max_tree_depth: usize) -> Result<()> {
+    if tree_depth < 3 {
+        return Err(anyhow::anyhow!("Invalid tree depth {tree_depth}: it must be at least 3 (leaves, batch and root)"));
+    }
+    if tree_depth > max_tree_depth {
+        return Err(anyhow::anyhow!(
+            "Tree depth {tree_depth} is above the maximum accepted depth {max_tree_depth}, the proof file is malformed"
+        ));
+    }
+
+    Ok(())
+}
+
+// the circuit sizes of an untrusted proof file set the size of every circuit built by rebuild_root_circuit (batch_size
+// accounts per batch circuit, recursive_size inner proofs per recursive circuit) and the tree depth their number, so
+// all of them are bounded before any circuit is built (see ProverConfig::validate and check_tree_depth)
+pub fn check_circuit_sizes(final_proof: &FinalProof, max_tree_depth: usize) -> Result<()> {
+    final_proof.prover_config().validate()?;
+    check_tree_depth(final_proof.tree_depth, max_tree_depth)
+}
+
+// the per-asset fields of an untrusted proof file are indexed by asset position and the public inputs are sliced by the
+// asset count (see ProofPublicInputs), so their lengths are checked before anything is printed or indexed: a malformed
+// file is then rejected with an error instead of a panic
+pub fn check_final_proof_shape(final_proof: &FinalProof) -> Result<()> {
+    let asset_count = final_proof.asset_names.len();
+    if asset_count == 0 {
+        return Err(anyhow::anyhow!("The proof file has no assets, it is malformed"));
+    }
+
+    if final_proof.asset_prices.len() != asset_count || final_proof.asset_decimals.len() != asset_count {
+        return Err(anyhow::anyhow!(
+            "The proof file has {} assets but {} prices and {} decimals, it is malformed",
+            asset_count,
+            final_proof.asset_prices.len(),
+            final_proof.asset_decimals.len()
+        ));
+    }
+
+    // the prices are printed as signed amounts (see print_global_information)
+    if let Some(i) = (0..asset_count).find(|&i| i64::try_from(final_proof.asset_prices[i]).is_err()) {
+        return Err(anyhow::anyhow!(
+            "Asset {} has an out of range price {}, the proof file is malformed",
+            final_proof.asset_names[i],
+            final_proof.asset_prices[i]
+        ));
+    }
+
+    if !final_proof.reserve_buffer.is_empty() && final_proof.reserve_buffer.len() != asset_count {
+        return Err(anyhow::anyhow!(
+            "The reserve buffer has {} balances but the proof has {} assets, the proof file is malformed",
+            final_proof.reserve_buffer.len(),
+            asset_count
+        ));
+    }
+
+    if let Some(dust) = &final_proof.dust
+        && dust.balances.len() != asset_count
+    {
+        return Err(anyhow::anyhow!(
+            "The dust aggregate has {} balances but the proof has {} assets, the proof file is malformed",
+            dust.balances.len(),
+            asset_count
+        ));
+    }
+
+    let public_input_count = RecursiveCircuit::get_root_hash_offset(asset_count).end;
+    if final_proof.proof.public_inputs.len() != public_input_count {
+        return Err(anyhow::anyhow!(
+            "The proof has {} public inputs but {} are expected for {} assets, the proof file is malformed",
+            final_proof.proof.public_inputs.len(),
+            public_input_count,
+            asset_count
+        ));
+    }
+
+    format_timestamp(final_proof.timestamp)
+        .map_err(|e| anyhow::anyhow!("Invalid proof timestamp {}: {e}", final_proof.timestamp))?;
+
+    // the reserve buffer is subtracted from the proven reserves when they are printed
+    final_proof.user_liabilities().map_err(|e| anyhow::anyhow!("{e}, the proof file is malformed"))?;
+
+    Ok(())
+}
+
+// rebuilds the root circuit for a proof shape and returns its digest
+// the digest is the sha-256 of the serialized root circuit verifier data (see verifier_data_digest), it only depends on
+// the asset count, the tree depth and the circuit sizes, so it can be computed once (offline) and pinned when verifying
+// untrusted proofs (VerifyOptions::expected_digest)
+pub fn expected_root_circuit_digest(asset_count: usize, tree_depth: usize, config: &ProverConfig) -> Result<Vec<u8>> {
+    config.validate()?;
+    check_tree_depth(tree_depth, DEFAULT_MAX_TREE_DEPTH)?;
+
+    let root_circuit = rebuild_root_circuit(asset_count, tree_depth - 1, config);
+    built_circuit_digest(&root_circuit)
+}
+
+// digest (see verifier_data_digest) of the verifier data of a rebuilt circuit, comparable to root_circuit_digest
+fn built_circuit_digest(root_circuit: &RecursiveCircuit) -> Result<Vec<u8>> {
+    let verifier_data = root_circuit
+        .circuit_data
+        .verifier_data()
+        .to_bytes(&DefaultGateSerializer)
+        .map_err(|_| anyhow::anyhow!("Failed to serialize root circuit verifier data"))?;
+
+    Ok(verifier_data_digest(&verifier_data))
+}
+
+// timings of bench_verify (capacity planning of a verifier service)
+// the root circuit is rebuilt once and its digest is pinned for every run, like a verifier keeping it cached between proofs
+#[derive(Clone, Debug)]
+pub struct VerifyBenchmark {
+    pub rebuild: Duration,            // root circuit rebuild (expected_root_circuit_digest)
+    pub proof_checks: Vec<Duration>,  // verify_final_proof with the pinned digest, one per run
+    pub merkle_checks: Vec<Duration>, // merkle tree checks of verify_root (root hash and full recomputation), one per run
+}
+
+// (min, median, max) of a non-empty list of durations
+pub fn duration_stats(durations: &[Duration]) -> Option<(Duration, Duration, Duration)> {
+    let mut sorted = durations.to_vec();
+    sorted.sort();
+
+    Some((*sorted.first()?, sorted[sorted.len() / 2], *sorted.last()?))
+}
+
+// times the phases of verify_root for a proof
+// the root circuit is rebuilt once and its digest is pinned (VerifyOptions::expected_digest), then every run goes through
+// the same verify_final_proof and merkle tree checks as verify_root, logs included (hide them with --quiet)
+// fails like verify_root if the proof doesn't verify
+pub fn bench_verify(final_proof: &FinalProof, merkle_tree: &MerkleTree, runs: usize) -> Result<VerifyBenchmark> {
+    if runs == 0 {
+        return Err(anyhow::anyhow!("The number of runs must be at least 1"));
+    }
+    check_circuit_sizes(final_proof, DEFAULT_MAX_TREE_DEPTH)?;
+    check_final_proof_shape(final_proof)?;
+    merkle_tree.check_depth(final_proof.tree_depth)?;
+
+    let timer = Instant::now();
+    let config = final_proof.prover_config();
+    let digest = expected_root_circuit_digest(final_proof.asset_names.len(), final_proof.tree_depth, &config)?;
+    let rebuild = timer.elapsed();
+
+    let options = VerifyOptions { expected_digest: Some(digest), ..VerifyOptions::default() };
+    let mut proof_checks = Vec::with_capacity(runs);
+    let mut merkle_checks = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let timer = Instant::now();
+        verify_final_proof(final_proof, &options)?;
+        proof_checks.push(timer.elapsed());
+
+        let timer = Instant::now();
+        verify_merkle_tree_against_proof(final_proof, merkle_tree, &options)?;
+        merkle_checks.push(timer.elapsed());
+    }
+
+    Ok(VerifyBenchmark { rebuild, proof_checks, merkle_checks })
+}
+
+// deserializes the root circuit verifier data embedded in the proof file (not trusted until checked)
+fn root_verifier_data(final_proof: &FinalProof) -> Result<VerifierCircuitData<F, C, D>> {
+    if final_proof.is_verifier_data_detached() {
+        return Err(anyhow::anyhow!(
+            "The root circuit verifier data is detached, it must be attached first (from {})",
+            FinalProof::VERIFIER_DATA_FILE_NAME
+        ));
+    }
+
+    VerifierCircuitData::from_bytes(final_proof.root_circuit_verifier_data.clone(), &DefaultGateSerializer)
+        .map_err(|_| anyhow::anyhow!("Failed to deserialize root circuit verifier data"))
+}
+
+// digest (see verifier_data_digest) of the root circuit verifier data embedded in the proof file (not trusted until checked)
+pub fn root_circuit_digest(final_proof: &FinalProof) -> Result<Vec<u8>> {
+    // fails if the verifier data is detached or malformed
+    root_verifier_data(final_proof)?;
+
+    Ok(verifier_data_digest(&final_proof.root_circuit_verifier_data))
+}
+
+// the circuits can change between releases, so a proof must be verified with the binary of the version that generated it
+// the mismatch is only tolerated with ignore_version (the verification is then expected to fail on the circuit digest)
+pub fn check_prover_version(final_proof: &FinalProof, ignore_version: bool) -> Result<()> {
+    let version = format!("v{}", env!("CARGO_PKG_VERSION"));
+    if final_proof.prover_version == version {
+        return Ok(());
+    }
+
+    // printed as errors so it is shown even with --quiet
+    log_error!("========================================================================");
+    log_error!("PROVER VERSION MISMATCH");
+    log_error!("The proof was generated by plonky2_por {}, this is plonky2_por {}", final_proof.prover_version, version);
+    log_error!(
+        "The circuits may differ between versions, use the {} binary from the repository releases to verify it",
+        final_proof.prover_version
+    );
+    log_error!("========================================================================");
+
+    if !ignore_version {
+        return Err(anyhow::anyhow!(
+            "Prover version mismatch (proof: {}, binary: {}), pass --ignore-version to continue anyway",
+            final_proof.prover_version,
+            version
+        ));
+    }
+    log_warning!("Continuing with a different prover version (--ignore-version), the verification may fail");
+
+    Ok(())
+}
+
 fn print_global_information(final_proof: &FinalProof) {
     // print the global information
     log_warning!("The following information was used to generate the proof, please manually verify if they are correct:");
     log_warning!("NOTE: This is not real-time information, verify if the information is correct relative to the time of the proof generation");
     log_warning!("NOTE2: Asset prices was rounded by some decimals, verify if they are close enough to the original price");
+    log_warning!("NOTE3: Asset names and decimals are not part of the zk proof (only the prices are), verify them against the published asset list");
 
     // iterate through the asset names and prices
     println!("======================");
@@ -52,19 +275,42 @@ fn print_global_information(final_proof: &FinalProof) {
     println!("Proof generation timestamp (ms): {}", final_proof.timestamp);
     println!("Number of accounted assets: {}", final_proof.asset_names.len());
 
+    // the assets are printed sorted by name, independently of their order in the proof
+    let order = final_proof.canonical_asset_order();
+
     println!("\n-----Asset prices-----");
-    for (i, asset_name) in final_proof.asset_names.iter().enumerate() {
+    for &i in &order {
+        let asset_name = &final_proof.asset_names[i];
         let asset_price = calculate_with_decimals(
             final_proof.asset_prices[i].try_into().unwrap(),
             final_proof.asset_decimals[i].usdt_decimals,
         );
-        println!("{asset_name}: US$ {asset_price}");
+        println!("{asset_name}: US$ {}", format_amount(&asset_price));
+    }
+
+    // a zero price hides the balances of an asset in the account equity (non-negativity check)
+    for &i in &order {
+        if final_proof.asset_prices[i] == 0 {
+            log_warning!(
+                "Asset {} has a zero price, its balances don't count in the account equity",
+                final_proof.asset_names[i]
+            );
+        }
     }
 
     println!("======================");
 }
 
-fn print_reserves(final_proof: &FinalProof){
+// the assets whose reserves are displayed or exported, sorted by name: all of them, or the selected ones
+fn reserves_order(final_proof: &FinalProof, assets: Option<&[String]>) -> Result<Vec<usize>> {
+    match assets {
+        Some(assets) => final_proof.selected_asset_order(assets),
+        None => Ok(final_proof.canonical_asset_order()),
+    }
+}
+
+// the reserves of the assets in order (see reserves_order)
+fn print_reserves(final_proof: &FinalProof, order: &[usize]) {
     println!();
     log_info!("The following information is the final needed asset reserves, which was validated by the Zero-Knowledge proof");
     log_warning!("NOTE: This is not real-time information, the information is relative to the time of the proof generation");
@@ -75,126 +321,680 @@ fn print_reserves(final_proof: &FinalProof){
     println!("Proof generation date: {}", format_timestamp(final_proof.timestamp).unwrap());
     println!("Proof generation timestamp (ms): {}", final_proof.timestamp);
     println!("Number of accounted assets: {}", final_proof.asset_names.len());
+    if order.len() != final_proof.asset_names.len() {
+        println!("Displayed assets: {} (all the assets were verified)", order.len());
+    }
 
-    let asset_count = final_proof.asset_names.len();
-    let final_balances_offsets = RecursiveCircuit::get_final_balances_offset(asset_count);
-    let asset_reserves = final_proof.proof.public_inputs[final_balances_offsets].to_vec();
+    let asset_reserves = final_proof.public_inputs().final_balances();
 
     println!("\n-----Asset reserves-----");
-    for (i, asset_name) in final_proof.asset_names.iter().enumerate() {
+    for &i in order {
+        let asset_name = &final_proof.asset_names[i];
         let asset_price = calculate_with_decimals(
-            asset_reserves[i].to_canonical_u64().try_into().unwrap(),
+            asset_reserves[i],
             final_proof.asset_decimals[i].balance_decimals,
         );
-        println!("{asset_name}: {asset_price}");
+        println!("{asset_name}: {}", format_amount(&asset_price));
+    }
+
+    // separate the user liabilities from the reserve buffer (insurance fund) if there is one
+    // the reserve buffer leaf is proven as part of the reserves, but the buffer printed here (and so the split) is copied
+    // from the proof file and is not a public input of the proof
+    // the liabilities were checked with the proof shape (see check_final_proof_shape)
+    if final_proof.reserve_buffer.iter().any(|balance| *balance != 0)
+        && let Ok(user_liabilities) = final_proof.user_liabilities()
+    {
+        println!("\n-----User liabilities (reserves minus the reserve buffer, declared, not proven: reported by the prover)-----");
+        for &i in order {
+            let asset_name = &final_proof.asset_names[i];
+            let liability = calculate_with_decimals(
+                user_liabilities[i],
+                final_proof.asset_decimals[i].balance_decimals,
+            );
+            println!("{asset_name}: {}", format_amount(&liability));
+        }
+
+        println!("\n-----Reserve buffer (declared, not proven: reported by the prover)-----");
+        for &i in order {
+            let asset_name = &final_proof.asset_names[i];
+            let buffer = calculate_with_decimals(
+                final_proof.reserve_buffer[i],
+                final_proof.asset_decimals[i].balance_decimals,
+            );
+            println!("{asset_name}: {}", format_amount(&buffer));
+        }
+    }
+
+    // the dust accounts are summed into the reserves but are not individually inclusion-provable
+    // the dust leaf is proven as part of the reserves, but its balances and the threshold printed here are copied from the
+    // proof file and are not public inputs of the proof
+    if let Some(dust) = &final_proof.dust {
+        let equity_decimals = final_proof.asset_decimals[0].usdt_decimals + final_proof.asset_decimals[0].balance_decimals;
+        let min_equity = unscale_price(dust.min_equity, equity_decimals);
+
+        println!(
+            "\n-----Dust accounts (equity below ${}, aggregated, not proven: reported by the prover)-----",
+            format_amount(&min_equity)
+        );
+        for &i in order {
+            let asset_name = &final_proof.asset_names[i];
+            let Some(balance) = dust.balances.get(i) else { continue };
+            let balance = calculate_with_decimals(
+                *balance,
+                final_proof.asset_decimals[i].balance_decimals,
+            );
+            println!("{asset_name}: {}", format_amount(&balance));
+        }
     }
 
     println!("======================\n");
 }
 
-pub fn verify_root(final_proof: FinalProof, merkle_tree: MerkleTree) {
+// quote a CSV field if it contains a separator, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// write the per-asset reserves of a (verified) proof into a CSV file, sorted by name like the printed reserves
+// only the assets named in assets are written if given (an unknown asset is an error)
+// columns: asset_name,reserve_raw,reserve_decimal,price,usd_value
+pub fn write_reserves_csv(final_proof: &FinalProof, path: &str, assets: Option<&[String]>) -> Result<()> {
+    let valuations = final_proof.asset_valuations();
+    let order = reserves_order(final_proof, assets)?;
+
+    let mut csv = String::from("asset_name,reserve_raw,reserve_decimal,price,usd_value\n");
+    for i in order {
+        let valuation = &valuations[i];
+
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            csv_field(&final_proof.asset_names[i]),
+            valuation.reserve_raw,
+            valuation.reserve,
+            valuation.price,
+            valuation.usd_value
+        ));
+    }
+
+    std::fs::write(path, csv).with_context(|| format!("Failed to write reserves CSV: {path}"))?;
+    Ok(())
+}
+
+// USD value of the reserves of each asset (reserve * price, same order as asset_names)
+fn asset_usd_reserves(final_proof: &FinalProof) -> Vec<BigDecimal> {
+    final_proof.asset_valuations().into_iter().map(|valuation| valuation.usd_value).collect()
+}
+
+// total USD value of the reserves of a proof (sum of reserve * price of every asset)
+fn total_usd_reserves(final_proof: &FinalProof) -> BigDecimal {
+    asset_usd_reserves(final_proof).into_iter().sum()
+}
+
+// coverage ratio of an asset (see coverage_ratios)
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssetCoverage {
+    pub asset: String,
+    // declared reserve / proven user liability, None when the users owe nothing of the asset
+    // (zero or net negative liability once the reserve buffer is subtracted): any reserve covers it
+    pub ratio: Option<BigDecimal>,
+}
+
+// coverage ratio of each asset (declared reserve / proven user liability), sorted by asset name
+// the declared reserves are the holdings the exchange reports (raw integers scaled by balance_decimals, like the ledger),
+// missing assets are zero. The liabilities are the proven reserves minus the reserve buffer (FinalProof::user_liabilities)
+pub fn coverage_ratios(
+    final_proof: &FinalProof,
+    declared_reserves: &HashMap<String, i64>,
+) -> Result<Vec<AssetCoverage>> {
+    if let Some(asset_name) = declared_reserves.keys().find(|name| !final_proof.asset_names.contains(name)) {
+        return Err(anyhow::anyhow!("Unknown asset {asset_name}, it is not in the final proof"));
+    }
+
+    let user_liabilities = final_proof.user_liabilities()?;
+    let ratios = final_proof
+        .canonical_asset_order()
+        .into_iter()
+        .map(|i| {
+            let asset = final_proof.asset_names[i].clone();
+            let declared_reserve = declared_reserves.get(&asset).copied().unwrap_or(0);
+
+            // both amounts have the same balance decimals, so the ratio is unitless
+            let ratio = (user_liabilities[i] > 0)
+                .then(|| BigDecimal::from(declared_reserve) / BigDecimal::from(user_liabilities[i]));
+            AssetCoverage { asset, ratio }
+        })
+        .collect();
+
+    Ok(ratios)
+}
+
+// check if an asset can be used as quote currency: it must be in the proof and have a nonzero price
+pub fn validate_quote_asset(final_proof: &FinalProof, quote_asset: &str) -> Result<()> {
+    let Some(index) = final_proof.asset_names.iter().position(|name| name == quote_asset) else {
+        return Err(anyhow::anyhow!("Quote asset {quote_asset} is not present in the proof"));
+    };
+
+    if final_proof.public_inputs().asset_prices()[index] == 0 {
+        return Err(anyhow::anyhow!("Quote asset {quote_asset} has a zero price in the proof"));
+    }
+
+    Ok(())
+}
+
+// print the reserves of a (verified) proof expressed in a quote asset (e.g. EUR or BTC) instead of USD
+// the values are rounded to the balance decimals of the quote asset
+// only the assets named in assets (and their total) are printed if given, the quote asset doesn't need to be one of them
+pub fn print_reserves_in_quote(final_proof: &FinalProof, quote_asset: &str, assets: Option<&[String]>) -> Result<()> {
+    validate_quote_asset(final_proof, quote_asset)?;
+    // sorted by name like the USD reserves (see reserves_order)
+    let order = reserves_order(final_proof, assets)?;
+
+    let quote_index = final_proof.asset_names.iter().position(|name| name == quote_asset).unwrap();
+    let quote_decimals = &final_proof.asset_decimals[quote_index];
+    let valuations = final_proof.asset_valuations();
+    let quote_price = &valuations[quote_index].price;
+
+    let asset_usd_reserves = valuations.iter().map(|valuation| &valuation.usd_value).collect::<Vec<_>>();
+
+    println!("======================");
+    println!("\n-----Asset reserves ({quote_asset})-----");
+    for &i in &order {
+        let value = (asset_usd_reserves[i] / quote_price).round(quote_decimals.balance_decimals);
+        println!("{}: {} {quote_asset}", final_proof.asset_names[i], format_amount(&value));
+    }
+
+    let total = order.iter().map(|&i| asset_usd_reserves[i]).sum::<BigDecimal>() / quote_price;
+    let label = if assets.is_some() { "Total reserves of the selected assets" } else { "Total reserves" };
+    println!("\n{label}: {} {quote_asset}", format_amount(&total.round(quote_decimals.balance_decimals)));
+    println!("======================\n");
+
+    Ok(())
+}
+
+// checks performed by verify_root, all enabled by default
+// disabling checks is useful for repeated spot-checks and debugging, a full verification must keep all of them
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyOptions {
+    pub rebuild_circuit: bool,   // 1. rebuild the root circuit (otherwise the circuit data inside the proof file is trusted)
+    pub check_prices: bool,      // 3. asset prices
+    pub check_decimals: bool,    // 4. asset decimals
+    pub check_root_hash: bool,   // 5. merkle tree root hash
+    pub check_merkle_tree: bool, // 6. full merkle tree recomputation
+    pub deep_check: bool,        // 7. re-sum the batch reserves of the merkle tree (opt-in, see check_batch_reserves)
+    pub max_tree_depth: usize,   // proofs with a deeper tree are rejected before any circuit is built (see check_tree_depth)
+    // 0. trusted root circuit digest (see expected_root_circuit_digest), checked before anything else
+    // it replaces the (slow) rebuild of step 1
+    pub expected_digest: Option<Vec<u8>>,
+    // only print the reserves of these assets (see FinalProof::selected_asset_order), every asset is still verified
+    pub assets: Option<Vec<String>>,
+}
+
+impl VerifyOptions {
+    // every check is enabled (the root circuit is either rebuilt or pinned by a trusted digest)
+    pub fn is_full(&self) -> bool {
+        (self.rebuild_circuit || self.expected_digest.is_some())
+            && self.check_prices
+            && self.check_decimals
+            && self.check_root_hash
+            && self.check_merkle_tree
+    }
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        VerifyOptions {
+            rebuild_circuit: true,
+            check_prices: true,
+            check_decimals: true,
+            check_root_hash: true,
+            check_merkle_tree: true,
+            deep_check: false,
+            max_tree_depth: DEFAULT_MAX_TREE_DEPTH,
+            expected_digest: None,
+            assets: None,
+        }
+    }
+}
+
+// verify the zk proof and the public information of a final proof (steps 1 to 4 of verify_root)
+fn verify_final_proof(final_proof: &FinalProof, options: &VerifyOptions) -> Result<()> {
     let asset_count = final_proof.asset_names.len();
 
     // deserialize the verifier data
-    let root_verifier_data: VerifierCircuitData<F, C, D> = VerifierCircuitData::from_bytes(
-        final_proof.root_circuit_verifier_data.clone(),
-        &DefaultGateSerializer,
-    )
-    .unwrap();
+    let root_verifier_data = root_verifier_data(final_proof)?;
 
-    // print the global information
-    print_global_information(&final_proof);
-
-    // START VERIFICATION
+    // 0. check the digest of the circuit data inside the proof file against the trusted one
+    // the digest is computed from the raw bytes, the circuit digest field inside the (untrusted) data is never used for it
+    if let Some(expected_digest) = &options.expected_digest {
+        let digest = verifier_data_digest(&final_proof.root_circuit_verifier_data);
+        if digest != *expected_digest {
+            return Err(anyhow::anyhow!(
+                "Root circuit digest {} does not match the expected digest {}",
+                bytes_to_hex(&digest),
+                bytes_to_hex(expected_digest)
+            ));
+        }
+        log_success!("Root circuit digest matches the expected digest!");
+    }
 
     // 1. rebuild the root circuit to verify if the digest is the same as specified in the proof file
     // we use depth - 2 because the last depth are the leaves (no circuit)
-    log_info!("Rebuilding root circuit... This might take several minutes...");
-    let built_root_circuit = rebuild_root_circuit(asset_count, final_proof.tree_depth - 1);
-    log_success!("Root circuit rebuilt successfully!");
+    // 2. verify the proof
+    if options.expected_digest.is_some() {
+        log_info!("Skipping the root circuit rebuild (the circuit data inside the file matches the expected digest)");
 
-    assert!(
-        built_root_circuit.circuit_data.verifier_only.circuit_digest
-            == root_verifier_data.verifier_only.circuit_digest,
-        "{}",
-        format_error("Root circuit digest does not match the proof file").as_str(),
-    );
+        log_info!("Verifying final proof...");
+        root_verifier_data
+            .verify(final_proof.proof.clone())
+            .map_err(|_| anyhow::anyhow!("Failed to verify proof"))?;
+    } else if options.rebuild_circuit {
+        log_info!("Rebuilding root circuit... This might take several minutes...");
+        let built_root_circuit = rebuild_root_circuit(asset_count, final_proof.tree_depth - 1, &final_proof.prover_config());
+        log_info!(
+            "Root circuit digest: {} (pin it with --expected-digest to skip the rebuild next time)",
+            bytes_to_hex(&built_circuit_digest(&built_root_circuit)?)
+        );
+        log_success!("Root circuit rebuilt successfully!");
 
-    // 2. verify the proof
-    log_info!("Verifying final proof...");
-    built_root_circuit
-        .circuit_data
-        .verify(final_proof.proof.clone())
-        .unwrap_or_else(|_| { panic!("{}", format_error("Failed to verify proof")) });
+        if built_root_circuit.circuit_data.verifier_only.circuit_digest != root_verifier_data.verifier_only.circuit_digest {
+            return Err(anyhow::anyhow!("Root circuit digest does not match the proof file"));
+        }
+
+        log_info!("Verifying final proof...");
+        built_root_circuit
+            .circuit_data
+            .verify(final_proof.proof.clone())
+            .map_err(|_| anyhow::anyhow!("Failed to verify proof"))?;
+    } else {
+        log_warning!("Skipping the root circuit rebuild (trusting circuit data inside the file)");
+
+        log_info!("Verifying final proof...");
+        root_verifier_data
+            .verify(final_proof.proof.clone())
+            .map_err(|_| anyhow::anyhow!("Failed to verify proof"))?;
+    }
     log_success!("Proof is valid!");
 
     // 3. verify the asset prices with the asset prices in the proof
-    log_info!("Verifying asset prices...");
-    let prices_offset = RecursiveCircuit::get_asset_prices_offset(asset_count);
-    let proof_asset_prices = final_proof.proof.public_inputs[prices_offset].to_vec();
-    for (i, proof_asset_price) in proof_asset_prices.iter().enumerate() {
-        let asset_name = &final_proof.asset_names[i];
+    if options.check_prices {
+        log_info!("Verifying asset prices...");
+        // each price is paired with its proven price by position, the assets are checked sorted by name so the first
+        // reported mismatch doesn't depend on the order of the assets in the proof
+        let proof_asset_prices = final_proof.public_inputs().asset_prices();
+        for i in final_proof.canonical_asset_order() {
+            if proof_asset_prices[i] != final_proof.asset_prices[i] {
+                return Err(anyhow::anyhow!(
+                    "Asset price for {} does not match the ZK proof",
+                    final_proof.asset_names[i]
+                ));
+            }
+        }
+        log_success!("Asset prices are valid!");
+    } else {
+        log_warning!("Skipping the asset prices verification");
+    }
 
-        assert!(
-            proof_asset_price.to_canonical_u64() == final_proof.asset_prices[i],
-            "{}",
-            format_error(
-                format!("Asset price for {asset_name} does not match the ZK proof").as_str()
-            ),
-        );
+    // 4. verify if the decimals are valid
+    if options.check_decimals {
+        log_info!("Verifying asset decimals...");
+
+        // we need to verify if the sum of the usdt_decimals and balance_decimals is equal for every asset
+        let summed_decimals = final_proof.asset_decimals[0].balance_decimals + final_proof.asset_decimals[0].usdt_decimals;
+        for i in final_proof.canonical_asset_order() {
+            let asset_decimals = &final_proof.asset_decimals[i];
+            if asset_decimals.usdt_decimals + asset_decimals.balance_decimals != summed_decimals {
+                return Err(anyhow::anyhow!("Asset {} decimals are not valid", final_proof.asset_names[i]));
+            }
+        }
+    
+        log_success!("Asset decimals are valid!");
+    } else {
+        log_warning!("Skipping the asset decimals verification");
     }
-    log_success!("Asset prices are valid!");
 
+    Ok(())
+}
 
-    // 4. verify if the decimals are valid
-    log_info!("Verifying asset decimals...");
-
-    // we need to verify if the sum of the usdt_decimals and balance_decimals is equal for every asset
-    let summed_decimals = final_proof.asset_decimals[0].balance_decimals + final_proof.asset_decimals[0].usdt_decimals;
-    for (i, asset_name) in final_proof.asset_names.iter().enumerate() {
-        let asset_decimals = &final_proof.asset_decimals[i];
-        let usdt_decimals = asset_decimals.usdt_decimals;
-        let balance_decimals = asset_decimals.balance_decimals;
-
-        assert!(
-            usdt_decimals + balance_decimals == summed_decimals,
-            "{}",
-            format_error(
-                format!("Asset {asset_name} decimals are not valid").as_str()
-            ),
-        );
+// deep check (defense in depth): the reserves of each batch recorded in the merkle tree are summed natively and must
+// be the reserves of the final proof. The batch reserves are not proven by themselves, so this doesn't replace the zk proof,
+// but a mismatch points to a prover bug in the recursive aggregation instead of a single failing proof
+pub fn check_batch_reserves(final_proof: &FinalProof, merkle_tree: &MerkleTree) -> Result<()> {
+    check_batch_reserves_sum(final_proof, &merkle_tree.batch_reserves, merkle_tree.batch_count())
+}
+
+// the deep check of a tree verified while it was read (see StreamedMerkleTree), same as check_batch_reserves
+pub fn check_streamed_batch_reserves(final_proof: &FinalProof, merkle_tree: &StreamedMerkleTree) -> Result<()> {
+    check_batch_reserves_sum(final_proof, &merkle_tree.batch_reserves, merkle_tree.batch_count())
+}
+
+fn check_batch_reserves_sum(final_proof: &FinalProof, batch_reserves: &[Vec<i64>], batch_count: usize) -> Result<()> {
+    let asset_count = final_proof.asset_names.len();
+
+    if batch_reserves.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The merkle tree has no batch reserves (written by an older version), the deep check can't be run"
+        ));
     }
-    
-    log_success!("Asset decimals are valid!");
+    if batch_reserves.len() != batch_count {
+        return Err(anyhow::anyhow!(
+            "The merkle tree has {} batch reserves for {} batches",
+            batch_reserves.len(),
+            batch_count
+        ));
+    }
+
+    // i128 so the sum of many batches can't overflow
+    let mut totals = vec![0i128; asset_count];
+    for (batch, reserves) in batch_reserves.iter().enumerate() {
+        if reserves.len() != asset_count {
+            return Err(anyhow::anyhow!(
+                "Batch {} has {} reserves, expected one per asset ({})",
+                batch,
+                reserves.len(),
+                asset_count
+            ));
+        }
+        for (total, reserve) in totals.iter_mut().zip(reserves) {
+            *total += *reserve as i128;
+        }
+    }
+
+    // listed sorted by asset name
+    let proven = final_proof.public_inputs().final_balances();
+    let mismatches = final_proof
+        .canonical_asset_order()
+        .into_iter()
+        .filter(|&i| totals[i] != proven[i] as i128)
+        .map(|i| format!("{}: batches sum to {}, proven {}", final_proof.asset_names[i], totals[i], proven[i]))
+        .collect::<Vec<_>>();
+
+    if !mismatches.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The batch reserves don't add up to the proven reserves ({})",
+            mismatches.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn verify_root(final_proof: FinalProof, merkle_tree: MerkleTree, options: &VerifyOptions) {
+    let _span = log_span!("verify_root");
 
+    // the proof file is untrusted, reject absurd circuit sizes before any work on the tree or the circuits
+    check_circuit_sizes(&final_proof, options.max_tree_depth)
+        .unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+
+    check_final_proof_shape(&final_proof).unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+    // the asset names must be unique, otherwise they can't be mapped to the prices, decimals and reserves printed below
+    check_unique_asset_names(&final_proof.asset_names)
+        .unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+    let displayed_assets = reserves_order(&final_proof, options.assets.as_deref())
+        .unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+
+    // print the global information
+    print_global_information(&final_proof);
+
+    // START VERIFICATION
+
+    // 0. check if the merkle tree has the proof shape before the expensive circuit rebuild
+    // (the circuit is rebuilt with the proof tree_depth)
+    merkle_tree
+        .check_depth(final_proof.tree_depth)
+        .unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+
+    // 1-4. verify the proof, asset prices and decimals
+    verify_final_proof(&final_proof, options).unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+
+    // 5-7. verify the merkle tree against the proof
+    verify_merkle_tree_against_proof(&final_proof, &merkle_tree, options)
+        .unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+
+    // all proofs are valid, print the reserves information
+    print_reserves(&final_proof, &displayed_assets);
+
+
+    if options.is_full() {
+        log_success!("All proofs are valid!");
+    } else {
+        log_success!("All enabled checks are valid (some checks were skipped)!");
+    }
+
+}
+
+// steps 5-7 of verify_root, once the final proof is verified (see verify_final_proof)
+fn verify_merkle_tree_against_proof(final_proof: &FinalProof, merkle_tree: &MerkleTree, options: &VerifyOptions) -> Result<()> {
     // 5. verify the merkle tree root hash with the root hash in the proofs
-    log_info!("Verifying merkle tree root hash...");
-    let hash_offset = RecursiveCircuit::get_root_hash_offset(asset_count);
-    let proof_hash = final_proof.proof.public_inputs[hash_offset].to_vec();
-    let proof_hash_bytes = pis_to_hash_bytes::<F, D>(&proof_hash);
+    if options.check_root_hash {
+        log_info!("Verifying merkle tree root hash...");
+        if merkle_tree.root.hash().as_ref() != Some(&final_proof.public_inputs().root_hash_bytes()) {
+            return Err(anyhow::anyhow!("Merkle tree root hash does not match the proof file"));
+        }
+        log_success!("Merkle tree root hash is valid!");
+    } else {
+        log_warning!("Skipping the merkle tree root hash verification");
+    }
+
+    // 6. verify the merkle tree
+    if options.check_merkle_tree {
+        log_info!("Verifying merkle tree...");
+        if !verify_merkle_tree_with_progress(merkle_tree) {
+            return Err(anyhow::anyhow!("Merkle tree verification failed"));
+        }
+        log_success!("Merkle tree is valid!");
+    } else {
+        log_warning!("Skipping the merkle tree verification");
+    }
+
+    // 7. re-sum the batch reserves and compare them with the proven reserves
+    if options.deep_check {
+        log_info!("Verifying the batch reserves add up to the proven reserves...");
+        check_batch_reserves(final_proof, merkle_tree)?;
+        log_success!("Batch reserves are valid!");
+    }
+
+    Ok(())
+}
+
+// same checks as verify_root, but the merkle tree is verified while it is read from the file (see VerifyingTreeSeed)
+// instead of being deserialized first: each node is dropped once it is checked against its children, so the memory
+// doesn't grow with the tree (only the batch reserves of the deep check are kept)
+// the tree structure is only known once the whole file is read, so the depth (step 0) is checked after the tree
+pub fn verify_root_streaming(final_proof: &FinalProof, merkle_tree_path: &str, options: &VerifyOptions) -> Result<()> {
+    let _span = log_span!("verify_root_streaming");
+
+    // the proof file is untrusted, reject absurd circuit sizes before any work on the tree or the circuits
+    check_circuit_sizes(final_proof, options.max_tree_depth)?;
+    check_final_proof_shape(final_proof)?;
+    check_unique_asset_names(&final_proof.asset_names)?;
+    let displayed_assets = reserves_order(final_proof, options.assets.as_deref())?;
+
+    print_global_information(final_proof);
+
+    // 1-4. verify the proof, asset prices and decimals
+    verify_final_proof(final_proof, options)?;
+
+    // 6. verify the merkle tree (always, it has to be read anyway to get its root hash)
+    log_info!("Verifying merkle tree while reading {merkle_tree_path}...");
+    let file = std::fs::File::open(merkle_tree_path).with_context(|| format!("Failed to read {merkle_tree_path}"))?;
+    let file_size = file.metadata().with_context(|| format!("Failed to read {merkle_tree_path}"))?.len() as usize;
+    let mut reader = ProgressReader::new(file, file_size);
+    let seed = VerifyingTreeSeed { max_depth: final_proof.tree_depth };
+    let merkle_tree = from_reader_seed(std::io::BufReader::new(&mut reader), seed);
+    reader.progress.clear_bar();
+    let merkle_tree = merkle_tree.with_context(|| format!("Failed to verify {merkle_tree_path}"))?;
+    log_success!("Merkle tree is valid!");
+
+    // 0. check if the merkle tree has the proof shape
+    merkle_tree.check_depth(final_proof.tree_depth)?;
+
+    // 5. verify the merkle tree root hash with the root hash in the proofs
+    if options.check_root_hash {
+        log_info!("Verifying merkle tree root hash...");
+        if merkle_tree.root_hash != Some(final_proof.public_inputs().root_hash_bytes()) {
+            return Err(anyhow::anyhow!("Merkle tree root hash does not match the proof file"));
+        }
+        log_success!("Merkle tree root hash is valid!");
+    } else {
+        log_warning!("Skipping the merkle tree root hash verification");
+    }
+
+    // 7. re-sum the batch reserves and compare them with the proven reserves
+    if options.deep_check {
+        log_info!("Verifying the batch reserves add up to the proven reserves...");
+        check_streamed_batch_reserves(final_proof, &merkle_tree)?;
+        log_success!("Batch reserves are valid!");
+    }
+
+    // all proofs are valid, print the reserves information
+    print_reserves(final_proof, &displayed_assets);
+
+    // the merkle tree was verified even if check_merkle_tree is off
+    if (VerifyOptions { check_merkle_tree: true, ..options.clone() }).is_full() {
+        log_success!("All proofs are valid!");
+    } else {
+        log_success!("All enabled checks are valid (some checks were skipped)!");
+    }
+
+    Ok(())
+}
+
+// reports the progress of a long read by the number of bytes read (e.g. a streamed merkle tree, whose size is only
+// known in bytes), the bar is only updated every 0.1% of the total
+struct ProgressReader<R> {
+    inner: R,
+    progress: ProveInclusionProgress,
+    pending: usize,
+    step: usize,
+}
+
+impl<R> ProgressReader<R> {
+    fn new(inner: R, total: usize) -> Self {
+        let progress = ProveInclusionProgress::new(total.max(1));
+        progress.print_progress_bar();
+
+        Self { inner, progress, pending: 0, step: (total / 1000).max(1) }
+    }
+}
+
+impl<R: std::io::Read> std::io::Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.pending += read;
+        if self.pending >= self.step {
+            self.progress.update_progress(self.pending);
+            self.pending = 0;
+        }
+
+        Ok(read)
+    }
+}
+
+// verify the proof without the merkle tree (steps 1 to 4 of verify_root) and print the proven reserves
+// the tree is only needed to check the root hash and its leaves (steps 5 to 7), so the root hash is printed instead: it
+// must be compared with the published one, otherwise the proof could be of any set of accounts
+pub fn verify_proof_only(final_proof: &FinalProof, options: &VerifyOptions) -> Result<()> {
+    let _span = log_span!("verify_proof_only");
+
+    // the proof file is untrusted, reject absurd circuit sizes before any circuit is built
+    check_circuit_sizes(final_proof, options.max_tree_depth)?;
+    check_final_proof_shape(final_proof)?;
+    check_unique_asset_names(&final_proof.asset_names)?;
+    let displayed_assets = reserves_order(final_proof, options.assets.as_deref())?;
+
+    print_global_information(final_proof);
+
+    // 1-4. verify the proof, asset prices and decimals
+    verify_final_proof(final_proof, options)?;
+
+    log_warning!("Skipping the merkle tree verification (no merkle tree supplied)");
+    log_warning!(
+        "Proven root hash: {}, verify it is the root hash published by the exchange",
+        bytes_to_hex(&final_proof.public_inputs().root_hash_bytes())
+    );
+
+    print_reserves(final_proof, &displayed_assets);
+
+    log_success!("The proof is valid (the merkle tree was not verified)!");
+
+    Ok(())
+}
+
+// verify every snapshot of a proof archive and print the timeline of the total USD reserves
+// there is no merkle tree in the archive, so only the proof, prices and decimals are verified for each snapshot
+pub fn verify_archive(archive: &ProofArchive, options: &VerifyOptions) {
+    let _span = log_span!("verify_archive");
 
     assert!(
-        merkle_tree.root.hash().clone().unwrap() == proof_hash_bytes,
+        !archive.snapshots.is_empty(),
         "{}",
-        format_error("Merkle tree root hash does not match the proof file")
+        format_error("The proof archive has no snapshots")
     );
-    log_success!("Merkle tree root hash is valid!");
 
-    // 6. verify the merkle tree
-    log_info!("Verifying merkle tree...");
+    // the archive file could have been edited, so the order is checked again
     assert!(
-        merkle_tree.verify(),
+        archive.timestamps().windows(2).all(|pair| pair[0] < pair[1]),
         "{}",
-        format_error("Merkle tree verification failed")
+        format_error("The proof archive snapshots are not strictly ordered by timestamp")
     );
-    log_success!("Merkle tree is valid!");
 
-    // all proofs are valid, print the reserves information
-    print_reserves(&final_proof);
+    // the archive file is untrusted, reject absurd circuit sizes before any circuit is built
+    for snapshot in &archive.snapshots {
+        check_circuit_sizes(snapshot, options.max_tree_depth)
+            .unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+        check_final_proof_shape(snapshot).unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+    }
 
+    let snapshot_count = archive.snapshots.len();
+    for (i, snapshot) in archive.snapshots.iter().enumerate() {
+        log_info!(
+            "Verifying snapshot {}/{} ({})...",
+            i + 1,
+            snapshot_count,
+            format_timestamp(snapshot.timestamp).unwrap()
+        );
+        verify_final_proof(snapshot, options).unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+    }
 
-    log_success!("All proofs are valid!");
+    // all snapshots are valid, print the timeline
+    println!();
+    log_info!("Total reserves timeline (USD), validated by the Zero-Knowledge proofs");
+    println!("======================");
+    for snapshot in &archive.snapshots {
+        println!(
+            "{}: US$ {}",
+            format_timestamp(snapshot.timestamp).unwrap(),
+            format_amount(&total_usd_reserves(snapshot))
+        );
+    }
+    println!("======================\n");
+
+    // the snapshots may have different asset sets (e.g. a delisted asset), report them by name
+    let asset_changes = archive.asset_changes();
+    if asset_changes.iter().any(|changes| !changes.is_empty()) {
+        log_info!("Asset changes between snapshots");
+        println!("======================");
+        for (snapshot, changes) in archive.snapshots[1..].iter().zip(asset_changes) {
+            if changes.is_empty() {
+                continue;
+            }
+
+            let date = format_timestamp(snapshot.timestamp).unwrap();
+            if !changes.added.is_empty() {
+                println!("{date}: added {}", changes.added.join(", "));
+            }
+            if !changes.removed.is_empty() {
+                println!("{date}: removed {}", changes.removed.join(", "));
+            }
+        }
+        println!("======================\n");
+    }
 
+    log_success!("All {} snapshots are valid!", snapshot_count);
 }
 
 fn print_account_information(final_proof: &FinalProof, inclusion_proof: &InclusionProof) {
@@ -209,63 +1009,348 @@ fn print_account_information(final_proof: &FinalProof, inclusion_proof: &Inclusi
     println!("Proof generation timestamp (ms): {}", final_proof.timestamp);
     println!("Number of accounted assets: {}", final_proof.asset_names.len());
 
+    // the shapes are checked by verify_user_inclusion, zipped so a short list can't panic here
     println!("\n-----Asset balances-----");
-    for (i, asset_name) in final_proof.asset_names.iter().enumerate() {
-        let asset_balance = calculate_with_decimals(
-            inclusion_proof.user_balances[i],
-            final_proof.asset_decimals[i].balance_decimals,
-        );
-        println!("{asset_name}: {asset_balance}");
+    let balances = final_proof.asset_names.iter().zip(&final_proof.asset_decimals).zip(&inclusion_proof.user_balances);
+    for ((asset_name, asset_decimals), balance) in balances {
+        let asset_balance = calculate_with_decimals(*balance, asset_decimals.balance_decimals);
+        println!("{asset_name}: {}", format_amount(&asset_balance));
     }
 
     println!("======================");
 }
 
+// balances of an inclusion proof outside the range accepted by the batch circuit, as (asset name, balance)
+// the leaf hash is computed over the same balances so it doesn't catch them, they are only misleading when displayed
+pub fn out_of_range_balances(final_proof: &FinalProof, inclusion_proof: &InclusionProof) -> Vec<(String, i64)> {
+    final_proof
+        .asset_names
+        .iter()
+        .zip(&inclusion_proof.user_balances)
+        .filter(|(_, balance)| check_balance_range(**balance).is_err())
+        .map(|(asset_name, balance)| (asset_name.clone(), *balance))
+        .collect()
+}
+
 pub fn verify_user_inclusion(final_proof: FinalProof, inclusion_proof: InclusionProof) {
-    let asset_count = final_proof.asset_names.len();
+    // the files are untrusted, a malformed one is reported before anything is printed
+    check_final_proof_shape(&final_proof)
+        .and_then(|()| check_inclusion_shape(&final_proof, &inclusion_proof))
+        .unwrap_or_else(|e| panic!("{}", format_error(&format!("Malformed proof file: {e}"))));
 
     // print the account information
     print_account_information(&final_proof, &inclusion_proof);
+    for (asset_name, balance) in out_of_range_balances(&final_proof, &inclusion_proof) {
+        log_warning!(
+            "The {} balance ({}) is outside the range accepted by the batch circuit, it can't be a proven balance",
+            asset_name,
+            balance
+        );
+    }
 
     // TODO: create a CLI flag to rebuild the circuit in user inclusions
     // 1. verify the proof
 
     log_info!("Verifying global proof (trusting circuit data inside the file)...");
-    let root_verifier_data: VerifierCircuitData<F, C, D> = VerifierCircuitData::from_bytes(
-        final_proof.root_circuit_verifier_data,
-        &DefaultGateSerializer,
-    )
-    .unwrap();
+    let trusted_root = verify_global_proof(&final_proof)
+        .unwrap_or_else(|e| panic!("{}", format_error(&format!("Failed to verify proof: {e:#}"))));
+    log_success!("Global proof is valid!");
+
+    // 2. verify if the user is included in the merkle tree
+    log_info!("Verifying inclusion proof...");
+    verify_inclusion_against_root(&inclusion_proof, &trusted_root)
+        .unwrap_or_else(|e| panic!("{}", format_error(&e.to_string())));
+
+    log_success!("Inclusion proof root hash is valid! The user is included in the merkle tree!");
+}
+
+// verifies the global proof with the root circuit verifier data inside the final proof (trusting the circuit data
+// inside the file, as verify_user_inclusion) and returns the proven merkle root hash, which can then be trusted to
+// check any number of inclusion proofs with verify_inclusion_against_root
+pub fn verify_global_proof(final_proof: &FinalProof) -> Result<Vec<u8>> {
+    let root_verifier_data = root_verifier_data(final_proof)?;
+
+    // plonky2 would also reject a proof of another circuit, but with an opaque error
+    let expected_public_inputs = RecursiveCircuit::get_root_hash_offset(final_proof.asset_names.len()).end;
+    if root_verifier_data.common.num_public_inputs != expected_public_inputs {
+        return Err(anyhow::anyhow!(
+            "Proof/verifier data mismatch: the verifier data expects {} public inputs, {} assets need {}",
+            root_verifier_data.common.num_public_inputs,
+            final_proof.asset_names.len(),
+            expected_public_inputs
+        ));
+    }
+    check_proof_shape(&final_proof.proof, &root_verifier_data.common)?;
 
     root_verifier_data
         .verify(final_proof.proof.clone())
-        .unwrap_or_else(|_| { panic!("{}", format_error("Failed to verify proof")) });
+        .context("Failed to verify proof")?;
+
+    Ok(final_proof.public_inputs().root_hash_bytes())
+}
+
+// checks that the shape of a proof (public inputs, openings, merkle caps and fri queries) is the one of the circuit
+// described by the common data, i.e. that the verifier data and the proof come from the same circuit
+pub fn check_proof_shape(proof: &ProofWithPublicInputs<F, C, D>, common: &CommonCircuitData<F, D>) -> Result<()> {
+    let config = &common.config;
+    let openings = &proof.proof.openings;
+    let fri_proof = &proof.proof.opening_proof;
+    let cap_len = 1 << config.fri_config.cap_height;
+
+    let checks = [
+        ("public inputs", proof.public_inputs.len(), common.num_public_inputs),
+        ("wire openings", openings.wires.len(), config.num_wires),
+        ("constant openings", openings.constants.len(), common.num_constants),
+        ("sigma openings", openings.plonk_sigmas.len(), config.num_routed_wires),
+        ("z openings", openings.plonk_zs.len(), config.num_challenges),
+        ("partial product openings", openings.partial_products.len(), config.num_challenges * common.num_partial_products),
+        ("quotient openings", openings.quotient_polys.len(), config.num_challenges * common.quotient_degree_factor),
+        ("wires cap", proof.proof.wires_cap.0.len(), cap_len),
+        ("quotient cap", proof.proof.quotient_polys_cap.0.len(), cap_len),
+        ("fri commit phases", fri_proof.commit_phase_merkle_caps.len(), common.fri_params.reduction_arity_bits.len()),
+        ("fri query rounds", fri_proof.query_round_proofs.len(), common.fri_params.config.num_query_rounds),
+    ];
+
+    for (name, actual, expected) in checks {
+        if actual != expected {
+            return Err(anyhow::anyhow!(
+                "Proof/verifier data mismatch: the proof has {actual} {name} but the verifier data expects {expected}"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// silent version of verify_user_inclusion, returns an error instead of panicking
+pub fn check_user_inclusion(final_proof: &FinalProof, inclusion_proof: &InclusionProof) -> Result<()> {
+    // 1. verify the global proof (trusting circuit data inside the file)
+    let trusted_root = verify_global_proof(final_proof)?;
+
+    // 2. recalculate the root hash from the account leaf and compare with the proof root hash
+    verify_inclusion_against_root(inclusion_proof, &trusted_root)
+}
+
+// fast path to check many users against the same final proof: only recalculates the root hash from the account leaf
+// (compute_leaf_hash) and the inclusion proof (calculate_merkle_root_hash) and compares it with a trusted root hash
+// the trusted root must come from a verified global proof (see verify_global_proof), it is not checked here
+pub fn verify_inclusion_against_root(inclusion_proof: &InclusionProof, trusted_root: &[u8]) -> Result<()> {
+    // the nonce must be a canonical field element (otherwise it would be reduced when hashed) and the user hash a hex
+    // string, both are checked by compute_leaf_hash
+    let account_hash = compute_leaf_hash(&inclusion_proof.user_balances, &inclusion_proof.user_hash, inclusion_proof.nonce)?;
+
+    if inclusion_proof.calculate_merkle_root_hash(account_hash) != trusted_root {
+        return Err(anyhow::anyhow!("Inclusion proof root hash does not match the calculated root hash"));
+    }
+
+    Ok(())
+}
+
+// MerkleTree::verify with a progress bar that moves with every verified batch (the hashing of huge trees takes minutes)
+// when the output is not a terminal it prints plain progress lines instead
+fn verify_merkle_tree_with_progress(merkle_tree: &MerkleTree) -> bool {
+    let batch_count = merkle_tree.batch_count();
+    if batch_count == 0 {
+        return merkle_tree.verify();
+    }
+
+    let mut progress = ProveInclusionProgress::new(batch_count);
+    progress.print_progress_bar();
+    let valid = merkle_tree.verify_with_progress(|| progress.update_progress(1));
+    progress.clear_bar();
+
+    valid
+}
+
+// integrity check of a merkle tree distributed without the final proof: it must hash to the published root
+// and every node must be the hash of its children (the zk proof is not checked, see verify_root for the full verification)
+pub fn verify_tree_against_root(merkle_tree: &MerkleTree, trusted_root: &[u8]) -> Result<()> {
+    match merkle_tree.root.hash() {
+        Some(root_hash) if root_hash == trusted_root => {}
+        Some(root_hash) => {
+            return Err(anyhow::anyhow!(
+                "The merkle tree root hash {} does not match the published root hash {}",
+                bytes_to_hex(root_hash),
+                bytes_to_hex(trusted_root)
+            ));
+        }
+        None => return Err(anyhow::anyhow!("The merkle tree has no root hash")),
+    }
+
+    if !verify_merkle_tree_with_progress(merkle_tree) {
+        return Err(anyhow::anyhow!("Merkle tree verification failed: a node is not the hash of its children"));
+    }
+
+    Ok(())
+}
+
+// result of verifying many inclusion proof files (see verify_inclusion_files)
+#[derive(Debug, Clone, Default)]
+pub struct InclusionReport {
+    pub valid: usize,
+    pub invalid: usize,
+    // first failing file (in the given order) and its error
+    pub first_failure: Option<(String, String)>,
+}
+
+// verify many inclusion proof files against the same final proof
+// the global proof is the same for every file, so it is verified once (an error is returned if it is not valid)
+// and only the merkle root recomputation of each user runs in parallel
+pub fn verify_inclusion_files(final_proof: &FinalProof, files: &[String]) -> Result<InclusionReport> {
+    let _span = log_span!("verify_inclusion_files");
+
+    log_info!("Verifying global proof (trusting circuit data inside the file)...");
+    let trusted_root = verify_global_proof(final_proof)?;
     log_success!("Global proof is valid!");
 
-    // 2. verify if the user is included in the merkle tree
-    log_info!("Verifying inclusion proof...");
+    log_info!("Verifying {} inclusion proofs...", files.len());
 
-    let hash_offset = RecursiveCircuit::get_root_hash_offset(asset_count);
-    let proof_hash = final_proof.proof.public_inputs[hash_offset].to_vec();
-    let proof_hash_bytes = pis_to_hash_bytes::<F, D>(&proof_hash);
+    let results = files
+        .par_iter()
+        .map(|file| {
+            let inclusion_proof: InclusionProof = read_from_file(file)?;
+            verify_inclusion_against_root(&inclusion_proof, &trusted_root)?;
 
-    // first, calculate the node hash of the account
-    let account_hash = hash_account(
-        &inclusion_proof.user_balances,
-        inclusion_proof.user_hash.clone(),
-        inclusion_proof.nonce
-    )
-    .to_bytes();
+            for (asset_name, balance) in out_of_range_balances(final_proof, &inclusion_proof) {
+                log_warning!(
+                    "Inclusion proof {} has a {} balance ({}) outside the range accepted by the batch circuit",
+                    file,
+                    asset_name,
+                    balance
+                );
+            }
+            Ok(())
+        })
+        .collect::<Vec<_>>();
 
-    // then, calculate the root hash of the merkle tree using the inclusion proof and the calculated hash
-    let calculated_root_hash = inclusion_proof.calculate_merkle_root_hash(account_hash);
+    Ok(inclusion_report(files, results))
+}
 
-    // finally, verify the calculated root hash with the proof root hash
-    assert!(
-        calculated_root_hash == proof_hash_bytes,
-        "{}",
-        format_error("Inclusion proof root hash does not match the calculated root hash")
-    );
+// counts the valid and invalid files, logging every failure
+fn inclusion_report(files: &[String], results: Vec<Result<()>>) -> InclusionReport {
+    let mut report = InclusionReport::default();
+    for (file, result) in files.iter().zip(results) {
+        match result {
+            Ok(()) => report.valid += 1,
+            Err(e) => {
+                log_error!("Inclusion proof {} is not valid: {}", file, e);
+                report.invalid += 1;
+                if report.first_failure.is_none() {
+                    report.first_failure = Some((file.clone(), e.to_string()));
+                }
+            }
+        }
+    }
 
-    log_success!("Inclusion proof root hash is valid! The user is included in the merkle tree!");
+    report
+}
+
+// the inclusion proof files (inclusion_proof_*.json, .bin or .msgpack) of a directory, sorted so the first failure is stable
+pub fn inclusion_proof_files(dir: &str) -> Result<Vec<String>> {
+    let re = Regex::new(r"^inclusion_proof_.*\.(json|bin|msgpack)$")?;
+    let entries = std::fs::read_dir(dir).with_context(|| format!("Failed to read the directory {dir}"))?;
+
+    let mut files = entries
+        .flatten()
+        .filter(|entry| re.is_match(&entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    Ok(files)
+}
+
+// pre-publication sanity sweep: every inclusion proof of a directory must recompute to the published root hash
+// there is no zk verification (see verify_inclusion_files), only the merkle path of each file is recomputed, in parallel
+pub fn check_inclusion_dir(dir: &str, published_root: &[u8]) -> Result<InclusionReport> {
+    let _span = log_span!("check_inclusion_dir");
+
+    let files = inclusion_proof_files(dir)?;
+    if files.is_empty() {
+        return Err(anyhow::anyhow!("No inclusion_proof_* files found in {dir}"));
+    }
+
+    log_info!("Checking {} inclusion proofs against the published root hash...", files.len());
+
+    let results = files
+        .par_iter()
+        .map(|file| {
+            let inclusion_proof: InclusionProof = read_from_file(file)?;
+            verify_inclusion_against_root(&inclusion_proof, published_root)
+        })
+        .collect::<Vec<_>>();
+
+    Ok(inclusion_report(&files, results))
+}
+
+// a balance per asset of the proof, otherwise the balances can't be read in the proof asset order
+fn check_inclusion_shape(final_proof: &FinalProof, inclusion_proof: &InclusionProof) -> Result<()> {
+    if inclusion_proof.user_balances.len() != final_proof.asset_names.len() {
+        return Err(anyhow::anyhow!(
+            "The inclusion proof has {} balances but the proof has {} assets",
+            inclusion_proof.user_balances.len(),
+            final_proof.asset_names.len()
+        ));
+    }
+    Ok(())
+}
+
+// the digest of the circuit data inside the proof file must be the trusted one (see VerifyOptions::expected_digest)
+fn check_root_circuit_digest(final_proof: &FinalProof, expected_digest: &[u8]) -> Result<()> {
+    let digest = root_circuit_digest(final_proof)?;
+    if digest != expected_digest {
+        return Err(anyhow::anyhow!(
+            "Root circuit digest {} does not match the expected digest {}",
+            bytes_to_hex(&digest),
+            bytes_to_hex(expected_digest)
+        ));
+    }
+    Ok(())
+}
+
+// render an inclusion proof into a summary that can be understood by end users
+// the global proof is checked with the circuit data inside the proof file, so the YES only rules out a forged circuit
+// with the digest published by the exchange (expected_digest, see circuit-digest), otherwise the summary says so
+pub fn inclusion_summary(final_proof: &FinalProof, inclusion_proof: &InclusionProof, expected_digest: Option<&[u8]>) -> String {
+    let mut summary = String::new();
+
+    summary.push_str("======================\n");
+    summary.push_str(&format!(
+        "Snapshot date: {}\n",
+        format_timestamp(final_proof.timestamp).unwrap_or_else(|e| e.to_string())
+    ));
+    summary.push_str(&format!("Account: {}\n", inclusion_proof.user_hash));
+
+    summary.push_str("\n-----Your balances-----\n");
+    let balances = final_proof.asset_names.iter().zip(&final_proof.asset_decimals).zip(&inclusion_proof.user_balances);
+    for ((asset_name, asset_decimals), balance) in balances {
+        let asset_balance = calculate_with_decimals(*balance, asset_decimals.balance_decimals);
+        summary.push_str(&format!("{asset_name}: {}\n", format_amount(&asset_balance)));
+    }
+    for (asset_name, _) in out_of_range_balances(final_proof, inclusion_proof) {
+        summary.push_str(&format!("WARNING: the {asset_name} balance is outside the supported range\n"));
+    }
+
+    summary.push_str("\n-----Result-----\n");
+    let result = check_inclusion_shape(final_proof, inclusion_proof)
+        .and_then(|()| match expected_digest {
+            Some(expected_digest) => check_root_circuit_digest(final_proof, expected_digest),
+            None => Ok(()),
+        })
+        .and_then(|()| check_user_inclusion(final_proof, inclusion_proof));
+    match (result, expected_digest) {
+        (Ok(()), Some(_)) => {
+            summary.push_str("YES - your account is included and the exchange's proof is valid\n");
+        }
+        (Ok(()), None) => {
+            summary.push_str("YES - your account is included and the exchange's proof is valid for the circuit in the proof file\n");
+            summary.push_str("NOTE: pin the root circuit digest published by the exchange (--expected-digest) to check that circuit\n");
+        }
+        (Err(e), _) => {
+            summary.push_str(&format!("NO - the inclusion proof could not be verified ({e})\n"));
+        }
+    }
+    summary.push_str("======================\n");
+
+    summary
 }
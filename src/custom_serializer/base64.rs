@@ -1,25 +1,43 @@
 // this module is a custom serialization to be used with Vec<u8>
 // it is meant to be less disk-consuming than the default serialization format
+// binary formats (not human readable) store the bytes raw instead of base64
 
 use serde::{Deserialize, Serialize};
 use serde::{Deserializer, Serializer};
+use serde_bytes::{ByteBuf, Bytes};
+
+use crate::config::HASH_BYTES;
 
 pub fn serialize<S: Serializer>(v: &Vec<u8>, s: S) -> Result<S::Ok, S::Error> {
+    if !s.is_human_readable() {
+        return Bytes::new(v).serialize(s);
+    }
     let base64 = base64::encode(v);
     String::serialize(&base64, s)
 }
 
 pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+    if !d.is_human_readable() {
+        return ByteBuf::deserialize(d).map(ByteBuf::into_vec);
+    }
     let base64 = String::deserialize(d)?;
     base64::decode(base64.as_bytes()).map_err(serde::de::Error::custom)
 }
 
 pub fn serialize_vec<S: Serializer>(v: &[Vec<u8>], s: S) -> Result<S::Ok, S::Error> {
+    if !s.is_human_readable() {
+        let raw = v.iter().map(|b| Bytes::new(b)).collect::<Vec<_>>();
+        return raw.serialize(s);
+    }
     let base64 = v.iter().map(base64::encode).collect::<Vec<_>>();
     Vec::<String>::serialize(&base64, s)
 }
 
 pub fn deserialize_vec<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Vec<u8>>, D::Error> {
+    if !d.is_human_readable() {
+        let raw = Vec::<ByteBuf>::deserialize(d)?;
+        return Ok(raw.into_iter().map(ByteBuf::into_vec).collect());
+    }
     let base64 = Vec::<String>::deserialize(d)?;
     let mut decoded = Vec::new();
     for b in base64.iter() {
@@ -29,11 +47,19 @@ pub fn deserialize_vec<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Vec<u8>>,
 }
 
 pub fn serialize_option<S: Serializer>(v: &Option<Vec<u8>>, s: S) -> Result<S::Ok, S::Error> {
+    if !s.is_human_readable() {
+        let raw = v.as_ref().map(|b| Bytes::new(b));
+        return raw.serialize(s);
+    }
     let base64 = v.as_ref().map(base64::encode);
     <Option<String>>::serialize(&base64, s)
 }
 
 pub fn deserialize_option<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+    if !d.is_human_readable() {
+        let raw = <Option<ByteBuf>>::deserialize(d)?;
+        return Ok(raw.map(ByteBuf::into_vec));
+    }
     let base64 = <Option<String>>::deserialize(d)?;
     match base64 {
         Some(v) => {
@@ -43,4 +69,25 @@ pub fn deserialize_option<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<
         },
         None => Ok(None),
     }
-}
\ No newline at end of file
+}
+
+// the hash variants also check the decoded length, so a truncated or corrupted hash is rejected while deserializing
+// instead of failing later when it is hashed (HashOut::from_bytes expects exactly HASH_BYTES bytes)
+fn check_hash_len<E: serde::de::Error>(hash: Vec<u8>) -> Result<Vec<u8>, E> {
+    if hash.len() != HASH_BYTES {
+        return Err(E::custom(format!("invalid hash length: {} bytes, expected {HASH_BYTES}", hash.len())));
+    }
+    Ok(hash)
+}
+
+pub fn deserialize_hash<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+    check_hash_len(deserialize(d)?)
+}
+
+pub fn deserialize_hash_vec<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Vec<u8>>, D::Error> {
+    deserialize_vec(d)?.into_iter().map(check_hash_len).collect()
+}
+
+pub fn deserialize_hash_option<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+    deserialize_option(d)?.map(check_hash_len).transpose()
+}
@@ -0,0 +1,268 @@
+// this module handles the on-disk format of the serialized artifacts (final proof, merkle tree, inclusion proofs)
+// json is the interoperable default, bincode and msgpack are compact binary alternatives for internal storage
+// binary files start with a magic header so the format can be detected on read
+
+use anyhow::{Context, Result};
+use bincode::Options;
+use serde::{Serialize, de::DeserializeOwned, de::DeserializeSeed};
+use std::cell::Cell;
+use std::io::{BufRead, BufWriter, ErrorKind, Write};
+use std::time::Duration;
+
+use crate::config::{CURRENT_FORMAT_VERSION, LEGACY_FORMAT_VERSION};
+use crate::log_warning;
+
+// bincode is positional, so the bincode files written before the format_version fields were recorded can't be told
+// apart by their fields: they keep the PORB magic and the versioned ones are written with a new magic
+const BINCODE_MAGIC: &[u8; 4] = b"PORV";
+const LEGACY_BINCODE_MAGIC: &[u8; 4] = b"PORB";
+const MSGPACK_MAGIC: &[u8; 4] = b"PORM";
+
+thread_local! {
+    // set while a legacy bincode file is deserialized, its artifacts have no format_version field to read
+    static LEGACY_BINCODE: Cell<bool> = const { Cell::new(false) };
+}
+
+// marks the current thread as deserializing a legacy bincode file until it is dropped (also on errors and panics)
+struct LegacyBincodeGuard;
+
+impl LegacyBincodeGuard {
+    fn new() -> Self {
+        LEGACY_BINCODE.with(|legacy| legacy.set(true));
+        LegacyBincodeGuard
+    }
+}
+
+impl Drop for LegacyBincodeGuard {
+    fn drop(&mut self) {
+        LEGACY_BINCODE.with(|legacy| legacy.set(false));
+    }
+}
+
+// default number of retries of a transient write error (see write_with_retry)
+pub const DEFAULT_WRITE_RETRIES: u32 = 3;
+// delay before the first retry, doubled on every retry
+const WRITE_RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SerializationFormat {
+    #[default]
+    Json,
+    Bincode,
+    Msgpack,
+}
+
+impl SerializationFormat {
+    // file extension used when writing artifacts in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SerializationFormat::Json => "json",
+            SerializationFormat::Bincode => "bin",
+            SerializationFormat::Msgpack => "msgpack",
+        }
+    }
+
+    // detect the format of serialized data by its magic header (json has no header)
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(BINCODE_MAGIC) || bytes.starts_with(LEGACY_BINCODE_MAGIC) {
+            SerializationFormat::Bincode
+        } else if bytes.starts_with(MSGPACK_MAGIC) {
+            SerializationFormat::Msgpack
+        } else {
+            SerializationFormat::Json
+        }
+    }
+}
+
+pub fn to_bytes<T: Serialize>(value: &T, format: SerializationFormat) -> Result<Vec<u8>> {
+    match format {
+        SerializationFormat::Json => Ok(serde_json::to_vec(value)?),
+        SerializationFormat::Bincode => {
+            let mut bytes = BINCODE_MAGIC.to_vec();
+            bytes.extend(bincode::serialize(value)?);
+            Ok(bytes)
+        }
+        SerializationFormat::Msgpack => {
+            let mut bytes = MSGPACK_MAGIC.to_vec();
+            bytes.extend(rmp_serde::to_vec_named(value)?);
+            Ok(bytes)
+        }
+    }
+}
+
+// same as to_bytes, but serialized straight into the writer (the artifact is never fully buffered in memory)
+pub fn to_writer<T: Serialize, W: Write>(value: &T, format: SerializationFormat, mut writer: W) -> Result<()> {
+    match format {
+        SerializationFormat::Json => serde_json::to_writer(&mut writer, value)?,
+        SerializationFormat::Bincode => {
+            writer.write_all(BINCODE_MAGIC)?;
+            bincode::serialize_into(&mut writer, value)?;
+        }
+        SerializationFormat::Msgpack => {
+            writer.write_all(MSGPACK_MAGIC)?;
+            rmp_serde::encode::write_named(&mut writer, value)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    match SerializationFormat::detect(bytes) {
+        SerializationFormat::Json => Ok(serde_json::from_slice(bytes)?),
+        SerializationFormat::Bincode => {
+            let _legacy = is_legacy_bincode(bytes).then(LegacyBincodeGuard::new);
+            Ok(bincode::deserialize(&bytes[BINCODE_MAGIC.len()..])?)
+        }
+        SerializationFormat::Msgpack => Ok(rmp_serde::from_slice(&bytes[MSGPACK_MAGIC.len()..])?),
+    }
+}
+
+// whether the data is a bincode file written before the format versions were recorded (see LEGACY_BINCODE_MAGIC)
+pub fn is_legacy_bincode(bytes: &[u8]) -> bool {
+    bytes.starts_with(LEGACY_BINCODE_MAGIC)
+}
+
+// same as from_bytes with a deserialize seed, but the artifact is deserialized straight from the reader (the format is
+// detected by peeking its magic header), so a seed can process huge artifacts without holding them in memory
+pub fn from_reader_seed<R, S, T>(mut reader: R, seed: S) -> Result<T>
+where
+    R: BufRead,
+    S: for<'de> DeserializeSeed<'de, Value = T>,
+{
+    let header = reader.fill_buf()?;
+    let format = SerializationFormat::detect(header);
+    let _legacy = is_legacy_bincode(header).then(LegacyBincodeGuard::new);
+    match format {
+        SerializationFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_reader(reader);
+            let value = seed.deserialize(&mut deserializer)?;
+            deserializer.end()?;
+            Ok(value)
+        }
+        SerializationFormat::Bincode => {
+            reader.consume(BINCODE_MAGIC.len());
+            // same options as bincode::deserialize
+            let options = bincode::DefaultOptions::new().with_fixint_encoding().allow_trailing_bytes();
+            Ok(options.deserialize_from_seed(seed, reader)?)
+        }
+        SerializationFormat::Msgpack => {
+            reader.consume(MSGPACK_MAGIC.len());
+            Ok(seed.deserialize(&mut rmp_serde::Deserializer::new(reader))?)
+        }
+    }
+}
+
+// the artifact is streamed into a buffered file, so large merkle trees don't need a second copy in memory
+pub fn write_to_file<T: Serialize>(path: &str, value: &T, format: SerializationFormat) -> Result<()> {
+    let file = std::fs::File::create(path).with_context(|| format!("Failed to write {path}"))?;
+    let mut writer = BufWriter::new(file);
+
+    to_writer(value, format, &mut writer).with_context(|| format!("Failed to write {path}"))?;
+    writer.flush().with_context(|| format!("Failed to write {path}"))
+}
+
+// same as write_to_file, but transient write errors are retried (see write_with_retry)
+pub fn write_to_file_with_retry<T: Serialize>(
+    path: &str,
+    value: &T,
+    format: SerializationFormat,
+    max_retries: u32,
+) -> Result<()> {
+    let bytes = to_bytes(value, format)?;
+    write_with_retry(path, &bytes, max_retries)
+}
+
+// write a file retrying transient errors (e.g. on networked filesystems) with exponential backoff
+// permanent errors, or transient errors after max_retries retries, are returned
+pub fn write_with_retry(path: &str, bytes: &[u8], max_retries: u32) -> Result<()> {
+    let mut retries = 0;
+
+    loop {
+        match std::fs::write(path, bytes) {
+            Ok(()) => return Ok(()),
+            Err(error) if retries < max_retries && is_transient_write_error(&error) => {
+                let delay = WRITE_RETRY_BASE_DELAY * 2u32.saturating_pow(retries);
+                retries += 1;
+
+                log_warning!(
+                    "Transient error while writing {} ({}), retrying in {:?} ({}/{})",
+                    path,
+                    error,
+                    delay,
+                    retries,
+                    max_retries
+                );
+                std::thread::sleep(delay);
+            }
+            Err(error) => return Err(error).with_context(|| format!("Failed to write {path}")),
+        }
+    }
+}
+
+// same as write_with_retry, but the bytes are written to a temporary file next to the path and renamed into place,
+// so an interrupted write (e.g. a full disk) never leaves a truncated file at the path
+pub fn write_atomic_with_retry(path: &str, bytes: &[u8], max_retries: u32) -> Result<()> {
+    let temp_path = format!("{path}.tmp");
+
+    let result = write_with_retry(&temp_path, bytes, max_retries)
+        .and_then(|()| std::fs::rename(&temp_path, path).with_context(|| format!("Failed to write {path}")));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// checks the format_version of an artifact (see CURRENT_FORMAT_VERSION), a newer version can't be read by this binary
+pub fn check_format_version(version: u32) -> Result<()> {
+    if !(LEGACY_FORMAT_VERSION..=CURRENT_FORMAT_VERSION).contains(&version) {
+        return Err(anyhow::anyhow!(
+            "Unsupported format version {version}, this version of the prover reads versions {LEGACY_FORMAT_VERSION} to {CURRENT_FORMAT_VERSION}. Use the version of the prover that wrote the file"
+        ));
+    }
+    Ok(())
+}
+
+// format_version of the files written before it was recorded
+pub fn legacy_format_version() -> u32 {
+    LEGACY_FORMAT_VERSION
+}
+
+// the format_version field is the first one of the artifacts and is checked while deserializing, so every reader
+// (files, bundles, servers) rejects an unsupported version before it fails on the fields it doesn't know
+// legacy bincode files don't have the field (see LEGACY_BINCODE_MAGIC): nothing is read, bincode is positional so the
+// next field is read from the same position, and the artifact (or the nested ones, e.g. in an archive) is LEGACY_FORMAT_VERSION
+pub fn deserialize_format_version<'de, D: serde::Deserializer<'de>>(d: D) -> Result<u32, D::Error> {
+    if LEGACY_BINCODE.with(Cell::get) {
+        return Ok(LEGACY_FORMAT_VERSION);
+    }
+
+    let version = <u32 as serde::Deserialize>::deserialize(d)?;
+    check_format_version(version).map_err(serde::de::Error::custom)?;
+    Ok(version)
+}
+
+// whether the error, or one of its causes, is a full storage (ENOSPC)
+pub fn is_storage_full(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|cause| cause.kind() == ErrorKind::StorageFull)
+}
+
+// errors that may go away by themselves (EINTR, EAGAIN, timeouts, a temporarily full or busy storage)
+fn is_transient_write_error(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::StorageFull
+            | ErrorKind::ResourceBusy
+    )
+}
+
+// reads an artifact from disk, detecting its format automatically
+pub fn read_from_file<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+    from_bytes(&bytes).with_context(|| format!("Failed to deserialize {path}"))
+}
@@ -0,0 +1,300 @@
+// this module reads the ledger json (e.g. private_ledger.json) into a Ledger without building a serde_json::Value of
+// the whole document: each account is deserialized straight into its balances while the file is streamed, so the
+// memory is bounded by the parsed ledger instead of a json tree several times its size
+// the assets and the accounts are sorted by name and hash (the order the json object maps have always given them),
+// so the leaf order, and the nonce positions, don't depend on the order of the keys in the file
+
+use anyhow::{anyhow, Result};
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+
+use crate::types::{Ledger, LedgerDecimals};
+use crate::utils::util::{parse_ledger_balance, parse_ledger_price, parse_ledger_timestamp};
+
+// reads a ledger from a json reader, the reader is buffered here
+pub fn read_ledger<R: Read>(reader: R) -> Result<Ledger> {
+    let mut deserializer = serde_json::Deserializer::from_reader(std::io::BufReader::new(reader));
+    let raw_ledger = LedgerSeed.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+
+    raw_ledger.into_ledger()
+}
+
+// Deserializes the ledger json into a RawLedger, the balances are only parsed once the assets are known (the
+// "accounts" field can come before the "assets" field).
+pub struct LedgerSeed;
+
+impl<'de> DeserializeSeed<'de> for LedgerSeed {
+    type Value = RawLedger;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(LedgerVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+struct LedgerAsset {
+    usdt_decimals: i64,
+    balance_decimals: i64,
+    #[serde(default)]
+    price: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum LedgerField {
+    Assets,
+    Accounts,
+    ReserveBuffer,
+    Timestamp,
+    #[serde(other)]
+    Other,
+}
+
+// the balances of an account (or the reserve buffer) as (asset index in AssetTable, balance), the balances are kept as
+// the json scalar so they are parsed (and their errors reported) only for the assets of the ledger
+type RawBalances = Vec<(usize, serde_json::Value)>;
+
+// asset names seen in the balances, each name is stored once instead of once per account
+#[derive(Default)]
+struct AssetTable {
+    names: Vec<String>,
+    indexes: HashMap<String, usize>,
+}
+
+impl AssetTable {
+    fn index(&mut self, asset_name: String) -> usize {
+        if let Some(index) = self.indexes.get(&asset_name) {
+            return *index;
+        }
+
+        self.names.push(asset_name.clone());
+        self.indexes.insert(asset_name, self.names.len() - 1);
+        self.names.len() - 1
+    }
+}
+
+pub struct RawLedger {
+    assets: Option<BTreeMap<String, LedgerAsset>>,
+    accounts: Option<Vec<(String, RawBalances)>>,
+    reserve_buffer: Option<RawBalances>,
+    timestamp: serde_json::Value,
+    asset_table: AssetTable,
+}
+
+struct LedgerVisitor;
+
+impl<'de> Visitor<'de> for LedgerVisitor {
+    type Value = RawLedger;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a ledger")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut asset_table = AssetTable::default();
+        let (mut assets, mut accounts, mut reserve_buffer) = (None, None, None);
+        let mut timestamp = serde_json::Value::Null;
+
+        while let Some(field) = map.next_key()? {
+            match field {
+                LedgerField::Assets => assets = Some(map.next_value()?),
+                LedgerField::Accounts => accounts = Some(map.next_value_seed(AccountsSeed { asset_table: &mut asset_table })?),
+                LedgerField::ReserveBuffer => {
+                    reserve_buffer = map.next_value_seed(OptionalBalancesSeed { asset_table: &mut asset_table })?
+                }
+                LedgerField::Timestamp => timestamp = map.next_value()?,
+                LedgerField::Other => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(RawLedger { assets, accounts, reserve_buffer, timestamp, asset_table })
+    }
+}
+
+// the "accounts" object, user hash -> balances
+struct AccountsSeed<'s> {
+    asset_table: &'s mut AssetTable,
+}
+
+impl<'de> DeserializeSeed<'de> for AccountsSeed<'_> {
+    type Value = Vec<(String, RawBalances)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for AccountsSeed<'_> {
+    type Value = Vec<(String, RawBalances)>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an object of accounts")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut accounts = Vec::with_capacity(map.size_hint().unwrap_or(0));
+
+        while let Some(hash) = map.next_key::<String>()? {
+            let balances = map.next_value_seed(BalancesSeed { asset_table: &mut *self.asset_table })?;
+            accounts.push((hash, balances));
+        }
+
+        Ok(accounts)
+    }
+}
+
+// the "reserve_buffer" object, null is the same as no reserve buffer
+struct OptionalBalancesSeed<'s> {
+    asset_table: &'s mut AssetTable,
+}
+
+impl<'de> DeserializeSeed<'de> for OptionalBalancesSeed<'_> {
+    type Value = Option<RawBalances>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for OptionalBalancesSeed<'_> {
+    type Value = Option<RawBalances>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an object of balances or null")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        BalancesSeed { asset_table: self.asset_table }.deserialize(deserializer).map(Some)
+    }
+}
+
+// an object of balances, asset name -> balance
+struct BalancesSeed<'s> {
+    asset_table: &'s mut AssetTable,
+}
+
+impl<'de> DeserializeSeed<'de> for BalancesSeed<'_> {
+    type Value = RawBalances;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for BalancesSeed<'_> {
+    type Value = RawBalances;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an object of balances")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut balances = Vec::with_capacity(map.size_hint().unwrap_or(0));
+
+        while let Some(asset_name) = map.next_key::<String>()? {
+            let balance = map.next_value::<serde_json::Value>()?;
+            balances.push((self.asset_table.index(asset_name), balance));
+        }
+
+        Ok(balances)
+    }
+}
+
+impl RawLedger {
+    // parses the balances in the order of the assets, a balance of an asset that is not in the assets field is ignored
+    pub fn into_ledger(self) -> Result<Ledger> {
+        let assets = self.assets.ok_or_else(|| anyhow!("The ledger has no assets field"))?;
+        let mut accounts = self.accounts.ok_or_else(|| anyhow!("The ledger has no accounts field"))?;
+
+        let mut asset_names = Vec::with_capacity(assets.len());
+        let mut asset_prices = Vec::with_capacity(assets.len());
+        let mut asset_decimals = Vec::with_capacity(assets.len());
+
+        for (asset_name, asset) in assets {
+            let price = parse_ledger_price(&asset.price, asset.usdt_decimals)
+                .map_err(|e| anyhow!("Asset {asset_name}: {e}"))?;
+
+            asset_names.push(asset_name);
+            asset_prices.push(price);
+            asset_decimals.push(LedgerDecimals {
+                usdt_decimals: asset.usdt_decimals,
+                balance_decimals: asset.balance_decimals,
+            });
+        }
+
+        // position in asset_names of each asset of the table
+        let positions = self
+            .asset_table
+            .names
+            .iter()
+            .map(|asset_name| asset_names.binary_search(asset_name).ok())
+            .collect::<Vec<_>>();
+        let order_balances = |raw_balances: RawBalances| {
+            let mut balances = vec![serde_json::Value::Null; asset_names.len()];
+            for (index, balance) in raw_balances {
+                if let Some(position) = positions[index] {
+                    balances[position] = balance;
+                }
+            }
+            balances
+        };
+
+        // the accounts are sorted by hash, a repeated hash would be two leaves of the same user
+        accounts.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        if let Some(pair) = accounts.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(anyhow!("Duplicate account {}", pair[0].0));
+        }
+
+        let mut hashes = Vec::with_capacity(accounts.len());
+        let mut account_balances = Vec::with_capacity(accounts.len());
+
+        for (hash, raw_balances) in accounts {
+            let balances = order_balances(raw_balances)
+                .iter()
+                .zip(&asset_names)
+                .map(|(balance, asset_name)| {
+                    parse_ledger_balance(balance).map_err(|e| anyhow!("Account {hash}, asset {asset_name}: {e}"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            hashes.push(hash);
+            account_balances.push(balances);
+        }
+
+        // a missing reserve buffer (or asset of the reserve buffer) is a zero balance
+        let reserve_buffer = order_balances(self.reserve_buffer.unwrap_or_default())
+            .iter()
+            .zip(&asset_names)
+            .map(|(balance, asset_name)| match balance {
+                serde_json::Value::Null => Ok(0),
+                balance => parse_ledger_balance(balance).map_err(|e| anyhow!("Reserve buffer, asset {asset_name}: {e}")),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let timestamp = parse_ledger_timestamp(&self.timestamp)?;
+
+        Ok(Ledger {
+            asset_names,
+            hashes,
+            account_balances,
+            asset_prices,
+            asset_decimals,
+            reserve_buffer,
+            dust: None,
+            timestamp,
+        })
+    }
+}
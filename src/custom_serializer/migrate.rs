@@ -0,0 +1,168 @@
+// this module upgrades the artifacts written by previous versions of the prover to the current schema
+// (CURRENT_FORMAT_VERSION), one version at a time: an artifact of version n is upgraded to n + 1 by its upgrade
+// function for version n, so each function only knows the schemas of two consecutive versions
+// the previous schemas are read with the current types (their new fields have serde defaults), the upgrade functions
+// fill the new fields with sensible values, so bumping CURRENT_FORMAT_VERSION needs one new function per artifact
+// the proofs and hashes are never changed, so migrating only makes an artifact readable again: it verifies if (and only
+// if) it was written by a prover with the same circuits and leaf hashes. The 1.x provers had another batch circuit and
+// the 1.x and 2.x provers hashed the leafs without the user hash, so their final proofs (identified by prover_version)
+// are rejected. The merkle trees and inclusion proofs don't record the prover version, the migrate command warns about
+// them instead
+// the legacy bincode files (PORB header, without the format_version fields) are read as version 1 by from_bytes and
+// rewritten with the versioned bincode header, so they are readable again by the other commands
+
+use anyhow::{anyhow, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::format::{check_format_version, from_bytes, to_bytes, write_atomic_with_retry, SerializationFormat};
+use crate::config::CURRENT_FORMAT_VERSION;
+use crate::merkle_tree::MerkleTree;
+use crate::types::{FinalProof, InclusionProof};
+
+// prover versions whose leaf hashes differ from this version (the user hash is part of the leaf since v3.0.0)
+const INCOMPATIBLE_PROVER_VERSIONS: &[&str] = &["v1.", "v2."];
+
+pub trait Migrate: Sized {
+    // name of the artifact in the messages
+    const NAME: &'static str;
+
+    fn format_version(&self) -> u32;
+
+    // upgrades an artifact of the given version to the next version
+    fn upgrade(self, from_version: u32) -> Result<Self>;
+}
+
+// upgrades an artifact of any supported version to CURRENT_FORMAT_VERSION (a current artifact is returned as is)
+pub fn migrate<T: Migrate>(mut artifact: T) -> Result<T> {
+    let mut version = artifact.format_version();
+    check_format_version(version)?;
+
+    while version < CURRENT_FORMAT_VERSION {
+        artifact = artifact
+            .upgrade(version)
+            .with_context(|| format!("Failed to migrate the {} from format version {version}", T::NAME))?;
+        if artifact.format_version() != version + 1 {
+            return Err(anyhow!("The {} migration from format version {version} did not upgrade it", T::NAME));
+        }
+        version += 1;
+    }
+
+    Ok(artifact)
+}
+
+impl Migrate for FinalProof {
+    const NAME: &'static str = "final proof";
+
+    fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    fn upgrade(self, from_version: u32) -> Result<Self> {
+        match from_version {
+            1 => final_proof_v1_to_v2(self),
+            _ => Err(anyhow!("No migration of the final proof from format version {from_version}")),
+        }
+    }
+}
+
+impl Migrate for MerkleTree {
+    const NAME: &'static str = "merkle tree";
+
+    fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    fn upgrade(self, from_version: u32) -> Result<Self> {
+        match from_version {
+            1 => Ok(merkle_tree_v1_to_v2(self)),
+            _ => Err(anyhow!("No migration of the merkle tree from format version {from_version}")),
+        }
+    }
+}
+
+impl Migrate for InclusionProof {
+    const NAME: &'static str = "inclusion proof";
+
+    fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    fn upgrade(self, from_version: u32) -> Result<Self> {
+        match from_version {
+            1 => Ok(inclusion_proof_v1_to_v2(self)),
+            _ => Err(anyhow!("No migration of the inclusion proof from format version {from_version}")),
+        }
+    }
+}
+
+// version 2 only records the format version, the fields added before it (reserve buffer, dust, annotations and the
+// detached verifier data digest) already default to "none"
+// a proof of the 1.x or 2.x prover would be marked as version 2 but could never verify (other leaf hashes, and another
+// batch circuit for 1.x)
+fn final_proof_v1_to_v2(mut final_proof: FinalProof) -> Result<FinalProof> {
+    let prover_version = &final_proof.prover_version;
+    if INCOMPATIBLE_PROVER_VERSIONS.iter().any(|prefix| prover_version.starts_with(prefix)) {
+        return Err(anyhow!(
+            "The final proof was generated by plonky2_por {prover_version}, whose circuits and leaf hashes differ from \
+             this version: verify it with the {prover_version} binary or prove the ledger again"
+        ));
+    }
+
+    final_proof.format_version = 2;
+    Ok(final_proof)
+}
+
+// version 2 records the format version, the level arities of older trees are filled from the tree structure
+// the real leaf count and the batch reserves can't be recovered from the tree (see rebuild-merkle-tree)
+fn merkle_tree_v1_to_v2(mut merkle_tree: MerkleTree) -> MerkleTree {
+    merkle_tree.level_arities = merkle_tree.level_arities();
+    merkle_tree.format_version = 2;
+    merkle_tree
+}
+
+fn inclusion_proof_v1_to_v2(mut inclusion_proof: InclusionProof) -> InclusionProof {
+    inclusion_proof.format_version = 2;
+    inclusion_proof
+}
+
+// result of migrate_file
+pub struct MigratedFile {
+    pub artifact: &'static str,
+    pub from_version: u32,
+    pub format: SerializationFormat,
+}
+
+// migrates a serialized artifact and writes it in the current schema (keeping its serialization format)
+// the kind of artifact is detected by trying to read it as a final proof, a merkle tree and an inclusion proof
+// the output is written atomically, so the input file can be rewritten in place
+pub fn migrate_file(path: &str, output: &str) -> Result<MigratedFile> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+    let format = SerializationFormat::detect(&bytes);
+
+    let mut errors = Vec::new();
+    for try_migrate in [migrate_bytes::<FinalProof>, migrate_bytes::<MerkleTree>, migrate_bytes::<InclusionProof>] {
+        match try_migrate(&bytes, format) {
+            Ok((artifact, from_version, migrated)) => {
+                write_atomic_with_retry(output, &migrated, 0)?;
+                return Ok(MigratedFile { artifact, from_version, format });
+            }
+            Err(error) => errors.push(format!("{error:#}")),
+        }
+    }
+
+    Err(anyhow!(
+        "{path} is not a final proof, merkle tree or inclusion proof of a supported version ({})",
+        errors.join("; ")
+    ))
+}
+
+// returns the name of the artifact, its format version and the migrated artifact serialized in the same format
+fn migrate_bytes<T: Migrate + Serialize + DeserializeOwned>(
+    bytes: &[u8],
+    format: SerializationFormat,
+) -> Result<(&'static str, u32, Vec<u8>)> {
+    let artifact: T = from_bytes(bytes).with_context(|| format!("not a {}", T::NAME))?;
+    let from_version = artifact.format_version();
+
+    Ok((T::NAME, from_version, to_bytes(&migrate(artifact)?, format)?))
+}
@@ -1 +1,5 @@
-pub mod base64;
\ No newline at end of file
+pub mod base64;
+pub mod format;
+pub mod ledger;
+pub mod migrate;
+pub mod storage;
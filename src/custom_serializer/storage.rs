@@ -0,0 +1,226 @@
+// this module abstracts where the ledger is read from and where the proofs are written to
+// locations are local paths by default, with the s3 feature enabled s3://bucket/key locations are read and written
+// through the object store (credentials and region are taken from the standard AWS environment variables)
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Read};
+
+use super::format::{to_bytes, write_to_file, SerializationFormat};
+
+const S3_SCHEME: &str = "s3://";
+
+// magic number at the start of every zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+pub trait ProofStore {
+    // opens a reader over the object, the object is streamed so large ledgers are never buffered in memory at once
+    fn read(&self, key: &str) -> Result<Box<dyn Read + Send>>;
+
+    // writes (or overwrites) the whole object
+    fn write(&self, key: &str, bytes: &[u8]) -> Result<()>;
+
+    // whether the object exists (e.g. to refuse overwriting a previous proof)
+    fn exists(&self, key: &str) -> Result<bool>;
+}
+
+// the local filesystem, keys are file paths
+pub struct LocalStore;
+
+impl ProofStore for LocalStore {
+    fn read(&self, key: &str) -> Result<Box<dyn Read + Send>> {
+        let file = std::fs::File::open(key).with_context(|| format!("Failed to read {key}"))?;
+        Ok(Box::new(file))
+    }
+
+    fn write(&self, key: &str, bytes: &[u8]) -> Result<()> {
+        std::fs::write(key, bytes).with_context(|| format!("Failed to write {key}"))
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        std::fs::exists(key).with_context(|| format!("Failed to check if {key} exists"))
+    }
+}
+
+#[cfg(feature = "s3")]
+pub use s3::S3Store;
+
+#[cfg(feature = "s3")]
+mod s3 {
+    use super::ProofStore;
+    use anyhow::{Context, Result};
+    use futures::stream::{BoxStream, StreamExt};
+    use object_store::{aws::AmazonS3, aws::AmazonS3Builder, path::Path, ObjectStore, PutPayload};
+    use std::io::Read;
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+
+    // an s3 bucket, keys are object keys inside the bucket
+    pub struct S3Store {
+        bucket: String,
+        store: AmazonS3,
+        // the object store is async, the rest of the prover is not
+        runtime: Arc<Runtime>,
+    }
+
+    impl S3Store {
+        pub fn new(bucket: &str) -> Result<Self> {
+            let store = AmazonS3Builder::from_env()
+                .with_bucket_name(bucket)
+                .build()
+                .with_context(|| format!("Failed to configure the s3 bucket {bucket}"))?;
+
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .context("Failed to start the object store runtime")?;
+
+            Ok(Self {
+                bucket: bucket.to_string(),
+                store,
+                runtime: Arc::new(runtime),
+            })
+        }
+    }
+
+    impl ProofStore for S3Store {
+        fn read(&self, key: &str) -> Result<Box<dyn Read + Send>> {
+            let result = self
+                .runtime
+                .block_on(self.store.get(&Path::from(key)))
+                .with_context(|| format!("Failed to read s3://{}/{key}", self.bucket))?;
+
+            Ok(Box::new(StreamReader {
+                runtime: self.runtime.clone(),
+                stream: result.into_stream(),
+                chunk: Vec::new(),
+                position: 0,
+            }))
+        }
+
+        fn write(&self, key: &str, bytes: &[u8]) -> Result<()> {
+            self.runtime
+                .block_on(self.store.put(&Path::from(key), PutPayload::from(bytes.to_vec())))
+                .with_context(|| format!("Failed to write s3://{}/{key}", self.bucket))?;
+
+            Ok(())
+        }
+
+        fn exists(&self, key: &str) -> Result<bool> {
+            match self.runtime.block_on(self.store.head(&Path::from(key))) {
+                Ok(_) => Ok(true),
+                Err(object_store::Error::NotFound { .. }) => Ok(false),
+                Err(e) => Err(e).with_context(|| format!("Failed to check if s3://{}/{key} exists", self.bucket)),
+            }
+        }
+    }
+
+    // blocking reader over the chunks of an object, only the current chunk is kept in memory
+    struct StreamReader {
+        runtime: Arc<Runtime>,
+        stream: BoxStream<'static, object_store::Result<bytes::Bytes>>,
+        chunk: Vec<u8>,
+        position: usize,
+    }
+
+    impl Read for StreamReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            while self.position == self.chunk.len() {
+                match self.runtime.block_on(self.stream.next()) {
+                    Some(Ok(chunk)) => {
+                        self.chunk = chunk.into();
+                        self.position = 0;
+                    }
+                    Some(Err(error)) => return Err(std::io::Error::other(error)),
+                    None => return Ok(0),
+                }
+            }
+
+            let len = buf.len().min(self.chunk.len() - self.position);
+            buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+            self.position += len;
+
+            Ok(len)
+        }
+    }
+}
+
+// splits an s3://bucket/key location into its bucket and key (None for local paths)
+pub fn parse_s3_location(location: &str) -> Result<Option<(&str, &str)>> {
+    let Some(path) = location.strip_prefix(S3_SCHEME) else {
+        return Ok(None);
+    };
+
+    match path.split_once('/') {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => Ok(Some((bucket, key))),
+        _ => Err(anyhow!("Invalid s3 location {location}, expected s3://bucket/key")),
+    }
+}
+
+// returns the store holding the location and the key of the location inside it
+pub fn store_for_location(location: &str) -> Result<(Box<dyn ProofStore>, &str)> {
+    match parse_s3_location(location)? {
+        None => Ok((Box::new(LocalStore), location)),
+        #[cfg(feature = "s3")]
+        Some((bucket, key)) => Ok((Box::new(S3Store::new(bucket)?), key)),
+        #[cfg(not(feature = "s3"))]
+        Some(_) => Err(anyhow!(
+            "Cannot access {location}: s3 locations require building with the s3 feature"
+        )),
+    }
+}
+
+// opens a streaming reader over a local path or an s3://bucket/key location
+pub fn read_location(location: &str) -> Result<Box<dyn Read + Send>> {
+    let (store, key) = store_for_location(location)?;
+    store.read(key)
+}
+
+// same as read_location, but zstd compressed input (detected by its magic number, not the extension) is decompressed
+// while it is read, so the decompression is pipelined with the parsing and the decompressed data is never held as a whole
+pub fn read_location_decompressed(location: &str) -> Result<Box<dyn Read + Send>> {
+    let mut reader = BufReader::new(read_location(location)?);
+    let is_zstd = reader
+        .fill_buf()
+        .with_context(|| format!("Failed to read {location}"))?
+        .starts_with(&ZSTD_MAGIC);
+
+    if is_zstd {
+        let decoder = zstd::stream::read::Decoder::with_buffer(reader)
+            .with_context(|| format!("Failed to decompress {location}"))?;
+        Ok(Box::new(decoder))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+// writes to a local path or an s3://bucket/key location
+pub fn write_location(location: &str, bytes: &[u8]) -> Result<()> {
+    let (store, key) = store_for_location(location)?;
+    store.write(key, bytes)
+}
+
+// whether a local path or an s3://bucket/key location exists
+pub fn location_exists(location: &str) -> Result<bool> {
+    let (store, key) = store_for_location(location)?;
+    store.exists(key)
+}
+
+// serializes an artifact to a local path or an s3://bucket/key location
+// local files are streamed (see write_to_file), objects are uploaded in a single request
+pub fn write_artifact<T: Serialize>(location: &str, value: &T, format: SerializationFormat) -> Result<()> {
+    if parse_s3_location(location)?.is_none() {
+        return write_to_file(location, value, format);
+    }
+
+    write_location(location, &to_bytes(value, format)?)
+}
+
+// joins a file name to a directory or an s3://bucket/prefix location
+pub fn join_location(dir: &str, file_name: &str) -> String {
+    if dir.starts_with(S3_SCHEME) {
+        format!("{}/{file_name}", dir.trim_end_matches('/'))
+    } else {
+        std::path::Path::new(dir).join(file_name).to_string_lossy().into_owned()
+    }
+}
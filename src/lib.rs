@@ -19,59 +19,159 @@ pub use std::time::Instant;
 // Re-export internal types used across modules
 pub use circuits::recursive_circuit::RecursiveCircuit;
 pub use utils::logger::format_error;
+pub use utils::signing::{read_signing_key, sign_commitment, signing_key_from_bytes, verify_commitment_signature};
+pub use utils::util::{
+    bytes_to_hex, check_unique_asset_names, compute_leaf_hash, hex_to_bytes, scale_price, unscale_price,
+    verifier_data_digest,
+};
 
 // Re-export commonly used types from types module
 pub use types::{
-    FinalProof, InclusionProof, Ledger, LedgerDecimals, MerkleProof,
+    AccountNonces, AccountNoncesRef, AssetChanges, AssetSummary, AssetValuation, BundleIndex, DustAggregate, FinalProof, InclusionProof, Ledger,
+    LedgerDecimals, MerkleProof, PaddingReport, PriceBounds, ProofAnnotations, ProofArchive, ProofPublicInputs, ProofSummary,
+    ProverConfig,
+};
+
+// Re-export serialization format helpers
+pub use custom_serializer::format::{
+    check_format_version, from_reader_seed, read_from_file, write_to_file, write_to_file_with_retry, SerializationFormat, DEFAULT_WRITE_RETRIES,
 };
 
+// Re-export the streaming ledger reader
+pub use custom_serializer::ledger::{read_ledger, LedgerSeed};
+
+// Re-export the migrations of the artifacts written by previous versions
+pub use custom_serializer::migrate::{migrate, migrate_file, Migrate, MigratedFile};
+
+// Re-export the proof storage (local filesystem, or s3 with the s3 feature)
+pub use custom_serializer::storage::{LocalStore, ProofStore};
+#[cfg(feature = "s3")]
+pub use custom_serializer::storage::S3Store;
+
+// Re-export the HTTP inclusion server
+#[cfg(feature = "http-server")]
+pub use core::http_server::serve_http;
+
 // Re-export merkle tree types
-pub use merkle_tree::{MerkleTree, Node};
+pub use merkle_tree::{compute_merkle_root, CompactMerkleTree, MerkleTree, Node, StreamedMerkleTree, VerifyingTreeSeed};
 
 // Re-export config constants
-pub use config::{BATCH_SIZE, RECURSIVE_SIZE, C, D, F, H};
+pub use config::{
+    BATCH_SIZE, CURRENT_FORMAT_VERSION, DEFAULT_GROUP_PREFIX_LEN, DEFAULT_INCLUSION_PROOFS_DIR, DEFAULT_MAX_BUNDLE_WRITERS, DEFAULT_MAX_TREE_DEPTH,
+    DEFAULT_VERIFY_SAMPLE_SIZE, MAX_ACCOUNT_BALANCE, MAX_ACCOUNT_BALANCE_BITS, MAX_ACCOUNT_EQUITY, MAX_BATCH_TOTAL_BITS, MAX_RANGE_CHECKED_BALANCE, MIN_RECURSIVE_SIZE,
+    MAX_RECURSIVE_SIZE, LEGACY_FORMAT_VERSION, RECURSIVE_SIZE, USER_HASH_CHUNK_HEX_LEN, C, D, F, H,
+};
 
 
 use anyhow::Result;
+#[cfg(feature = "prover")]
 use crate::core::prover::*;
 use crate::core::verifier::{verify_root, verify_user_inclusion};
+#[cfg(feature = "prover")]
+pub use crate::core::prover::{
+    check_inclusion_sample, check_unique_user_hashes, nonces_by_hash, prove_inclusion_all_with, read_bundle_index, read_bundled_inclusion_proof,
+    prove_user_inclusion_compact, read_nonces, resolve_nonces, resolve_user_nonce, check_user_nonces_count,
+};
+#[cfg(feature = "fixtures")]
+pub use crate::core::test_vector::{
+    generate_test_vector, seeded_nonces, test_vector_ledger, TestVector, TEST_VECTOR_FILE, TEST_VECTOR_NONCE_SEED,
+};
+pub use crate::core::verifier::{
+    bench_verify, check_batch_reserves, check_circuit_sizes, check_final_proof_shape, check_proof_shape, check_prover_version, check_tree_depth,
+    coverage_ratios, AssetCoverage,
+    duration_stats, expected_root_circuit_digest, out_of_range_balances, root_circuit_digest, verify_global_proof,
+    verify_inclusion_against_root, verify_proof_only, verify_root_streaming, verify_tree_against_root, VerifyBenchmark,
+    VerifyOptions,
+};
 use crate::merkle_tree::*;
+use crate::custom_serializer::storage::read_location_decompressed;
+#[cfg(feature = "prover")]
+use crate::custom_serializer::storage::{join_location, write_artifact};
 use crate::types::*;
 use crate::utils::logger::*;
 
+// Helper function to write the global proof outputs into the output directory (if any)
+// the nonces are required to generate inclusion proofs later, so warn if they are not persisted
+#[cfg(feature = "prover")]
+fn write_prove_outputs(
+    output_dir: Option<&str>,
+    final_proof: &FinalProof,
+    merkle_tree: &MerkleTree,
+    account_nonces: &[u64],
+) -> Result<()> {
+    let Some(output_dir) = output_dir else {
+        log_warning!(
+            "No output directory provided: make sure to persist the returned nonces, otherwise it won't be possible to generate inclusion proofs for this proof"
+        );
+        return Ok(());
+    };
+
+    // the output directory can also be an s3://bucket/prefix location
+    let json = SerializationFormat::Json;
+    write_artifact(&join_location(output_dir, "final_proof.json"), final_proof, json)?;
+    write_artifact(&join_location(output_dir, "merkle_tree.json"), merkle_tree, json)?;
+    write_artifact(&join_location(output_dir, "private_nonces.json"), &account_nonces, json)?;
+
+    Ok(())
+}
+
 /// Feature: Prove - Generates a global proof of reserves from a ledger file
+#[cfg(feature = "prover")]
 pub fn prove_from_file(ledger_file_path: &str, output_dir: Option<&str>) -> Result<(FinalProof, MerkleTree, Vec<u64>)> {
     // log_info!("Reading and deserializing ledger...");
     let ledger = get_ledger_values_from_file(ledger_file_path);
     // log_success!("Ledger read successfully!");
+    ledger.validate_prices(&PriceBounds::default())?;
 
     // log_info!("Starting to prove reserves... This might take some hours depending on the ledger size...");
     let (final_proof, merkle_tree, account_nonces) = prove_global(ledger)?;
-    
-    if let Some(output_dir) = output_dir {
-        std::fs::write(format!("{}/final_proof.json", output_dir), serde_json::to_string(&final_proof)?)?;
-        std::fs::write(format!("{}/merkle_tree.json", output_dir), serde_json::to_string(&merkle_tree)?)?;
-        std::fs::write(format!("{}/private_nonces.json", output_dir), serde_json::to_string(&account_nonces)?)?;
-    }
+
+    write_prove_outputs(output_dir, &final_proof, &merkle_tree, &account_nonces)?;
 
     Ok((final_proof, merkle_tree, account_nonces))
 }
 
 /// Feature: Prove - Generates a global proof of reserves from ledger data
+#[cfg(feature = "prover")]
 pub fn prove_from_data(ledger: Ledger, output_dir: Option<&str>) -> Result<(FinalProof, MerkleTree, Vec<u64>)> {
+    ledger.validate_prices(&PriceBounds::default())?;
+
     // log_info!("Starting to prove reserves... This might take some hours depending on the ledger size...");
     let (final_proof, merkle_tree, account_nonces) = prove_global(ledger)?;
-    
-    if let Some(output_dir) = output_dir {
-        std::fs::write(format!("{}/final_proof.json", output_dir), serde_json::to_string(&final_proof)?)?;
-        std::fs::write(format!("{}/merkle_tree.json", output_dir), serde_json::to_string(&merkle_tree)?)?;
-        std::fs::write(format!("{}/private_nonces.json", output_dir), serde_json::to_string(&account_nonces)?)?;
-    }
+
+    write_prove_outputs(output_dir, &final_proof, &merkle_tree, &account_nonces)?;
 
     Ok((final_proof, merkle_tree, account_nonces))
 }
 
+/// Feature: Rebuild merkle tree - Regenerates the merkle tree of a global proof from the ledger and nonces files
+/// Only the hashing is re-run (no proving), the rebuilt root hash is checked against the final proof
+#[cfg(feature = "prover")]
+pub fn rebuild_merkle_tree_from_files(
+    ledger_file: &str,
+    nonces_file: &str,
+    final_proof_file: &str,
+    output_file: Option<&str>,
+) -> Result<MerkleTree> {
+    let final_proof = FinalProof::read(final_proof_file)?;
+    let nonces: Vec<u64> = read_from_file(nonces_file)?;
+    let mut ledger = get_ledger_values_from_file(ledger_file);
+
+    assert_config(&final_proof);
+    ledger.apply_proof_dust(&final_proof)?;
+
+    let merkle_tree = rebuild_merkle_tree(ledger, &nonces, &final_proof.prover_config())?;
+    check_merkle_root(&final_proof, &merkle_tree)?;
+
+    if let Some(output_file) = output_file {
+        std::fs::write(output_file, serde_json::to_string(&merkle_tree)?)?;
+    }
+
+    Ok(merkle_tree)
+}
+
 /// Feature: Prove inclusion (single file) - Generates an inclusion proof for a specific user from files
+#[cfg(feature = "prover")]
 pub fn prove_inclusion_from_files(
     user_hash: &str,
     merkle_tree_file: &str,
@@ -80,14 +180,15 @@ pub fn prove_inclusion_from_files(
     ledger_file: &str,
     output_file: Option<&str>,
 ) -> Result<InclusionProof> {
-    let merkle_tree: MerkleTree = serde_json::from_str(&std::fs::read_to_string(merkle_tree_file)?)?;
-    let final_proof: FinalProof = serde_json::from_str(&std::fs::read_to_string(final_proof_file)?)?;
-    let nonces: Vec<u64> = serde_json::from_str(&std::fs::read_to_string(nonces_file)?)?;
-    let ledger = get_ledger_values_from_file(ledger_file);
+    let merkle_tree: MerkleTree = read_from_file(merkle_tree_file)?;
+    let final_proof = FinalProof::read(final_proof_file)?;
+    let nonces = read_nonces(nonces_file)?;
+    let mut ledger = get_ledger_values_from_file(ledger_file);
     
     assert_config(&final_proof);
+    ledger.apply_proof_dust(&final_proof)?;
 
-    let inclusion_proof = prove_user_inclusion_by_hash(user_hash.to_string(), &merkle_tree, &nonces, &ledger)?;
+    let inclusion_proof = prove_single_user_inclusion(user_hash, &merkle_tree, (&nonces).into(), &ledger)?;
 
     if let Some(output_file) = output_file {
         std::fs::write(output_file, serde_json::to_string(&inclusion_proof)?)?;
@@ -97,17 +198,95 @@ pub fn prove_inclusion_from_files(
 }
 
 /// Feature: Prove inclusion (single user) - Generates an inclusion proof for a specific user from data
-pub fn prove_inclusion_from_data(
+/// If the proof aggregated dust accounts, the ledger must be aggregated the same way (see Ledger::apply_proof_dust)
+/// The nonces are borrowed, either positional (&[u64], &Vec<u64>) or keyed by user hash (&HashMap<String, u64>), only
+/// the nonce of the user is read
+#[cfg(feature = "prover")]
+pub fn prove_inclusion_from_data<'a>(
     user_hash: &str,
     merkle_tree: &MerkleTree,
     final_proof: &FinalProof,
-    nonces: &[u64],
+    nonces: impl Into<AccountNoncesRef<'a>>,
     ledger: &Ledger,
     output_file: Option<&str>,
 ) -> Result<InclusionProof> {
     assert_config(final_proof);
 
-    let inclusion_proof = prove_user_inclusion_by_hash(user_hash.to_string(), merkle_tree, nonces, ledger)?;
+    let inclusion_proof = prove_single_user_inclusion(user_hash, merkle_tree, nonces.into(), ledger)?;
+
+    if let Some(output_file) = output_file {
+        std::fs::write(output_file, serde_json::to_string(&inclusion_proof)?)?;
+    }
+
+    Ok(inclusion_proof)
+}
+
+// inclusion proof of one user, the nonces are checked but only the nonce of the user is read (see resolve_user_nonce)
+#[cfg(feature = "prover")]
+fn prove_single_user_inclusion(
+    user_hash: &str,
+    merkle_tree: &MerkleTree,
+    nonces: AccountNoncesRef<'_>,
+    ledger: &Ledger,
+) -> Result<InclusionProof> {
+    let user_index = ledger
+        .hashes
+        .iter()
+        .position(|hash| hash == user_hash)
+        .ok_or_else(|| anyhow::anyhow!("User hash not found in ledger"))?;
+    let nonce = resolve_user_nonce(nonces, user_index, ledger, merkle_tree)?;
+
+    prove_user_inclusion(user_index, user_hash.to_string(), nonce, merkle_tree, ledger)
+}
+
+/// Feature: Prove inclusion (by index) - Generates an inclusion proof for the user at the given ledger index
+/// Avoids the linear hash lookup for callers that already know the user indices
+#[cfg(feature = "prover")]
+pub fn prove_inclusion_by_index(
+    index: usize,
+    merkle_tree: &MerkleTree,
+    nonces: &[u64],
+    ledger: &Ledger,
+) -> Result<InclusionProof> {
+    if index >= ledger.hashes.len() {
+        return Err(anyhow::anyhow!(
+            "User index {} out of bounds (ledger has {} accounts)",
+            index,
+            ledger.hashes.len()
+        ));
+    }
+    check_user_nonces_count(nonces, ledger)?;
+
+    prove_user_inclusion(index, ledger.hashes[index].clone(), nonces[index], merkle_tree, ledger)
+}
+
+/// Feature: Prove inclusion (without the merkle tree) - Generates an inclusion proof for a specific user from the ledger and nonces files
+/// Only the path of the user is rebuilt (low-memory tree, merkle_tree.<ext> is not read) and checked against the published root
+/// The published root defaults to the root hash of the final proof, the nonces file must be positional (one nonce per leaf)
+#[cfg(feature = "prover")]
+pub fn prove_inclusion_compact_from_files(
+    user_hash: &str,
+    final_proof_file: &str,
+    nonces_file: &str,
+    ledger_file: &str,
+    published_root: Option<&[u8]>,
+    output_file: Option<&str>,
+) -> Result<InclusionProof> {
+    let final_proof = FinalProof::read(final_proof_file)?;
+    let nonces: Vec<u64> = read_from_file(nonces_file)?;
+    let mut ledger = get_ledger_values_from_file(ledger_file);
+
+    assert_config(&final_proof);
+    ledger.apply_proof_dust(&final_proof)?;
+
+    let published_root = published_root.map(<[u8]>::to_vec).unwrap_or_else(|| final_proof.root_hash());
+    let inclusion_proof = prove_user_inclusion_compact(
+        user_hash.to_string(),
+        ledger,
+        &nonces,
+        &final_proof.prover_config(),
+        &published_root,
+    )?;
 
     if let Some(output_file) = output_file {
         std::fs::write(output_file, serde_json::to_string(&inclusion_proof)?)?;
@@ -117,121 +296,115 @@ pub fn prove_inclusion_from_data(
 }
 
 /// Feature: Prove inclusion (all files batched) - Generates inclusion proofs for all users in batches from files
+#[cfg(feature = "prover")]
 pub fn prove_inclusion_batched_from_files(
     merkle_tree_file: &str,
     final_proof_file: &str,
     nonces_file: &str,
     ledger_file: &str,
 ) -> Result<()> {
-    let merkle_tree: MerkleTree = serde_json::from_str(&std::fs::read_to_string(merkle_tree_file)?)?;
-    let final_proof: FinalProof = serde_json::from_str(&std::fs::read_to_string(final_proof_file)?)?;
-    let nonces: Vec<u64> = serde_json::from_str(&std::fs::read_to_string(nonces_file)?)?;
-    let ledger = get_ledger_values_from_file(ledger_file);
+    let merkle_tree: MerkleTree = read_from_file(merkle_tree_file)?;
+    let final_proof = FinalProof::read(final_proof_file)?;
+    let nonces = read_nonces(nonces_file)?;
+    let mut ledger = get_ledger_values_from_file(ledger_file);
     
     assert_config(&final_proof);
-
-    prove_inclusion_all_batched(&ledger, &merkle_tree, nonces)?;
+    ledger.apply_proof_dust(&final_proof)?;
+    let nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree)?;
+
+    prove_inclusion_all_batched(
+        &ledger,
+        &merkle_tree,
+        nonces,
+        DEFAULT_GROUP_PREFIX_LEN,
+        DEFAULT_MAX_BUNDLE_WRITERS,
+        DEFAULT_WRITE_RETRIES,
+        DEFAULT_INCLUSION_PROOFS_DIR,
+    )?;
     
     Ok(())
 }
 
 /// Feature: Prove inclusion (all users batched) - Generates inclusion proofs for all users in batches from data
+/// The nonces are either positional (Vec<u64>) or keyed by user hash (HashMap<String, u64>)
+#[cfg(feature = "prover")]
 pub fn prove_inclusion_batched_from_data(
     merkle_tree: &MerkleTree,
     final_proof: &FinalProof,
-    nonces: Vec<u64>,
+    nonces: impl Into<AccountNonces>,
     ledger: &Ledger,
 ) -> Result<()> {
     assert_config(final_proof);
-
-    prove_inclusion_all_batched(ledger, merkle_tree, nonces)?;
+    let nonces: AccountNonces = nonces.into();
+
+    prove_inclusion_all_batched(
+        ledger,
+        merkle_tree,
+        resolve_nonces((&nonces).into(), ledger, merkle_tree)?,
+        DEFAULT_GROUP_PREFIX_LEN,
+        DEFAULT_MAX_BUNDLE_WRITERS,
+        DEFAULT_WRITE_RETRIES,
+        DEFAULT_INCLUSION_PROOFS_DIR,
+    )?;
     
     Ok(())
 }
 
 /// Verify a global proof of reserves from files
 pub fn verify_from_files(final_proof_file: &str, merkle_tree_file: &str) -> Result<()> {
-    let final_proof: FinalProof = serde_json::from_str(&std::fs::read_to_string(final_proof_file)?)?;
-    let merkle_tree: MerkleTree = serde_json::from_str(&std::fs::read_to_string(merkle_tree_file)?)?;
+    verify_from_files_with_options(final_proof_file, merkle_tree_file, &VerifyOptions::default())
+}
+
+/// Feature: Verify a global proof of reserves from files, running only the checks enabled in the options
+pub fn verify_from_files_with_options(
+    final_proof_file: &str,
+    merkle_tree_file: &str,
+    options: &VerifyOptions,
+) -> Result<()> {
+    let final_proof = FinalProof::read(final_proof_file)?;
+    let merkle_tree: MerkleTree = read_from_file(merkle_tree_file)?;
 
     assert_config(&final_proof);
-    verify_root(final_proof, merkle_tree);
+    verify_root(final_proof, merkle_tree, options);
     Ok(())
 }
 
-// Helper function to read ledger from file
-pub fn get_ledger_values_from_file(filename: &str) -> Ledger {
-    let ledger_file = std::fs::read_to_string(filename).unwrap();
-    let ledger_json: serde_json::Value = serde_json::from_str(&ledger_file).unwrap();
-
-    // get decimals from "assets" field
-    let assets = ledger_json["assets"].as_object().unwrap();
-
-    let mut asset_names = Vec::new();
-    let mut decimals = Vec::new();
-    let mut prices = Vec::new();
-
-    for (asset_name, asset) in assets {
-        let asset_decimals = asset["usdt_decimals"].as_i64().unwrap();
-        let balance_decimals = asset["balance_decimals"].as_i64().unwrap();
-
-        asset_names.push(asset_name.clone());
-        prices.push(asset["price"].as_u64().unwrap());
-
-        decimals.push(LedgerDecimals {
-            usdt_decimals: asset_decimals,
-            balance_decimals,
-        });
-    }
-
-    // get balances from "accounts" field
-    let accounts = ledger_json["accounts"].as_object().unwrap();
-
-    let mut account_balances = Vec::new();
-    let mut hashes = Vec::new();
-
-    for (hash, account) in accounts {
-        let account = account.as_object().unwrap();
-        let mut balances = Vec::new();
+/// Feature: Verify a global proof of reserves from files, verifying the merkle tree while it is read (constant memory)
+/// The tree is never fully deserialized, so huge trees can be verified on machines that can't hold them in memory
+pub fn verify_streaming_from_files(
+    final_proof_file: &str,
+    merkle_tree_file: &str,
+    options: &VerifyOptions,
+) -> Result<()> {
+    let final_proof = FinalProof::read(final_proof_file)?;
 
-        // the order of the assets in the account is the same as in the assets field
-        for asset_name in asset_names.iter() {
-            let balance = account[asset_name].as_i64().unwrap();
-            balances.push(balance);
-        }
+    assert_config(&final_proof);
+    verify_root_streaming(&final_proof, merkle_tree_file, options)
+}
 
-        // the order of the hashes is the same as in the accounts field
-        account_balances.push(balances);
-        hashes.push(hash.clone());
-    }
+/// Feature: Verify a final proof from a file without its merkle tree (the proof and the reserves, not the accounts)
+pub fn verify_proof_only_from_file(final_proof_file: &str, options: &VerifyOptions) -> Result<()> {
+    let final_proof = FinalProof::read(final_proof_file)?;
 
-    let timestamp = ledger_json["timestamp"].as_u64().unwrap();
+    assert_config(&final_proof);
+    verify_proof_only(&final_proof, options)
+}
 
-    Ledger {
-        asset_names,
-        hashes,
-        account_balances,
-        asset_prices: prices,
-        asset_decimals: decimals,
-        timestamp,
-    }
+// Helper function to read ledger from file
+pub fn get_ledger_values_from_file(filename: &str) -> Ledger {
+    // streamed from the local file or the object store (s3://bucket/key) instead of reading the whole file first,
+    // zstd compressed ledgers (e.g. private_ledger.json.zst) are decompressed on the fly, and the accounts are parsed
+    // while they are read (see custom_serializer::ledger)
+    read_location_decompressed(filename)
+        .and_then(read_ledger)
+        .unwrap_or_else(|e| panic!("{}", format_error(&format!("Failed to read the ledger {filename}: {e}"))))
 }
 
 // Helper function to assert configuration
 pub fn assert_config(final_proof: &FinalProof) {
-    if final_proof.batch_size != BATCH_SIZE {
-        log_error!(
-            "Batch size mismatch! Expected: {}, Found: {}. Consider recompiling the code with the correct config",
-            BATCH_SIZE,
-            final_proof.batch_size
-        );
-    }
-    if final_proof.recursive_size != RECURSIVE_SIZE {
-        log_error!(
-            "Batch size mismatch! Expected: {}, Found: {}. Consider recompiling the code with the correct config",
-            RECURSIVE_SIZE,
-            final_proof.recursive_size
-        );
+    // the circuit sizes are chosen when proving (stored in the final proof), only check if they are valid
+    if let Err(e) = final_proof.prover_config().validate() {
+        log_error!("{}", e);
     }
     if final_proof.prover_version != format!("v{}", env!("CARGO_PKG_VERSION")) {
         log_error!(
@@ -11,7 +11,17 @@ use circuits::recursive_circuit::RecursiveCircuit;
 use clap::{Args, Parser, Subcommand};
 use config::*;
 use core::prover::*;
-use core::verifier::{verify_root, verify_user_inclusion};
+use core::verifier::{
+    bench_verify, check_final_proof_shape, check_inclusion_dir, check_prover_version, duration_stats, expected_root_circuit_digest, inclusion_proof_files,
+    inclusion_summary, print_reserves_in_quote, validate_quote_asset, verify_archive, verify_inclusion_files, verify_proof_only,
+    verify_root, verify_root_streaming, verify_tree_against_root, write_reserves_csv, VerifyOptions,
+};
+use custom_serializer::format::*;
+use custom_serializer::ledger::read_ledger;
+use custom_serializer::migrate::migrate_file;
+use custom_serializer::storage::{
+    join_location, location_exists, read_location, read_location_decompressed, write_artifact, write_location,
+};
 use merkle_tree::*;
 use plonky2::hash::hash_types::HashOut;
 use plonky2::plonk::circuit_data::VerifierCircuitData;
@@ -19,9 +29,17 @@ use plonky2::plonk::config::GenericHashOut;
 use plonky2::plonk::proof::ProofWithPublicInputs;
 use regex::Regex;
 use std::fs::File;
+use std::io::Read;
 use std::time::Instant;
 use types::*;
 use utils::logger::*;
+use utils::signing::read_signing_key;
+use utils::util::{
+    bytes_to_hex, compute_leaf_hash, hex_to_bytes, parse_ledger_balance, set_display_precision, verifier_data_digest,
+};
+
+#[cfg(feature = "fixtures")]
+use core::test_vector::{generate_test_vector, test_vector_ledger, TEST_VECTOR_FILE, TEST_VECTOR_NONCE_SEED};
 
 #[cfg(target_family = "unix")]
 use core::server::*;
@@ -38,60 +56,12 @@ use signal_hook::{
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 fn get_ledger_values_from_file(filename: &str) -> Ledger {
-    let ledger_file = std::fs::read_to_string(filename).unwrap();
-    let ledger_json: serde_json::Value = serde_json::from_str(&ledger_file).unwrap();
-
-    // get decimals from "assets" field
-    let assets = ledger_json["assets"].as_object().unwrap();
-
-    let mut asset_names = Vec::new();
-    let mut decimals = Vec::new();
-    let mut prices = Vec::new();
-
-    for (asset_name, asset) in assets {
-        let asset_decimals = asset["usdt_decimals"].as_i64().unwrap();
-        let balance_decimals = asset["balance_decimals"].as_i64().unwrap();
-
-        asset_names.push(asset_name.clone());
-        prices.push(asset["price"].as_u64().unwrap());
-
-        decimals.push(LedgerDecimals {
-            usdt_decimals: asset_decimals,
-            balance_decimals,
-        });
-    }
-
-    // get balances from "accounts" field
-    let accounts = ledger_json["accounts"].as_object().unwrap();
-
-    let mut account_balances = Vec::new();
-    let mut hashes = Vec::new();
-
-    for (hash, account) in accounts {
-        let account = account.as_object().unwrap();
-        let mut balances = Vec::new();
-
-        // the order of the assets in the account is the same as in the assets field
-        for asset_name in asset_names.iter() {
-            let balance = account[asset_name].as_i64().unwrap();
-            balances.push(balance);
-        }
-
-        // the order of the hashes is the same as in the accounts field
-        account_balances.push(balances);
-        hashes.push(hash.clone());
-    }
-
-    let timestamp = ledger_json["timestamp"].as_u64().unwrap();
-
-    Ledger {
-        asset_names,
-        hashes,
-        account_balances,
-        asset_prices: prices,
-        asset_decimals: decimals,
-        timestamp,
-    }
+    // streamed from the local file or the object store (s3://bucket/key) instead of reading the whole file first,
+    // zstd compressed ledgers (e.g. private_ledger.json.zst) are decompressed on the fly, and the accounts are parsed
+    // while they are read (see custom_serializer::ledger)
+    read_location_decompressed(filename)
+        .and_then(read_ledger)
+        .unwrap_or_else(|e| panic!("{}", format_error(&format!("Failed to read the ledger {filename}: {e}"))))
 }
 
 #[derive(Parser, Debug)]
@@ -99,22 +69,96 @@ fn get_ledger_values_from_file(filename: &str) -> Ledger {
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+
+    /// Serialization format used when writing proofs, merkle tree and nonces (detected automatically on read)
+    #[clap(long, global = true, value_enum, default_value_t = SerializationFormat::Json)]
+    format: SerializationFormat,
+
+    /// Only print errors (no logs or progress bar)
+    #[clap(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print debug information such as circuit build and proving timings
+    #[clap(long, short, global = true)]
+    verbose: bool,
+
+    /// Number of decimals of the printed amounts (display only, the verification always uses the exact values)
+    #[clap(long, global = true)]
+    display_precision: Option<u32>,
+
+    /// Continue when the proof was generated by a different prover version (the circuits may not match)
+    #[clap(long, global = true)]
+    ignore_version: bool,
 }
 
 #[derive(Subcommand, Debug, Clone)]
 enum Commands {
     /// Generates a global proof
-    Prove,
+    Prove(ProveArgs),
     /// Generates an inclusion proof for a specific user or for all users
     ProveInclusion(ProveInclusionArgs),
+    /// Regenerates merkle_tree from the ledger and nonces without proving (checked against final_proof)
+    RebuildMerkleTree(RebuildMerkleTreeArgs),
+    /// Prints the merkle tree root hash, the proof timestamp and the proof commitment to be published
+    PublishRoot,
+    /// Writes the reserves and metadata of the final proof as a JSON report (does not verify the proof)
+    Summary(SummaryArgs),
+    /// Adds metadata (exchange id, anchor url, signature of the commitment) to an existing proof without re-proving
+    Annotate(AnnotateArgs),
+    /// Rewrites a final proof, merkle tree or inclusion proof written by a previous version in the current format version
+    Migrate(MigrateArgs),
+    /// Regenerates the test vector guarding the hashing, merkle tree and serialization outputs (fixtures feature)
+    #[cfg(feature = "fixtures")]
+    GenFixture(GenFixtureArgs),
+    /// Writes the merkle tree as a Graphviz DOT graph (debugging aid for small trees)
+    TreeDot(TreeDotArgs),
+    /// Lists the user hashes committed in the merkle tree with their leaf hashes (membership audits)
+    ListUsers(ListUsersArgs),
     /// Verifies the global proof
-    Verify,
+    Verify(VerifyArgs),
+    /// Checks that a merkle tree is consistent and hashes to a published root hash (without the final proof)
+    VerifyTree(VerifyTreeArgs),
+    /// Rebuilds the root circuit of a proof shape and prints its digest (to pin it with verify --expected-digest)
+    CircuitDigest(CircuitDigestArgs),
+    /// Times the verification of the global proof (circuit rebuild versus proof checks) for capacity planning
+    BenchVerify(BenchVerifyArgs),
+    /// Reports the padding overhead of the merkle tree for a number of accounts (no ledger or proof needed)
+    Padding(PaddingArgs),
     /// Verifies an inclusion proof
     VerifyInclusion,
+    /// Prints a human-readable summary of an inclusion proof for end users
+    InclusionSummary(InclusionSummaryArgs),
+    /// Recomputes your leaf hash from your balances and nonce and the root hash implied by your inclusion proof
+    CheckLeaf(CheckLeafArgs),
+    /// Checks the structure of an inclusion proof file and prints its calculated root hash (no final_proof needed)
+    CheckInclusionFile(CheckInclusionFileArgs),
+    /// Checks that every inclusion proof of a directory recomputes to the published root hash (no zk verification)
+    CheckInclusionDir(CheckInclusionDirArgs),
+    /// Appends the current global proof to a proof archive (history of snapshots)
+    ArchiveAppend(ArchiveArgs),
+    /// Verifies every snapshot of a proof archive and prints the reserves timeline
+    VerifyArchive(VerifyArchiveArgs),
+    /// Checks if the inclusion proof daemon is running and which proof it loaded
+    #[cfg(target_family = "unix")]
+    Status,
     /// Get the version of the code
     Version,
 }
 
+// output layout of prove-inclusion --all, new layouts only need a variant and a match arm in the dispatch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum InclusionLayout {
+    /// One inclusion_proof_<hash> file per user (in the --format serialization format)
+    Files,
+    /// zstd compressed JSON bundles grouped by user hash prefix, with a bundle_index.json
+    Bundles,
+}
+
+// parser of the limits where 0 would never let anything through (e.g. every daemon request answered busy)
+fn at_least_one() -> clap::builder::RangedU64ValueParser<usize> {
+    clap::builder::RangedU64ValueParser::new().range(1..)
+}
+
 // Define a separate struct for the ProveInclusion subcommand arguments
 #[derive(Args, Debug, Clone)]
 #[clap(group = clap::ArgGroup::new("inclusion_target").required(true))]
@@ -123,41 +167,475 @@ struct ProveInclusionArgs {
     #[clap(long, short, group = "inclusion_target")]
     daemon: bool,
 
+    /// Max number of inclusion requests processed at the same time by the daemon (others get a "busy" response)
+    #[cfg(target_family = "unix")]
+    #[clap(long, requires = "daemon", default_value_t = DEFAULT_MAX_IN_FLIGHT, value_parser = at_least_one())]
+    max_in_flight: usize,
+
+    /// Number of inclusion proofs kept in memory by the daemon for repeated requests (0 disables the cache)
+    #[cfg(target_family = "unix")]
+    #[clap(long, requires = "daemon", default_value_t = DEFAULT_CACHE_SIZE)]
+    cache_size: usize,
+
+    /// Use the async (tokio) server in daemon mode, which scales to many more concurrent clients
+    #[cfg(feature = "async-server")]
+    #[clap(long, requires = "daemon")]
+    async_server: bool,
+
+    /// Serve inclusion proofs over HTTP on the given address (e.g. 127.0.0.1:8080)
+    #[cfg(feature = "http-server")]
+    #[clap(long, group = "inclusion_target")]
+    http: Option<String>,
+
     /// The hash of the user to prove inclusion for
     #[clap(long, group = "inclusion_target")]
     userhash: Option<String>,
 
-    /// Prove inclusion for all users
+    /// Prove inclusion for all users, written with the --layout output layout
     #[clap(long, group = "inclusion_target")]
     all: bool,
 
-    /// Prove inclusion for all users in batches (grouped by first 3 characters)
-    #[clap(long, group = "inclusion_target")]
-    all_batched: bool,
+    /// Output layout of the inclusion proofs of all users
+    #[clap(long, requires = "all", value_enum, default_value_t = InclusionLayout::Files)]
+    layout: InclusionLayout,
+
+    /// Number of leading user hash characters grouping the proofs in bundles with --layout bundles (more characters, more and smaller bundles)
+    #[clap(long, requires = "all", default_value_t = DEFAULT_GROUP_PREFIX_LEN)]
+    group_prefix_len: usize,
+
+    /// Number of threads serializing, compressing and writing the bundles with --layout bundles, also the size of their queue (each proving thread holds one more bundle)
+    #[clap(long, requires = "all", default_value_t = DEFAULT_MAX_BUNDLE_WRITERS)]
+    max_writers: usize,
+
+    /// Max number of inclusion proofs held in memory at the same time with --layout files (defaults to one per core, larger values are clamped to it)
+    #[clap(long, requires = "all", value_parser = at_least_one())]
+    max_inflight: Option<usize>,
+
+    /// Max retries (with exponential backoff) of transient errors when writing the inclusion proof files
+    #[clap(long, default_value_t = DEFAULT_WRITE_RETRIES)]
+    write_retries: u32,
+
+    /// Directory the inclusion proofs (or the bundles of --all --layout bundles) are written to, created if missing
+    #[clap(long, default_value = DEFAULT_INCLUSION_PROOFS_DIR)]
+    out_dir: String,
+
+    /// Read the nonces keyed by user hash (private_nonces_by_hash, see prove --nonces-by-hash) instead of private_nonces
+    #[clap(long)]
+    nonces_by_hash: bool,
+
+    /// Rebuild only the path of --userhash from the ledger and nonces (low memory, merkle_tree.<ext> is not read)
+    #[clap(long, requires = "userhash", conflicts_with = "nonces_by_hash")]
+    compact: bool,
+
+    /// Published root hash (hex) the rebuilt tree is checked against with --compact (defaults to the final proof root hash)
+    #[clap(long, requires = "compact")]
+    root: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+struct ProveArgs {
+    /// Ledger file to prove, either a local path or an s3://bucket/key location (requires the s3 feature)
+    #[clap(long, default_value = "private_ledger.json")]
+    ledger: String,
+
+    /// Directory to write the final proof, merkle tree and nonces to, either a local path or an s3://bucket/prefix location
+    #[clap(long)]
+    output_dir: Option<String>,
+
+    /// Additional ledger files to merge into the ledger before proving (e.g. derivatives balances)
+    #[clap(long)]
+    merge: Vec<String>,
+
+    /// Minimum accepted asset price (scaled by usdt_decimals)
+    #[clap(long, default_value_t = DEFAULT_MIN_ASSET_PRICE)]
+    min_price: u64,
+
+    /// Maximum accepted asset price (scaled by usdt_decimals)
+    #[clap(long, default_value_t = DEFAULT_MAX_ASSET_PRICE)]
+    max_price: u64,
+
+    /// Assets that intentionally have a zero price
+    #[clap(long)]
+    allow_zero_price: Vec<String>,
+
+    /// Number of inner proofs verified by each recursive circuit (a larger arity reduces the tree depth)
+    #[clap(long, default_value_t = RECURSIVE_SIZE)]
+    recursive_size: usize,
+
+    /// Minimum account equity in USD (e.g. 1.50), the accounts below it are aggregated into a single dust leaf
+    #[clap(long)]
+    min_equity: Option<String>,
+
+    /// Verify the written proof and the inclusion proofs of a random sample of users right after proving
+    /// (keeps a copy of the ledger in memory while proving)
+    #[clap(long)]
+    verify: bool,
+
+    /// Number of random users whose inclusion proofs are checked by --verify
+    #[clap(long, requires = "verify", default_value_t = DEFAULT_VERIFY_SAMPLE_SIZE)]
+    verify_sample_size: usize,
+
+    /// Also write the nonces keyed by user hash (private_nonces_by_hash), so the inclusion proofs don't depend on the
+    /// position of the accounts in the ledger
+    #[clap(long)]
+    nonces_by_hash: bool,
+
+    /// Write the root circuit verifier data to verifier_data.bin (shared by the proofs of the same shape) instead of
+    /// embedding it in the final proof, which references it by its circuit digest
+    #[clap(long)]
+    detach_verifier_data: bool,
+
+    /// Overwrite the final proof, merkle tree and nonces of a previous run in the output directory
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+struct ListUsersArgs {
+    /// The ledger the proof was generated from
+    #[clap(long, default_value = "private_ledger.json")]
+    ledger: String,
+
+    /// Write the list to a CSV file (user_hash,leaf_hash) instead of printing it
+    #[clap(long)]
+    output: Option<String>,
+
+    /// Read the nonces keyed by user hash (private_nonces_by_hash, see prove --nonces-by-hash) instead of private_nonces
+    #[clap(long)]
+    nonces_by_hash: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+struct TreeDotArgs {
+    /// Output DOT file (render it with e.g. `dot -Tsvg merkle_tree.dot -o merkle_tree.svg`)
+    #[clap(long, default_value = "merkle_tree.dot")]
+    output: String,
+
+    /// Maximum number of nodes in the graph, the rest of the tree is summarized in a single node
+    #[clap(long, default_value_t = 1000)]
+    max_nodes: usize,
+}
+
+#[derive(Args, Debug, Clone)]
+struct RebuildMerkleTreeArgs {
+    /// Additional ledger files that were merged into private_ledger.json when proving
+    #[clap(long)]
+    merge: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+struct VerifyArgs {
+    /// Write the verified per-asset reserves to a CSV file
+    #[clap(long)]
+    reserves_csv: Option<String>,
+
+    /// Also print the reserves and the grand total in this asset (e.g. EUR or BTC) instead of USD
+    #[clap(long)]
+    quote: Option<String>,
+
+    /// Only display and export the reserves of these assets (e.g. BTC,ETH), the whole proof is still verified
+    #[clap(long, value_delimiter = ',')]
+    assets: Option<Vec<String>>,
+
+    /// Skip rebuilding the root circuit (trusts the circuit data inside the proof file)
+    #[clap(long)]
+    skip_rebuild_circuit: bool,
+
+    /// Skip the asset prices check
+    #[clap(long)]
+    skip_prices: bool,
+
+    /// Skip the asset decimals check
+    #[clap(long)]
+    skip_decimals: bool,
+
+    /// Skip the merkle tree root hash check
+    #[clap(long)]
+    skip_root_hash: bool,
+
+    /// Skip the full merkle tree recomputation
+    #[clap(long)]
+    skip_merkle: bool,
+
+    /// Only verify the proof and the reserves, without the merkle tree (merkle_tree.<ext> is not read)
+    #[clap(long, conflicts_with_all = ["skip_root_hash", "skip_merkle", "deep_check"])]
+    proof_only: bool,
+
+    /// Verify the merkle tree while it is read instead of deserializing it first (constant memory, for huge trees)
+    #[clap(long, conflicts_with_all = ["proof_only", "skip_merkle"])]
+    stream: bool,
+
+    /// Trusted root circuit digest (hex, see circuit-digest), checked first and used instead of rebuilding the root circuit
+    #[clap(long)]
+    expected_digest: Option<String>,
+
+    /// Also check that the batch reserves recorded in the merkle tree add up to the proven reserves
+    #[clap(long)]
+    deep_check: bool,
+
+    /// Reject proofs whose merkle tree is deeper than this (protects against malformed proof files)
+    #[clap(long, default_value_t = DEFAULT_MAX_TREE_DEPTH)]
+    max_tree_depth: usize,
 }
 
-fn assert_config(final_proof: &FinalProof) {
-    if final_proof.batch_size != BATCH_SIZE {
-        log_error!(
-            "Batch size mismatch! Expected: {}, Found: {}. Consider recompiling the code with the correct config",
-            BATCH_SIZE,
-            final_proof.batch_size
-        );
+impl VerifyArgs {
+    fn options(&self) -> Result<VerifyOptions> {
+        let expected_digest = parse_expected_digest(self.expected_digest.as_deref())?;
+
+        Ok(VerifyOptions {
+            rebuild_circuit: !self.skip_rebuild_circuit,
+            check_prices: !self.skip_prices,
+            check_decimals: !self.skip_decimals,
+            check_root_hash: !self.skip_root_hash,
+            check_merkle_tree: !self.skip_merkle,
+            deep_check: self.deep_check,
+            max_tree_depth: self.max_tree_depth,
+            expected_digest,
+            assets: self.assets.clone(),
+        })
     }
-    if final_proof.recursive_size != RECURSIVE_SIZE {
-        log_error!(
-            "Batch size mismatch! Expected: {}, Found: {}. Consider recompiling the code with the correct config",
-            RECURSIVE_SIZE,
-            final_proof.recursive_size
-        );
+}
+
+// a root circuit digest given on the command line (see circuit-digest)
+fn parse_expected_digest(digest: Option<&str>) -> Result<Option<Vec<u8>>> {
+    match digest {
+        Some(digest) => {
+            let digest = hex_to_bytes(digest).context(format_error("Invalid expected digest"))?;
+            if digest.len() != 32 {
+                return Err(anyhow::anyhow!(format_error("The expected digest must be 32 bytes (64 hex characters)")));
+            }
+            Ok(Some(digest))
+        }
+        None => Ok(None),
     }
-    if final_proof.prover_version != format!("v{}", env!("CARGO_PKG_VERSION")) {
-        log_error!(
-            "Prover version mismatch! Expected: {}, Found: {}. Consider downloading the correct version from the repository",
-            env!("CARGO_PKG_VERSION"),
-            final_proof.prover_version
-        );
+}
+
+#[derive(Args, Debug, Clone)]
+struct CircuitDigestArgs {
+    /// Number of assets (defaults to the one of final_proof)
+    #[clap(long)]
+    asset_count: Option<usize>,
+
+    /// Merkle tree depth (defaults to the one of final_proof)
+    #[clap(long)]
+    tree_depth: Option<usize>,
+
+    /// Accounts per batch circuit (defaults to the one of final_proof, or BATCH_SIZE)
+    #[clap(long)]
+    batch_size: Option<usize>,
+
+    /// Inner proofs per recursive circuit (defaults to the one of final_proof, or RECURSIVE_SIZE)
+    #[clap(long)]
+    recursive_size: Option<usize>,
+}
+
+#[derive(Args, Debug, Clone)]
+struct VerifyTreeArgs {
+    /// Merkle tree file (defaults to merkle_tree.<ext> of --format)
+    #[clap(long)]
+    tree: Option<String>,
+
+    /// Published root hash (hex)
+    #[clap(long)]
+    root: String,
+}
+
+#[derive(Args, Debug, Clone)]
+struct SummaryArgs {
+    /// Output JSON file
+    #[clap(long, short, default_value = "proof_summary.json")]
+    output: String,
+}
+
+#[derive(Args, Debug, Clone)]
+#[clap(group = clap::ArgGroup::new("annotation").required(true).multiple(true))]
+struct AnnotateArgs {
+    /// Final proof file to annotate (any format, detected automatically)
+    proof: String,
+
+    /// Identifier of the exchange publishing the proof
+    #[clap(long, group = "annotation")]
+    exchange_id: Option<String>,
+
+    /// Where the proof commitment is anchored (e.g. a transaction or transparency log entry)
+    #[clap(long, group = "annotation")]
+    anchor_url: Option<String>,
+
+    /// Ed25519 private key (PKCS#8, PEM or DER) to sign the proof commitment printed by publish-root
+    #[clap(long, group = "annotation")]
+    sign_key: Option<String>,
+
+    /// Output file (defaults to overwriting the proof file, keeping its format)
+    #[clap(long, short)]
+    output: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+struct MigrateArgs {
+    /// File to migrate (final proof, merkle tree or inclusion proof in any format, detected automatically)
+    file: String,
+
+    /// Output file (defaults to overwriting the file, keeping its format)
+    #[clap(long, short)]
+    output: Option<String>,
+}
+
+#[cfg(feature = "fixtures")]
+#[derive(Args, Debug, Clone)]
+struct GenFixtureArgs {
+    /// Output file (the committed test vector by default)
+    #[clap(long, short, default_value = TEST_VECTOR_FILE)]
+    output: String,
+
+    /// Seed the nonces are derived from
+    #[clap(long, default_value_t = TEST_VECTOR_NONCE_SEED)]
+    seed: u64,
+}
+
+#[derive(Args, Debug, Clone)]
+struct PaddingArgs {
+    /// Number of accounts (add one for the dust aggregate and one for the reserve buffer, if the proof has them)
+    accounts: usize,
+
+    /// Accounts per batch circuit
+    #[clap(long, default_value_t = BATCH_SIZE)]
+    batch_size: usize,
+
+    /// Inner proofs per recursive circuit
+    #[clap(long, default_value_t = RECURSIVE_SIZE)]
+    recursive_size: usize,
+}
+
+#[derive(Args, Debug, Clone)]
+struct BenchVerifyArgs {
+    /// Number of times the proof and merkle tree checks are run (the root circuit is only rebuilt once)
+    #[clap(long, short, default_value_t = 5)]
+    runs: usize,
+}
+
+#[derive(Args, Debug, Clone)]
+struct InclusionSummaryArgs {
+    /// The inclusion proof file to summarize
+    inclusion_proof: String,
+
+    /// Also write the summary to a text file
+    #[clap(long, short)]
+    output: Option<String>,
+
+    /// Trusted root circuit digest (hex, see circuit-digest), without it the proof is checked with the circuit data inside the file
+    #[clap(long)]
+    expected_digest: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+struct CheckLeafArgs {
+    /// Your user hash
+    #[clap(long)]
+    hash: String,
+
+    /// JSON file with your balances: an array in the proof asset order, or an object keyed by asset name (needs final_proof)
+    #[clap(long)]
+    balances: String,
+
+    /// Your nonce
+    #[clap(long)]
+    nonce: u64,
+
+    /// Your inclusion proof file (its merkle path is used to compute the root hash)
+    #[clap(long)]
+    inclusion_proof: String,
+}
+
+#[derive(Args, Debug, Clone)]
+struct CheckInclusionFileArgs {
+    /// The inclusion proof file to check
+    path: String,
+}
+
+#[derive(Args, Debug, Clone)]
+struct CheckInclusionDirArgs {
+    /// Directory of the inclusion_proof_* files
+    #[clap(default_value = "inclusion_proofs")]
+    dir: String,
+
+    /// Published root hash (hex)
+    #[clap(long)]
+    root: String,
+}
+
+#[derive(Args, Debug, Clone)]
+struct ArchiveArgs {
+    /// The proof archive file (created if it does not exist, defaults to proof_archive.<format extension>)
+    #[clap(long)]
+    archive: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+struct VerifyArchiveArgs {
+    /// The proof archive file (defaults to proof_archive.<format extension>)
+    #[clap(long)]
+    archive: Option<String>,
+
+    /// Skip rebuilding the root circuit of each snapshot (trusts the circuit data inside the archive)
+    #[clap(long)]
+    skip_rebuild_circuit: bool,
+
+    /// Reject proofs whose merkle tree is deeper than this (protects against malformed proof files)
+    #[clap(long, default_value_t = DEFAULT_MAX_TREE_DEPTH)]
+    max_tree_depth: usize,
+}
+
+// read the balances of the check-leaf command
+// --> array: balances in the proof asset order
+// --> object: balances keyed by asset name, ordered with the asset names of the final proof (missing assets are zero)
+fn read_balances_file(path: &str, ext: &str) -> Result<Vec<i64>> {
+    let balances_json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+    match balances_json {
+        serde_json::Value::Array(balances) => balances
+            .iter()
+            .map(parse_ledger_balance)
+            .collect(),
+        serde_json::Value::Object(balances) => {
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))
+                .context(format!("Balances keyed by asset name need final_proof.{ext} for the asset order"))?;
+
+            if let Some(asset_name) = balances.keys().find(|name| !final_proof.asset_names.contains(name)) {
+                return Err(anyhow::anyhow!("Unknown asset {asset_name}, it is not in the final proof"));
+            }
+
+            final_proof
+                .asset_names
+                .iter()
+                .map(|asset_name| match balances.get(asset_name) {
+                    Some(balance) => parse_ledger_balance(balance).context(format!("Invalid {asset_name} balance")),
+                    None => Ok(0),
+                })
+                .collect()
+        }
+        _ => Err(anyhow::anyhow!("The balances must be a JSON array or object")),
+    }
+}
+
+// the path of a single-user inclusion proof in --out-dir (created if missing), a proof of a previous run is overwritten
+fn inclusion_proof_path(out_dir: &str, userhash: &str, ext: &str) -> Result<String> {
+    std::fs::create_dir_all(out_dir).context(format_error(&format!("Failed to create the {out_dir} directory")))?;
+    let path = std::path::Path::new(out_dir).join(format!("inclusion_proof_{userhash}.{ext}"));
+    if path.exists() {
+        log_warning!("{} already exists, it will be overwritten", path.display());
     }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+// the circuit layouts can change between versions, so a proof from another prover version usually fails
+// with a confusing circuit digest mismatch: it is rejected unless ignore_version is set (--ignore-version)
+fn assert_config(final_proof: &FinalProof, ignore_version: bool) -> Result<()> {
+    // the circuit sizes are chosen when proving (stored in the final proof), only check if they are valid
+    if let Err(e) = final_proof.prover_config().validate() {
+        log_error!("{}", e);
+    }
+
+    check_prover_version(final_proof, ignore_version)
 }
 
 fn main() -> Result<()> {
@@ -165,35 +643,233 @@ fn main() -> Result<()> {
     let global_timer = Instant::now();
 
     let cli = Cli::parse();
+    let ext = cli.format.extension();
+
+    if cli.quiet {
+        set_verbosity(Verbosity::Quiet);
+    } else if cli.verbose {
+        set_verbosity(Verbosity::Verbose);
+    }
+
+    set_display_precision(cli.display_precision);
 
     print_header();
 
     match &cli.command {
-        Commands::Prove => {
+        Commands::Prove(args) => {
+            let output_location = |file_name: String| match &args.output_dir {
+                Some(output_dir) => join_location(output_dir, &file_name),
+                None => file_name,
+            };
+
+            // an accidental re-run must not clobber a previous (possibly published) proof,
+            // checked before proving so no hours of proving are lost on a refusal
+            let mut output_files = vec![
+                output_location(format!("final_proof.{ext}")),
+                output_location(format!("merkle_tree.{ext}")),
+                output_location(format!("private_nonces.{ext}")),
+            ];
+            if args.nonces_by_hash {
+                output_files.push(output_location(format!("private_nonces_by_hash.{ext}")));
+            }
+            let mut existing_files = Vec::new();
+            for output_file in output_files {
+                if location_exists(&output_file)? {
+                    existing_files.push(output_file);
+                }
+            }
+            if !existing_files.is_empty() {
+                if !args.force {
+                    return Err(anyhow::anyhow!(format_error(&format!(
+                        "Refusing to overwrite the outputs of a previous proof: {}. Pass --force to overwrite them or choose another --output-dir",
+                        existing_files.join(", ")
+                    ))));
+                }
+                log_warning!("Overwriting the outputs of a previous proof: {}", existing_files.join(", "));
+            }
+
+            // the verifier data file is shared by the proofs of the same shape, it is never replaced by the one of another
+            // circuit (other proofs may reference it): it is checked against the shape of the ledger before proving
+            let verifier_data_location = output_location(FinalProof::VERIFIER_DATA_FILE_NAME.to_string());
+            let existing_verifier_data = if args.detach_verifier_data && location_exists(&verifier_data_location)? {
+                let mut existing = Vec::new();
+                read_location(&verifier_data_location)?
+                    .read_to_end(&mut existing)
+                    .with_context(|| format!("Failed to read {verifier_data_location}"))?;
+                Some(existing)
+            } else {
+                None
+            };
+
             log_info!("Reading and deserializing ledger...");
-            let ledger = get_ledger_values_from_file("private_ledger.json");
+            let mut ledger = get_ledger_values_from_file(&args.ledger);
+            for ledger_file in &args.merge {
+                log_info!("Merging ledger {}...", ledger_file);
+                ledger = ledger.merge(get_ledger_values_from_file(ledger_file))?;
+            }
             log_success!("Ledger read successfully!");
 
+            let price_bounds = PriceBounds {
+                min: args.min_price,
+                max: args.max_price,
+                allow_zero: args.allow_zero_price.clone(),
+            };
+            ledger.validate_prices(&price_bounds).context(format_error("Asset prices validation failed"))?;
+
+            if let Some(min_equity) = &args.min_equity {
+                let min_equity = ledger.scale_equity(min_equity).context(format_error("Invalid minimum equity"))?;
+                let dust_count = ledger.aggregate_dust(min_equity)?;
+                log_warning!(
+                    "Aggregated {} accounts below the minimum equity into a single dust leaf, they are not individually inclusion-provable",
+                    dust_count
+                );
+            }
+
+            // the nonces are keyed by user hash after proving, a duplicate hash is reported now instead
+            if args.nonces_by_hash {
+                check_unique_user_hashes(&ledger.hashes).context(format_error("Invalid ledger for --nonces-by-hash"))?;
+            }
+
+            let existing_verifier_data_digest = match &existing_verifier_data {
+                Some(existing) => {
+                    let config = ProverConfig { recursive_size: args.recursive_size, ..ProverConfig::default() };
+                    let tree_depth = config.padding_report(ledger.leaf_count())?.tree_depth;
+
+                    log_info!("Rebuilding the root circuit to check {}...", verifier_data_location);
+                    let digest = bytes_to_hex(&expected_root_circuit_digest(ledger.asset_names.len(), tree_depth, &config)?);
+                    if bytes_to_hex(&verifier_data_digest(existing)) != digest {
+                        return Err(anyhow::anyhow!(format_error(&format!(
+                            "{verifier_data_location} holds the verifier data of another circuit (other proofs may reference it), write this proof to another directory"
+                        ))));
+                    }
+                    Some(digest)
+                }
+                None => None,
+            };
+
+            // report where proving stopped instead of dying silently on Ctrl-C
+            // (there is no checkpointing: an interrupted run must be restarted from scratch)
+            // the handler is disarmed once proving returns, the writes and the self-check get the default Ctrl-C
+            #[cfg(target_family = "unix")]
+            let interrupt_handler = {
+                let mut signals = Signals::new([SIGINT])?;
+                let handle = signals.handle();
+                let closed = handle.clone();
+
+                let thread = std::thread::spawn(move || {
+                    if signals.forever().next().is_some() && !closed.is_closed() {
+                        println!();
+                        match prove_progress_status() {
+                            Some(status) => log_error!("Proving interrupted at {}, no proof was written", status),
+                            None => log_error!("Proving interrupted before the first batch, no proof was written"),
+                        }
+                        std::process::exit(130);
+                    }
+                    // dropping signals unregisters the handler
+                });
+                (handle, thread)
+            };
+
             log_info!(
                 "Starting to prove reserves... This might take some hours depending on the ledger size..."
             );
-            let (final_proof, merkle_tree, account_nonces) = prove_global(ledger)?;
-            
+            // the ledger is consumed by the prover, the self-check needs it to generate the inclusion proofs
+            let verify_ledger = args.verify.then(|| ledger.clone());
+            let user_hashes = args.nonces_by_hash.then(|| ledger.hashes.clone());
+            let (final_proof, merkle_tree, account_nonces) = prove_global_with_arity(ledger, args.recursive_size)?;
+            #[cfg(target_family = "unix")]
+            {
+                let (handle, thread) = interrupt_handler;
+                handle.close();
+                let _ = thread.join();
+            }
+
             // Serialize and save the results to files
             log_info!("Serializing final proof, merkle tree and nonces into disk...");
-            let final_proof_json = serde_json::to_string(&final_proof)?;
-            let merkle_tree_json = serde_json::to_string(&merkle_tree)?;
-            let nonces_json = serde_json::to_string(&account_nonces)?;
-
-            std::fs::write("final_proof.json", final_proof_json)?;
-            std::fs::write("merkle_tree.json", merkle_tree_json)?;
-            std::fs::write("private_nonces.json", nonces_json)?;
-            
+            if args.detach_verifier_data {
+                let mut detached_proof = final_proof.clone();
+                let verifier_data = detached_proof.detach_verifier_data()?;
+
+                // the existing file was checked before proving (existing_verifier_data_digest)
+                match existing_verifier_data_digest {
+                    Some(digest) => {
+                        if Some(digest) != detached_proof.verifier_data_digest {
+                            return Err(anyhow::anyhow!(format_error(&format!(
+                                "{verifier_data_location} holds the verifier data of another circuit (other proofs may reference it), write this proof to another directory"
+                            ))));
+                        }
+                        log_info!("Reusing the verifier data of {}", verifier_data_location);
+                    }
+                    None => write_location(&verifier_data_location, &verifier_data)?,
+                }
+                write_artifact(&output_location(format!("final_proof.{ext}")), &detached_proof, cli.format)?;
+            } else {
+                write_artifact(&output_location(format!("final_proof.{ext}")), &final_proof, cli.format)?;
+            }
+            write_artifact(&output_location(format!("merkle_tree.{ext}")), &merkle_tree, cli.format)?;
+            write_artifact(&output_location(format!("private_nonces.{ext}")), &account_nonces, cli.format)?;
+            if let Some(user_hashes) = &user_hashes {
+                let nonces = nonces_by_hash(user_hashes, &account_nonces)?;
+                write_artifact(&output_location(format!("private_nonces_by_hash.{ext}")), &nonces, cli.format)?;
+            }
+
             log_success!("Serialization completed successfully!");
+
+            // self-check of the fresh outputs before they are published, a failure here is a prover bug
+            if let Some(ledger) = verify_ledger {
+                log_info!("Verifying the inclusion proofs of a random sample of users...");
+                let user_nonces = resolve_nonces((&account_nonces).into(), &ledger, &merkle_tree)?;
+                let checked = check_inclusion_sample(
+                    &final_proof.root_hash(),
+                    &merkle_tree,
+                    &user_nonces,
+                    &ledger,
+                    args.verify_sample_size,
+                )
+                .context(format_error("Self-check failed: do not publish this proof"))?;
+                log_success!("The inclusion proofs of {} users are valid!", checked);
+
+                log_info!("Verifying the generated proof...");
+                let options = VerifyOptions {
+                    deep_check: true,
+                    ..VerifyOptions::default()
+                };
+                verify_root(final_proof, merkle_tree, &options);
+            }
         }
         Commands::ProveInclusion(args) => {
-            // create the inclusion_proofs directory
-            let _ = std::fs::create_dir_all("inclusion_proofs");
+            // the compact mode never needs the serialized merkle tree (nor the server holding it)
+            if args.compact {
+                let userhash = args.userhash.as_ref().unwrap();
+                let published_root = match &args.root {
+                    Some(root) => Some(hex_to_bytes(root).context(format_error("Invalid root hash"))?),
+                    None => None,
+                };
+
+                let final_proof = FinalProof::read(&format!("final_proof.{ext}"))?;
+                assert_config(&final_proof, cli.ignore_version)?;
+                let published_root = published_root.unwrap_or_else(|| final_proof.root_hash());
+
+                let nonces: Vec<u64> = read_from_file(&format!("private_nonces.{ext}"))?;
+                let mut ledger = get_ledger_values_from_file("private_ledger.json");
+                ledger.apply_proof_dust(&final_proof)?;
+
+                log_info!("Rebuilding the inclusion path for user hash {userhash} from the ledger and nonces...");
+                let inclusion_proof = prove_user_inclusion_compact(
+                    userhash.clone(),
+                    ledger,
+                    &nonces,
+                    &final_proof.prover_config(),
+                    &published_root,
+                )
+                .context(format_error("Failed to prove inclusion"))?;
+
+                let inclusion_filename = inclusion_proof_path(&args.out_dir, userhash, ext)?;
+                write_to_file(&inclusion_filename, &inclusion_proof, cli.format)?;
+                log_success!("Inclusion proof written to {inclusion_filename}");
+
+                return Ok(());
+            }
 
             // if userhash and socket exists, just send the hash to the server (only on unix)
             #[cfg(target_family = "unix")]
@@ -208,20 +884,26 @@ fn main() -> Result<()> {
             log_info!(
                 "Reading and deserializing proof, merkle tree, ledger and nonces... This might take a while"
             );
-            let merkle_tree_file = std::fs::read_to_string("merkle_tree.json")?;
-            let merkle_tree: MerkleTree = serde_json::from_str(&merkle_tree_file)?;
+            let merkle_tree: MerkleTree = read_from_file(&format!("merkle_tree.{ext}"))?;
 
-            let final_proof_file = std::fs::read_to_string("final_proof.json")?;
-            let final_proof: FinalProof = serde_json::from_str(&final_proof_file)?;
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))?;
 
             // Assert the configuration of the final proof
-            assert_config(&final_proof);
+            assert_config(&final_proof, cli.ignore_version)?;
 
             // deserialize nonces
-            let nonces_file = std::fs::read_to_string("private_nonces.json")?;
-            let nonces: Vec<u64> = serde_json::from_str(&nonces_file)?;
+            let nonces_file = if args.nonces_by_hash {
+                format!("private_nonces_by_hash.{ext}")
+            } else {
+                format!("private_nonces.{ext}")
+            };
+            let nonces = read_nonces(&nonces_file)?;
 
-            let ledger = get_ledger_values_from_file("private_ledger.json");
+            let mut ledger = get_ledger_values_from_file("private_ledger.json");
+            // the dust accounts are not in the merkle tree, aggregate them as when proving to keep the nonce positions
+            ledger.apply_proof_dust(&final_proof)?;
+            // checked before daemonizing, so a stale nonces file is reported here and not in the daemon logs
+            let nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).context(format_error("Invalid nonces file"))?;
             log_success!("Reading and deserializing completed!");
 
             // create the server if daemon is true (only on unix)
@@ -257,7 +939,20 @@ fn main() -> Result<()> {
                             }
                         });
 
-                        create_local_server(merkle_tree, nonces, ledger)?
+                        #[cfg(feature = "async-server")]
+                        if args.async_server {
+                            create_local_server_async(
+                                merkle_tree,
+                                nonces,
+                                ledger,
+                                args.max_in_flight,
+                                args.cache_size,
+                                &args.out_dir,
+                            )?;
+                            return Ok(());
+                        }
+
+                        create_local_server(merkle_tree, nonces, ledger, args.max_in_flight, args.cache_size, &args.out_dir)?
                     }
                     Err(_) => log_error!(
                         "Error while starting daemon process. Check if there are other process already being executed."
@@ -267,85 +962,590 @@ fn main() -> Result<()> {
                 return Ok(());
             }
 
+            #[cfg(feature = "http-server")]
+            if let Some(addr) = &args.http {
+                core::http_server::serve_http(addr, merkle_tree, nonces, ledger)?;
+                return Ok(());
+            }
+
             if args.all {
-                log_info!("Proving inclusion for all users...");
-                prove_inclusion_all(&ledger, &merkle_tree, nonces)?;
-                log_success!("Successfully generated inclusion proofs for all users!");
-            } else if args.all_batched {
-                log_info!("Proving inclusion for all users in batches...");
-                prove_inclusion_all_batched(&ledger, &merkle_tree, nonces)?;
-                log_success!("Successfully generated batched inclusion proofs for all users!");
+                // the proofs of a previous run are overwritten, and the ones of users no longer in the ledger are kept
+                let inclusion_dir = std::path::Path::new(&args.out_dir);
+                if inclusion_dir.is_dir() {
+                    let previous_proofs = inclusion_proof_files(&args.out_dir)?.len();
+                    let has_bundle_index = inclusion_dir.join(BundleIndex::FILE_NAME).exists();
+                    if previous_proofs > 0 || has_bundle_index {
+                        log_warning!(
+                            "{} already contains inclusion proofs ({} proof files{}), they will be overwritten or mixed with the new ones",
+                            args.out_dir,
+                            previous_proofs,
+                            if has_bundle_index { " and a bundle index" } else { "" }
+                        );
+                    }
+                }
+
+                match args.layout {
+                    InclusionLayout::Files => {
+                        log_info!("Proving inclusion for all users...");
+                        prove_inclusion_all(
+                            &ledger,
+                            &merkle_tree,
+                            nonces,
+                            cli.format,
+                            args.write_retries,
+                            args.max_inflight,
+                            &args.out_dir,
+                        )?;
+                        log_success!("Successfully generated inclusion proofs for all users!");
+                    }
+                    InclusionLayout::Bundles => {
+                        log_info!("Proving inclusion for all users in batches...");
+                        prove_inclusion_all_batched(
+                            &ledger,
+                            &merkle_tree,
+                            nonces,
+                            args.group_prefix_len,
+                            args.max_writers,
+                            args.write_retries,
+                            &args.out_dir,
+                        )?;
+                        log_success!("Successfully generated batched inclusion proofs for all users!");
+                    }
+                }
             } else if let Some(userhash) = &args.userhash {
                 log_info!("Proving inclusion for user hash: {}", userhash);
                 let inclusion_proof =
                     prove_user_inclusion_by_hash(userhash.clone(), &merkle_tree, &nonces, &ledger)?;
 
-                let inclusion_filename =
-                    format!("inclusion_proofs/inclusion_proof_{userhash}.json");
-                let inclusion_proof_json = serde_json::to_string(&inclusion_proof)?;
-                std::fs::write(inclusion_filename, inclusion_proof_json)?;
+                let inclusion_filename = inclusion_proof_path(&args.out_dir, userhash, ext)?;
+                write_to_file(&inclusion_filename, &inclusion_proof, cli.format)?;
             } else {
                 log_error!("No user hash provided for inclusion proof.");
                 return Ok(());
             }
         }
-        Commands::Verify => {
+        Commands::RebuildMerkleTree(args) => {
+            log_info!("Reading and deserializing ledger...");
+            let mut ledger = get_ledger_values_from_file("private_ledger.json");
+            for ledger_file in &args.merge {
+                log_info!("Merging ledger {}...", ledger_file);
+                ledger = ledger.merge(get_ledger_values_from_file(ledger_file))?;
+            }
+
+            let nonces: Vec<u64> = read_from_file(&format!("private_nonces.{ext}"))?;
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))
+                .context(format_error(&format!("Failed to read final_proof.{ext}")))?;
+
+            assert_config(&final_proof, cli.ignore_version)?;
+            ledger.apply_proof_dust(&final_proof)?;
+
+            log_info!("Rebuilding the merkle tree...");
+            let merkle_tree = rebuild_merkle_tree(ledger, &nonces, &final_proof.prover_config())?;
+            check_merkle_root(&final_proof, &merkle_tree).context(format_error("Failed to rebuild the merkle tree"))?;
+            log_success!("Merkle tree rebuilt successfully, the root hash matches the final proof!");
+
+            write_to_file(&format!("merkle_tree.{ext}"), &merkle_tree, cli.format)?;
+        }
+        Commands::ListUsers(args) => {
+            let merkle_tree: MerkleTree = read_from_file(&format!("merkle_tree.{ext}"))
+                .context(format_error(&format!("Failed to read merkle_tree.{ext}")))?;
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))
+                .context(format_error(&format!("Failed to read final_proof.{ext}")))?;
+            check_merkle_root(&final_proof, &merkle_tree).context(format_error("Failed to list the users"))?;
+
+            // the dust accounts are not in the merkle tree, aggregate them as when proving to keep the leaf positions
+            let mut ledger = get_ledger_values_from_file(&args.ledger);
+            ledger.apply_proof_dust(&final_proof)?;
+
+            // the leafs are recomputed from the ledger and nonces, so only the committed users are listed
+            let nonces_file = if args.nonces_by_hash {
+                format!("private_nonces_by_hash.{ext}")
+            } else {
+                format!("private_nonces.{ext}")
+            };
+            let nonces = read_nonces(&nonces_file)?;
+            let nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).context(format_error("Invalid nonces file"))?;
+
+            let users = committed_user_hashes(&merkle_tree, &ledger, &nonces).context(format_error("Failed to list the users"))?;
+            let csv = std::iter::once("user_hash,leaf_hash".to_string())
+                .chain(users.iter().map(|(user_hash, leaf_hash)| format!("{user_hash},{}", bytes_to_hex(leaf_hash))))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            match &args.output {
+                Some(output) => {
+                    std::fs::write(output, csv + "\n").context(format_error(&format!("Failed to write {output}")))?;
+                    log_success!("{} user hashes written to {}", users.len(), output);
+                }
+                None => println!("{csv}"),
+            }
+        }
+        Commands::TreeDot(args) => {
+            let merkle_tree: MerkleTree = read_from_file(&format!("merkle_tree.{ext}"))
+                .context(format_error(&format!("Failed to read merkle_tree.{ext}")))?;
+
+            let dot = merkle_tree.to_dot(args.max_nodes);
+            std::fs::write(&args.output, &dot).context(format_error(&format!("Failed to write {}", args.output)))?;
+
+            if dot.contains("truncated [") {
+                log_warning!("The tree has more than {} nodes, the graph was truncated (see --max-nodes)", args.max_nodes);
+            }
+            log_success!("Merkle tree graph written to {}", args.output);
+        }
+        Commands::PublishRoot => {
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))
+                .context(format_error(&format!("Failed to read final_proof.{ext}")))?;
+            let merkle_tree: MerkleTree = read_from_file(&format!("merkle_tree.{ext}"))
+                .context(format_error(&format!("Failed to read merkle_tree.{ext}")))?;
+
+            // only publish a root hash that belongs to the proof
+            check_merkle_root(&final_proof, &merkle_tree).context(format_error("Failed to publish the root hash"))?;
+
+            let root_hash = merkle_tree.root.hash().clone().unwrap();
+            println!("======================");
+            println!("Proof generation date: {}", utils::util::format_timestamp(final_proof.timestamp).unwrap());
+            println!("Proof generation timestamp (ms): {}", final_proof.timestamp);
+            println!("Root hash (hex): {}", merkle_tree.root_hash_hex().unwrap());
+            println!(
+                "Root hash (base64, as in the inclusion proofs): {}",
+                base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &root_hash)
+            );
+            println!("Proof commitment (hex): {}", bytes_to_hex(&final_proof.commitment()));
+            println!("======================");
+        }
+        Commands::VerifyTree(args) => {
+            // parse the root before reading the (potentially huge) tree
+            let root = hex_to_bytes(&args.root).context(format_error("Invalid root hash"))?;
+            let tree = args.tree.clone().unwrap_or_else(|| format!("merkle_tree.{ext}"));
+            let merkle_tree: MerkleTree = read_from_file(&tree)
+                .context(format_error(&format!("Failed to read {tree}")))?;
+
+            log_info!("Verifying the merkle tree against the published root hash...");
+            verify_tree_against_root(&merkle_tree, &root).context(format_error("Merkle tree verification failed"))?;
+            log_success!("The merkle tree is consistent and hashes to the published root hash!");
+        }
+        Commands::Summary(args) => {
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))
+                .context(format_error(&format!("Failed to read final_proof.{ext}")))?;
+
+            let summary = serde_json::to_string_pretty(&final_proof.summary())?;
+            std::fs::write(&args.output, summary)
+                .context(format_error(&format!("Failed to write the proof summary: {}", args.output)))?;
+            log_success!("Proof summary written to {} (run verify to check the proof it was read from)", args.output);
+        }
+        Commands::Annotate(args) => {
+            let bytes = std::fs::read(&args.proof).context(format_error(&format!("Failed to read {}", args.proof)))?;
+            let format = SerializationFormat::detect(&bytes);
+            let mut final_proof: FinalProof =
+                from_bytes(&bytes).context(format_error(&format!("Failed to deserialize {}", args.proof)))?;
+            check_final_proof_shape(&final_proof).context(format_error(&format!("Invalid final proof {}", args.proof)))?;
+            // the commitment covers the verifier data, so detached verifier data is attached while annotating
+            let detached = final_proof
+                .load_detached_verifier_data(&args.proof)
+                .context(format_error("Failed to attach the verifier data"))?;
+
+            let sign_key = match &args.sign_key {
+                Some(path) => Some(read_signing_key(path).context(format_error("Failed to read the signing key"))?),
+                None => None,
+            };
+
+            let commitment = final_proof.commitment();
+            final_proof
+                .annotate(
+                    args.exchange_id.clone(),
+                    args.anchor_url.clone(),
+                    sign_key.as_ref(),
+                    chrono::Utc::now().timestamp_millis() as u64,
+                )
+                .context(format_error("Failed to annotate the proof"))?;
+            // the annotations are metadata only, the proof and its commitment are untouched
+            if commitment != final_proof.commitment() {
+                return Err(anyhow::anyhow!(format_error("The annotations changed the commitment of the proof")));
+            }
+            if let (Some(_), Some(public_key)) = (&sign_key, &final_proof.annotations.public_key) {
+                log_info!("Commitment signed with the ed25519 public key {public_key}");
+            }
+
+            if detached {
+                final_proof.detach_verifier_data()?;
+            }
+            let output = args.output.clone().unwrap_or(args.proof.clone());
+            write_to_file(&output, &final_proof, format)?;
+            log_success!("Annotated proof written to {} (commitment: {})", output, bytes_to_hex(&commitment));
+        }
+        Commands::Migrate(args) => {
+            let output = args.output.clone().unwrap_or(args.file.clone());
+            let migrated = migrate_file(&args.file, &output)
+                .context(format_error(&format!("Failed to migrate {}", args.file)))?;
+
+            if migrated.from_version == CURRENT_FORMAT_VERSION {
+                log_info!("The {} is already in the current format version ({})", migrated.artifact, CURRENT_FORMAT_VERSION);
+            } else {
+                log_success!(
+                    "Migrated the {} from format version {} to {}, written to {}",
+                    migrated.artifact,
+                    migrated.from_version,
+                    CURRENT_FORMAT_VERSION,
+                    output
+                );
+                // only the final proofs record the prover version (a 1.x or 2.x final proof is rejected by migrate_file)
+                if migrated.artifact != "final proof" {
+                    log_warning!(
+                        "The {} doesn't record the prover version: if it was written by plonky2_por 1.x or 2.x, its leaf \
+                         hashes differ from this version and it will not verify",
+                        migrated.artifact
+                    );
+                }
+            }
+        }
+        #[cfg(feature = "fixtures")]
+        Commands::GenFixture(args) => {
+            log_info!("Generating the test vector...");
+            let test_vector = generate_test_vector(test_vector_ledger(), args.seed, &ProverConfig::testing())?;
+
+            if let Some(dir) = std::path::Path::new(&args.output).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+            std::fs::write(&args.output, serde_json::to_string_pretty(&test_vector)? + "\n")
+                .with_context(|| format!("Failed to write {}", args.output))?;
+
+            log_success!("Test vector written to {} (root hash {})", args.output, test_vector.root_hash);
+        }
+        Commands::Verify(args) => {
             log_info!("Verifying the proof of reserves...");
-            let final_proof_file = std::fs::read_to_string("final_proof.json")?;
-            let final_proof: FinalProof = serde_json::from_str(&final_proof_file)?;
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))?;
 
-            let merkle_tree_file = std::fs::read_to_string("merkle_tree.json")?;
-            let merkle_tree: MerkleTree = serde_json::from_str(&merkle_tree_file)?;
+            // without the merkle tree only the proof and the reserves can be verified, so it has to be asked for
+            let merkle_tree_file = format!("merkle_tree.{ext}");
+            if !args.proof_only && !std::path::Path::new(&merkle_tree_file).exists() {
+                return Err(anyhow::anyhow!(format_error(&format!(
+                    "{merkle_tree_file} not found, use --proof-only to verify the proof without the merkle tree"
+                ))));
+            }
 
-            assert_config(&final_proof);
+            assert_config(&final_proof, cli.ignore_version)?;
 
-            verify_root(final_proof, merkle_tree);
+            // check the quote asset and the asset filter before the (slow) verification
+            if let Some(quote) = &args.quote {
+                validate_quote_asset(&final_proof, quote).context(format_error("Invalid quote asset"))?;
+            }
+            if let Some(assets) = &args.assets {
+                final_proof.selected_asset_order(assets).context(format_error("Invalid asset filter"))?;
+            }
+
+            // parse the options (e.g. the expected digest) before the (slow) verification
+            let options = args.options()?;
+
+            // the exported reserves are read as verified, so they are refused when a check is skipped
+            let full_verification = options.is_full() && !args.proof_only;
+            if !full_verification && (args.reserves_csv.is_some() || args.quote.is_some()) {
+                return Err(anyhow::anyhow!(format_error(
+                    "--reserves-csv and --quote need a full verification, they can't be combined with --proof-only or a --skip-* flag (without --expected-digest for --skip-rebuild-circuit)"
+                )));
+            }
+
+            if args.proof_only {
+                verify_proof_only(&final_proof, &options).context(format_error("Failed to verify the proof"))?;
+            } else if args.stream {
+                verify_root_streaming(&final_proof, &merkle_tree_file, &options)
+                    .context(format_error("Failed to verify the proof"))?;
+            } else {
+                let merkle_tree: MerkleTree = read_from_file(&merkle_tree_file)?;
+                verify_root(final_proof.clone(), merkle_tree, &options);
+            }
+
+            if let Some(quote) = &args.quote {
+                print_reserves_in_quote(&final_proof, quote, args.assets.as_deref())?;
+            }
+
+            // only reached if the full verification succeeded (verify_root panics otherwise)
+            if let Some(reserves_csv) = &args.reserves_csv {
+                write_reserves_csv(&final_proof, reserves_csv, args.assets.as_deref())?;
+                log_success!("Reserves written to {}", reserves_csv);
+            }
         }
         Commands::VerifyInclusion => {
             println!("Verifying inclusion proofs with a predefined pattern...");
-            let final_proof_file = std::fs::read_to_string("final_proof.json")
-                .context(format_error("Failed to read final_proof.json"))?;
-            let final_proof: FinalProof = serde_json::from_str(&final_proof_file)
-                .context(format_error("Failed to deserialize final_proof.json"))?;
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))
+                .context(format_error(&format!("Failed to read final_proof.{ext}")))?;
 
-            assert_config(&final_proof);
+            assert_config(&final_proof, cli.ignore_version)?;
 
-            let pattern = r"^inclusion_proof_.*\.json$";
+            let pattern = r"^inclusion_proof_.*\.(json|bin|msgpack)$";
             let re = Regex::new(pattern).context(format_error("Failed to create regex"))?;
 
             let entries =
                 std::fs::read_dir(".").context(format_error("Failed to read current directory"))?;
 
-            // iterate over the entries in the current directory
-            for entry in entries.flatten() {
-                // check the filename against the regex
-                let filename = entry.file_name().to_string_lossy().to_string();
+            // collect the entries of the current directory matching the regex (sorted, so the first failure is stable)
+            let mut files = entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|filename| re.is_match(filename))
+                .collect::<Vec<_>>();
+            files.sort();
 
-                if re.is_match(&filename) {
-                    log_info!("Found and verifying inclusion proof file: {}", filename);
+            // the global proof is verified once, the inclusion proofs are verified in parallel
+            let report = verify_inclusion_files(&final_proof, &files).context(format_error("Failed to verify the global proof"))?;
 
-                    // Read and deserialize the inclusion proof file
-                    let inclusion_proof_file: String = std::fs::read_to_string(entry.path())
-                        .context(format_error(&format!(
-                            "Failed to read inclusion proof file: {filename}"
-                        )))?;
+            println!();
+            if let Some((filename, error)) = &report.first_failure {
+                return Err(anyhow::anyhow!(format_error(&format!(
+                    "{} of {} inclusion proofs are not valid (first failing file: {filename}: {error})",
+                    report.invalid,
+                    files.len()
+                ))));
+            }
+            log_success!("All {} inclusion proofs are valid!", report.valid);
+        }
+        Commands::InclusionSummary(args) => {
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))
+                .context(format_error(&format!("Failed to read final_proof.{ext}")))?;
+            let inclusion_proof: InclusionProof = read_from_file(&args.inclusion_proof)
+                .context(format_error(&format!(
+                    "Failed to read inclusion proof file: {}",
+                    args.inclusion_proof
+                )))?;
+
+            let expected_digest = parse_expected_digest(args.expected_digest.as_deref())?;
+            let summary = inclusion_summary(&final_proof, &inclusion_proof, expected_digest.as_deref());
+            print!("{summary}");
+
+            if let Some(output) = &args.output {
+                std::fs::write(output, &summary)?;
+                log_success!("Summary written to {}", output);
+            }
+        }
+        Commands::CircuitDigest(args) => {
+            // the shape is read from final_proof only for the values that are not given
+            let needs_proof = args.asset_count.is_none()
+                || args.tree_depth.is_none()
+                || args.batch_size.is_none()
+                || args.recursive_size.is_none();
+            let final_proof: Option<FinalProof> = if needs_proof {
+                std::path::Path::new(&format!("final_proof.{ext}"))
+                    .exists()
+                    .then(|| read_from_file(&format!("final_proof.{ext}")))
+                    .transpose()?
+            } else {
+                None
+            };
 
-                    let inclusion_proof: InclusionProof =
-                        serde_json::from_str(&inclusion_proof_file).context(format_error(
-                            &format!("Failed to deserialize inclusion proof file: {filename}"),
-                        ))?;
+            let asset_count = args
+                .asset_count
+                .or(final_proof.as_ref().map(|proof| proof.asset_names.len()))
+                .context(format_error("Missing --asset-count (no final proof to read it from)"))?;
+            let tree_depth = args
+                .tree_depth
+                .or(final_proof.as_ref().map(|proof| proof.tree_depth))
+                .context(format_error("Missing --tree-depth (no final proof to read it from)"))?;
+            let default_config = final_proof.as_ref().map(|proof| proof.prover_config()).unwrap_or_default();
+            let config = ProverConfig {
+                batch_size: args.batch_size.unwrap_or(default_config.batch_size),
+                recursive_size: args.recursive_size.unwrap_or(default_config.recursive_size),
+            };
 
-                    // Verify the inclusion proof
-                    verify_user_inclusion(final_proof.clone(), inclusion_proof);
+            log_info!("Rebuilding the root circuit... This might take several minutes...");
+            let digest = expected_root_circuit_digest(asset_count, tree_depth, &config)?;
 
-                    log_success!(
-                        "Successfully verified inclusion proof for file: {}",
-                        filename
-                    );
+            println!("======================");
+            println!(
+                "Assets: {}, tree depth: {}, batch size: {}, recursive size: {}",
+                asset_count, tree_depth, config.batch_size, config.recursive_size
+            );
+            println!("Root circuit digest (hex): {}", bytes_to_hex(&digest));
+            println!("======================");
+        }
+        Commands::BenchVerify(args) => {
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))?;
+            let merkle_tree: MerkleTree = read_from_file(&format!("merkle_tree.{ext}"))?;
+
+            assert_config(&final_proof, cli.ignore_version)?;
+
+            log_info!("Benchmarking the verification ({} runs)... This might take several minutes...", args.runs);
+            let benchmark = bench_verify(&final_proof, &merkle_tree, args.runs).context(format_error("Verification failed"))?;
+
+            let print_stats = |phase: &str, durations: &[std::time::Duration]| {
+                if let Some((min, median, max)) = duration_stats(durations) {
+                    println!("{phase}: min {:?}, median {:?}, max {:?}", min, median, max);
                 }
+            };
+
+            println!("======================");
+            println!(
+                "Assets: {}, tree depth: {}, batch size: {}, recursive size: {}",
+                final_proof.asset_names.len(),
+                final_proof.tree_depth,
+                final_proof.batch_size,
+                final_proof.recursive_size
+            );
+            println!("Root circuit rebuild (once, cached for the runs): {:?}", benchmark.rebuild);
+            print_stats("Proof check", &benchmark.proof_checks);
+            print_stats("Merkle tree check", &benchmark.merkle_checks);
+            println!("======================");
+        }
+        Commands::Padding(args) => {
+            let config = ProverConfig {
+                batch_size: args.batch_size,
+                recursive_size: args.recursive_size,
+            };
+            let report = config.padding_report(args.accounts).context(format_error("Failed to compute the padding"))?;
+
+            println!("======================");
+            println!("Accounts: {}, batch size: {}, recursive size: {}", report.leaf_count, config.batch_size, config.recursive_size);
+            println!("Batch proofs: {} ({} padding accounts in the last batch)", report.batch_count, report.padding_accounts);
+            println!("Padding batches: {}", report.padding_batches);
+            println!("Leaves with padding: {} ({:.2}% padding)", report.padded_leaf_count, report.padding_percentage());
+            println!("Merkle tree depth: {}", report.tree_depth);
+            match report.accounts_to_drop_level {
+                Some(accounts) => println!("Removing {accounts} accounts would drop a tree level"),
+                None => println!("The tree already has a single recursive level"),
             }
+            println!("======================");
+        }
+        Commands::CheckLeaf(args) => {
+            let inclusion_proof: InclusionProof = read_from_file(&args.inclusion_proof)
+                .context(format_error(&format!(
+                    "Failed to read inclusion proof file: {}",
+                    args.inclusion_proof
+                )))?;
+            let balances = read_balances_file(&args.balances, ext)
+                .context(format_error(&format!("Failed to read balances file: {}", args.balances)))?;
+
+            // compute the leaf from your own data and walk the merkle path of the inclusion proof
+            let leaf_hash = compute_leaf_hash(&balances, &args.hash, args.nonce)
+                .context(format_error("Failed to compute the leaf hash"))?;
+            let root_hash = inclusion_proof.calculate_merkle_root_hash(leaf_hash.clone());
+
+            println!("======================");
+            println!("Leaf hash (hex): {}", bytes_to_hex(&leaf_hash));
+            println!("Calculated root hash (hex): {}", bytes_to_hex(&root_hash));
+            println!(
+                "Calculated root hash (base64): {}",
+                base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &root_hash)
+            );
+            println!("======================");
+
+            if root_hash != inclusion_proof.root_hash {
+                return Err(anyhow::anyhow!(format_error(
+                    "The calculated root hash does not match the inclusion proof, check your hash, balances and nonce"
+                )));
+            }
+            log_success!("The calculated root hash matches the inclusion proof, compare it with the published root hash");
+        }
+        Commands::CheckInclusionFile(args) => {
+            let inclusion_proof: InclusionProof = read_from_file(&args.path)
+                .context(format_error(&format!("Failed to read inclusion proof file: {}", args.path)))?;
+            inclusion_proof
+                .validate_structure()
+                .context(format_error("The inclusion proof file is malformed"))?;
+
+            // the leaf is hashed from the balances, user hash and nonce of the file
+            let leaf_hash = compute_leaf_hash(&inclusion_proof.user_balances, &inclusion_proof.user_hash, inclusion_proof.nonce)?;
+            let root_hash = inclusion_proof.calculate_merkle_root_hash(leaf_hash.clone());
+            let level_widths = inclusion_proof
+                .level_widths()
+                .iter()
+                .map(|width| width.to_string())
+                .collect::<Vec<_>>();
+
+            println!("======================");
+            println!("User hash: {}", inclusion_proof.user_hash);
+            println!("Merkle proof levels (hashes per level, from the leaf): {}", level_widths.join(", "));
+            println!("Leaf hash (hex): {}", bytes_to_hex(&leaf_hash));
+            println!("Calculated root hash (hex): {}", bytes_to_hex(&root_hash));
+            println!(
+                "Calculated root hash (base64): {}",
+                base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &root_hash)
+            );
+            println!("======================");
+
+            if root_hash != inclusion_proof.root_hash {
+                return Err(anyhow::anyhow!(format_error(
+                    "The calculated root hash does not match the root hash of the file, the inclusion proof is inconsistent"
+                )));
+            }
+            log_success!(
+                "The inclusion proof file is well-formed, compare the root hash with the published one (or run verify-inclusion)"
+            );
+        }
+        Commands::CheckInclusionDir(args) => {
+            let root = hex_to_bytes(&args.root).context(format_error("Invalid root hash"))?;
+            let report = check_inclusion_dir(&args.dir, &root).context(format_error("Failed to check the inclusion proofs"))?;
+
             println!();
-            log_success!("All inclusion proofs are valid!");
+            if let Some((filename, error)) = &report.first_failure {
+                return Err(anyhow::anyhow!(format_error(&format!(
+                    "{} of {} inclusion proofs don't recompute to the published root hash (first failing file: {filename}: {error})",
+                    report.invalid,
+                    report.valid + report.invalid
+                ))));
+            }
+            log_success!(
+                "All {} inclusion proofs recompute to the published root hash (run verify-inclusion for the full verification)",
+                report.valid
+            );
+        }
+        Commands::ArchiveAppend(args) => {
+            let archive_file = args.archive.clone().unwrap_or(format!("proof_archive.{ext}"));
+            let final_proof = FinalProof::read(&format!("final_proof.{ext}"))
+                .context(format_error(&format!("Failed to read final_proof.{ext}")))?;
+
+            let mut archive: ProofArchive = if std::path::Path::new(&archive_file).exists() {
+                read_from_file(&archive_file)?
+            } else {
+                log_info!("Creating a new proof archive: {}", archive_file);
+                ProofArchive::default()
+            };
+
+            archive.append(final_proof).context(format_error("Failed to append the proof to the archive"))?;
+            write_to_file(&archive_file, &archive, cli.format)?;
+            log_success!("Proof appended to {} ({} snapshots)", archive_file, archive.snapshots.len());
+        }
+        Commands::VerifyArchive(args) => {
+            let archive_file = args.archive.clone().unwrap_or(format!("proof_archive.{ext}"));
+            log_info!("Verifying the proof archive {}...", archive_file);
+            let archive: ProofArchive = read_from_file(&archive_file)
+                .context(format_error(&format!("Failed to read {archive_file}")))?;
+
+            for snapshot in &archive.snapshots {
+                assert_config(snapshot, cli.ignore_version)?;
+            }
+
+            let options = VerifyOptions {
+                rebuild_circuit: !args.skip_rebuild_circuit,
+                max_tree_depth: args.max_tree_depth,
+                ..VerifyOptions::default()
+            };
+            verify_archive(&archive, &options);
+        }
+        #[cfg(target_family = "unix")]
+        Commands::Status => {
+            let Some(status) = query_server_status()? else {
+                log_warning!("The prover server is not running");
+                return Ok(());
+            };
+
+            // the status is a list of key=value pairs
+            let fields: std::collections::HashMap<&str, &str> = status
+                .split_whitespace()
+                .filter_map(|field| field.split_once('='))
+                .collect();
+
+            log_success!("The prover server is running");
+            if let Some(timestamp) = fields.get("timestamp").and_then(|t| t.parse::<u64>().ok()) {
+                println!("Loaded proof date: {}", utils::util::format_timestamp(timestamp).unwrap());
+                println!("Loaded proof timestamp (ms): {timestamp}");
+            }
+            if let Some(accounts) = fields.get("accounts") {
+                println!("Accounts: {accounts}");
+            }
+            if let Some(uptime) = fields.get("uptime_secs") {
+                println!("Uptime: {uptime}s");
+            }
+            if let (Some(hits), Some(misses)) = (fields.get("cache_hits"), fields.get("cache_misses")) {
+                println!("Inclusion proof cache: {hits} hits, {misses} misses");
+            }
         }
         Commands::Version => {
             println!("PoR version: v{}", env!("CARGO_PKG_VERSION"));
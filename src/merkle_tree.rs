@@ -1,12 +1,16 @@
-use crate::{config::*, utils::util::hash_n_subhashes, types::*};
+use crate::{config::*, utils::{logger::format_error, util::{bytes_to_hex, hash_n_subhashes}}, types::*};
+use anyhow::{anyhow, Result};
+use plonky2::hash::hash_types::HashOut;
 use plonky2::plonk::config::GenericHashOut;
+use rayon::prelude::*;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
 use serde::{Deserialize, Serialize};
-use crate::custom_serializer::base64;
+use crate::custom_serializer::{base64, format};
 
 // This module implements a Merkle tree structure for storing and verifying data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
-    #[serde(serialize_with = "base64::serialize_option", deserialize_with = "base64::deserialize_option")]
+    #[serde(serialize_with = "base64::serialize_option", deserialize_with = "base64::deserialize_hash_option")]
     hash: Option<Vec<u8>>,
     children: Option<Vec<Node>>,
 }
@@ -25,6 +29,11 @@ impl Node {
         &self.hash
     }
 
+    // Returns the children of the node (None for leaves and padding nodes).
+    pub fn children(&self) -> &Option<Vec<Node>> {
+        &self.children
+    }
+
     pub fn set_hash(&mut self, hash: Vec<u8>) {
         self.hash = Some(hash);
     }
@@ -33,6 +42,18 @@ impl Node {
         self.children = Some(children);
     }
 
+    fn collect_nodes_at_depth<'a>(&'a self, target_depth: usize, result: &mut Vec<&'a Node>, current_depth: usize) {
+        if current_depth == target_depth {
+            result.push(self);
+        } else if current_depth < target_depth
+            && let Some(ref children) = self.children
+        {
+            for child in children {
+                child.collect_nodes_at_depth(target_depth, result, current_depth + 1);
+            }
+        }
+    }
+
     fn collect_nodes_at_depth_mut<'a>(
         &'a mut self,
         target_depth: usize,
@@ -53,8 +74,23 @@ impl Node {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MerkleTree {
+    // schema version (see CURRENT_FORMAT_VERSION), checked on load
+    #[serde(default = "format::legacy_format_version", deserialize_with = "format::deserialize_format_version")]
+    pub format_version: u32,
     pub root: Node,
     pub depth: usize,
+    // fan-out of the nodes at each depth, from the root (depth 1) to the batch level (depth n-1)
+    // empty for trees serialized before it was recorded, in which case it is derived from the tree structure
+    #[serde(default)]
+    pub level_arities: Vec<usize>,
+    // number of real leafs (accounts, dust aggregate and reserve buffer), the padding leafs come after them
+    // None for trees serialized before it was recorded (and trees built without it), see real_leaf_count()
+    #[serde(default)]
+    pub real_leaf_count: Option<usize>,
+    // reserves (summed balances of each asset) of each non-padding batch, in batch order, only used by the deep check
+    // of the verifier (see check_batch_reserves), empty for trees serialized before they were recorded
+    #[serde(default)]
+    pub batch_reserves: Vec<Vec<i64>>,
 }
 
 // This struct represents an adapted Merkle tree, which is not a binary tree where each non-leaf node is the hash of its children.
@@ -66,24 +102,32 @@ pub struct MerkleTree {
 // Depth n --> leaves (merkle.depth)
 
 impl MerkleTree {
-    // Creates a new Merkle tree recursively with the given root hash and proof.
+    // Creates a new Merkle tree recursively with the given root hash and proof (with the default circuit sizes).
     pub fn new_from_leafs(leafs: Vec<Node>, depth: usize, batch: bool) -> Self {
+        Self::new_from_leafs_with_config(leafs, depth, batch, &ProverConfig::default())
+    }
+
+    // Creates a new Merkle tree recursively, the batch level has config.batch_size children and the recursive levels config.recursive_size.
+    pub fn new_from_leafs_with_config(leafs: Vec<Node>, depth: usize, batch: bool, config: &ProverConfig) -> Self {
+        let ProverConfig { batch_size, recursive_size } = *config;
+
         // recursively generate the entire tree structure from the leafs
         let mut nodes = Vec::new();
 
-        // if batch is true, chunk the leafs into BATCH_SIZE length chunks --> only in the first depth
+        // if batch is true, chunk the leafs into batch_size length chunks --> only in the first depth
         let mut padded_nodes = Vec::new();
+        let arity = if batch { batch_size } else { recursive_size };
         let chunks = if batch {
-            // account leafs are already padded with BATCH_SIZE, but we need to pad the batch_circuit nodes
-            leafs.chunks(BATCH_SIZE)           
+            // account leafs are already padded with batch_size, but we need to pad the batch_circuit nodes
+            leafs.chunks(batch_size)
         } else {
-            // must pad to be multiple of RECURSIVE_SIZE (if it is not the root)
-            leafs.chunks(RECURSIVE_SIZE)
+            // must pad to be multiple of recursive_size (if it is not the root)
+            leafs.chunks(recursive_size)
         };
 
-        // pad to be multiple of RECURSIVE_SIZE
-        if chunks.len() % RECURSIVE_SIZE != 0 {
-            let padding_size = RECURSIVE_SIZE - (chunks.len() % RECURSIVE_SIZE);
+        // pad to be multiple of recursive_size
+        if chunks.len() % recursive_size != 0 {
+            let padding_size = recursive_size - (chunks.len() % recursive_size);
             for _ in 0..padding_size {
                 padded_nodes.push(Node::new(None));
             }
@@ -99,16 +143,30 @@ impl MerkleTree {
         // if there is only one node (and it is not the batch circuit), set it as the root
         if nodes.len() == 1 && !batch {
             Self {
+                format_version: CURRENT_FORMAT_VERSION,
                 root: nodes[0].clone(),
                 depth: depth + 1, // minimum depth is 2 --> 1 for the leafs and 1 for the root
+                level_arities: vec![leafs.len()],
+                real_leaf_count: None,
+                batch_reserves: Vec::new(),
             }
         } else {
             // otherwise, include the padding chunks and continue recursively generating the tree
             nodes.extend(padded_nodes);
-            Self::new_from_leafs(nodes, depth + 1, false)
+            let mut tree = Self::new_from_leafs_with_config(nodes, depth + 1, false, config);
+
+            // the upper levels are already recorded, this level is the deepest so far
+            tree.level_arities.push(arity);
+            tree
         }
     }
 
+    // Records the number of real leafs (the first leafs of the tree, the rest are padding), see real_leaf_count().
+    pub fn with_real_leaf_count(mut self, real_leaf_count: usize) -> Self {
+        self.real_leaf_count = Some(real_leaf_count);
+        self
+    }
+
     pub fn get_nodes_from_depth(&mut self, depth: usize) -> Vec<&mut Node> {
         let mut result = Vec::new();
 
@@ -129,35 +187,200 @@ impl MerkleTree {
         }
 
         new_tree.depth -= 1;
+        new_tree.level_arities = self.level_arities()[..self.depth - 2].to_vec();
 
         new_tree
     }
 
+    // Returns the root hash as a lowercase hex string (None if the tree is not populated), e.g. to publish it.
+    pub fn root_hash_hex(&self) -> Option<String> {
+        self.root
+            .hash
+            .as_ref()
+            .map(|hash| bytes_to_hex(hash))
+    }
+
+    // Returns the number of leaf slots of the tree (accounts and padding), the product of the level arities.
+    pub fn leaf_count(&self) -> usize {
+        self.level_arities().iter().product()
+    }
+
+    // Returns the number of real leafs: the accounts, the dust aggregate and the reserve buffer leafs.
+    // It is recorded when the tree is built by the prover. For older trees it falls back to counting the leafs with a
+    // non-zero hash, which also counts the padding accounts of the last batch (hashed like accounts with a random nonce).
+    pub fn real_leaf_count(&self) -> usize {
+        match self.real_leaf_count {
+            Some(real_leaf_count) => real_leaf_count,
+            None => self.leaf_hashes().len(),
+        }
+    }
+
+    // Returns the hashes of the real leafs in leaf order (see real_leaf_count), i.e. the leaf hashes of the accounts
+    // followed by the dust aggregate and reserve buffer leafs.
+    pub fn leaf_hashes(&self) -> Vec<Vec<u8>> {
+        let leafs = self.stored_leafs();
+
+        match self.real_leaf_count {
+            Some(real_leaf_count) => leafs
+                .iter()
+                .take(real_leaf_count)
+                .filter_map(|leaf| leaf.hash.clone())
+                .collect(),
+            // older trees: the leafs of the padding batches have the zero hash by convention
+            None => {
+                let zero_hash = HashOut::<F>::default().to_bytes();
+                leafs
+                    .iter()
+                    .filter_map(|leaf| leaf.hash.clone())
+                    .filter(|hash| *hash != zero_hash)
+                    .collect()
+            }
+        }
+    }
+
+    // Returns the leafs stored in the tree (the children of the batch nodes) in leaf order.
+    // The leafs of the padding batches are not stored.
+    fn stored_leafs(&self) -> Vec<&Node> {
+        // the batch nodes are walked once, without cloning the tree
+        let mut batch_nodes = Vec::new();
+        self.root.collect_nodes_at_depth(self.depth - 1, &mut batch_nodes, 1);
+
+        batch_nodes
+            .into_iter()
+            .filter_map(|node| node.children.as_ref())
+            .flatten()
+            .collect()
+    }
+
+    // Returns the number of non-padding batch nodes (the batch nodes with leafs).
+    pub fn batch_count(&self) -> usize {
+        let mut batch_nodes = Vec::new();
+        self.root.collect_nodes_at_depth(self.depth - 1, &mut batch_nodes, 1);
+
+        batch_nodes.iter().filter(|node| node.children.is_some()).count()
+    }
+
+    // Returns the number of padding leaf slots (padding accounts and the leafs of the padding batches).
+    pub fn padding_leaf_count(&self) -> usize {
+        self.leaf_count() - self.real_leaf_count()
+    }
+
+    // Renders the tree as a Graphviz DOT graph (debugging aid for small test trees), breadth-first from the root.
+    // Each node shows its depth and the first bytes of its hash, padding nodes (no hash or children) are dashed.
+    // At most max_nodes nodes are emitted, the rest are summarized in a single "truncated" node.
+    pub fn to_dot(&self, max_nodes: usize) -> String {
+        let mut dot = String::from("digraph merkle_tree {\n    node [shape=box, fontname=\"monospace\"];\n");
+
+        // (node, id, depth) in breadth-first order, the children are only queued while there is room to emit them
+        let mut queue = std::collections::VecDeque::from([(&self.root, 0usize, 1usize)]);
+        let mut emitted = 0;
+        let mut skipped = 0;
+
+        while let Some((node, id, depth)) = queue.pop_front() {
+            let hash = match &node.hash {
+                Some(hash) => bytes_to_hex(&hash[..hash.len().min(4)]),
+                None => "none".to_string(),
+            };
+            let style = if node.children.is_none() && depth < self.depth { ", style=dashed" } else { "" };
+            dot.push_str(&format!("    n{id} [label=\"d{depth}\\n{hash}\"{style}];\n"));
+            emitted += 1;
+
+            for child in node.children.iter().flatten() {
+                if emitted + queue.len() < max_nodes {
+                    let child_id = emitted + queue.len();
+                    dot.push_str(&format!("    n{id} -> n{child_id};\n"));
+                    queue.push_back((child, child_id, depth + 1));
+                } else {
+                    skipped += 1 + Self::count_descendants(child);
+                }
+            }
+        }
+
+        if skipped > 0 {
+            dot.push_str(&format!("    truncated [label=\"{skipped} more nodes (max_nodes = {max_nodes})\", shape=note];\n"));
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    fn count_descendants(node: &Node) -> usize {
+        node.children
+            .iter()
+            .flatten()
+            .map(|child| 1 + Self::count_descendants(child))
+            .sum()
+    }
+
+    // Returns the fan-out (number of children) of the nodes at each depth, from the root to the batch level.
+    pub fn level_arities(&self) -> Vec<usize> {
+        if self.level_arities.is_empty() {
+            self.derive_level_arities()
+        } else {
+            self.level_arities.clone()
+        }
+    }
+
+    // Derives the level arities from the tree structure by walking the first child of each level, which is never a padding node.
+    fn derive_level_arities(&self) -> Vec<usize> {
+        let mut arities = Vec::new();
+        let mut current_node = &self.root;
+
+        while let Some(children) = current_node.children.as_ref() {
+            arities.push(children.len());
+            current_node = &children[0];
+        }
+
+        arities
+    }
+
+    // Checks if the tree has the expected depth (e.g. the tree_depth of the final proof it belongs to).
+    // The depth field is also checked against the tree structure, so a tampered depth is detected.
+    pub fn check_depth(&self, expected_depth: usize) -> Result<()> {
+        let structure_depth = self.derive_level_arities().len() + 1;
+        if self.depth != structure_depth {
+            return Err(anyhow!(
+                "Merkle tree depth ({}) does not match its structure ({} levels)",
+                self.depth,
+                structure_depth
+            ));
+        }
+
+        if self.depth != expected_depth {
+            return Err(anyhow!(
+                "Merkle tree depth ({}) does not match the proof tree depth ({}), the merkle tree doesn't belong to this proof",
+                self.depth,
+                expected_depth
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn get_nth_leaf_path(&self, n: usize) -> Option<Vec<usize>> {
         // get the leaf at the nth position
+        let arities = self.level_arities();
+        if arities.len() + 1 != self.depth {
+            return None;
+        }
 
         let mut start_position = 0;
-        let mut node_leafs;
         let mut current_node = &self.root;
-        let mut path = Vec::new();
+        let mut path = vec![0]; // the root node is always 0
 
-        for current_depth in 1..self.depth {
-            // calculate what is the next node to enter
-            node_leafs = RECURSIVE_SIZE.pow(self.depth as u32 - current_depth as u32 - 1) * BATCH_SIZE;
+        for level in 0..arities.len() {
+            // number of leafs below each node of the next level (product of the fan-outs below it, 1 for the leafs)
+            let node_leafs: usize = arities[level + 1..].iter().product();
 
             // get the index of next node
             let index = (n - start_position) / node_leafs;
-            
-            current_node = &current_node.children.as_ref().unwrap()[index];
+
+            current_node = current_node.children.as_ref()?.get(index)?;
             start_position += index * node_leafs;
 
             path.push(index);
         }
 
-        // get the leaf index at the nth position
-        let leaf_index = n - start_position;
-        path.push(leaf_index);
-
         // return the path
         if path.len() == self.depth {
             return Some(path);
@@ -166,41 +389,42 @@ impl MerkleTree {
         None
     }
 
-    fn verify_recursive(root_node: &Node) -> bool{
+    // on_batch is called after each batch node (depth batch_depth, the root has depth 1) is verified
+    fn verify_recursive(root_node: &Node, depth: usize, batch_depth: usize, on_batch: &mut dyn FnMut()) -> bool{
         // check if the node is a leaf
-        if root_node.children.is_none() {
+        let Some(children) = &root_node.children else {
             return true;
-        }
+        };
 
-        // check if the node has children
-        if let Some(ref children) = root_node.children {
-            for child in children {
-                // recursively verify each child
-                Self::verify_recursive(child);
-            }
+        // recursively verify each child, a single invalid subtree invalidates the tree
+        if !children.iter().all(|child| Self::verify_recursive(child, depth + 1, batch_depth, on_batch)) {
+            return false;
         }
-
-        // check if the node has a hash
-        if root_node.hash.is_none() {
-            return false
+        if depth == batch_depth {
+            on_batch();
         }
 
-        // verify if the hash is the same as the hash of the children (Poseidon)
-        let children_hashes = root_node.children.as_ref().unwrap().iter()
-            .filter_map(|child| child.hash.clone())
-            .collect::<Vec<_>>();
-
-        let hash = hash_n_subhashes::<F, D>(&children_hashes).to_bytes();
-        if root_node.hash.as_ref().unwrap() != &hash {
-            return false
-        }
+        // check if the node and all its children have a hash
+        let Some(node_hash) = &root_node.hash else {
+            return false;
+        };
+        let Some(children_hashes) = children.iter().map(|child| child.hash.clone()).collect::<Option<Vec<_>>>() else {
+            return false;
+        };
 
-        true
+        // verify if the hash is the same as the hash of the children (Poseidon)
+        *node_hash == hash_n_subhashes::<F, D>(&children_hashes).to_bytes()
     }
 
     pub fn verify(&self) -> bool {
         // check if the tree is a valid merkle tree
-        Self::verify_recursive(&self.root)
+        self.verify_with_progress(|| {})
+    }
+
+    // same as verify, on_batch is called for every verified batch (batch_count times if the tree is valid), e.g. to
+    // report the progress of huge trees
+    pub fn verify_with_progress(&self, mut on_batch: impl FnMut()) -> bool {
+        Self::verify_recursive(&self.root, 1, self.depth - 1, &mut on_batch)
     }
 
     pub fn prove_inclusion(&self, path: Vec<usize>) -> MerkleProof {
@@ -208,10 +432,14 @@ impl MerkleTree {
         let mut merkle_proof: Option<MerkleProof> = None;
 
         let mut current_node = &self.root;
+        let arities = self.level_arities();
+
+        assert_eq!(path.len(), arities.len() + 1, "{}", format_error("Invalid path length for the merkle tree"));
 
         for i in 0..path.len()-1 {
             // get the left and right hashes related to the leaf path node
             let index = path[i+1]; // we use +1 to skip the root node (always 0 but it is included in the path)
+            assert!(index < arities[i], "{}", format_error("Invalid path index for the merkle tree"));
 
             let nodes = current_node.children.as_ref().unwrap();
             let hashes = nodes.iter().map(|node| node.hash.clone().unwrap()).collect::<Vec<_>>();
@@ -242,3 +470,468 @@ impl MerkleTree {
     }
 
 }
+
+// Low-memory alternative to MerkleTree for provers that only need the root hash and some inclusion paths.
+// The hashes of each level are stored in a flat array (HASH_BYTES per node) instead of nested nodes, with the same
+// structure and hashes as the populated MerkleTree (see compute_merkle_root). It is not serialized, use MerkleTree for that.
+pub struct CompactMerkleTree {
+    // levels[0] are the leafs (accounts and padding accounts) and the last level is the root
+    // the padding nodes are stored (root hashes of the empty proofs), the leafs of the padding batches are not
+    levels: Vec<Vec<u8>>,
+    config: ProverConfig,
+}
+
+impl CompactMerkleTree {
+    // Hashes all the levels from the leaf hashes (the accounts, padded to a multiple of config.batch_size).
+    pub fn new(leaf_hashes: &[Vec<u8>], config: &ProverConfig) -> Result<Self> {
+        let mut levels = vec![leaf_hashes.concat()];
+        let root_hash = hash_tree_levels(leaf_hashes, config, |level| levels.push(level))?;
+
+        levels.push(root_hash);
+        Ok(Self { levels, config: *config })
+    }
+
+    // fan-out of the parents of the nodes of a level (batch_size for the leafs)
+    fn arity(&self, level: usize) -> usize {
+        if level == 0 { self.config.batch_size } else { self.config.recursive_size }
+    }
+
+    fn node_hashes(&self, level: usize, nodes: std::ops::Range<usize>) -> Vec<Vec<u8>> {
+        self.levels[level][nodes.start * HASH_BYTES..nodes.end * HASH_BYTES]
+            .chunks(HASH_BYTES)
+            .map(|hash| hash.to_vec())
+            .collect()
+    }
+
+    // Returns the root hash (same as the root hash of the populated MerkleTree).
+    pub fn root_hash(&self) -> Vec<u8> {
+        self.levels.last().unwrap().clone()
+    }
+
+    // Returns the number of levels, including the leafs and the root (same as MerkleTree::depth).
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    // Returns the number of stored leafs (accounts and padding accounts).
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len() / HASH_BYTES
+    }
+
+    // Returns the merkle proof of the nth leaf, the same proof as MerkleTree::prove_inclusion(get_nth_leaf_path(n)).
+    pub fn merkle_proof(&self, n: usize) -> Result<MerkleProof> {
+        if n >= self.leaf_count() {
+            return Err(anyhow!("Leaf {} out of bounds (the merkle tree has {} leafs)", n, self.leaf_count()));
+        }
+
+        // index of the node of the path at each level, from the leaf to the root
+        let mut indices = vec![n];
+        for level in 0..self.levels.len() - 1 {
+            indices.push(indices[level] / self.arity(level));
+        }
+
+        // the proof of each level wraps the proof of the level above (from the children of the root to the leaf)
+        let mut merkle_proof: Option<MerkleProof> = None;
+        for level in (0..self.levels.len() - 1).rev() {
+            let index = indices[level];
+            let start = index - index % self.arity(level);
+            let end = (start + self.arity(level)).min(self.levels[level].len() / HASH_BYTES);
+
+            merkle_proof = Some(MerkleProof {
+                left_hashes: self.node_hashes(level, start..index),
+                right_hashes: self.node_hashes(level, index + 1..end),
+                parent_hashes: merkle_proof.map(Box::new),
+            });
+        }
+
+        Ok(merkle_proof.unwrap())
+    }
+}
+
+// Computes the root hash of the merkle tree of the given leaf hashes (the accounts, padded to a multiple of batch_size),
+// with the same structure as new_from_leafs_with_config and the same hashes as the circuits (see rebuild_merkle_tree),
+// without building the tree: only the hashes of the current level are kept in memory.
+// The padding nodes are the root hashes of the empty proofs (the empty batch leafs are zero hashes).
+pub fn compute_merkle_root(leaf_hashes: &[Vec<u8>], batch_size: usize, recursive_size: usize) -> Result<Vec<u8>> {
+    hash_tree_levels(leaf_hashes, &ProverConfig { batch_size, recursive_size }, drop)
+}
+
+// Hashes the levels above the leafs and returns the root hash, shared by compute_merkle_root and CompactMerkleTree.
+// Each level (HASH_BYTES per node), from the batch level to the children of the root, is handed to on_level once it is
+// padded and its parents are hashed. The batch level is never the root, so every level is padded to a multiple of
+// recursive_size with the root hash of an empty proof of its height.
+fn hash_tree_levels(leaf_hashes: &[Vec<u8>], config: &ProverConfig, mut on_level: impl FnMut(Vec<u8>)) -> Result<Vec<u8>> {
+    config.validate()?;
+    if leaf_hashes.is_empty() {
+        return Err(anyhow!("Cannot build a merkle tree without leafs"));
+    }
+    if !leaf_hashes.len().is_multiple_of(config.batch_size) {
+        return Err(anyhow!(
+            "{} leafs are not whole batches of {} leafs, the accounts must be padded to a multiple of the batch size",
+            leaf_hashes.len(),
+            config.batch_size
+        ));
+    }
+    if let Some(index) = leaf_hashes.iter().position(|hash| hash.len() != HASH_BYTES) {
+        return Err(anyhow!("Leaf {} is not a {} bytes hash", index, HASH_BYTES));
+    }
+
+    let mut level = leaf_hashes
+        .par_chunks(config.batch_size)
+        .flat_map_iter(|chunk| hash_n_subhashes::<F, D>(chunk).to_bytes())
+        .collect::<Vec<_>>();
+    let mut padding_hash =
+        hash_n_subhashes::<F, D>(&vec![HashOut::<F>::default().to_bytes(); config.batch_size]).to_bytes();
+
+    loop {
+        while !(level.len() / HASH_BYTES).is_multiple_of(config.recursive_size) {
+            level.extend_from_slice(&padding_hash);
+        }
+
+        let parents = hash_level(&level, config.recursive_size);
+        on_level(level);
+
+        if parents.len() == HASH_BYTES {
+            return Ok(parents);
+        }
+
+        padding_hash = hash_n_subhashes::<F, D>(&vec![padding_hash; config.recursive_size]).to_bytes();
+        level = parents;
+    }
+}
+
+// hashes each chunk of arity nodes of a level (HASH_BYTES per node) into their parent node
+fn hash_level(level: &[u8], arity: usize) -> Vec<u8> {
+    level
+        .par_chunks(arity * HASH_BYTES)
+        .flat_map_iter(|chunk| {
+            let children_hashes = chunk.chunks(HASH_BYTES).map(|hash| hash.to_vec()).collect::<Vec<_>>();
+            hash_n_subhashes::<F, D>(&children_hashes).to_bytes()
+        })
+        .collect()
+}
+
+// Summary of a merkle tree verified while it is deserialized (see VerifyingTreeSeed), for verifiers that can't hold the
+// whole tree in memory. The nodes are never built: each node is checked against the hashes of its
+// children as soon as they are read and only its hash is kept, so the memory is bounded by the depth times the arity.
+#[derive(Debug)]
+pub struct StreamedMerkleTree {
+    pub format_version: u32,
+    pub root_hash: Option<Vec<u8>>,
+    pub depth: usize,
+    pub level_arities: Vec<usize>,
+    pub real_leaf_count: Option<usize>,
+    pub batch_reserves: Vec<Vec<i64>>,
+    // fan-out of each node of the first child path (see derive_level_arities)
+    structure_arities: Vec<usize>,
+    // number of nodes with children at each depth, from the root
+    parent_counts: Vec<usize>,
+}
+
+impl StreamedMerkleTree {
+    // Same as MerkleTree::check_depth.
+    pub fn check_depth(&self, expected_depth: usize) -> Result<()> {
+        let structure_depth = self.structure_arities.len() + 1;
+        if self.depth != structure_depth {
+            return Err(anyhow!(
+                "Merkle tree depth ({}) does not match its structure ({} levels)",
+                self.depth,
+                structure_depth
+            ));
+        }
+
+        if self.depth != expected_depth {
+            return Err(anyhow!(
+                "Merkle tree depth ({}) does not match the proof tree depth ({}), the merkle tree doesn't belong to this proof",
+                self.depth,
+                expected_depth
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Same as MerkleTree::batch_count.
+    pub fn batch_count(&self) -> usize {
+        self.depth.checked_sub(2).and_then(|depth| self.parent_counts.get(depth)).copied().unwrap_or(0)
+    }
+}
+
+// Deserializes a serialized MerkleTree into a StreamedMerkleTree, verifying it (see MerkleTree::verify) in a single
+// depth-first pass and failing on the first node that is not the hash of its children.
+// The nodes deeper than max_depth are rejected while they are read, before the tree depth field is known.
+pub struct VerifyingTreeSeed {
+    pub max_depth: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for VerifyingTreeSeed {
+    type Value = StreamedMerkleTree;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let mut state = StreamState {
+            max_depth: self.max_depth,
+            structure_arities: Vec::new(),
+            parent_counts: Vec::new(),
+        };
+        deserializer.deserialize_struct("MerkleTree", TREE_FIELDS, TreeVisitor { state: &mut state })
+    }
+}
+
+const TREE_FIELDS: &[&str] = &["format_version", "root", "depth", "level_arities", "real_leaf_count", "batch_reserves"];
+const NODE_FIELDS: &[&str] = &["hash", "children"];
+
+struct StreamState {
+    max_depth: usize,
+    structure_arities: Vec<usize>,
+    parent_counts: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum TreeField {
+    FormatVersion,
+    Root,
+    Depth,
+    LevelArities,
+    RealLeafCount,
+    BatchReserves,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum NodeField {
+    Hash,
+    Children,
+    #[serde(other)]
+    Other,
+}
+
+// the format version is checked as soon as it is read (see format::deserialize_format_version)
+struct FormatVersionSeed;
+
+impl<'de> DeserializeSeed<'de> for FormatVersionSeed {
+    type Value = u32;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        format::deserialize_format_version(deserializer)
+    }
+}
+
+#[derive(Deserialize)]
+struct NodeHash(#[serde(deserialize_with = "base64::deserialize_hash_option")] Option<Vec<u8>>);
+
+struct TreeVisitor<'s> {
+    state: &'s mut StreamState,
+}
+
+impl<'de> Visitor<'de> for TreeVisitor<'_> {
+    type Value = StreamedMerkleTree;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a merkle tree")
+    }
+
+    // json and msgpack (named fields)
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut format_version = LEGACY_FORMAT_VERSION;
+        let mut root = None;
+        let mut depth = None;
+        let (mut level_arities, mut real_leaf_count, mut batch_reserves) = (Vec::new(), None, Vec::new());
+
+        while let Some(field) = map.next_key()? {
+            match field {
+                TreeField::FormatVersion => format_version = map.next_value_seed(FormatVersionSeed)?,
+                TreeField::Root => root = Some(map.next_value_seed(NodeSeed { depth: 1, first: true, state: &mut *self.state })?),
+                TreeField::Depth => depth = Some(map.next_value()?),
+                TreeField::LevelArities => level_arities = map.next_value()?,
+                TreeField::RealLeafCount => real_leaf_count = map.next_value()?,
+                TreeField::BatchReserves => batch_reserves = map.next_value()?,
+                TreeField::Other => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let root = root.ok_or_else(|| de::Error::missing_field("root"))?;
+        let depth = depth.ok_or_else(|| de::Error::missing_field("depth"))?;
+        Ok(self.finish(format_version, root, depth, level_arities, real_leaf_count, batch_reserves))
+    }
+
+    // bincode (fields in declaration order)
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let format_version = seq.next_element_seed(FormatVersionSeed)?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let root = seq
+            .next_element_seed(NodeSeed { depth: 1, first: true, state: &mut *self.state })?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let depth = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let level_arities = seq.next_element()?.unwrap_or_default();
+        let real_leaf_count = seq.next_element()?.unwrap_or_default();
+        let batch_reserves = seq.next_element()?.unwrap_or_default();
+
+        Ok(self.finish(format_version, root, depth, level_arities, real_leaf_count, batch_reserves))
+    }
+}
+
+impl TreeVisitor<'_> {
+    fn finish(
+        self,
+        format_version: u32,
+        root: Option<Vec<u8>>,
+        depth: usize,
+        level_arities: Vec<usize>,
+        real_leaf_count: Option<usize>,
+        batch_reserves: Vec<Vec<i64>>,
+    ) -> StreamedMerkleTree {
+        StreamedMerkleTree {
+            format_version,
+            root_hash: root,
+            depth,
+            level_arities,
+            real_leaf_count,
+            batch_reserves,
+            // the first child path is verified bottom-up, so its fan-outs were recorded from the batch level to the root
+            structure_arities: self.state.structure_arities.drain(..).rev().collect(),
+            parent_counts: std::mem::take(&mut self.state.parent_counts),
+        }
+    }
+}
+
+// a node is deserialized into its hash once verified
+// first: the node is on the first child path, which records the structure of the tree (see derive_level_arities)
+struct NodeSeed<'s> {
+    depth: usize,
+    first: bool,
+    state: &'s mut StreamState,
+}
+
+impl<'de> DeserializeSeed<'de> for NodeSeed<'_> {
+    type Value = Option<Vec<u8>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        if self.depth > self.state.max_depth {
+            return Err(de::Error::custom(format!(
+                "The merkle tree is deeper than the maximum depth ({})",
+                self.state.max_depth
+            )));
+        }
+        deserializer.deserialize_struct("Node", NODE_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for NodeSeed<'_> {
+    type Value = Option<Vec<u8>>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a merkle tree node")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut hash = None;
+        let mut children = None;
+
+        while let Some(field) = map.next_key()? {
+            match field {
+                NodeField::Hash => hash = map.next_value::<NodeHash>()?.0,
+                NodeField::Children => {
+                    children = map.next_value_seed(ChildrenSeed { depth: self.depth, first: self.first, state: &mut *self.state })?
+                }
+                NodeField::Other => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        self.verify(hash, children).map_err(de::Error::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let hash = seq.next_element::<NodeHash>()?.ok_or_else(|| de::Error::invalid_length(0, &"a merkle tree node"))?.0;
+        let children = seq
+            .next_element_seed(ChildrenSeed { depth: self.depth, first: self.first, state: &mut *self.state })?
+            .ok_or_else(|| de::Error::invalid_length(1, &"a merkle tree node"))?;
+
+        self.verify(hash, children).map_err(de::Error::custom)
+    }
+}
+
+impl NodeSeed<'_> {
+    // same checks as verify_recursive, the children are already verified
+    fn verify(self, hash: Option<Vec<u8>>, children: Option<Vec<Option<Vec<u8>>>>) -> Result<Option<Vec<u8>>> {
+        let Some(children) = children else {
+            return Ok(hash);
+        };
+
+        let Some(node_hash) = &hash else {
+            return Err(anyhow!("Merkle tree verification failed: a node at depth {} has children but no hash", self.depth));
+        };
+        let Some(children_hashes) = children.into_iter().collect::<Option<Vec<_>>>() else {
+            return Err(anyhow!("Merkle tree verification failed: a node at depth {} has no hash", self.depth + 1));
+        };
+        if *node_hash != hash_n_subhashes::<F, D>(&children_hashes).to_bytes() {
+            return Err(anyhow!(
+                "Merkle tree verification failed: the hash of a node at depth {} is not the hash of its children",
+                self.depth
+            ));
+        }
+
+        let state = self.state;
+        if self.first {
+            state.structure_arities.push(children_hashes.len());
+        }
+        if state.parent_counts.len() < self.depth {
+            state.parent_counts.resize(self.depth, 0);
+        }
+        state.parent_counts[self.depth - 1] += 1;
+
+        Ok(hash)
+    }
+}
+
+// the children of a node at depth, each child is verified (and dropped) as soon as it is read
+struct ChildrenSeed<'s> {
+    depth: usize,
+    first: bool,
+    state: &'s mut StreamState,
+}
+
+impl<'de> DeserializeSeed<'de> for ChildrenSeed<'_> {
+    type Value = Option<Vec<Option<Vec<u8>>>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ChildrenSeed<'_> {
+    type Value = Option<Vec<Option<Vec<u8>>>>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("the children of a merkle tree node")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut children = Vec::new();
+        loop {
+            let first = self.first && children.is_empty();
+            let seed = NodeSeed { depth: self.depth + 1, first, state: &mut *self.state };
+            match seq.next_element_seed(seed)? {
+                Some(child) => children.push(child),
+                None => return Ok(Some(children)),
+            }
+        }
+    }
+}
@@ -1,17 +1,163 @@
-use plonky2::plonk::config::GenericHashOut;
+use plonky2::field::types::{Field, Field64, PrimeField64};
+use plonky2::plonk::config::{GenericHashOut, Hasher};
 use plonky2::plonk::proof::ProofWithPublicInputs;
+use anyhow::{anyhow, Context, Result};
+use bigdecimal::BigDecimal;
+use ring::signature::Ed25519KeyPair;
 use serde::{Deserialize, Serialize};
-use crate::utils::util::hash_n_subhashes;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::circuits::recursive_circuit::RecursiveCircuit;
+use crate::core::verifier::check_final_proof_shape;
+use crate::utils::signing::{sign_commitment, verify_commitment_signature};
+use crate::utils::util::{
+    bytes_to_hex, calculate_with_decimals, check_balance_range, compute_leaf_hash, hash_n_subhashes, is_reserved_leaf_hash,
+    pis_to_hash_bytes, scale_price, unscale_price, user_hash_elements, verifier_data_digest,
+};
 use crate::config::*;
 use crate::custom_serializer::base64;
+use crate::custom_serializer::format::{self, read_from_file};
 
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LedgerDecimals {
     pub usdt_decimals: i64,
     pub balance_decimals: i64,
 }
 
+// accepted range of the asset prices, assets in allow_zero may have an (intentionally) zero price
+#[derive(Clone, Debug)]
+pub struct PriceBounds {
+    pub min: u64,
+    pub max: u64,
+    pub allow_zero: Vec<String>,
+}
+
+impl Default for PriceBounds {
+    fn default() -> Self {
+        PriceBounds {
+            min: DEFAULT_MIN_ASSET_PRICE,
+            max: DEFAULT_MAX_ASSET_PRICE,
+            allow_zero: Vec::new(),
+        }
+    }
+}
+
+// sizes of the circuits of a proof: accounts per batch circuit and inner proofs per recursive circuit
+// both are stored in the final proof, so the verifier rebuilds the same circuits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProverConfig {
+    pub batch_size: usize,
+    pub recursive_size: usize,
+}
+
+impl Default for ProverConfig {
+    fn default() -> Self {
+        ProverConfig {
+            batch_size: BATCH_SIZE,
+            recursive_size: RECURSIVE_SIZE,
+        }
+    }
+}
+
+impl ProverConfig {
+    // tiny circuits that prove a small ledger end-to-end in seconds, for tests only
+    // (a real ledger would need a huge number of batch proofs and recursive levels)
+    pub fn testing() -> Self {
+        ProverConfig {
+            batch_size: 4,
+            recursive_size: MIN_RECURSIVE_SIZE,
+        }
+    }
+
+    // the balances range checks are sized for BATCH_SIZE accounts (see MAX_ACCOUNT_BALANCE_BITS),
+    // so a batch can be smaller (its total can't overflow either) but never larger
+    pub fn validate(&self) -> Result<()> {
+        if self.batch_size == 0 || self.batch_size > BATCH_SIZE {
+            return Err(anyhow!(
+                "Invalid batch size {}: it must be between 1 and {}",
+                self.batch_size,
+                BATCH_SIZE
+            ));
+        }
+        if self.recursive_size < MIN_RECURSIVE_SIZE || self.recursive_size > MAX_RECURSIVE_SIZE {
+            return Err(anyhow!(
+                "Invalid recursive size {}: it must be between {} and {}",
+                self.recursive_size,
+                MIN_RECURSIVE_SIZE,
+                MAX_RECURSIVE_SIZE
+            ));
+        }
+        Ok(())
+    }
+
+    // padding added by the tree construction for leaf_count leaves (accounts, plus one leaf for the dust aggregate and
+    // one for the reserve buffer if any): the accounts are padded to a multiple of batch_size, and every recursive
+    // level pads its inner proofs to a multiple of recursive_size, so the tree always has recursive_size^k batches
+    pub fn padding_report(&self, leaf_count: usize) -> Result<PaddingReport> {
+        self.validate()?;
+        if leaf_count == 0 {
+            return Err(anyhow!("Cannot compute the padding of an empty ledger"));
+        }
+
+        let batch_count = leaf_count.div_ceil(self.batch_size);
+        let mut recursive_levels = 1;
+        let mut batch_slots = self.recursive_size;
+        while batch_slots < batch_count {
+            batch_slots = batch_slots
+                .checked_mul(self.recursive_size)
+                .ok_or_else(|| anyhow!("Too many accounts: {leaf_count}"))?;
+            recursive_levels += 1;
+        }
+
+        // the largest ledger with one recursive level less
+        let accounts_to_drop_level =
+            (recursive_levels > 1).then(|| leaf_count - batch_slots / self.recursive_size * self.batch_size);
+
+        Ok(PaddingReport {
+            leaf_count,
+            batch_count,
+            padding_accounts: batch_count * self.batch_size - leaf_count,
+            padding_batches: batch_slots - batch_count,
+            padded_leaf_count: batch_slots * self.batch_size,
+            tree_depth: recursive_levels + 2, // accounts and batch levels
+            accounts_to_drop_level,
+        })
+    }
+}
+
+// result of ProverConfig::padding_report, the leaf counts are the ones of MerkleTree::leaf_count and real_leaf_count
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaddingReport {
+    pub leaf_count: usize,
+    // batch proofs over real leaves
+    pub batch_count: usize,
+    // zero accounts filling the last batch
+    pub padding_accounts: usize,
+    // batches without any real leaf (covered by the precomputed empty proofs)
+    pub padding_batches: usize,
+    pub padded_leaf_count: usize,
+    pub tree_depth: usize,
+    // number of leaves to remove to drop a recursive level, None if the tree has a single recursive level
+    pub accounts_to_drop_level: Option<usize>,
+}
+
+impl PaddingReport {
+    // share of the leaf slots of the tree that are padding
+    pub fn padding_percentage(&self) -> f64 {
+        (self.padded_leaf_count - self.leaf_count) as f64 * 100.0 / self.padded_leaf_count as f64
+    }
+}
+
+// accounts with an equity below min_equity, aggregated into a single leaf (see Ledger::aggregate_dust)
+// they still count toward the reserves, but they are not individually inclusion-provable
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DustAggregate {
+    // minimum equity (in units of 10^-(usdt_decimals + balance_decimals) USD) of the individually proven accounts
+    pub min_equity: u64,
+    // sum of the balances of the dust accounts (same order as asset_names)
+    pub balances: Vec<i64>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Ledger {
     pub asset_names: Vec<String>,
@@ -19,12 +165,364 @@ pub struct Ledger {
     pub account_balances: Vec<Vec<i64>>,
     pub asset_prices: Vec<u64>,
     pub asset_decimals: Vec<LedgerDecimals>,
+    // balances held by the exchange beyond user liabilities (same order as asset_names)
+    // they are summed into the proven reserves but are not a user account
+    pub reserve_buffer: Vec<i64>,
+    // accounts below the minimum equity aggregated into a single leaf, None if every account is proven individually
+    pub dust: Option<DustAggregate>,
     pub timestamp: u64
 }
 
+impl Ledger {
+    // number of merkle tree leaves before the padding: the accounts, then the dust aggregate and reserve buffer leafs (if any)
+    // with ProverConfig::padding_report it gives the shape of the proof before proving
+    pub fn leaf_count(&self) -> usize {
+        self.hashes.len() + self.dust.is_some() as usize + self.reserve_buffer.iter().any(|balance| *balance != 0) as usize
+    }
+
+    // check that every asset price is within the bounds (guards against price feed bugs)
+    // all the offending assets are reported at once
+    pub fn validate_prices(&self, bounds: &PriceBounds) -> Result<()> {
+        let mut offending = Vec::new();
+
+        for (asset_name, price) in self.asset_names.iter().zip(self.asset_prices.iter()) {
+            if *price == 0 {
+                if !bounds.allow_zero.contains(asset_name) {
+                    offending.push(format!("{asset_name} has a zero price"));
+                }
+            } else if *price < bounds.min || *price > bounds.max {
+                offending.push(format!(
+                    "{asset_name} price {price} is out of bounds [{}, {}]",
+                    bounds.min, bounds.max
+                ));
+            }
+        }
+
+        if !offending.is_empty() {
+            return Err(anyhow!("Invalid asset prices: {}", offending.join(", ")));
+        }
+        Ok(())
+    }
+
+    // check that every account has exactly one balance per asset (the circuits assume it)
+    // the offending user hash is reported, e.g. an account with extra or duplicate keys
+    pub fn validate_accounts(&self) -> Result<()> {
+        let asset_count = self.asset_names.len();
+
+        if self.asset_prices.len() != asset_count || self.asset_decimals.len() != asset_count {
+            return Err(anyhow!(
+                "Invalid ledger: {} assets but {} prices and {} decimals",
+                asset_count,
+                self.asset_prices.len(),
+                self.asset_decimals.len()
+            ));
+        }
+
+        if self.hashes.len() != self.account_balances.len() {
+            return Err(anyhow!(
+                "Invalid ledger: {} user hashes but {} accounts",
+                self.hashes.len(),
+                self.account_balances.len()
+            ));
+        }
+
+        for (hash, balances) in self.hashes.iter().zip(self.account_balances.iter()) {
+            // the user hash is part of the leaf hash (see hash_account)
+            user_hash_elements(hash).with_context(|| format!("Invalid account {hash}"))?;
+            // a user leaf with a reserved hash would be taken for the reserve buffer or the dust aggregate
+            if is_reserved_leaf_hash(hash) {
+                return Err(anyhow!(
+                    "Invalid account {hash}: the hash is reserved for the reserve buffer and dust aggregate leafs"
+                ));
+            }
+            if balances.len() != asset_count {
+                return Err(anyhow!(
+                    "Invalid account {}: it has {} balances but the ledger has {} assets",
+                    hash,
+                    balances.len(),
+                    asset_count
+                ));
+            }
+            // the batch circuit range checks every balance, a programmatic ledger isn't checked by parse_ledger_balance
+            for (asset_name, balance) in self.asset_names.iter().zip(balances) {
+                check_balance_range(*balance).map_err(|e| anyhow!("Invalid account {hash}, asset {asset_name}: {e}"))?;
+            }
+        }
+
+        if !self.reserve_buffer.is_empty() && self.reserve_buffer.len() != asset_count {
+            return Err(anyhow!(
+                "Invalid reserve buffer: it has {} balances but the ledger has {} assets",
+                self.reserve_buffer.len(),
+                asset_count
+            ));
+        }
+        for (asset_name, balance) in self.asset_names.iter().zip(&self.reserve_buffer) {
+            check_balance_range(*balance).map_err(|e| anyhow!("Invalid reserve buffer of asset {asset_name}: {e}"))?;
+        }
+
+        if let Some(dust) = &self.dust {
+            if dust.balances.len() != asset_count {
+                return Err(anyhow!(
+                    "Invalid dust aggregate: it has {} balances but the ledger has {} assets",
+                    dust.balances.len(),
+                    asset_count
+                ));
+            }
+            for (asset_name, balance) in self.asset_names.iter().zip(&dust.balances) {
+                check_balance_range(*balance).map_err(|e| anyhow!("Dust aggregate of asset {asset_name}: {e}"))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // convert a USD amount (e.g. "10.50") into an equity in the same units as account_equity
+    // the sum of usdt_decimals and balance_decimals is the same for all assets (checked by verify), the amount is scaled
+    // like a decimal price with that many decimals (see scale_price)
+    pub fn scale_equity(&self, amount: &str) -> Result<u64> {
+        let decimals = self
+            .asset_decimals
+            .first()
+            .map(|decimals| decimals.usdt_decimals + decimals.balance_decimals)
+            .ok_or_else(|| anyhow!("The ledger has no assets"))?;
+
+        scale_price(amount, decimals)
+    }
+
+    // equity of an account (sum of balance * price), in units of 10^-(usdt_decimals + balance_decimals) USD
+    pub fn account_equity(&self, index: usize) -> i128 {
+        self.account_balances[index]
+            .iter()
+            .zip(self.asset_prices.iter())
+            .map(|(balance, price)| *balance as i128 * *price as i128)
+            .sum()
+    }
+
+    // the equity of every account must fit in the field without wrapping (see MAX_ACCOUNT_EQUITY)
+    // it is computed exactly (i128), so unlike the price bounds it holds for any price
+    pub fn check_equity_range(&self) -> Result<()> {
+        for (index, hash) in self.hashes.iter().enumerate() {
+            let equity = self.account_equity(index);
+            if equity.unsigned_abs() > MAX_ACCOUNT_EQUITY as u128 {
+                return Err(anyhow!(
+                    "The equity {} of account {} overflows the field (the largest supported absolute equity is {}), check the asset prices",
+                    equity,
+                    hash,
+                    MAX_ACCOUNT_EQUITY
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // aggregate the accounts with an equity below min_equity into a single dust leaf, returns the number of dust accounts
+    // the relative order of the remaining accounts is kept, so aggregating the same ledger again
+    // gives the same accounts (and the same nonce positions), e.g. to prove inclusion later
+    pub fn aggregate_dust(&mut self, min_equity: u64) -> Result<usize> {
+        if self.dust.is_some() {
+            return Err(anyhow!("The dust accounts of this ledger are already aggregated"));
+        }
+
+        let mut balances = vec![0i64; self.asset_names.len()];
+        let mut hashes = Vec::new();
+        let mut account_balances = Vec::new();
+        let mut dust_count = 0;
+
+        for index in 0..self.hashes.len() {
+            if self.account_equity(index) >= min_equity as i128 {
+                hashes.push(self.hashes[index].clone());
+                account_balances.push(self.account_balances[index].clone());
+                continue;
+            }
+
+            for (i, balance) in self.account_balances[index].iter().enumerate() {
+                balances[i] = balances[i].checked_add(*balance).ok_or_else(|| {
+                    anyhow!("Dust aggregate overflow on asset {}", self.asset_names[i])
+                })?;
+            }
+            dust_count += 1;
+        }
+
+        // the dust leaf is range checked by the batch circuit like any account, so a sum of offsetting dust balances
+        // (e.g. a large debt covered by another asset) must be rejected here instead of failing the proving
+        for (asset_name, balance) in self.asset_names.iter().zip(&balances) {
+            check_balance_range(*balance).map_err(|e| anyhow!("Dust aggregate of asset {asset_name}: {e}"))?;
+        }
+
+        if hashes.is_empty() {
+            return Err(anyhow!("Every account is below the minimum equity, there is nothing to prove individually"));
+        }
+
+        self.hashes = hashes;
+        self.account_balances = account_balances;
+        if dust_count > 0 {
+            self.dust = Some(DustAggregate { min_equity, balances });
+        }
+
+        Ok(dust_count)
+    }
+
+    // aggregate the dust accounts with the same threshold used to generate the final proof (if any)
+    // the ledger must be aggregated the same way as when proving, otherwise the nonce positions don't match
+    pub fn apply_proof_dust(&mut self, final_proof: &FinalProof) -> Result<()> {
+        if let Some(dust) = &final_proof.dust {
+            self.aggregate_dust(dust.min_equity)?;
+        }
+        Ok(())
+    }
+
+    // build a user hash -> index map once, so repeated inclusion lookups are O(1) instead of a linear scan
+    pub fn build_index(&self) -> HashMap<String, usize> {
+        self.hashes
+            .iter()
+            .enumerate()
+            .map(|(index, hash)| (hash.clone(), index))
+            .collect()
+    }
+
+    // merge two ledgers (e.g. spot and derivatives) keyed by the same user hashes
+    // assets are unioned (missing balances are zero), shared assets must have the same price and decimals
+    // balances of shared user hashes are summed
+    // the result is sorted by asset name and user hash so the resulting proof is reproducible
+    pub fn merge(self, other: Ledger) -> Result<Ledger> {
+        // union the assets
+        let mut assets: BTreeMap<String, (u64, LedgerDecimals)> = BTreeMap::new();
+        for ledger in [&self, &other] {
+            for (i, asset_name) in ledger.asset_names.iter().enumerate() {
+                let price = ledger.asset_prices[i];
+                let decimals = ledger.asset_decimals[i].clone();
+
+                if let Some((existing_price, existing_decimals)) = assets.get(asset_name) {
+                    if *existing_price != price {
+                        return Err(anyhow!(
+                            "Conflicting prices for asset {asset_name}: {existing_price} and {price}"
+                        ));
+                    }
+                    if *existing_decimals != decimals {
+                        return Err(anyhow!(
+                            "Conflicting decimals for asset {asset_name}: {existing_decimals:?} and {decimals:?}"
+                        ));
+                    }
+                } else {
+                    assets.insert(asset_name.clone(), (price, decimals));
+                }
+            }
+        }
+
+        let asset_names: Vec<String> = assets.keys().cloned().collect();
+        let asset_index: HashMap<&String, usize> =
+            asset_names.iter().enumerate().map(|(i, name)| (name, i)).collect();
+
+        // the dust accounts must be aggregated after merging (the equity depends on all the balances)
+        if self.dust.is_some() || other.dust.is_some() {
+            return Err(anyhow!("Cannot merge ledgers with aggregated dust accounts, merge them before aggregating"));
+        }
+
+        // sum the reserve buffers
+        let mut reserve_buffer = vec![0i64; asset_names.len()];
+        for ledger in [&self, &other] {
+            for (i, balance) in ledger.reserve_buffer.iter().enumerate() {
+                let index = asset_index[&ledger.asset_names[i]];
+                reserve_buffer[index] = reserve_buffer[index].checked_add(*balance).ok_or_else(|| {
+                    anyhow!("Reserve buffer overflow while merging asset {}", ledger.asset_names[i])
+                })?;
+            }
+        }
+
+        // union the accounts and sum the shared ones
+        let mut accounts: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        for ledger in [&self, &other] {
+            for (hash, balances) in ledger.hashes.iter().zip(ledger.account_balances.iter()) {
+                let merged = accounts
+                    .entry(hash.clone())
+                    .or_insert_with(|| vec![0; asset_names.len()]);
+
+                for (i, balance) in balances.iter().enumerate() {
+                    let index = asset_index[&ledger.asset_names[i]];
+                    merged[index] = merged[index].checked_add(*balance).ok_or_else(|| {
+                        anyhow!("Balance overflow while merging asset {} of account {hash}", ledger.asset_names[i])
+                    })?;
+                }
+            }
+        }
+
+        let (asset_prices, asset_decimals) = assets.into_values().unzip();
+        let (hashes, account_balances) = accounts.into_iter().unzip();
+
+        Ok(Ledger {
+            asset_names,
+            hashes,
+            account_balances,
+            asset_prices,
+            asset_decimals,
+            reserve_buffer,
+            dust: None,
+            // both snapshots are expected to be taken at the same time, keep the latest one
+            timestamp: self.timestamp.max(other.timestamp),
+        })
+    }
+}
+
+// nonces of the accounts, either positional (private_nonces, one per merkle tree leaf in the order of the ledger hashes)
+// or keyed by user hash (private_nonces_by_hash, only the users), the keyed form doesn't depend on the account order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountNonces {
+    Positional(Vec<u64>),
+    ByHash(HashMap<String, u64>),
+}
+
+impl From<Vec<u64>> for AccountNonces {
+    fn from(nonces: Vec<u64>) -> Self {
+        AccountNonces::Positional(nonces)
+    }
+}
+
+impl From<HashMap<String, u64>> for AccountNonces {
+    fn from(nonces: HashMap<String, u64>) -> Self {
+        AccountNonces::ByHash(nonces)
+    }
+}
+
+// borrowed AccountNonces, so the inclusion provers read the nonce of a user without copying one nonce per leaf
+// (see resolve_user_nonce), or copy only the user nonces once (see resolve_nonces)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountNoncesRef<'a> {
+    Positional(&'a [u64]),
+    ByHash(&'a HashMap<String, u64>),
+}
+
+impl<'a> From<&'a [u64]> for AccountNoncesRef<'a> {
+    fn from(nonces: &'a [u64]) -> Self {
+        AccountNoncesRef::Positional(nonces)
+    }
+}
+
+impl<'a> From<&'a Vec<u64>> for AccountNoncesRef<'a> {
+    fn from(nonces: &'a Vec<u64>) -> Self {
+        AccountNoncesRef::Positional(nonces)
+    }
+}
+
+impl<'a> From<&'a HashMap<String, u64>> for AccountNoncesRef<'a> {
+    fn from(nonces: &'a HashMap<String, u64>) -> Self {
+        AccountNoncesRef::ByHash(nonces)
+    }
+}
+
+impl<'a> From<&'a AccountNonces> for AccountNoncesRef<'a> {
+    fn from(nonces: &'a AccountNonces) -> Self {
+        match nonces {
+            AccountNonces::Positional(nonces) => AccountNoncesRef::Positional(nonces),
+            AccountNonces::ByHash(nonces) => AccountNoncesRef::ByHash(nonces),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FinalProof{
+    // schema version (see CURRENT_FORMAT_VERSION), checked on load, prover_version identifies the circuits
+    #[serde(default = "format::legacy_format_version", deserialize_with = "format::deserialize_format_version")]
+    pub format_version: u32,
     pub proof: ProofWithPublicInputs<F, C, D>,
     pub batch_size: usize,
     pub recursive_size: usize,
@@ -34,32 +532,572 @@ pub struct FinalProof{
     pub tree_depth: usize,
     pub timestamp: u64,
     pub prover_version: String,
+    // reserve buffer (insurance fund) included in the proven reserves, empty if there is none
+    #[serde(default)]
+    pub reserve_buffer: Vec<i64>,
+    // aggregate of the accounts below the minimum equity (--min-equity), None if every account is proven individually
+    #[serde(default)]
+    pub dust: Option<DustAggregate>,
+    // metadata added after proving (see FinalProof::annotate), not part of the proof nor of the commitment
+    #[serde(default)]
+    pub annotations: ProofAnnotations,
+    // sha-256 (hex, see verifier_data_digest) of the root circuit verifier data bytes, set when the verifier data is written
+    // to a separate file (see FinalProof::detach_verifier_data), root_circuit_verifier_data is then empty until it is
+    // attached again
+    #[serde(default)]
+    pub verifier_data_digest: Option<String>,
     // custom serialization --> for whatever reason Serialize and Deserialize traits are not implemented for VerifierCircuitData
     // so we serialize it as a Vec<u8> and deserialize it back in our code
     #[serde(serialize_with = "base64::serialize", deserialize_with = "base64::deserialize")]
     pub root_circuit_verifier_data: Vec<u8> 
 }
 
+// machine-readable summary of a final proof (see FinalProof::summary), published next to the proof for dashboards
+// the decimal amounts are exact plain strings (no float rounding), the raw values are the ones of the public inputs
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofSummary {
+    pub timestamp: u64,
+    pub prover_version: String,
+    pub root_hash: String, // hex
+    pub assets: Vec<AssetSummary>,
+    pub total_usd_value: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetSummary {
+    pub name: String,
+    pub price: String, // US$, unscaled
+    pub decimals: LedgerDecimals,
+    pub reserve_raw: i64,
+    pub reserve_decimal: String,
+    pub usd_value: String,
+}
+
+// reserve of an asset of a final proof and its US$ value (see FinalProof::asset_valuations)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssetValuation {
+    pub reserve_raw: i64,
+    pub reserve: BigDecimal, // scaled by balance_decimals
+    pub price: BigDecimal,   // US$, unscaled
+    pub usd_value: BigDecimal,
+}
+
+// metadata attached to a final proof after proving (annotate subcommand), every field is optional
+// annotating never changes what was proven, so the commitment is the same before and after
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofAnnotations {
+    pub exchange_id: Option<String>,
+    pub anchor_url: Option<String>,
+    // ed25519 signature (hex) of the commitment (annotate --sign-key), checked with public_key
+    pub signature: Option<String>,
+    // ed25519 public key (hex) of the signature, to compare with the key published by the exchange
+    pub public_key: Option<String>,
+    // commitment (hex) the annotations were made for, recomputed on every annotation
+    pub commitment: Option<String>,
+    // when the proof was last annotated (unix ms), the proof timestamp itself is kept
+    pub annotated_at: Option<u64>,
+}
+
+// typed view over the public inputs of a root (recursive circuit) proof, so the offsets are computed in a single place
+// layout: final balances (asset_count) | asset prices (asset_count) | root hash (4)
+pub struct ProofPublicInputs<'a> {
+    public_inputs: &'a [F],
+    asset_count: usize,
+}
+
+impl<'a> ProofPublicInputs<'a> {
+    pub fn new(proof: &'a ProofWithPublicInputs<F, C, D>, asset_count: usize) -> Self {
+        ProofPublicInputs {
+            public_inputs: &proof.public_inputs,
+            asset_count,
+        }
+    }
+
+    // summed balances of each asset, field elements above (ORDER - 1) / 2 represent negative numbers
+    pub fn final_balances(&self) -> Vec<i64> {
+        self.public_inputs[RecursiveCircuit::get_final_balances_offset(self.asset_count)]
+            .iter()
+            .map(|balance| {
+                let value = balance.to_canonical_u64();
+                if value > (F::ORDER - 1) / 2 {
+                    -((F::ORDER - value) as i64)
+                } else {
+                    value as i64
+                }
+            })
+            .collect()
+    }
+
+    // asset prices the balances were checked against
+    pub fn asset_prices(&self) -> Vec<u64> {
+        self.public_inputs[RecursiveCircuit::get_asset_prices_offset(self.asset_count)]
+            .iter()
+            .map(|price| price.to_canonical_u64())
+            .collect()
+    }
+
+    // merkle tree root hash
+    pub fn root_hash_bytes(&self) -> Vec<u8> {
+        pis_to_hash_bytes::<F, D>(&self.public_inputs[RecursiveCircuit::get_root_hash_offset(self.asset_count)])
+    }
+}
+
+impl FinalProof {
+    // file holding the detached root circuit verifier data, next to the final proof
+    pub const VERIFIER_DATA_FILE_NAME: &'static str = "verifier_data.bin";
+
+    // reads a final proof (any format), the detached verifier data (if any) is loaded from the proof directory
+    // a file whose fields don't match its asset count is rejected here (see check_final_proof_shape)
+    pub fn read(path: &str) -> Result<FinalProof> {
+        let mut final_proof: FinalProof = read_from_file(path)?;
+        check_final_proof_shape(&final_proof).with_context(|| format!("Invalid final proof {path}"))?;
+        final_proof.load_detached_verifier_data(path)?;
+        Ok(final_proof)
+    }
+
+    // the root circuit verifier data is in a separate file (see detach_verifier_data)
+    pub fn is_verifier_data_detached(&self) -> bool {
+        self.root_circuit_verifier_data.is_empty() && self.verifier_data_digest.is_some()
+    }
+
+    // moves the root circuit verifier data out of the proof, to write it once for all the proofs of the same shape
+    // the proof keeps the hash of the raw verifier data bytes to check the verifier data it is attached to later
+    pub fn detach_verifier_data(&mut self) -> Result<Vec<u8>> {
+        if self.is_verifier_data_detached() {
+            return Err(anyhow!("The verifier data of the proof is already detached"));
+        }
+
+        let digest = verifier_data_digest(&self.root_circuit_verifier_data);
+        self.verifier_data_digest = Some(bytes_to_hex(&digest));
+        Ok(std::mem::take(&mut self.root_circuit_verifier_data))
+    }
+
+    // puts back detached verifier data, it must be the one the proof was detached from (same bytes, see verifier_data_digest)
+    pub fn attach_verifier_data(&mut self, verifier_data: Vec<u8>) -> Result<()> {
+        let Some(expected_digest) = &self.verifier_data_digest else {
+            return Err(anyhow!("The verifier data of the proof is not detached"));
+        };
+
+        let digest = bytes_to_hex(&verifier_data_digest(&verifier_data));
+        if digest != *expected_digest {
+            return Err(anyhow!(
+                "The verifier data has the digest {digest}, but the proof references {expected_digest}"
+            ));
+        }
+
+        self.root_circuit_verifier_data = verifier_data;
+        Ok(())
+    }
+
+    // if the verifier data is detached, attaches the VERIFIER_DATA_FILE_NAME next to the proof file
+    // returns whether it was detached (e.g. to detach it again before writing the proof back)
+    pub fn load_detached_verifier_data(&mut self, proof_path: &str) -> Result<bool> {
+        if !self.is_verifier_data_detached() {
+            return Ok(false);
+        }
+
+        let verifier_data_path = std::path::Path::new(proof_path).with_file_name(Self::VERIFIER_DATA_FILE_NAME);
+        let verifier_data = std::fs::read(&verifier_data_path).with_context(|| {
+            format!(
+                "The proof {proof_path} has no embedded verifier data, failed to read {}",
+                verifier_data_path.display()
+            )
+        })?;
+        self.attach_verifier_data(verifier_data)
+            .with_context(|| format!("Invalid verifier data in {}", verifier_data_path.display()))?;
+
+        Ok(true)
+    }
+
+    // typed view over the proof public inputs
+    pub fn public_inputs(&self) -> ProofPublicInputs<'_> {
+        ProofPublicInputs::new(&self.proof, self.asset_names.len())
+    }
+
+    // circuit sizes the proof was generated with
+    pub fn prover_config(&self) -> ProverConfig {
+        ProverConfig {
+            batch_size: self.batch_size,
+            recursive_size: self.recursive_size,
+        }
+    }
+
+    // total reserves of each asset (same order as asset_names), read from the proof public inputs
+    // field elements above (ORDER - 1) / 2 represent negative numbers
+    pub fn asset_reserves(&self) -> Vec<i64> {
+        self.public_inputs().final_balances()
+    }
+
+    // total user liabilities of each asset (proven reserves minus the reserve buffer)
+    // the reserve buffer is copied from the proof file and is not a public input: the split is declared, not proven
+    pub fn user_liabilities(&self) -> Result<Vec<i64>> {
+        let mut liabilities = self.asset_reserves();
+        if self.reserve_buffer.is_empty() {
+            return Ok(liabilities);
+        }
+        if self.reserve_buffer.len() != liabilities.len() {
+            return Err(anyhow!(
+                "The reserve buffer has {} balances but the proof has {} assets",
+                self.reserve_buffer.len(),
+                liabilities.len()
+            ));
+        }
+
+        for (i, buffer) in self.reserve_buffer.iter().enumerate() {
+            liabilities[i] = liabilities[i].checked_sub(*buffer).ok_or_else(|| {
+                anyhow!("The {} reserve buffer overflows the proven reserves", self.asset_names[i])
+            })?;
+        }
+        Ok(liabilities)
+    }
+
+    // indices of the assets sorted by name, the order the verifier prints and checks them in
+    // the proof order (the one of the ledger, fixed by the public inputs layout) is arbitrary, so two tools sorting the
+    // assets differently still show the same output (the asset names are unique, checked by the verifier)
+    pub fn canonical_asset_order(&self) -> Vec<usize> {
+        let mut order = (0..self.asset_names.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| self.asset_names[*a].cmp(&self.asset_names[*b]));
+        order
+    }
+
+    // indices of the named assets sorted by name (a subset of canonical_asset_order), e.g. to only display some reserves
+    // an asset that is not in the proof is an error, so a typo doesn't silently hide it
+    pub fn selected_asset_order(&self, asset_names: &[String]) -> Result<Vec<usize>> {
+        if let Some(asset_name) = asset_names.iter().find(|name| !self.asset_names.contains(name)) {
+            return Err(anyhow!("Asset {asset_name} is not present in the proof"));
+        }
+
+        Ok(self
+            .canonical_asset_order()
+            .into_iter()
+            .filter(|&i| asset_names.contains(&self.asset_names[i]))
+            .collect())
+    }
+
+    // merkle tree root hash proven by the root circuit, read from the proof public inputs
+    pub fn root_hash(&self) -> Vec<u8> {
+        self.public_inputs().root_hash_bytes()
+    }
+
+    // reserve, price and US$ value of each asset (same order as asset_names), the reserves and prices are read from the
+    // public inputs: the asset_prices field is only compared with them by the verifier (step 3), which can be skipped
+    // every report of the reserves (summary, reserves CSV, totals) goes through it, so they can't disagree
+    pub fn asset_valuations(&self) -> Vec<AssetValuation> {
+        let public_inputs = self.public_inputs();
+        let asset_prices = public_inputs.asset_prices();
+        let asset_reserves = public_inputs.final_balances();
+
+        (0..self.asset_names.len())
+            .map(|i| {
+                let decimals = &self.asset_decimals[i];
+                let price = unscale_price(asset_prices[i], decimals.usdt_decimals);
+                let reserve = calculate_with_decimals(asset_reserves[i], decimals.balance_decimals);
+                let usd_value = &reserve * &price;
+
+                AssetValuation { reserve_raw: asset_reserves[i], reserve, price, usd_value }
+            })
+            .collect()
+    }
+
+    // reserves and metadata of the proof as a single serializable report, the assets are sorted by name
+    // the prices and reserves are read from the public inputs, so it is only meaningful for a verified proof
+    pub fn summary(&self) -> ProofSummary {
+        let valuations = self.asset_valuations();
+
+        let mut assets = Vec::with_capacity(self.asset_names.len());
+        let mut total_usd_value = BigDecimal::from(0);
+        for i in self.canonical_asset_order() {
+            let valuation = &valuations[i];
+            total_usd_value += &valuation.usd_value;
+
+            assets.push(AssetSummary {
+                name: self.asset_names[i].clone(),
+                price: valuation.price.to_plain_string(),
+                decimals: self.asset_decimals[i].clone(),
+                reserve_raw: valuation.reserve_raw,
+                reserve_decimal: valuation.reserve.to_plain_string(),
+                usd_value: valuation.usd_value.to_plain_string(),
+            });
+        }
+
+        ProofSummary {
+            timestamp: self.timestamp,
+            prover_version: self.prover_version.clone(),
+            root_hash: bytes_to_hex(&self.root_hash()),
+            assets,
+            total_usd_value: total_usd_value.to_plain_string(),
+        }
+    }
+
+    // stable 32-byte commitment to the proof contents (e.g. to sign it or anchor it on-chain)
+    // it is the Poseidon hash of a canonical encoding of, in this order:
+    // --> domain tag (COMMITMENT_DOMAIN)
+    // --> proof bytes (plonky2 binary serialization, including the public inputs) and root circuit verifier data bytes
+    // --> batch_size, recursive_size and tree_depth
+    // --> asset names, prices and decimals (usdt_decimals, balance_decimals)
+    // --> reserve buffer
+    // --> dust aggregate (minimum equity and balances), if any
+    // --> timestamp
+    // --> merkle tree root hash
+    // prover_version and the annotations are excluded, they are not part of what is proven and don't change the meaning of
+    // the proof
+    // the encoding doesn't depend on the file format (json, bincode, msgpack) or pretty-printing
+    // detached verifier data must be attached first (see FinalProof::read), otherwise the commitment is not the same
+    pub fn commitment(&self) -> [u8; 32] {
+        let mut input = CommitmentInput::default();
+
+        input.push_bytes(COMMITMENT_DOMAIN);
+        input.push_bytes(&self.proof.to_bytes());
+        input.push_bytes(&self.root_circuit_verifier_data);
+
+        input.push_u64(self.batch_size as u64);
+        input.push_u64(self.recursive_size as u64);
+        input.push_u64(self.tree_depth as u64);
+
+        input.push_u64(self.asset_names.len() as u64);
+        for (i, asset_name) in self.asset_names.iter().enumerate() {
+            input.push_bytes(asset_name.as_bytes());
+            input.push_u64(self.asset_prices[i]);
+            input.push_u64(self.asset_decimals[i].usdt_decimals as u64);
+            input.push_u64(self.asset_decimals[i].balance_decimals as u64);
+        }
+
+        input.push_u64(self.reserve_buffer.len() as u64);
+        for buffer in &self.reserve_buffer {
+            input.push_u64(*buffer as u64);
+        }
+
+        match &self.dust {
+            Some(dust) => {
+                input.push_u64(1);
+                input.push_u64(dust.min_equity);
+                input.push_u64(dust.balances.len() as u64);
+                for balance in &dust.balances {
+                    input.push_u64(*balance as u64);
+                }
+            }
+            None => input.push_u64(0),
+        }
+
+        input.push_u64(self.timestamp);
+        input.push_bytes(&self.root_hash());
+
+        H::hash_no_pad(&input.elements)
+            .to_bytes()
+            .try_into()
+            .unwrap()
+    }
+
+    // adds the given metadata (fields that are None are kept) and records the commitment it was made for
+    // with a signing key, the commitment is signed and the signature replaces the previous one
+    pub fn annotate(
+        &mut self,
+        exchange_id: Option<String>,
+        anchor_url: Option<String>,
+        sign_key: Option<&Ed25519KeyPair>,
+        annotated_at: u64,
+    ) -> Result<()> {
+        let commitment = self.commitment();
+        if let Some(sign_key) = sign_key {
+            let (public_key, signature) = sign_commitment(sign_key, &commitment);
+            self.annotations.public_key = Some(public_key);
+            self.annotations.signature = Some(signature);
+        }
+
+        let annotations = &mut self.annotations;
+        annotations.exchange_id = exchange_id.or(annotations.exchange_id.take());
+        annotations.anchor_url = anchor_url.or(annotations.anchor_url.take());
+        annotations.annotated_at = Some(annotated_at);
+        annotations.commitment = Some(bytes_to_hex(&commitment));
+
+        // a signature kept from a previous annotation must still be valid
+        self.verify_annotations()
+    }
+
+    // checks the annotations were made for the commitment of this proof and, if signed, the signature of the commitment
+    // the signer is only identified by its public key, which the caller has to compare with the exchange key
+    pub fn verify_annotations(&self) -> Result<()> {
+        let annotations = &self.annotations;
+        let commitment = self.commitment();
+
+        if let Some(annotated_commitment) = &annotations.commitment
+            && *annotated_commitment != bytes_to_hex(&commitment)
+        {
+            return Err(anyhow!("The annotations were made for a different commitment ({annotated_commitment})"));
+        }
+
+        match (&annotations.signature, &annotations.public_key) {
+            (None, None) => Ok(()),
+            (Some(signature), Some(public_key)) => verify_commitment_signature(public_key, signature, &commitment),
+            _ => Err(anyhow!("The commitment signature and its public key must be given together")),
+        }
+    }
+}
+
+// domain separation tag of the final proof commitment, bump the version if the encoding changes
+const COMMITMENT_DOMAIN: &[u8] = b"plonky2_por/final_proof_commitment/v1";
+
+// canonical encoding of the commitment input as field elements
+// every value is split into u32 limbs (so any u64 fits in the field) and byte strings are prefixed by their length
+#[derive(Default)]
+struct CommitmentInput {
+    elements: Vec<F>,
+}
+
+impl CommitmentInput {
+    fn push_u64(&mut self, value: u64) {
+        self.elements.push(F::from_canonical_u32(value as u32));
+        self.elements.push(F::from_canonical_u32((value >> 32) as u32));
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        self.push_u64(bytes.len() as u64);
+        for chunk in bytes.chunks(4) {
+            let mut limb = [0u8; 4];
+            limb[..chunk.len()].copy_from_slice(chunk);
+            self.elements.push(F::from_canonical_u32(u32::from_le_bytes(limb)));
+        }
+    }
+}
+
+// assets added and removed between two proofs (e.g. a listing or a delisting), compared by asset name
+// within a proof the asset layout is positional, but two proofs may have different asset sets
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssetChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl AssetChanges {
+    pub fn between(previous_assets: &[String], current_assets: &[String]) -> Self {
+        let previous: BTreeSet<&String> = previous_assets.iter().collect();
+        let current: BTreeSet<&String> = current_assets.iter().collect();
+
+        AssetChanges {
+            added: current.difference(&previous).map(|name| name.to_string()).collect(),
+            removed: previous.difference(&current).map(|name| name.to_string()).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+// history of published proofs (e.g. weekly snapshots) in a single artifact, ordered by timestamp
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProofArchive {
+    pub snapshots: Vec<FinalProof>,
+}
+
+impl ProofArchive {
+    // append a new snapshot, it must be strictly newer than the last one
+    pub fn append(&mut self, final_proof: FinalProof) -> Result<()> {
+        if let Some(last) = self.snapshots.last()
+            && final_proof.timestamp <= last.timestamp
+        {
+            return Err(anyhow!(
+                "Snapshot timestamp {} is not newer than the last archived snapshot ({})",
+                final_proof.timestamp,
+                last.timestamp
+            ));
+        }
+
+        self.snapshots.push(final_proof);
+        Ok(())
+    }
+
+    // timestamps of the archived snapshots, from oldest to newest
+    pub fn timestamps(&self) -> Vec<u64> {
+        self.snapshots.iter().map(|snapshot| snapshot.timestamp).collect()
+    }
+
+    // asset changes of each snapshot relative to the previous one (one entry per snapshot after the first)
+    pub fn asset_changes(&self) -> Vec<AssetChanges> {
+        self.snapshots
+            .windows(2)
+            .map(|pair| AssetChanges::between(&pair[0].asset_names, &pair[1].asset_names))
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MerkleProof{
-    #[serde(serialize_with = "base64::serialize_vec", deserialize_with = "base64::deserialize_vec")]
+    #[serde(serialize_with = "base64::serialize_vec", deserialize_with = "base64::deserialize_hash_vec")]
     pub left_hashes: Vec<Vec<u8>>,
-    #[serde(serialize_with = "base64::serialize_vec", deserialize_with = "base64::deserialize_vec")]
+    #[serde(serialize_with = "base64::serialize_vec", deserialize_with = "base64::deserialize_hash_vec")]
     pub right_hashes: Vec<Vec<u8>>,
     pub parent_hashes: Option<Box<MerkleProof>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InclusionProof{
+    // schema version (see CURRENT_FORMAT_VERSION), checked on load
+    #[serde(default = "format::legacy_format_version", deserialize_with = "format::deserialize_format_version")]
+    pub format_version: u32,
     pub user_balances: Vec<i64>,
     pub user_hash: String,
     pub nonce: u64,
     pub merkle_proof: MerkleProof,
-    #[serde(serialize_with = "base64::serialize", deserialize_with = "base64::deserialize")]
+    #[serde(serialize_with = "base64::serialize", deserialize_with = "base64::deserialize_hash")]
     pub root_hash: Vec<u8>,
 }
 
 impl InclusionProof {
+    // number of hashes (siblings and the path node) of each level of the merkle proof, from the leaf level to the root
+    pub fn level_widths(&self) -> Vec<usize> {
+        let mut widths = Vec::new();
+        let mut current_node = Some(&self.merkle_proof);
+
+        while let Some(node) = current_node {
+            widths.push(node.left_hashes.len() + node.right_hashes.len() + 1);
+            current_node = node.parent_hashes.as_deref();
+        }
+
+        widths
+    }
+
+    // checks the internal consistency of the proof without the global proof, so calculate_merkle_root_hash can't panic:
+    // --> every hash has HASH_BYTES bytes
+    // --> there is a batch level and at least one recursive level, and the recursive levels have the same width
+    // --> the leaf can be hashed (hex user hash and canonical nonce)
+    pub fn validate_structure(&self) -> Result<()> {
+        if self.root_hash.len() != HASH_BYTES {
+            return Err(anyhow!("The root hash has {} bytes, expected {}", self.root_hash.len(), HASH_BYTES));
+        }
+
+        let mut current_node = Some(&self.merkle_proof);
+        let mut level = 0;
+        while let Some(node) = current_node {
+            if let Some(hash) = node.left_hashes.iter().chain(&node.right_hashes).find(|hash| hash.len() != HASH_BYTES) {
+                return Err(anyhow!("Level {} has a hash of {} bytes, expected {}", level, hash.len(), HASH_BYTES));
+            }
+            current_node = node.parent_hashes.as_deref();
+            level += 1;
+        }
+
+        // level 0 is the batch level (batch_size leafs), the others are recursive levels (recursive_size children)
+        let widths = self.level_widths();
+        if widths.len() < 2 {
+            return Err(anyhow!("The merkle proof has {} levels, expected at least 2 (batch and recursive)", widths.len()));
+        }
+        if widths[1] < MIN_RECURSIVE_SIZE {
+            return Err(anyhow!("The recursive levels have {} hashes, expected at least {}", widths[1], MIN_RECURSIVE_SIZE));
+        }
+        if let Some(level) = (2..widths.len()).find(|level| widths[*level] != widths[1]) {
+            return Err(anyhow!(
+                "Level {} has {} hashes, expected {} like the other recursive levels",
+                level,
+                widths[level],
+                widths[1]
+            ));
+        }
+
+        compute_leaf_hash(&self.user_balances, &self.user_hash, self.nonce)?;
+
+        Ok(())
+    }
+
     pub fn calculate_merkle_root_hash(&self, leaf_hash: Vec<u8>) -> Vec<u8>{
         let mut current_hash = leaf_hash;
         let mut current_node = Some(&self.merkle_proof);
@@ -84,4 +1122,27 @@ impl InclusionProof {
 
         current_hash
     }
-}
\ No newline at end of file
+}
+// index of the inclusion proof bundles (prove-inclusion --all --layout bundles), written next to the bundles so consumers
+// find the bundle of a user hash with the same prefix length used to group them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleIndex {
+    pub group_prefix_len: usize,
+    pub bundle_count: usize,
+    pub proof_count: usize,
+}
+
+impl BundleIndex {
+    // index file name inside the bundles directory
+    pub const FILE_NAME: &'static str = "bundle_index.json";
+
+    // group of a user hash: its first group_prefix_len characters
+    pub fn group_prefix(&self, user_hash: &str) -> String {
+        user_hash.chars().take(self.group_prefix_len).collect()
+    }
+
+    // name of the bundle file (inside the bundles directory) holding the inclusion proof of a user hash
+    pub fn bundle_filename(&self, user_hash: &str) -> String {
+        format!("inclusion_proofs_{}.json.zst", self.group_prefix(user_hash))
+    }
+}
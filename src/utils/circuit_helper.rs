@@ -15,6 +15,15 @@ pub fn is_negative(builder: &mut CircuitBuilder<F, D>, x: Target) -> BoolTarget
     builder.is_equal(divided, one)
 }
 
+// range check a signed value: x must be in [-2^bits, 2^bits)
+// negative numbers are field elements close to ORDER, so x is shifted by 2^bits before the (unsigned) range check
+#[inline]
+pub fn range_check_signed(builder: &mut CircuitBuilder<F, D>, x: Target, bits: usize) {
+    let offset = builder.constant(F::from_canonical_u64(1u64 << bits));
+    let shifted = builder.add(x, offset);
+    builder.range_check(shifted, bits + 1);
+}
+
 #[inline]
 pub fn is_positive(builder: &mut CircuitBuilder<F, D>, x: Target) -> BoolTarget {
     let is_negative = is_negative(builder, x);
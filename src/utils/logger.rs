@@ -1,5 +1,72 @@
-use crate::config::*;
-use std::io::Write;
+use std::cell::Cell;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verbosity {
+    Quiet = 0,   // errors only, no progress bar
+    Normal = 1,
+    Verbose = 2, // include debug timings
+}
+
+// global log verbosity (set once by the CLI)
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    VERBOSITY.load(Ordering::Relaxed) == Verbosity::Quiet as u8
+}
+
+pub fn is_verbose() -> bool {
+    VERBOSITY.load(Ordering::Relaxed) == Verbosity::Verbose as u8
+}
+
+// debug timings are shown in debug builds or with --verbose (never in quiet mode)
+pub fn show_timings() -> bool {
+    !is_quiet() && (cfg!(debug_assertions) || is_verbose())
+}
+
+// last proving progress, shared so other threads can report where proving stopped (e.g. the CLI SIGINT handler)
+static DONE_BATCH_CIRCUITS: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_BATCH_CIRCUITS: AtomicUsize = AtomicUsize::new(0);
+static DONE_RECURSIVE_PROOFS: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_RECURSIVE_PROOFS: AtomicUsize = AtomicUsize::new(0);
+
+// describes the current proving stage, None if nothing is being proven
+pub fn prove_progress_status() -> Option<String> {
+    let total_batches = TOTAL_BATCH_CIRCUITS.load(Ordering::Relaxed);
+    if total_batches == 0 {
+        return None;
+    }
+
+    let done_batches = DONE_BATCH_CIRCUITS.load(Ordering::Relaxed);
+    if done_batches < total_batches {
+        return Some(format!("batch {done_batches}/{total_batches}"));
+    }
+
+    Some(format!(
+        "recursive proof {}/{} (all {} batches proven)",
+        DONE_RECURSIVE_PROOFS.load(Ordering::Relaxed),
+        TOTAL_RECURSIVE_PROOFS.load(Ordering::Relaxed),
+        total_batches
+    ))
+}
+
+// when stdout is not a terminal (pipe or log file), the progress is printed as plain lines every PLAIN_PROGRESS_STEP%
+// instead of animating the bar with carriage returns
+const PLAIN_PROGRESS_STEP: f64 = 5.;
+
+// print a plain progress line if the progress reached a new step
+fn print_plain_progress(progress: f64, last_step: &Cell<usize>) {
+    let step = (progress / PLAIN_PROGRESS_STEP).floor() as usize;
+    if step > last_step.get() {
+        last_step.set(step);
+        println!("Progress: {progress:.2}%");
+    }
+}
 
 pub struct ProveProgress{
     total_batch_circuits: usize,
@@ -10,6 +77,8 @@ pub struct ProveProgress{
     created_recursive_circuits: usize,
     total_progress: f64,
     bar_width: usize,
+    is_terminal: bool,
+    last_plain_step: Cell<usize>,
 }
 
 const BATCH_PROVE_PROGRESS: f64 = 50.; // 50% of the time is spent in batch proving (estimated)
@@ -18,17 +87,22 @@ const RECURSIVE_PROVE_PROGRESS: f64 = 35.; // 35% of the time is spent in recurs
 
 
 impl ProveProgress{
-    pub fn new(total_batch_circuits: usize) -> Self {
+    pub fn new(total_batch_circuits: usize, recursive_size: usize) -> Self {
         let mut total_recursive_proofs = 1; // 1 to account for the root proof
         let mut total_recursive_circuits = 0;
         let mut remaining = total_batch_circuits;
 
         while remaining > 1{
             total_recursive_circuits += 1;
-            total_recursive_proofs += remaining / RECURSIVE_SIZE;
-            remaining /= RECURSIVE_SIZE;
+            total_recursive_proofs += remaining / recursive_size;
+            remaining /= recursive_size;
         }
 
+        DONE_BATCH_CIRCUITS.store(0, Ordering::Relaxed);
+        TOTAL_BATCH_CIRCUITS.store(total_batch_circuits, Ordering::Relaxed);
+        DONE_RECURSIVE_PROOFS.store(0, Ordering::Relaxed);
+        TOTAL_RECURSIVE_PROOFS.store(total_recursive_proofs, Ordering::Relaxed);
+
         ProveProgress{
             total_batch_circuits,
             done_batch_circuits: 0,
@@ -38,15 +112,25 @@ impl ProveProgress{
             done_recursive_proofs: 0,
             total_progress: 0.,
             bar_width: 50,
+            is_terminal: std::io::stdout().is_terminal(),
+            last_plain_step: Cell::new(0),
         }
     }
 
     pub fn print_progress_bar(&self) {
+        if is_quiet() {
+            return;
+        }
 
         let progress = self.total_progress;
         let bar_width = self.bar_width;
         // Ensure progress is within the valid range [0.0, 100.0]
         let clamped_progress = progress.clamp(0.0, 100.0);
+
+        if !self.is_terminal {
+            print_plain_progress(clamped_progress, &self.last_plain_step);
+            return;
+        }
     
         // Calculate the number of filled characters for the bar
         let progress_chars = (clamped_progress / 100.0 * bar_width as f64).floor() as usize;
@@ -71,6 +155,10 @@ impl ProveProgress{
     }
 
     pub fn clear_bar(&self){
+        if is_quiet() || !self.is_terminal {
+            return;
+        }
+
         let clear_line = " ".repeat(self.bar_width + 10); // Add some buffer just in case
         print!("\r{clear_line}\r");
     }
@@ -83,6 +171,7 @@ impl ProveProgress{
 
     pub fn update_batch_progress(&mut self){
         self.done_batch_circuits += 1;
+        DONE_BATCH_CIRCUITS.store(self.done_batch_circuits, Ordering::Relaxed);
         self.update_total_progress();
 
         self.print_progress_bar();
@@ -90,6 +179,7 @@ impl ProveProgress{
 
     pub fn update_recursive_progress(&mut self){
         self.done_recursive_proofs += 1;
+        DONE_RECURSIVE_PROOFS.store(self.done_recursive_proofs, Ordering::Relaxed);
         self.update_total_progress();
 
         self.print_progress_bar();
@@ -107,6 +197,8 @@ pub struct ProveInclusionProgress{
     total_users: usize,
     done_users: usize,
     bar_width: usize,
+    is_terminal: bool,
+    last_plain_step: Cell<usize>,
 }
 
 impl ProveInclusionProgress{
@@ -115,14 +207,25 @@ impl ProveInclusionProgress{
             total_users,
             done_users: 0,
             bar_width: 50,
+            is_terminal: std::io::stdout().is_terminal(),
+            last_plain_step: Cell::new(0),
         }
     }
 
     pub fn print_progress_bar(&self) {
+        if is_quiet() {
+            return;
+        }
+
         let progress = (self.done_users as f64 / self.total_users as f64) * 100.0;
         let bar_width = self.bar_width;
         // Ensure progress is within the valid range [0.0, 100.0]
         let clamped_progress = progress.clamp(0.0, 100.0);
+
+        if !self.is_terminal {
+            print_plain_progress(clamped_progress, &self.last_plain_step);
+            return;
+        }
     
         // Calculate the number of filled characters for the bar
         let progress_chars = (clamped_progress / 100.0 * bar_width as f64).floor() as usize;
@@ -147,6 +250,10 @@ impl ProveInclusionProgress{
     }
 
     pub fn clear_bar(&self){
+        if is_quiet() || !self.is_terminal {
+            return;
+        }
+
         let clear_line = " ".repeat(self.bar_width + 10); // Add some buffer just in case
         print!("\r{clear_line}\r");
     }
@@ -158,13 +265,18 @@ impl ProveInclusionProgress{
 
 }
 
+// colored output printed directly to stdout/stderr (default)
+#[cfg(not(feature = "tracing"))]
 #[macro_export]
 macro_rules! log_success {
     ($($arg:tt)*) => {
-        println!("\x1b[32m[+] {}\x1b[0m", format!($($arg)*));
+        if !$crate::utils::logger::is_quiet() {
+            println!("\x1b[32m[+] {}\x1b[0m", format!($($arg)*));
+        }
     };
 }
 
+#[cfg(not(feature = "tracing"))]
 #[macro_export]
 macro_rules! log_error {
     ($($arg:tt)*) => {
@@ -172,26 +284,101 @@ macro_rules! log_error {
     };
 }
 
+#[cfg(not(feature = "tracing"))]
 #[macro_export]
 macro_rules! log_info {
     ($($arg:tt)*) => {
-        println!("\x1b[34m[!] {}\x1b[0m", format!($($arg)*));
+        if !$crate::utils::logger::is_quiet() {
+            println!("\x1b[34m[!] {}\x1b[0m", format!($($arg)*));
+        }
     };
 }
 
+#[cfg(not(feature = "tracing"))]
 #[macro_export]
 macro_rules! log_warning {
     ($($arg:tt)*) => {
-        println!("\x1b[33m[!] {}\x1b[0m", format!($($arg)*));
+        if !$crate::utils::logger::is_quiet() {
+            println!("\x1b[33m[!] {}\x1b[0m", format!($($arg)*));
+        }
     };
 }
 
+#[cfg(not(feature = "tracing"))]
+#[macro_export]
+macro_rules! log_span {
+    ($name:expr) => {
+        $crate::utils::logger::NoopSpan
+    };
+}
+
+// "tracing" feature: route the logs through the tracing facade so the host controls formatting, levels and destinations
+// (tracing falls back to the log facade when no subscriber is installed), --quiet still only lets the errors through
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! log_success {
+    ($($arg:tt)*) => {
+        if !$crate::utils::logger::is_quiet() {
+            ::tracing::info!(success = true, "{}", format!($($arg)*));
+        }
+    };
+}
+
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        ::tracing::error!("{}", format!($($arg)*))
+    };
+}
+
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        if !$crate::utils::logger::is_quiet() {
+            ::tracing::info!("{}", format!($($arg)*));
+        }
+    };
+}
+
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! log_warning {
+    ($($arg:tt)*) => {
+        if !$crate::utils::logger::is_quiet() {
+            ::tracing::warn!("{}", format!($($arg)*));
+        }
+    };
+}
+
+// enter a span for a proving phase, the span is exited when the returned guard is dropped
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! log_span {
+    ($name:expr) => {
+        ::tracing::info_span!($name).entered()
+    };
+}
+
+// guard returned by log_span! when the "tracing" feature is disabled
+pub struct NoopSpan;
+
 // to be used with .expect() or similar
+// the message is left uncolored with the "tracing" feature, the subscriber formats it
 pub fn format_error(message: &str) -> String {
+    if cfg!(feature = "tracing") {
+        return message.to_string();
+    }
+
     format!("\x1b[31m[-] {message}\x1b[0m")
 }
 
 pub fn print_header(){
+    if is_quiet() {
+        return;
+    }
+
     println!("========================================================================");
     println!(r"   ____  _   _            _____             _____      _____       ___  
   / __ \| | | |          / ____|           |  __ \    |  __ \     |__ \ 
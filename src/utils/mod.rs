@@ -1,3 +1,4 @@
 pub mod logger;
+pub mod signing;
 pub mod util;
 pub mod circuit_helper;
\ No newline at end of file
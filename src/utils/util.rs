@@ -1,8 +1,8 @@
 use crate::config::*;
-use anyhow::Result;
-use bigdecimal::BigDecimal;
+use anyhow::{anyhow, Result};
+use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive};
 use plonky2::{
-    field::{extension::Extendable, types::Field},
+    field::{extension::Extendable, types::{Field, Field64}},
     hash::{
         hash_types::{HashOut, RichField},
         poseidon::PoseidonHash,
@@ -13,17 +13,31 @@ use plonky2::{
     },
 };
 use chrono::{DateTime, Utc};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
 
 
 // NEED TO ADD PADDING TO RECURSIVE TREES
 
 // pad accounts to have a multiple of BATCH_SIZE
+// the accounts and hashes are batched side by side, so a length mismatch is rejected here instead of panicking mid-prove
 pub fn pad_accounts(
     accounts: &mut Vec<Vec<i64>>,
     hashes: &mut Vec<String>,
     asset_count: usize,
     batch_size: usize,
 ) -> Result<()> {
+    if accounts.len() != hashes.len() {
+        return Err(anyhow!(
+            "Cannot pad the accounts: {} user hashes but {} accounts",
+            hashes.len(),
+            accounts.len()
+        ));
+    }
+    if hashes.is_empty() {
+        return Err(anyhow!("Cannot pad the accounts: the ledger has no accounts"));
+    }
+
     let mut padded_accounts = Vec::new();
     let mut padded_hashes = Vec::new();
 
@@ -44,13 +58,35 @@ pub fn pad_accounts(
     Ok(())
 }
 
+// padding accounts use a zero hash (see pad_accounts)
+pub fn is_padding_hash(hash: &str) -> bool {
+    !hash.is_empty() && hash.chars().all(|c| c == '0')
+}
+
+// reserved leaf hash used for the reserve buffer (insurance fund) account
+pub fn reserve_buffer_hash(hash_nibbles: usize) -> String {
+    "f".repeat(hash_nibbles)
+}
+
+// reserved leaf hash used for the aggregate of the dust accounts (below the minimum equity)
+pub fn dust_aggregate_hash(hash_nibbles: usize) -> String {
+    "e".repeat(hash_nibbles)
+}
+
+// the reserve buffer and dust aggregate leaf hashes can't be user hashes, whatever their length or case
+pub fn is_reserved_leaf_hash(hash: &str) -> bool {
+    let hash = hash.to_ascii_lowercase();
+    !hash.is_empty() && (hash == reserve_buffer_hash(hash.len()) || hash == dust_aggregate_hash(hash.len()))
+}
+
 pub fn pad_recursive_proofs(
     proofs: &mut Vec<ProofWithPublicInputs<F, C, D>>,
     empty_proof: &ProofWithPublicInputs<F, C, D>,
+    recursive_size: usize,
 ) {
-    // only pad if the number of proofs is not a multiple of RECURSIVE_SIZE
-    if proofs.len() % RECURSIVE_SIZE != 0 {
-        let padding = RECURSIVE_SIZE - (proofs.len() % RECURSIVE_SIZE);
+    // only pad if the number of proofs is not a multiple of recursive_size
+    if proofs.len() % recursive_size != 0 {
+        let padding = recursive_size - (proofs.len() % recursive_size);
         for _ in 0..padding {
             proofs.push(empty_proof.clone());
         }
@@ -75,27 +111,131 @@ pub fn hash_n_subhashes<F: RichField + Extendable<D>, const D: usize>(
     PoseidonHash::hash_no_pad(inputs.as_slice())
 }
 
+// generate a random leaf nonce, it must be a canonical field element (in [0, F::ORDER)) so it is hashed as is
+pub fn random_nonce() -> u64 {
+    rand::random_range(0..F::ORDER)
+}
+
+// check if a nonce is a canonical field element, a larger one would be reduced when hashed
+pub fn check_nonce(nonce: u64) -> Result<()> {
+    if nonce >= F::ORDER {
+        return Err(anyhow!(
+            "Invalid nonce {nonce}: it must be lower than the field order ({})",
+            F::ORDER
+        ));
+    }
+    Ok(())
+}
+
+// leaf hash of an account, same as hash_account but the inputs are validated first (e.g. user provided)
+pub fn compute_leaf_hash(balances: &[i64], user_hash: &str, nonce: u64) -> Result<Vec<u8>> {
+    user_hash_elements(user_hash)?;
+    check_nonce(nonce)?;
+
+    Ok(hash_account(&balances.to_vec(), user_hash.to_string(), nonce).to_bytes())
+}
+
+// lowercase hex encoding of a hash (e.g. to publish or compare root hashes)
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// decodes a lowercase or uppercase hex string (optionally 0x prefixed), the inverse of bytes_to_hex
+pub fn hex_to_bytes(hex: &str) -> Result<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid hex string {hex}"));
+    }
+
+    Ok((0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+// asset names drive the positional mapping of prices, decimals and reserves, so a name can't appear twice
+pub fn check_unique_asset_names(asset_names: &[String]) -> Result<()> {
+    let mut seen = std::collections::HashSet::new();
+    for name in asset_names {
+        if !seen.insert(name) {
+            return Err(anyhow!("Duplicated asset name {name} in the proof"));
+        }
+    }
+
+    Ok(())
+}
+
+// user hashes are non-empty lowercase hex strings (see user_hash_elements)
+pub fn is_valid_user_hash(user_hash: &str) -> bool {
+    !user_hash.is_empty() && user_hash.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+// converts a hex user hash of any length to field elements, so a verifier can reproduce it exactly:
+// - user hashes are lowercase hex, an uppercase character is rejected (the ledger and the inclusion lookups compare the
+//   raw strings, so "AB" and "ab" must not be two users with the same leaf encoding)
+// - the first element is the number of hex characters (so "ab" and "ab0" give different elements)
+// - then one element per USER_HASH_CHUNK_HEX_LEN characters, read as a big endian number (60 bits, below the field
+//   order, so two hashes of the same length never give the same elements)
+// - the last chunk, if shorter, is zero-extended on the right ("ab" is read as "ab0000000000000")
+pub fn user_hash_elements(user_hash: &str) -> Result<Vec<F>> {
+    user_hash_elements_with_chunk_len(user_hash, USER_HASH_CHUNK_HEX_LEN)
+}
+
+// same scheme as user_hash_elements with chunks of chunk_hex_len characters, for verifiers of another chunking
+// the chunks must stay below the field order to never be reduced, so at most USER_HASH_CHUNK_HEX_LEN characters
+pub fn user_hash_elements_with_chunk_len(user_hash: &str, chunk_hex_len: usize) -> Result<Vec<F>> {
+    if !(1..=USER_HASH_CHUNK_HEX_LEN).contains(&chunk_hex_len) {
+        return Err(anyhow!(
+            "Invalid user hash chunk length {chunk_hex_len}, it must be between 1 and {USER_HASH_CHUNK_HEX_LEN} hex characters"
+        ));
+    }
+    if !is_valid_user_hash(user_hash) {
+        return Err(anyhow!("Invalid user hash {user_hash}: it must be a non-empty lowercase hex string"));
+    }
+
+    let mut elements = vec![F::from_canonical_usize(user_hash.len())];
+    for chunk in user_hash.as_bytes().chunks(chunk_hex_len) {
+        // the chunk is ascii hex (checked above)
+        let hex = format!("{:0<width$}", std::str::from_utf8(chunk)?, width = chunk_hex_len);
+        elements.push(F::from_canonical_u64(u64::from_str_radix(&hex, 16)?));
+    }
+
+    Ok(elements)
+}
+
 // hash account balances and userhash
+// hash input: balances | user hash elements (see user_hash_elements) | nonce
+// the user hash must be a hex string, it is validated with the ledger (see Ledger::validate_accounts) or before hashing
+// an untrusted inclusion proof
 pub fn hash_account(balances: &Vec<i64>, userhash: String, nonce: u64) -> HashOut<F> {
+    hash_account_with_chunk_len(balances, &userhash, nonce, USER_HASH_CHUNK_HEX_LEN)
+        .expect("user hashes are validated before hashing")
+}
+
+// hash_account with user hash chunks of chunk_hex_len characters (see user_hash_elements_with_chunk_len)
+// the prover always uses USER_HASH_CHUNK_HEX_LEN, it is part of the leaf hash scheme of its prover version
+pub fn hash_account_with_chunk_len(balances: &[i64], user_hash: &str, nonce: u64, chunk_hex_len: usize) -> Result<HashOut<F>> {
     // convert everything to Field
     let mut hash_input = Vec::new();
     for balance in balances {
         hash_input.push(F::from_noncanonical_i64(*balance));
     }
 
-    // convert hex hash to vec of u64
-    let mut hash_input_hex = Vec::new();
-    for i in (0..userhash.len()).step_by(16) {
-        let hex = &userhash[i..i + 16];
-        let num = u64::from_str_radix(hex, 16).unwrap();
-        hash_input_hex.push(F::from_canonical_u64(num));
-    }
+    // convert hex hash to field elements
+    hash_input.extend(user_hash_elements_with_chunk_len(user_hash, chunk_hex_len)?);
 
     // convert nonce to Field
     let nonce_field = F::from_canonical_u64(nonce);
     hash_input.push(nonce_field);
 
-    PoseidonHash::hash_no_pad(hash_input.as_slice())
+    Ok(PoseidonHash::hash_no_pad(hash_input.as_slice()))
+}
+
+// sha-256 of serialized verifier data (e.g. the root circuit verifier data of a final proof)
+// unlike the plonky2 circuit digest, which only covers the constants and sigmas cap and can be set to anything in an
+// untrusted file, it covers every byte (gates, common data, ...), so equal digests mean the exact same verifier data
+pub fn verifier_data_digest(verifier_data: &[u8]) -> Vec<u8> {
+    ring::digest::digest(&ring::digest::SHA256, verifier_data).as_ref().to_vec()
 }
 
 // convert HashOut elements to hash bytes
@@ -107,6 +247,151 @@ pub fn calculate_with_decimals(value: i64, decimals: i64) -> BigDecimal {
     BigDecimal::new(value.into(), decimals)
 }
 
+// convert a human decimal price (e.g. "0.000012345") into the integer price scaled by usdt_decimals
+// the price must be exact with usdt_decimals decimals (no precision is lost) and fit in a u64
+pub fn scale_price(decimal_price: &str, usdt_decimals: i64) -> Result<u64> {
+    let price = BigDecimal::from_str(decimal_price.trim())
+        .map_err(|e| anyhow!("Invalid price {decimal_price}: {e}"))?;
+
+    let scaled = price * BigDecimal::new(1.into(), -usdt_decimals);
+    if !scaled.is_integer() {
+        return Err(anyhow!(
+            "Price {decimal_price} has more decimals than usdt_decimals ({usdt_decimals})"
+        ));
+    }
+
+    scaled
+        .to_u64()
+        .ok_or_else(|| anyhow!("Price {decimal_price} scaled by {usdt_decimals} decimals does not fit in a u64"))
+}
+
+// inverse of scale_price: convert a scaled integer price into its decimal value
+pub fn unscale_price(price: u64, usdt_decimals: i64) -> BigDecimal {
+    BigDecimal::new(price.into(), usdt_decimals)
+}
+
+// parse the price of an asset in the ledger
+// --> integers are prices already scaled by usdt_decimals
+// --> strings and non-integer numbers are decimal prices, scaled with scale_price (prefer strings to keep the exact value)
+pub fn parse_ledger_price(price: &serde_json::Value, usdt_decimals: i64) -> Result<u64> {
+    if let Some(price) = price.as_u64() {
+        return Ok(price);
+    }
+
+    match price {
+        serde_json::Value::String(price) => scale_price(price, usdt_decimals),
+        serde_json::Value::Number(price) => scale_price(&price.to_string(), usdt_decimals),
+        _ => Err(anyhow!("Invalid price: {price}")),
+    }
+}
+
+// parse an account (or reserve buffer) balance of the ledger, an integer scaled by balance_decimals
+// --> integers are read as is
+// --> strings are integers too (exchanges export them as strings to preserve the precision), without decimals
+// the balance must be in the range accepted by the batch circuit range check, checked here so a bad ledger is
+// rejected when it is read instead of in the middle of the proving
+pub fn parse_ledger_balance(balance: &serde_json::Value) -> Result<i64> {
+    let value = match balance {
+        serde_json::Value::Null => return Err(anyhow!("The balance is missing")),
+        serde_json::Value::String(value) => value
+            .trim()
+            .parse::<i64>()
+            .map_err(|e| anyhow!("Invalid balance {balance}, expected an integer scaled by balance_decimals: {e}"))?,
+        _ => balance
+            .as_i64()
+            .ok_or_else(|| anyhow!("Invalid balance {balance}, expected an integer scaled by balance_decimals in the i64 range"))?,
+    };
+
+    check_balance_range(value)?;
+
+    Ok(value)
+}
+
+// a balance must be in the range accepted by the batch circuit range check: [-2^MAX_ACCOUNT_BALANCE_BITS,
+// MAX_RANGE_CHECKED_BALANCE]
+pub fn check_balance_range(value: i64) -> Result<()> {
+    let min_balance = -(1i64 << MAX_ACCOUNT_BALANCE_BITS);
+    let max_balance = MAX_RANGE_CHECKED_BALANCE as i64;
+    if value < min_balance || value > max_balance {
+        return Err(anyhow!("Balance {value} is out of the supported range [{min_balance}, {max_balance}]"));
+    }
+
+    Ok(())
+}
+
+// parse the ledger timestamp (milliseconds since the Unix epoch), it must be present, nonzero and plausibly recent
+// (see MIN_LEDGER_TIMESTAMP and MAX_LEDGER_TIMESTAMP_SKEW), since every report renders it with format_timestamp
+pub fn parse_ledger_timestamp(timestamp: &serde_json::Value) -> Result<u64> {
+    let timestamp = match timestamp {
+        serde_json::Value::Null => return Err(anyhow!("The ledger timestamp is missing")),
+        timestamp => timestamp
+            .as_u64()
+            .ok_or_else(|| anyhow!("Invalid ledger timestamp {timestamp}, expected milliseconds since the Unix epoch"))?,
+    };
+
+    if timestamp == 0 {
+        return Err(anyhow!("The ledger timestamp is zero"));
+    }
+    if timestamp < MIN_LEDGER_TIMESTAMP {
+        return Err(anyhow!(
+            "The ledger timestamp {timestamp} is before 2020-01-01, make sure it is in milliseconds (not seconds)"
+        ));
+    }
+
+    let now = Utc::now().timestamp_millis() as u64;
+    if timestamp > now + MAX_LEDGER_TIMESTAMP_SKEW {
+        return Err(anyhow!("The ledger timestamp {timestamp} is in the future"));
+    }
+
+    Ok(timestamp)
+}
+
+// number of decimals of the printed amounts (set once by the CLI), negative means exact (no rounding)
+// it is only used for display, the verification always uses the exact values
+static DISPLAY_PRECISION: AtomicI64 = AtomicI64::new(-1);
+
+pub fn set_display_precision(precision: Option<u32>) {
+    DISPLAY_PRECISION.store(precision.map_or(-1, i64::from), Ordering::Relaxed);
+}
+
+// format an amount for display: rounded to the display precision (if set) and with thousands grouping
+pub fn format_amount(value: &BigDecimal) -> String {
+    let precision = DISPLAY_PRECISION.load(Ordering::Relaxed);
+    let plain = if precision >= 0 {
+        value.with_scale_round(precision, RoundingMode::HalfUp).to_plain_string()
+    } else {
+        value.to_plain_string()
+    };
+
+    group_thousands(&plain)
+}
+
+// insert thousands separators in the integer part of a plain decimal number (e.g. -1234567.89 -> -1,234,567.89)
+fn group_thousands(number: &str) -> String {
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", number),
+    };
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (unsigned, None),
+    };
+
+    let mut grouped = String::from(sign);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+
+    if let Some(fraction) = fraction {
+        grouped.push('.');
+        grouped.push_str(fraction);
+    }
+
+    grouped
+}
 
 pub fn format_timestamp(timestamp_milliseconds: u64) -> Result<String, &'static str> {
     // Convert u64 to i64. chrono::DateTime::from_timestamp_opt requires i64.
@@ -0,0 +1,57 @@
+// The proof annotations (annotate): exchange id, anchor URL and an ed25519 signature of the proof commitment. They are
+// metadata outside the commitment, so annotating never changes what was proven and the annotated proof still verifies.
+// It uses the tiny circuits of ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::core::verifier::verify_root;
+use plonky2_por::{bytes_to_hex, signing_key_from_bytes, ProverConfig, VerifyOptions};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use common::ledger;
+
+fn generate_signing_key() -> Ed25519KeyPair {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    signing_key_from_bytes(pkcs8.as_ref()).unwrap()
+}
+
+#[test]
+fn annotating_keeps_the_commitment() {
+    let (final_proof, merkle_tree, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+    let commitment = final_proof.commitment();
+    let sign_key = generate_signing_key();
+
+    let mut annotated_proof = final_proof.clone();
+    annotated_proof.annotate(Some("otter".to_string()), None, Some(&sign_key), 1746488438000).unwrap();
+    annotated_proof.annotate(None, Some("https://example.com/anchor".to_string()), None, 1746488439000).unwrap();
+    assert_eq!(annotated_proof.commitment(), commitment);
+    assert_eq!(annotated_proof.annotations.commitment, Some(bytes_to_hex(&commitment)));
+    assert_eq!(annotated_proof.annotations.exchange_id.as_deref(), Some("otter"));
+    assert_eq!(annotated_proof.annotations.annotated_at, Some(1746488439000));
+    assert_eq!(annotated_proof.annotations.public_key, Some(bytes_to_hex(sign_key.public_key().as_ref())));
+    annotated_proof.verify_annotations().unwrap();
+
+    verify_root(annotated_proof, merkle_tree, &VerifyOptions::default());
+}
+
+#[test]
+fn signature_is_checked_against_the_commitment_and_its_key() {
+    let (final_proof, _, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+
+    let mut annotated_proof = final_proof.clone();
+    annotated_proof.annotate(None, None, Some(&generate_signing_key()), 1746488438000).unwrap();
+    annotated_proof.verify_annotations().unwrap();
+
+    // another public key
+    let mut tampered_proof = annotated_proof.clone();
+    tampered_proof.annotations.public_key = Some(bytes_to_hex(generate_signing_key().public_key().as_ref()));
+    assert!(tampered_proof.verify_annotations().is_err());
+
+    // another commitment, which can't be annotated either
+    let mut tampered_proof = annotated_proof.clone();
+    tampered_proof.timestamp += 1;
+    assert!(tampered_proof.verify_annotations().is_err());
+    assert!(tampered_proof.annotate(None, None, None, 0).is_err());
+}
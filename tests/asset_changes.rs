@@ -0,0 +1,37 @@
+// Proofs of different periods may have different asset sets (listings and delistings),
+// so they are compared by asset name and never by position.
+
+use plonky2_por::{check_unique_asset_names, AssetChanges};
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn same_assets_have_no_changes() {
+    let assets = names(&["BTC", "ETH", "USDT"]);
+    assert!(AssetChanges::between(&assets, &assets).is_empty());
+}
+
+#[test]
+fn reordered_assets_have_no_changes() {
+    let changes = AssetChanges::between(&names(&["BTC", "ETH", "USDT"]), &names(&["USDT", "BTC", "ETH"]));
+    assert!(changes.is_empty());
+}
+
+#[test]
+fn delisted_and_listed_assets_are_reported_by_name() {
+    let changes = AssetChanges::between(&names(&["BTC", "ETH", "LUNA"]), &names(&["BTC", "SOL", "ETH"]));
+
+    assert_eq!(changes.added, names(&["SOL"]));
+    assert_eq!(changes.removed, names(&["LUNA"]));
+    assert!(!changes.is_empty());
+}
+
+#[test]
+fn duplicated_asset_name_is_reported() {
+    assert!(check_unique_asset_names(&names(&["BTC", "ETH", "USDT"])).is_ok());
+
+    let error = check_unique_asset_names(&names(&["BTC", "ETH", "BTC"])).unwrap_err().to_string();
+    assert!(error.contains("BTC"), "{error}");
+}
@@ -0,0 +1,96 @@
+// A full batch of the largest (negative) balances accepted by the range checks must be proven with the exact total,
+// and balances out of the range must be rejected before proving.
+#![cfg(feature = "prover")]
+
+use plonky2::field::types::{Field64, PrimeField64};
+use plonky2_por::circuits::batch_circuit::BatchCircuit;
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::{HashOut, Ledger, LedgerDecimals, ProverConfig, BATCH_SIZE, F, MAX_ACCOUNT_BALANCE_BITS};
+
+fn batch_total(proof_total: F) -> i128 {
+    let value = proof_total.to_canonical_u64();
+    if value > (F::ORDER - 1) / 2 {
+        -((F::ORDER - value) as i128)
+    } else {
+        value as i128
+    }
+}
+
+#[test]
+fn batch_of_large_negative_balances_is_proven_without_underflow() {
+    let batch_circuit = BatchCircuit::new(1);
+
+    let min_balance = -(1i64 << MAX_ACCOUNT_BALANCE_BITS);
+    let accounts = vec![vec![min_balance]; BATCH_SIZE];
+    let leaf_hashes = vec![HashOut::<F>::default(); BATCH_SIZE];
+
+    let proof = batch_circuit
+        .prove_batch_circuit(&[1], &accounts, &leaf_hashes)
+        .unwrap();
+    batch_circuit.circuit_data.verify(proof.clone()).unwrap();
+
+    let total = proof.public_inputs[BatchCircuit::get_final_balances_offset(1)][0];
+    assert_eq!(batch_total(total), min_balance as i128 * BATCH_SIZE as i128);
+}
+
+#[test]
+fn balance_out_of_range_is_rejected() {
+    let batch_circuit = BatchCircuit::new(1);
+    let leaf_hashes = vec![HashOut::<F>::default(); BATCH_SIZE];
+
+    for balance in [-(1i64 << MAX_ACCOUNT_BALANCE_BITS) - 1, 1i64 << MAX_ACCOUNT_BALANCE_BITS] {
+        let mut accounts = vec![vec![0]; BATCH_SIZE];
+        accounts[BATCH_SIZE - 1][0] = balance;
+
+        assert!(
+            batch_circuit
+                .prove_batch_circuit(&[1], &accounts, &leaf_hashes)
+                .is_err()
+        );
+    }
+}
+
+// a programmatic ledger isn't checked by the ledger parser, its balances are checked before the first batch is proven
+#[test]
+fn ledger_balance_out_of_range_is_rejected_before_proving() {
+    let mut ledger = Ledger {
+        asset_names: vec!["BTC".to_string()],
+        hashes: (1..=3).map(|i| format!("{i:064x}")).collect(),
+        account_balances: vec![vec![1], vec![2], vec![1i64 << MAX_ACCOUNT_BALANCE_BITS]],
+        asset_prices: vec![1],
+        asset_decimals: vec![LedgerDecimals { usdt_decimals: 2, balance_decimals: 4 }],
+        reserve_buffer: Vec::new(),
+        dust: None,
+        timestamp: 1746488437000,
+    };
+
+    let error = ledger.validate_accounts().unwrap_err().to_string();
+    assert!(error.contains("asset BTC") && error.contains("out of the supported range"), "{error}");
+    assert!(prove_global_with_config(ledger.clone(), &ProverConfig::testing()).is_err());
+
+    ledger.account_balances[2][0] = 3;
+    ledger.reserve_buffer = vec![-(1i64 << MAX_ACCOUNT_BALANCE_BITS) - 1];
+    let error = ledger.validate_accounts().unwrap_err().to_string();
+    assert!(error.contains("Invalid reserve buffer"), "{error}");
+}
+
+// the witness is built without the range check of prove_batch_circuit, so only the circuit constraints stand between
+// an out of range balance and a batch proof: proving must fail (the witness generation panics in debug builds) or the
+// proof must not verify
+#[test]
+fn circuit_rejects_balance_out_of_range() {
+    let batch_circuit = BatchCircuit::new(1);
+    let leaf_hashes = vec![HashOut::<F>::default(); BATCH_SIZE];
+
+    for balance in [-(1i64 << MAX_ACCOUNT_BALANCE_BITS) - 1, 1i64 << MAX_ACCOUNT_BALANCE_BITS, i64::MIN, i64::MAX] {
+        let mut accounts = vec![vec![0]; BATCH_SIZE];
+        accounts[BATCH_SIZE - 1][0] = balance;
+
+        let proven = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| -> anyhow::Result<()> {
+            let witness = batch_circuit.witness(&[1], &accounts, &leaf_hashes)?;
+            let proof = batch_circuit.circuit_data.prove(witness)?;
+            batch_circuit.circuit_data.verify(proof)
+        }));
+        assert!(!matches!(proven, Ok(Ok(()))), "the balance {balance} was proven");
+    }
+}
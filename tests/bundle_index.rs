@@ -0,0 +1,62 @@
+// Batched inclusion proofs are grouped in bundles by a configurable user hash prefix length, which is stored in
+// the bundle index so the bundle of a user hash can be found with the same prefix length.
+#![cfg(feature = "prover")]
+
+use plonky2_por::{
+    read_bundle_index, read_bundled_inclusion_proof, BundleIndex, InclusionProof, MerkleProof, CURRENT_FORMAT_VERSION,
+};
+use std::collections::HashMap;
+
+fn bundles_dir(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("por_bundles_{name}_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.to_string_lossy().into_owned()
+}
+
+fn inclusion_proof(user_hash: &str) -> InclusionProof {
+    InclusionProof {
+        format_version: CURRENT_FORMAT_VERSION,
+        user_balances: vec![1, 2],
+        user_hash: user_hash.to_string(),
+        nonce: 7,
+        merkle_proof: MerkleProof { left_hashes: Vec::new(), right_hashes: Vec::new(), parent_hashes: None },
+        root_hash: vec![0; 32],
+    }
+}
+
+#[test]
+fn bundle_filename_uses_the_prefix_length() {
+    let index = |group_prefix_len| BundleIndex { group_prefix_len, bundle_count: 0, proof_count: 0 };
+
+    assert_eq!(index(3).bundle_filename("abcdef"), "inclusion_proofs_abc.json.zst");
+    assert_eq!(index(1).bundle_filename("abcdef"), "inclusion_proofs_a.json.zst");
+    assert_eq!(index(5).bundle_filename("abc"), "inclusion_proofs_abc.json.zst");
+}
+
+#[test]
+fn bundles_without_index_use_the_default_prefix_length() {
+    let dir = bundles_dir("legacy");
+    assert_eq!(read_bundle_index(&dir).unwrap().group_prefix_len, plonky2_por::DEFAULT_GROUP_PREFIX_LEN);
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn bundled_proof_is_found_with_the_indexed_prefix_length() {
+    let dir = bundles_dir("indexed");
+    let index = BundleIndex { group_prefix_len: 2, bundle_count: 1, proof_count: 2 };
+    std::fs::write(format!("{dir}/{}", BundleIndex::FILE_NAME), serde_json::to_vec(&index).unwrap()).unwrap();
+
+    let bundle = ["ab01", "ab02"]
+        .into_iter()
+        .map(|hash| (hash.to_string(), inclusion_proof(hash)))
+        .collect::<HashMap<_, _>>();
+    let compressed = zstd::encode_all(serde_json::to_vec(&bundle).unwrap().as_slice(), 3).unwrap();
+    std::fs::write(format!("{dir}/inclusion_proofs_ab.json.zst"), compressed).unwrap();
+
+    assert_eq!(read_bundled_inclusion_proof(&dir, "ab02").unwrap().user_hash, "ab02");
+    assert!(read_bundled_inclusion_proof(&dir, "ab03").is_err());
+    // another prefix is in another (missing) bundle
+    assert!(read_bundled_inclusion_proof(&dir, "cd01").is_err());
+
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,64 @@
+// compute_leaf_hash (used by the check-leaf command) must give the leaf hashed by the prover and reject invalid inputs.
+
+use plonky2::plonk::config::GenericHashOut;
+use plonky2_por::utils::util::hash_account;
+use plonky2_por::{bytes_to_hex, compute_leaf_hash, hex_to_bytes};
+
+const USER_HASH: &str = "6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b";
+
+#[test]
+fn leaf_hash_matches_the_prover_leaf() {
+    let balances = vec![534054, -4761, 0];
+    let nonce = 1234567890;
+
+    let leaf_hash = compute_leaf_hash(&balances, USER_HASH, nonce).unwrap();
+    assert_eq!(leaf_hash, hash_account(&balances, USER_HASH.to_string(), nonce).to_bytes());
+}
+
+#[test]
+fn leaf_hash_commits_to_the_user_hash() {
+    let balances = vec![534054, -4761, 0];
+    let nonce = 1234567890;
+    let leaf_hash = compute_leaf_hash(&balances, USER_HASH, nonce).unwrap();
+
+    // same balances and nonce, another user
+    let other_user = format!("{}0", &USER_HASH[..63]);
+    assert_ne!(compute_leaf_hash(&balances, &other_user, nonce).unwrap(), leaf_hash);
+    assert_ne!(compute_leaf_hash(&balances, &USER_HASH[..60], nonce).unwrap(), leaf_hash);
+
+    // user hashes are lowercase, the uppercase spelling is not the same user
+    assert!(compute_leaf_hash(&balances, &USER_HASH.to_ascii_uppercase(), nonce).is_err());
+}
+
+#[test]
+fn invalid_user_hash_is_rejected() {
+    for user_hash in ["", "zz86b273ff34fce1", "0x6b86b273"] {
+        assert!(compute_leaf_hash(&[1], user_hash, 1).is_err(), "{user_hash}");
+    }
+
+    // any hex length is accepted (see user_hash_elements)
+    for user_hash in ["abc", &USER_HASH[..40], &USER_HASH[..60]] {
+        assert!(compute_leaf_hash(&[1], user_hash, 1).is_ok(), "{user_hash}");
+    }
+}
+
+#[test]
+fn out_of_range_nonce_is_rejected() {
+    assert!(compute_leaf_hash(&[1], USER_HASH, u64::MAX).is_err());
+}
+
+#[test]
+fn hex_encoding_is_lowercase_and_padded() {
+    assert_eq!(bytes_to_hex(&[0x00, 0x0f, 0xab, 0xff]), "000fabff");
+}
+
+#[test]
+fn hex_decoding_is_the_inverse_of_encoding() {
+    assert_eq!(hex_to_bytes("000fabff").unwrap(), vec![0x00, 0x0f, 0xab, 0xff]);
+    assert_eq!(hex_to_bytes("0x000FABFF").unwrap(), vec![0x00, 0x0f, 0xab, 0xff]);
+    assert_eq!(hex_to_bytes(USER_HASH).map(|bytes| bytes_to_hex(&bytes)).unwrap(), USER_HASH);
+
+    for hex in ["abc", "zz", "0x0"] {
+        assert!(hex_to_bytes(hex).is_err(), "{hex}");
+    }
+}
@@ -0,0 +1,49 @@
+// Ledgers shared by the test files (each one declares `mod common;` and uses what it needs).
+#![allow(dead_code)]
+
+#[cfg(feature = "prover")]
+use plonky2_por::core::prover::prove_global_with_config;
+#[cfg(feature = "prover")]
+use plonky2_por::{nonces_by_hash, resolve_nonces, ProverConfig};
+use plonky2_por::{Ledger, LedgerDecimals, MerkleTree};
+
+// BTC and ETH ($60000.00 and $2000.00), one account per balances, the user hashes are the account indexes from 0
+pub fn ledger_with_balances(account_balances: Vec<Vec<i64>>) -> Ledger {
+    Ledger {
+        asset_names: vec!["BTC".to_string(), "ETH".to_string()],
+        hashes: (0..account_balances.len()).map(|i| format!("{i:064x}")).collect(),
+        account_balances,
+        asset_prices: vec![6000000, 200000],
+        asset_decimals: vec![
+            LedgerDecimals { usdt_decimals: 2, balance_decimals: 4 },
+            LedgerDecimals { usdt_decimals: 2, balance_decimals: 4 },
+        ],
+        reserve_buffer: Vec::new(),
+        dust: None,
+        timestamp: 1746488437000,
+    }
+}
+
+// account i (from 1) holds i * 1000 BTC and i * 20000 ETH units, its user hash is i
+// with the circuits of ProverConfig::testing(): 6 accounts --> 2 batches (the second one padded with 2 accounts),
+// 9 accounts --> 3 batches (padded to 4 batch nodes) --> 2 recursive levels
+pub fn ledger(account_count: usize) -> Ledger {
+    let mut ledger = ledger_with_balances((1..=account_count as i64).map(|i| vec![i * 1000, i * 20000]).collect());
+    ledger.hashes = (1..=account_count).map(|i| format!("{i:064x}")).collect();
+    ledger
+}
+
+// the merkle tree and the nonces keyed by user hash of ledger(6), resolved for the servers
+#[cfg(feature = "prover")]
+pub fn keyed_inputs() -> (Ledger, MerkleTree, Vec<u64>) {
+    let ledger = ledger(6);
+    let (_, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &ProverConfig::testing()).unwrap();
+    let keyed_nonces = nonces_by_hash(&ledger.hashes, &nonces).unwrap();
+    let user_nonces = resolve_nonces((&keyed_nonces).into(), &ledger, &merkle_tree).unwrap();
+
+    (ledger, merkle_tree, user_nonces)
+}
+
+pub fn root_hash(merkle_tree: &MerkleTree) -> Vec<u8> {
+    merkle_tree.root.hash().clone().unwrap()
+}
@@ -0,0 +1,72 @@
+// The inclusion daemon (server) answers the user hashes sent on its local socket with the path of the inclusion proof
+// it wrote, and rejects the requests above its in-flight limit. It uses the tiny circuits of ProverConfig::testing()
+// (batch 4, recursive 2).
+#![cfg(all(feature = "prover", target_family = "unix"))]
+
+mod common;
+
+use plonky2_por::core::server::{create_local_server, send_hash_to_server, ConcurrencyLimiter, SOCKET_PATH};
+use plonky2_por::{verify_inclusion_against_root, InclusionProof};
+use common::{keyed_inputs, root_hash};
+
+// a limit of 0 would answer every request busy, it is rejected before the socket is bound
+#[test]
+fn rejects_a_zero_in_flight_limit() {
+    let (ledger, merkle_tree, user_nonces) = keyed_inputs();
+    let out_dir = std::env::temp_dir().join(format!("por_server_zero_{}", std::process::id()));
+    let error = create_local_server(merkle_tree.clone(), user_nonces.clone(), ledger.clone(), 0, 4, &out_dir.to_string_lossy())
+        .unwrap_err();
+    assert!(error.to_string().contains("at least 1"), "{error}");
+
+    #[cfg(feature = "async-server")]
+    {
+        use plonky2_por::core::server::create_local_server_async;
+
+        let error = create_local_server_async(merkle_tree, user_nonces, ledger, 0, 4, &out_dir.to_string_lossy()).unwrap_err();
+        assert!(error.to_string().contains("at least 1"), "{error}");
+    }
+}
+
+#[test]
+fn serves_the_keyed_nonces() {
+    let (ledger, merkle_tree, user_nonces) = keyed_inputs();
+    let trusted_root = root_hash(&merkle_tree);
+    let user_hash = ledger.hashes[5].clone();
+
+    let out_dir = std::env::temp_dir().join(format!("por_server_nonces_{}", std::process::id()));
+    let out_dir_arg = out_dir.to_string_lossy().into_owned();
+    let _ = std::fs::remove_file(SOCKET_PATH);
+    std::thread::spawn(move || create_local_server(merkle_tree, user_nonces, ledger, 1, 4, &out_dir_arg));
+
+    for _ in 0..100 {
+        if std::path::Path::new(SOCKET_PATH).exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    send_hash_to_server(&user_hash).unwrap();
+    // an unknown user is answered, not failed
+    let error = send_hash_to_server(&"f".repeat(64)).unwrap_err().to_string();
+    assert!(error.contains("not found in the ledger"), "{error}");
+
+    let proof_file = out_dir.join(format!("inclusion_proof_{user_hash}.json"));
+    let inclusion_proof: InclusionProof = serde_json::from_slice(&std::fs::read(&proof_file).unwrap()).unwrap();
+    assert_eq!(inclusion_proof.user_hash, user_hash);
+    verify_inclusion_against_root(&inclusion_proof, &trusted_root).unwrap();
+
+    let _ = std::fs::remove_dir_all(out_dir);
+    let _ = std::fs::remove_file(SOCKET_PATH);
+}
+
+#[test]
+fn requests_above_the_in_flight_limit_are_busy() {
+    let limiter = ConcurrencyLimiter::new(2);
+    let first = limiter.try_acquire().unwrap();
+    let second = limiter.try_acquire().unwrap();
+    assert!(limiter.try_acquire().is_none());
+
+    // the slot is released when the request is done
+    drop(first);
+    assert!(limiter.try_acquire().is_some());
+    drop(second);
+}
@@ -0,0 +1,71 @@
+// The root circuit verifier data can be written to a separate file (prove --detach-verifier-data). The proof references
+// it by the sha-256 of its raw bytes, so only the exact verifier data it was detached from can be attached back.
+// It uses the tiny circuits of ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::util::serialization::DefaultGateSerializer;
+use plonky2_por::config::{C, D, F};
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::{
+    bytes_to_hex, root_circuit_digest, verifier_data_digest, verify_global_proof, verify_proof_only, write_to_file,
+    FinalProof, ProverConfig, SerializationFormat, VerifyOptions,
+};
+use common::ledger;
+
+#[test]
+fn the_shape_is_known_before_proving() {
+    let config = ProverConfig::testing();
+    let ledger = ledger(9);
+    let tree_depth = config.padding_report(ledger.leaf_count()).unwrap().tree_depth;
+
+    let (_, merkle_tree, _) = prove_global_with_config(ledger, &config).unwrap();
+    assert_eq!(merkle_tree.depth, tree_depth);
+}
+
+#[test]
+fn only_the_detached_bytes_can_be_attached() {
+    let (final_proof, _, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+
+    let mut detached = final_proof.clone();
+    let verifier_data = detached.detach_verifier_data().unwrap();
+    assert_eq!(detached.verifier_data_digest, Some(bytes_to_hex(&verifier_data_digest(&verifier_data))));
+
+    // same plonky2 circuit digest, other common data
+    let mut tampered: VerifierCircuitData<F, C, D> =
+        VerifierCircuitData::from_bytes(verifier_data.clone(), &DefaultGateSerializer).unwrap();
+    tampered.common.config.security_bits -= 1;
+    let tampered = tampered.to_bytes(&DefaultGateSerializer).unwrap();
+    let error = detached.clone().attach_verifier_data(tampered).unwrap_err().to_string();
+    assert!(error.contains("but the proof references"), "{error}");
+
+    detached.attach_verifier_data(verifier_data).unwrap();
+    assert_eq!(detached.commitment(), final_proof.commitment());
+}
+
+#[test]
+fn detached_proof_is_read_with_its_verifier_data_file() {
+    let (final_proof, _, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+
+    // the proof references the verifier data by the hash of its bytes, the root circuit digest
+    let mut detached = final_proof.clone();
+    let verifier_data = detached.detach_verifier_data().unwrap();
+    assert!(detached.is_verifier_data_detached());
+    assert_eq!(detached.verifier_data_digest, Some(bytes_to_hex(&root_circuit_digest(&final_proof).unwrap())));
+    assert!(verify_global_proof(&detached).unwrap_err().to_string().contains("detached"));
+    assert!(detached.clone().attach_verifier_data(verifier_data[1..].to_vec()).is_err());
+
+    // the verifier data file is looked up next to the proof file
+    let dir = std::env::temp_dir().join(format!("por_verifier_data_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let proof_file = dir.join("final_proof.json").to_string_lossy().into_owned();
+    write_to_file(&proof_file, &detached, SerializationFormat::Json).unwrap();
+    assert!(FinalProof::read(&proof_file).is_err());
+    std::fs::write(dir.join(FinalProof::VERIFIER_DATA_FILE_NAME), &verifier_data).unwrap();
+    let attached = FinalProof::read(&proof_file).unwrap();
+    assert_eq!(attached.commitment(), final_proof.commitment());
+    verify_proof_only(&attached, &VerifyOptions::default()).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
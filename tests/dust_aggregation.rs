@@ -0,0 +1,75 @@
+// Accounts below the minimum equity are aggregated into a single dust leaf (Ledger::aggregate_dust).
+
+mod common;
+
+use plonky2_por::{DustAggregate, MAX_RANGE_CHECKED_BALANCE};
+use common::ledger_with_balances;
+
+#[test]
+fn accounts_below_min_equity_are_aggregated() {
+    // equities: $6.00, $0.20, $60000.00, $0.00 (negative ETH covered by BTC)
+    let mut ledger = ledger_with_balances(vec![vec![1, 0], vec![0, 1], vec![10000, 0], vec![1, -30]]);
+    let hashes = ledger.hashes.clone();
+
+    let min_equity = ledger.scale_equity("1").unwrap();
+    assert_eq!(ledger.aggregate_dust(min_equity).unwrap(), 2);
+
+    // the relative order of the proven accounts is kept
+    assert_eq!(ledger.hashes, vec![hashes[0].clone(), hashes[2].clone()]);
+    assert_eq!(ledger.account_balances, vec![vec![1, 0], vec![10000, 0]]);
+    assert_eq!(
+        ledger.dust,
+        Some(DustAggregate { min_equity, balances: vec![1, -29] })
+    );
+    assert!(ledger.validate_accounts().is_ok());
+}
+
+#[test]
+fn no_dust_aggregate_without_dust_accounts() {
+    let mut ledger = ledger_with_balances(vec![vec![1, 0], vec![10000, 0]]);
+    let min_equity = ledger.scale_equity("0.50").unwrap();
+
+    assert_eq!(ledger.aggregate_dust(min_equity).unwrap(), 0);
+    assert!(ledger.dust.is_none());
+    assert_eq!(ledger.hashes.len(), 2);
+}
+
+#[test]
+fn dust_is_aggregated_once() {
+    let mut ledger = ledger_with_balances(vec![vec![1, 0], vec![0, 1]]);
+    let min_equity = ledger.scale_equity("1").unwrap();
+
+    ledger.aggregate_dust(min_equity).unwrap();
+    assert!(ledger.aggregate_dust(min_equity).is_err());
+    assert!(ledger.clone().merge(ledger.clone()).is_err());
+}
+
+#[test]
+fn every_account_below_min_equity_is_rejected() {
+    let mut ledger = ledger_with_balances(vec![vec![0, 1], vec![0, 2]]);
+    let min_equity = ledger.scale_equity("100").unwrap();
+
+    assert!(ledger.aggregate_dust(min_equity).is_err());
+}
+
+#[test]
+fn min_equity_must_be_exact() {
+    let ledger = ledger_with_balances(vec![vec![1, 0]]);
+
+    // 6 decimals (usdt_decimals + balance_decimals)
+    assert_eq!(ledger.scale_equity("1.5").unwrap(), 1500000);
+    assert!(ledger.scale_equity("0.0000001").is_err());
+    assert!(ledger.scale_equity("-1").is_err());
+}
+
+#[test]
+fn dust_aggregate_out_of_range_is_rejected() {
+    // each dust account is in range and below the minimum equity (a BTC debt covered by ETH is still negative), but
+    // their BTC debts sum below the range accepted by the batch circuit
+    let max = MAX_RANGE_CHECKED_BALANCE as i64;
+    let mut ledger = ledger_with_balances(vec![vec![-max, max], vec![-max, max], vec![10000, 0]]);
+    let min_equity = ledger.scale_equity("1").unwrap();
+
+    let error = ledger.aggregate_dust(min_equity).unwrap_err().to_string();
+    assert!(error.contains("Dust aggregate of asset BTC"), "{error}");
+}
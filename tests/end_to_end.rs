@@ -0,0 +1,63 @@
+// Full pipeline regression test: prove a synthetic ledger, verify the root, generate an inclusion proof and verify it.
+// The default circuit sizes (BATCH_SIZE accounts per batch) make this far too slow for CI, so it uses the tiny
+// circuits of ProverConfig::testing() (batch 4, recursive 2). The other verify and inclusion paths have their own test
+// files (verify_modes.rs, reserves_report.rs, inclusion_provers.rs, ...).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::core::verifier::{check_user_inclusion, verify_root};
+use plonky2_por::{
+    prove_inclusion_by_index, resolve_nonces, verify_global_proof, verify_inclusion_against_root, ProverConfig,
+    VerifyOptions,
+};
+use common::ledger;
+
+#[test]
+fn prove_verify_and_check_inclusion() {
+    let config = ProverConfig::testing();
+    let ledger = ledger(9);
+
+    let (final_proof, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &config).unwrap();
+    assert_eq!(final_proof.prover_config(), config);
+    assert_eq!(merkle_tree.depth, 4);
+
+    // the proven reserves are the sum of every account
+    let expected_totals = (0..ledger.asset_names.len())
+        .map(|asset| ledger.account_balances.iter().map(|balances| balances[asset]).sum::<i64>())
+        .collect::<Vec<_>>();
+    assert_eq!(final_proof.asset_reserves(), expected_totals);
+
+    // the public inputs expose the proven prices and the merkle tree root
+    let public_inputs = final_proof.public_inputs();
+    assert_eq!(public_inputs.final_balances(), expected_totals);
+    assert_eq!(public_inputs.asset_prices(), ledger.asset_prices);
+    assert_eq!(&Some(public_inputs.root_hash_bytes()), merkle_tree.root.hash());
+
+    // panics if any check fails (rebuilds the testing circuits from the sizes stored in the final proof)
+    verify_root(final_proof.clone(), merkle_tree.clone(), &VerifyOptions::default());
+
+    // the global proof is verified once, then each user only recomputes the root
+    let trusted_root = verify_global_proof(&final_proof).unwrap();
+    let user_nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).unwrap();
+
+    for index in [0, 4, ledger.hashes.len() - 1] {
+        let inclusion_proof = prove_inclusion_by_index(index, &merkle_tree, &user_nonces, &ledger).unwrap();
+        check_user_inclusion(&final_proof, &inclusion_proof).unwrap();
+        verify_inclusion_against_root(&inclusion_proof, &trusted_root).unwrap();
+
+        // a tampered balance doesn't recompute the proven root
+        let mut tampered = inclusion_proof.clone();
+        tampered.user_balances[0] += 1;
+        assert!(check_user_inclusion(&final_proof, &tampered).is_err());
+        assert!(verify_inclusion_against_root(&tampered, &trusted_root).is_err());
+    }
+}
+
+#[test]
+fn batch_size_above_range_checks_is_rejected() {
+    let config = ProverConfig { batch_size: plonky2_por::BATCH_SIZE + 1, ..ProverConfig::testing() };
+    assert!(config.validate().is_err());
+    assert!(prove_global_with_config(ledger(9), &config).is_err());
+}
@@ -0,0 +1,83 @@
+// The root circuit can be pinned with a trusted digest (--expected-digest) instead of being rebuilt. The digest covers the
+// whole serialized verifier data, so crafted verifier data is rejected even if it keeps the plonky2 circuit digest field.
+// It uses the tiny circuits of ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::util::serialization::DefaultGateSerializer;
+use plonky2_por::config::{C, D, F};
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::core::verifier::verify_root;
+use plonky2_por::{
+    expected_root_circuit_digest, root_circuit_digest, verifier_data_digest, verify_proof_only, ProverConfig,
+    VerifyOptions,
+};
+use common::ledger;
+
+fn pinned(expected_digest: Vec<u8>) -> VerifyOptions {
+    VerifyOptions {
+        rebuild_circuit: false,
+        expected_digest: Some(expected_digest),
+        ..VerifyOptions::default()
+    }
+}
+
+#[test]
+fn digest_is_the_hash_of_the_whole_verifier_data() {
+    let config = ProverConfig::testing();
+    let (final_proof, merkle_tree, _) = prove_global_with_config(ledger(9), &config).unwrap();
+
+    let expected_digest = expected_root_circuit_digest(final_proof.asset_names.len(), merkle_tree.depth, &config).unwrap();
+    assert_eq!(expected_digest.len(), 32);
+    assert_eq!(expected_digest, verifier_data_digest(&final_proof.root_circuit_verifier_data));
+    assert_eq!(root_circuit_digest(&final_proof).unwrap(), expected_digest);
+
+    verify_proof_only(&final_proof, &pinned(expected_digest)).unwrap();
+}
+
+#[test]
+fn pinned_digest_verifies_the_root_without_rebuilding_the_circuit() {
+    let config = ProverConfig::testing();
+    let (final_proof, merkle_tree, _) = prove_global_with_config(ledger(9), &config).unwrap();
+
+    // the digest computed from the proof shape alone is the one of the circuit data inside the proof
+    let expected_digest = expected_root_circuit_digest(final_proof.asset_names.len(), merkle_tree.depth, &config).unwrap();
+    let options = pinned(expected_digest);
+    assert!(options.is_full());
+    verify_root(final_proof, merkle_tree, &options);
+}
+
+#[test]
+#[should_panic(expected = "does not match the expected digest")]
+fn proof_with_another_circuit_digest_is_rejected() {
+    let (final_proof, merkle_tree, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+
+    let options = VerifyOptions {
+        expected_digest: Some(vec![0; 32]),
+        ..VerifyOptions::default()
+    };
+    verify_root(final_proof, merkle_tree, &options);
+}
+
+#[test]
+fn tampered_verifier_data_keeping_the_circuit_digest_is_rejected() {
+    let config = ProverConfig::testing();
+    let (final_proof, merkle_tree, _) = prove_global_with_config(ledger(9), &config).unwrap();
+    let expected_digest = expected_root_circuit_digest(final_proof.asset_names.len(), merkle_tree.depth, &config).unwrap();
+
+    // the plonky2 circuit digest doesn't cover the common data, so this change keeps a valid looking circuit digest
+    let mut verifier_data: VerifierCircuitData<F, C, D> =
+        VerifierCircuitData::from_bytes(final_proof.root_circuit_verifier_data.clone(), &DefaultGateSerializer).unwrap();
+    let circuit_digest = verifier_data.verifier_only.circuit_digest;
+    verifier_data.common.config.security_bits -= 1;
+    assert_eq!(verifier_data.verifier_only.circuit_digest, circuit_digest);
+
+    let mut tampered = final_proof.clone();
+    tampered.root_circuit_verifier_data = verifier_data.to_bytes(&DefaultGateSerializer).unwrap();
+    assert_ne!(tampered.root_circuit_verifier_data, final_proof.root_circuit_verifier_data);
+
+    let error = verify_proof_only(&tampered, &pinned(expected_digest)).unwrap_err().to_string();
+    assert!(error.contains("does not match the expected digest"), "{error}");
+}
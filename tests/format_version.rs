@@ -0,0 +1,201 @@
+// The artifacts record the version of their schema (CURRENT_FORMAT_VERSION): files written before it was recorded
+// are read as LEGACY_FORMAT_VERSION, newer versions are rejected with a clear error and previous versions are migrated.
+// The final proof of the 1.x prover check uses the tiny circuits of ProverConfig::testing() (batch 4, recursive 2).
+
+mod common;
+
+use plonky2_por::custom_serializer::format::{from_bytes, is_legacy_bincode, to_bytes};
+use plonky2_por::{
+    check_format_version, InclusionProof, MerkleProof, MerkleTree, Node, ProverConfig, SerializationFormat,
+    CURRENT_FORMAT_VERSION, LEGACY_FORMAT_VERSION,
+};
+use serde_json::Value;
+
+fn inclusion_proof() -> InclusionProof {
+    InclusionProof {
+        format_version: CURRENT_FORMAT_VERSION,
+        user_balances: vec![1, 2],
+        user_hash: "0".repeat(63) + "1",
+        nonce: 7,
+        merkle_proof: MerkleProof {
+            left_hashes: vec![vec![1; 32]],
+            right_hashes: vec![],
+            parent_hashes: None,
+        },
+        root_hash: vec![4; 32],
+    }
+}
+
+fn merkle_tree() -> MerkleTree {
+    let leafs = (0..4u8).map(|i| Node::new(Some(vec![i; 32]))).collect();
+    MerkleTree::new_from_leafs_with_config(leafs, 1, true, &ProverConfig::testing())
+}
+
+fn with_version(mut json: Value, version: Option<u32>) -> Vec<u8> {
+    match version {
+        Some(version) => json["format_version"] = version.into(),
+        None => {
+            json.as_object_mut().unwrap().remove("format_version");
+        }
+    }
+    serde_json::to_vec(&json).unwrap()
+}
+
+#[test]
+fn artifacts_are_written_with_the_current_version() {
+    assert_eq!(merkle_tree().format_version, CURRENT_FORMAT_VERSION);
+
+    for format in [SerializationFormat::Json, SerializationFormat::Bincode, SerializationFormat::Msgpack] {
+        let bytes = to_bytes(&inclusion_proof(), format).unwrap();
+        assert_eq!(from_bytes::<InclusionProof>(&bytes).unwrap().format_version, CURRENT_FORMAT_VERSION);
+        let bytes = to_bytes(&merkle_tree(), format).unwrap();
+        assert_eq!(from_bytes::<MerkleTree>(&bytes).unwrap().format_version, CURRENT_FORMAT_VERSION);
+    }
+}
+
+#[test]
+fn unversioned_files_are_read_as_the_legacy_version() {
+    let json = serde_json::to_value(inclusion_proof()).unwrap();
+    let proof: InclusionProof = from_bytes(&with_version(json, None)).unwrap();
+    assert_eq!(proof.format_version, LEGACY_FORMAT_VERSION);
+
+    let json = serde_json::to_value(merkle_tree()).unwrap();
+    let tree: MerkleTree = from_bytes(&with_version(json, None)).unwrap();
+    assert_eq!(tree.format_version, LEGACY_FORMAT_VERSION);
+}
+
+// bincode files written before the format_version fields existed (tests/fixtures/legacy, PORB header)
+fn legacy_fixture(name: &str) -> Vec<u8> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/legacy").join(name);
+    std::fs::read(path).unwrap()
+}
+
+#[test]
+fn legacy_bincode_files_are_read_as_the_legacy_version() {
+    let bytes = legacy_fixture("inclusion_proof.bin");
+    assert!(is_legacy_bincode(&bytes));
+    assert_eq!(SerializationFormat::detect(&bytes), SerializationFormat::Bincode);
+    let proof: InclusionProof = from_bytes(&bytes).unwrap();
+    assert_eq!(proof.format_version, LEGACY_FORMAT_VERSION);
+    assert_eq!((proof.user_balances, proof.nonce), (vec![1, 2], 7));
+    assert_eq!(proof.user_hash, "0".repeat(63) + "1");
+    assert_eq!(proof.merkle_proof.left_hashes, vec![vec![1; 32]]);
+    assert_eq!(proof.root_hash, vec![4; 32]);
+
+    let bytes = legacy_fixture("merkle_tree.bin");
+    let tree: MerkleTree = from_bytes(&bytes).unwrap();
+    assert_eq!(tree.format_version, LEGACY_FORMAT_VERSION);
+    assert_eq!((tree.depth, tree.real_leaf_count), (2, Some(2)));
+    assert_eq!(tree.root.hash(), &Some(vec![9; 32]));
+    assert_eq!(tree.leaf_hashes(), vec![vec![1; 32], vec![2; 32]]);
+    assert_eq!(tree.batch_reserves, vec![vec![3, 4]]);
+
+    // the versioned files have another header, and the legacy mode doesn't leak into the next reads
+    let bytes = to_bytes(&inclusion_proof(), SerializationFormat::Bincode).unwrap();
+    assert!(!is_legacy_bincode(&bytes));
+    assert_eq!(from_bytes::<InclusionProof>(&bytes).unwrap().format_version, CURRENT_FORMAT_VERSION);
+}
+
+#[test]
+fn unsupported_versions_are_rejected() {
+    for version in [0, CURRENT_FORMAT_VERSION + 1] {
+        assert!(check_format_version(version).is_err());
+
+        let json = serde_json::to_value(inclusion_proof()).unwrap();
+        let error = from_bytes::<InclusionProof>(&with_version(json, Some(version))).unwrap_err().to_string();
+        assert!(error.contains(&format!("Unsupported format version {version}")), "{error}");
+
+        let json = serde_json::to_value(merkle_tree()).unwrap();
+        let error = from_bytes::<MerkleTree>(&with_version(json, Some(version))).unwrap_err().to_string();
+        assert!(error.contains(&format!("Unsupported format version {version}")), "{error}");
+    }
+
+    // binary formats too (the version is their first field)
+    let mut newer = inclusion_proof();
+    newer.format_version = CURRENT_FORMAT_VERSION + 1;
+    let bytes = to_bytes(&newer, SerializationFormat::Bincode).unwrap();
+    assert!(from_bytes::<InclusionProof>(&bytes).unwrap_err().to_string().contains("Unsupported format version"));
+}
+
+#[test]
+fn previous_versions_are_migrated_to_the_current_version() {
+    use plonky2_por::{migrate, migrate_file};
+
+    // an unversioned tree gets the current version and its level arities, the hashes are untouched
+    let mut tree = merkle_tree();
+    tree.format_version = LEGACY_FORMAT_VERSION;
+    tree.level_arities = Vec::new();
+    let migrated = migrate(tree.clone()).unwrap();
+    assert_eq!(migrated.format_version, CURRENT_FORMAT_VERSION);
+    assert_eq!(migrated.level_arities, tree.level_arities());
+    assert_eq!(migrated.root.hash(), tree.root.hash());
+
+    let mut newer = merkle_tree();
+    newer.format_version = CURRENT_FORMAT_VERSION + 1;
+    assert!(migrate(newer).is_err());
+
+    // files are rewritten in their own serialization format, the kind of artifact is detected
+    let dir = std::env::temp_dir().join(format!("por_migrate_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("inclusion_proof.msgpack").to_string_lossy().into_owned();
+    let mut proof = inclusion_proof();
+    proof.format_version = LEGACY_FORMAT_VERSION;
+    std::fs::write(&path, to_bytes(&proof, SerializationFormat::Msgpack).unwrap()).unwrap();
+
+    let migrated = migrate_file(&path, &path).unwrap();
+    assert_eq!((migrated.artifact, migrated.from_version), ("inclusion proof", LEGACY_FORMAT_VERSION));
+    assert_eq!(migrated.format, SerializationFormat::Msgpack);
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(SerializationFormat::detect(&bytes), SerializationFormat::Msgpack);
+    let proof: InclusionProof = from_bytes(&bytes).unwrap();
+    assert_eq!((proof.format_version, proof.root_hash), (CURRENT_FORMAT_VERSION, vec![4; 32]));
+
+    // the unversioned json and legacy bincode files written by previous versions (tests/fixtures/legacy)
+    for (name, artifact) in [
+        ("inclusion_proof.json", "inclusion proof"),
+        ("inclusion_proof.bin", "inclusion proof"),
+        ("merkle_tree.json", "merkle tree"),
+        ("merkle_tree.bin", "merkle tree"),
+    ] {
+        let input = dir.join(name).to_string_lossy().into_owned();
+        std::fs::write(&input, legacy_fixture(name)).unwrap();
+        let output = dir.join(format!("migrated_{name}")).to_string_lossy().into_owned();
+
+        let migrated = migrate_file(&input, &output).unwrap();
+        assert_eq!((migrated.artifact, migrated.from_version), (artifact, LEGACY_FORMAT_VERSION));
+        let bytes = std::fs::read(&output).unwrap();
+        assert_eq!(SerializationFormat::detect(&bytes), migrated.format);
+        assert!(!is_legacy_bincode(&bytes));
+        if artifact == "inclusion proof" {
+            let proof: InclusionProof = from_bytes(&bytes).unwrap();
+            assert_eq!((proof.format_version, proof.nonce, proof.root_hash), (CURRENT_FORMAT_VERSION, 7, vec![4; 32]));
+        } else {
+            let tree: MerkleTree = from_bytes(&bytes).unwrap();
+            assert_eq!((tree.format_version, tree.depth), (CURRENT_FORMAT_VERSION, 2));
+            assert_eq!(tree.root.hash(), &Some(vec![9; 32]));
+        }
+    }
+
+    // anything else is rejected
+    std::fs::write(&path, b"{\"user_hash\": 1}").unwrap();
+    assert!(migrate_file(&path, &path).is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+#[cfg(feature = "prover")]
+fn final_proofs_of_the_1x_prover_are_not_migrated() {
+    use plonky2_por::core::prover::prove_global_with_config;
+    use plonky2_por::migrate;
+
+    let (mut final_proof, _, _) = prove_global_with_config(common::ledger(9), &ProverConfig::testing()).unwrap();
+    final_proof.format_version = LEGACY_FORMAT_VERSION;
+    assert_eq!(migrate(final_proof.clone()).unwrap().format_version, CURRENT_FORMAT_VERSION);
+
+    // its leaf hashes (and batch circuit) differ, marking it as the current version would not make it verify
+    for prover_version in ["v1.2.0", "v2.0.0"] {
+        final_proof.prover_version = prover_version.to_string();
+        let error = migrate(final_proof.clone()).unwrap_err();
+        assert!(format!("{error:#}").contains(&format!("plonky2_por {prover_version}")), "{error:#}");
+    }
+}
@@ -0,0 +1,72 @@
+// Hash fields (inclusion proof root and sibling hashes, merkle tree node hashes) must decode to exactly 32 bytes,
+// a truncated or corrupted hash is rejected while deserializing.
+
+use plonky2_por::custom_serializer::format::{from_bytes, to_bytes};
+use plonky2_por::{InclusionProof, MerkleProof, Node, SerializationFormat, CURRENT_FORMAT_VERSION};
+use serde_json::{json, Value};
+
+fn inclusion_proof() -> InclusionProof {
+    InclusionProof {
+        format_version: CURRENT_FORMAT_VERSION,
+        user_balances: vec![1, 2],
+        user_hash: "0".repeat(63) + "1",
+        nonce: 7,
+        merkle_proof: MerkleProof {
+            left_hashes: vec![vec![1; 32]],
+            right_hashes: vec![vec![2; 32], vec![3; 32]],
+            parent_hashes: None,
+        },
+        root_hash: vec![4; 32],
+    }
+}
+
+fn deserialize(value: Value) -> String {
+    serde_json::from_value::<InclusionProof>(value).unwrap_err().to_string()
+}
+
+#[test]
+fn well_formed_hashes_are_accepted() {
+    let json = serde_json::to_value(inclusion_proof()).unwrap();
+    assert_eq!(serde_json::from_value::<InclusionProof>(json).unwrap().root_hash, vec![4; 32]);
+}
+
+#[test]
+fn mangled_base64_hashes_are_rejected() {
+    let json = serde_json::to_value(inclusion_proof()).unwrap();
+
+    // the last base64 group (2 bytes and the padding) is missing
+    let mut truncated = json.clone();
+    let root_hash = json["root_hash"].as_str().unwrap();
+    truncated["root_hash"] = json!(root_hash[..root_hash.len() - 4]);
+    let error = deserialize(truncated);
+    assert!(error.contains("invalid hash length: 30 bytes, expected 32"), "{error}");
+
+    let mut extended = json.clone();
+    extended["merkle_proof"]["right_hashes"][1] = json!("AAAA".repeat(11));
+    assert!(deserialize(extended).contains("invalid hash length: 33 bytes"));
+
+    let mut empty = json.clone();
+    empty["merkle_proof"]["left_hashes"][0] = json!("");
+    assert!(deserialize(empty).contains("invalid hash length: 0 bytes"));
+}
+
+#[test]
+fn wrong_sized_node_hash_is_rejected() {
+    let node = serde_json::to_value(Node::new(Some(vec![5; 32]))).unwrap();
+    assert!(serde_json::from_value::<Node>(node.clone()).is_ok());
+
+    let mut mangled = node;
+    mangled["hash"] = json!("AAAA");
+    assert!(serde_json::from_value::<Node>(mangled).is_err());
+}
+
+#[test]
+fn wrong_sized_hash_is_rejected_in_binary_formats() {
+    let mut proof = inclusion_proof();
+    proof.root_hash.pop();
+
+    for format in [SerializationFormat::Bincode, SerializationFormat::Msgpack] {
+        let bytes = to_bytes(&proof, format).unwrap();
+        assert!(from_bytes::<InclusionProof>(&bytes).is_err(), "{format:?}");
+    }
+}
@@ -0,0 +1,82 @@
+// The HTTP inclusion server (serve --http) answers GET /inclusion/<user hash> with the inclusion proof json, and the
+// malformed requests, unknown users and proving failures with their HTTP status. It uses the tiny circuits of
+// ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "http-server")]
+
+mod common;
+
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::{serve_http, verify_inclusion_against_root, InclusionProof, ProverConfig};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use common::{keyed_inputs, root_hash};
+
+// sends a request to the HTTP server, retried while it starts, and returns the raw response
+fn http_request(addr: &str, method: &str, path: &str) -> String {
+    let mut stream = (0..100)
+        .find_map(|_| {
+            TcpStream::connect(addr).ok().or_else(|| {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                None
+            })
+        })
+        .unwrap();
+    write!(stream, "{method} {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn serves_the_keyed_nonces() {
+    let (ledger, merkle_tree, user_nonces) = keyed_inputs();
+    let trusted_root = root_hash(&merkle_tree);
+    let user_hash = ledger.hashes[5].clone();
+
+    // a free port, released just before the server binds it
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let server_addr = addr.clone();
+    std::thread::spawn(move || serve_http(&server_addr, merkle_tree, user_nonces, ledger));
+
+    // the query string is not part of the route
+    for path in [format!("/inclusion/{user_hash}"), format!("/inclusion/{user_hash}?t=1")] {
+        let response = http_request(&addr, "GET", &path);
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        let body = response.split_once("\r\n\r\n").unwrap().1;
+        let inclusion_proof: InclusionProof = serde_json::from_str(body).unwrap();
+        assert_eq!(inclusion_proof.user_hash, user_hash);
+        verify_inclusion_against_root(&inclusion_proof, &trusted_root).unwrap();
+    }
+
+    // unknown users and paths are not found, malformed requests are client errors
+    for (method, path, status) in [
+        ("GET", format!("/inclusion/{}", "a".repeat(64)), "404"),
+        ("GET", "/proofs/1".to_string(), "404"),
+        ("POST", format!("/inclusion/{user_hash}"), "405"),
+        ("GET", "/inclusion/not-a-hash".to_string(), "400"),
+        ("GET", format!("/inclusion/{}", "A".repeat(64)), "400"),
+        ("GET", "/inclusion/".to_string(), "400"),
+        ("GET", format!("/inclusion/{}", "0".repeat(64)), "400"),
+    ] {
+        let response = http_request(&addr, method, &path);
+        assert!(response.starts_with(&format!("HTTP/1.1 {status}")), "{method} {path}: {response}");
+    }
+}
+
+#[test]
+fn reports_proving_failures_as_server_errors() {
+    // the merkle tree of another ledger: the user is in the ledger, but its leaf is not in the tree
+    let (ledger, _, user_nonces) = keyed_inputs();
+    let mut other_ledger = ledger.clone();
+    other_ledger.hashes.truncate(2);
+    other_ledger.account_balances.truncate(2);
+    let (_, other_tree, _) = prove_global_with_config(other_ledger, &ProverConfig::testing()).unwrap();
+    let user_hash = ledger.hashes[5].clone();
+
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let server_addr = addr.clone();
+    std::thread::spawn(move || serve_http(&server_addr, other_tree, user_nonces, ledger));
+
+    let response = http_request(&addr, "GET", &format!("/inclusion/{user_hash}"));
+    assert!(response.starts_with("HTTP/1.1 500"), "{response}");
+}
@@ -0,0 +1,201 @@
+// The inclusion provers: by index from the merkle tree (one nonce per user), compact from the ledger alone (one nonce
+// per leaf), the sample self-check of prove --verify, the sink of prove_inclusion_all_with and the verification of the
+// inclusion proof files. It uses the tiny circuits of ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2_por::core::prover::{committed_user_hashes, prove_global_with_config, rebuild_merkle_tree};
+use plonky2_por::core::verifier::{check_inclusion_dir, inclusion_summary, verify_inclusion_files, verify_user_inclusion};
+use plonky2_por::{
+    check_inclusion_sample, compute_leaf_hash, compute_merkle_root, out_of_range_balances, prove_inclusion_all_with,
+    prove_inclusion_by_index, prove_user_inclusion_compact, resolve_nonces, root_circuit_digest, verify_global_proof,
+    verify_inclusion_against_root, write_to_file, ProverConfig, SerializationFormat,
+};
+use common::ledger;
+
+#[test]
+fn inclusion_provers_take_exact_nonce_counts() {
+    let config = ProverConfig::testing();
+    let ledger = ledger(9);
+    let (final_proof, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &config).unwrap();
+
+    // the provers from the merkle tree take exactly one nonce per user, the leaf nonces are resolved first
+    let user_nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).unwrap();
+    assert_eq!(user_nonces.len(), ledger.hashes.len());
+    let error = committed_user_hashes(&merkle_tree, &ledger, &nonces).unwrap_err().to_string();
+    assert!(error.contains("Nonces length mismatch"), "{error}");
+    assert!(prove_inclusion_by_index(0, &merkle_tree, &nonces, &ledger).is_err());
+
+    // and the compact prover and the tree rebuild exactly one nonce per leaf
+    let root_hash = final_proof.root_hash();
+    let error = prove_user_inclusion_compact(ledger.hashes[0].clone(), ledger.clone(), &user_nonces, &config, &root_hash)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("Nonces length mismatch"), "{error}");
+    assert!(rebuild_merkle_tree(ledger, &user_nonces, &config).is_err());
+}
+
+#[test]
+fn committed_leafs_map_back_to_the_ledger_users() {
+    let ledger = ledger(9);
+    let (_, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &ProverConfig::testing()).unwrap();
+    let user_nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).unwrap();
+
+    // in ledger order, the padding accounts of the last batch are not real leafs
+    let users = committed_user_hashes(&merkle_tree, &ledger, &user_nonces).unwrap();
+    assert_eq!(users.len(), ledger.hashes.len());
+    assert_eq!(merkle_tree.real_leaf_count(), ledger.hashes.len());
+    for (i, (user_hash, leaf_hash)) in users.iter().enumerate() {
+        assert_eq!(user_hash, &ledger.hashes[i]);
+        assert_eq!(leaf_hash, &compute_leaf_hash(&ledger.account_balances[i], user_hash, user_nonces[i]).unwrap());
+    }
+
+    // a ledger that doesn't match the leafs (another user hash or balance at the same position) is not listed
+    let mut other_ledger = ledger.clone();
+    other_ledger.hashes.swap(1, 2);
+    let error = committed_user_hashes(&merkle_tree, &other_ledger, &user_nonces).unwrap_err().to_string();
+    assert!(error.contains("is not the leaf of user"), "{error}");
+    let mut other_ledger = ledger.clone();
+    other_ledger.account_balances[5][0] += 1;
+    assert!(committed_user_hashes(&merkle_tree, &other_ledger, &user_nonces).is_err());
+}
+
+#[test]
+fn compact_prover_rebuilds_the_path_of_the_merkle_tree() {
+    let config = ProverConfig::testing();
+    let ledger = ledger(9);
+    let (final_proof, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &config).unwrap();
+    let user_nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).unwrap();
+
+    // the root can be computed from the leaf hashes alone (users, then the padding accounts of the last batch)
+    let padding_hash = "0".repeat(64);
+    let leaf_hashes = (0..nonces.len())
+        .map(|i| match ledger.hashes.get(i) {
+            Some(user_hash) => compute_leaf_hash(&ledger.account_balances[i], user_hash, nonces[i]).unwrap(),
+            None => compute_leaf_hash(&[0, 0], &padding_hash, nonces[i]).unwrap(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(compute_merkle_root(&leaf_hashes, config.batch_size, config.recursive_size).unwrap(), final_proof.root_hash());
+
+    // the merkle tree can be rebuilt from the ledger and nonces
+    let rebuilt_tree = rebuild_merkle_tree(ledger.clone(), &nonces, &final_proof.prover_config()).unwrap();
+    assert_eq!(rebuilt_tree.root.hash(), merkle_tree.root.hash());
+
+    // a single inclusion path can be rebuilt without the merkle tree, checked against the published root
+    let user_hash = ledger.hashes[4].clone();
+    let compact_proof =
+        prove_user_inclusion_compact(user_hash.clone(), ledger.clone(), &nonces, &config, &final_proof.root_hash()).unwrap();
+    assert_eq!(
+        serde_json::to_string(&compact_proof).unwrap(),
+        serde_json::to_string(&prove_inclusion_by_index(4, &merkle_tree, &user_nonces, &ledger).unwrap()).unwrap()
+    );
+    let error = prove_user_inclusion_compact(user_hash, ledger, &nonces, &config, &[0; 32]).unwrap_err().to_string();
+    assert!(error.contains("does not match the published root hash"), "{error}");
+}
+
+#[test]
+fn inclusion_proofs_are_checked_against_the_proven_root() {
+    let ledger = ledger(9);
+    let (final_proof, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &ProverConfig::testing()).unwrap();
+    let user_nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).unwrap();
+    let trusted_root = verify_global_proof(&final_proof).unwrap();
+
+    // the prove --verify self-check: a sample larger than the ledger checks every user, another root is rejected
+    assert_eq!(check_inclusion_sample(&trusted_root, &merkle_tree, &user_nonces, &ledger, 3).unwrap(), 3);
+    assert_eq!(check_inclusion_sample(&trusted_root, &merkle_tree, &user_nonces, &ledger, 100).unwrap(), ledger.hashes.len());
+    let mut wrong_root = trusted_root.clone();
+    wrong_root[0] ^= 1;
+    assert!(check_inclusion_sample(&wrong_root, &merkle_tree, &user_nonces, &ledger, 1).is_err());
+
+    // every proof is handed to the sink instead of being written, the first sink error stops the proving
+    let sunk = std::sync::Mutex::new(Vec::new());
+    prove_inclusion_all_with(&ledger, &merkle_tree, &user_nonces, |inclusion_proof| {
+        verify_inclusion_against_root(&inclusion_proof, &trusted_root)?;
+        sunk.lock().unwrap().push(inclusion_proof.user_hash);
+        Ok(())
+    })
+    .unwrap();
+    let mut sunk = sunk.into_inner().unwrap();
+    sunk.sort();
+    assert_eq!(sunk, ledger.hashes);
+    let error = prove_inclusion_all_with(&ledger, &merkle_tree, &user_nonces, |_| Err(anyhow::anyhow!("queue is full")));
+    assert_eq!(error.unwrap_err().to_string(), "queue is full");
+
+    // a balance outside the range of the batch circuit is flagged for display, even with a matching leaf hash
+    let inclusion_proof = prove_inclusion_by_index(1, &merkle_tree, &user_nonces, &ledger).unwrap();
+    assert!(out_of_range_balances(&final_proof, &inclusion_proof).is_empty());
+    let mut absurd = inclusion_proof.clone();
+    absurd.user_balances[1] = i64::MAX;
+    assert_eq!(out_of_range_balances(&final_proof, &absurd), vec![("ETH".to_string(), i64::MAX)]);
+    let summary = inclusion_summary(&final_proof, &absurd, None);
+    assert!(summary.contains("WARNING: the ETH balance is outside the supported range"));
+
+    // a short balances list is a NO (not a panic), and the YES says which circuit it was checked with
+    let mut short = inclusion_proof.clone();
+    short.user_balances.pop();
+    assert!(inclusion_summary(&final_proof, &short, None).contains("NO - the inclusion proof could not be verified"));
+    let summary = inclusion_summary(&final_proof, &inclusion_proof, None);
+    assert!(summary.contains("YES") && summary.contains("--expected-digest"), "{summary}");
+    let digest = root_circuit_digest(&final_proof).unwrap();
+    let summary = inclusion_summary(&final_proof, &inclusion_proof, Some(&digest));
+    assert!(summary.contains("YES") && !summary.contains("--expected-digest"), "{summary}");
+    let summary = inclusion_summary(&final_proof, &inclusion_proof, Some(&[0; 32]));
+    assert!(summary.contains("NO") && summary.contains("does not match the expected digest"), "{summary}");
+}
+
+#[test]
+fn tampered_inclusion_proof_files_are_reported() {
+    let ledger = ledger(9);
+    let (final_proof, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &ProverConfig::testing()).unwrap();
+    let user_nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).unwrap();
+    let trusted_root = verify_global_proof(&final_proof).unwrap();
+
+    // the second file has a tampered balance
+    let mut files = Vec::new();
+    for index in [0, 4, ledger.hashes.len() - 1] {
+        let mut inclusion_proof = prove_inclusion_by_index(index, &merkle_tree, &user_nonces, &ledger).unwrap();
+        if index == 4 {
+            inclusion_proof.user_balances[0] += 1;
+        }
+
+        let file = std::env::temp_dir().join(format!("por_inclusion_{}_{index}.json", std::process::id()));
+        let file = file.to_string_lossy().into_owned();
+        write_to_file(&file, &inclusion_proof, SerializationFormat::Json).unwrap();
+        files.push(file);
+    }
+
+    // the inclusion proof files are verified in parallel
+    let report = verify_inclusion_files(&final_proof, &files).unwrap();
+    assert_eq!((report.valid, report.invalid), (2, 1));
+    assert_eq!(report.first_failure.unwrap().0, files[1]);
+
+    // the directory sweep only recomputes the root of the inclusion_proof_* files
+    let dir = std::env::temp_dir().join(format!("por_inclusion_dir_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (i, file) in files.iter().enumerate() {
+        std::fs::copy(file, dir.join(format!("inclusion_proof_{i}.json"))).unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), "not an inclusion proof").unwrap();
+    let report = check_inclusion_dir(dir.to_str().unwrap(), &trusted_root).unwrap();
+    assert_eq!((report.valid, report.invalid), (2, 1));
+    assert!(report.first_failure.unwrap().0.ends_with("inclusion_proof_1.json"));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    for file in files {
+        let _ = std::fs::remove_file(file);
+    }
+}
+
+// the malformed file is reported before the balances are printed, not by an out of bounds index
+#[test]
+#[should_panic(expected = "The inclusion proof has 1 balances but the proof has 2 assets")]
+fn short_balances_list_is_reported_by_verify_user_inclusion() {
+    let ledger = ledger(6);
+    let (final_proof, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &ProverConfig::testing()).unwrap();
+    let user_nonces = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).unwrap();
+
+    let mut inclusion_proof = prove_inclusion_by_index(0, &merkle_tree, &user_nonces, &ledger).unwrap();
+    inclusion_proof.user_balances.pop();
+    verify_user_inclusion(final_proof, inclusion_proof);
+}
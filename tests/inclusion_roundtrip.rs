@@ -0,0 +1,161 @@
+// Property-based roundtrip check for inclusion proofs: for any tree shape, the path of a leaf
+// (get_nth_leaf_path) + the inclusion proof (prove_inclusion) must recompute the tree root (calculate_merkle_root_hash).
+//
+// Proving global is too slow for property testing, so the tree is built directly from random leaves
+// with the same structure prove_global creates (new_from_leafs), and the parent hashes are filled with
+// the same hash used by the batch/recursive circuits (hash of the concatenated children hashes).
+// Padding nodes (no children) get an arbitrary hash, like the empty proofs in prove_global.
+
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::HashOut;
+use plonky2::plonk::config::GenericHashOut;
+use plonky2_por::utils::util::hash_n_subhashes;
+use plonky2_por::{InclusionProof, MerkleTree, Node, BATCH_SIZE, CURRENT_FORMAT_VERSION, D, F, RECURSIVE_SIZE};
+use proptest::prelude::*;
+
+fn leaf_hash(seed: u64) -> Vec<u8> {
+    HashOut::<F>::from_partial(&[F::from_canonical_u64(seed)]).to_bytes()
+}
+
+// build a populated merkle tree with account_count leaves (padded to a multiple of BATCH_SIZE)
+fn build_tree(account_count: usize, seed: u64) -> (MerkleTree, Vec<Vec<u8>>) {
+    let padded_count = account_count.div_ceil(BATCH_SIZE) * BATCH_SIZE;
+    let leaves = (0..padded_count as u64)
+        .map(|i| leaf_hash(seed.wrapping_add(i)))
+        .collect::<Vec<_>>();
+
+    let leaf_nodes = leaves.iter().map(|hash| Node::new(Some(hash.clone()))).collect();
+    let mut merkle_tree = MerkleTree::new_from_leafs(leaf_nodes, 1, true);
+
+    // populate the parent hashes bottom-up
+    for depth in (1..merkle_tree.depth).rev() {
+        for (i, node) in merkle_tree.get_nodes_from_depth(depth).into_iter().enumerate() {
+            let hash = match node.children() {
+                Some(children) => {
+                    let children_hashes = children
+                        .iter()
+                        .map(|child| child.hash().clone().unwrap())
+                        .collect::<Vec<_>>();
+                    hash_n_subhashes::<F, D>(&children_hashes).to_bytes()
+                }
+                // padding node
+                None => leaf_hash(u64::MAX - depth as u64 - i as u64),
+            };
+            node.set_hash(hash);
+        }
+    }
+
+    (merkle_tree, leaves)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn inclusion_proof_recomputes_root(
+        // spans trees with 1 batch up to more than RECURSIVE_SIZE^2 batches (several recursive levels)
+        account_count in 1..=(BATCH_SIZE * RECURSIVE_SIZE * RECURSIVE_SIZE + BATCH_SIZE),
+        seed in any::<u64>(),
+        samples in prop::collection::vec(any::<prop::sample::Index>(), 1..16),
+    ) {
+        let (merkle_tree, leaves) = build_tree(account_count, seed);
+        prop_assert!(merkle_tree.verify());
+
+        let root_hash = merkle_tree.root.hash().clone().unwrap();
+
+        // always check the first and last accounts, plus a random sample
+        let mut users = vec![0, account_count - 1];
+        users.extend(samples.iter().map(|index| index.index(account_count)));
+
+        for user_index in users {
+            let path = merkle_tree.get_nth_leaf_path(user_index);
+            prop_assert!(path.is_some(), "no path for user {}", user_index);
+
+            let inclusion_proof = InclusionProof {
+                format_version: CURRENT_FORMAT_VERSION,
+                user_balances: vec![],
+                user_hash: String::new(),
+                nonce: 0,
+                merkle_proof: merkle_tree.prove_inclusion(path.unwrap()),
+                root_hash: root_hash.clone(),
+            };
+
+            let calculated_root = inclusion_proof.calculate_merkle_root_hash(leaves[user_index].clone());
+            prop_assert_eq!(&calculated_root, &root_hash, "wrong root for user {} of {}", user_index, account_count);
+        }
+    }
+}
+
+// account counts around the batch and recursive boundaries, where the batch level (BATCH_SIZE children)
+// and the recursive levels (RECURSIVE_SIZE children) interact
+#[test]
+fn inclusion_proof_recomputes_root_at_level_boundaries() {
+    let account_counts = [
+        1,
+        BATCH_SIZE - 1,
+        BATCH_SIZE,
+        BATCH_SIZE + 1,
+        BATCH_SIZE * RECURSIVE_SIZE,
+        BATCH_SIZE * RECURSIVE_SIZE + 1,
+        BATCH_SIZE * RECURSIVE_SIZE * RECURSIVE_SIZE,
+        BATCH_SIZE * RECURSIVE_SIZE * RECURSIVE_SIZE + 1,
+    ];
+
+    for account_count in account_counts {
+        let (merkle_tree, leaves) = build_tree(account_count, account_count as u64);
+        let root_hash = merkle_tree.root.hash().clone().unwrap();
+
+        for user_index in [0, account_count / 2, account_count - 1] {
+            let path = merkle_tree
+                .get_nth_leaf_path(user_index)
+                .unwrap_or_else(|| panic!("no path for user {user_index} of {account_count}"));
+
+            let inclusion_proof = InclusionProof {
+                format_version: CURRENT_FORMAT_VERSION,
+                user_balances: vec![],
+                user_hash: String::new(),
+                nonce: 0,
+                merkle_proof: merkle_tree.prove_inclusion(path),
+                root_hash: root_hash.clone(),
+            };
+
+            let calculated_root = inclusion_proof.calculate_merkle_root_hash(leaves[user_index].clone());
+            assert_eq!(calculated_root, root_hash, "wrong root for user {user_index} of {account_count}");
+        }
+
+        // a position past the last leaf has no path
+        assert!(merkle_tree.get_nth_leaf_path(usize::MAX).is_none());
+    }
+}
+
+#[test]
+fn inclusion_proof_structure_is_validated() {
+    let account_count = BATCH_SIZE * RECURSIVE_SIZE + 1;
+    let (merkle_tree, _) = build_tree(account_count, 7);
+
+    let inclusion_proof = InclusionProof {
+        format_version: CURRENT_FORMAT_VERSION,
+        user_balances: vec![1, 2],
+        user_hash: format!("{:064x}", 1),
+        nonce: 0,
+        merkle_proof: merkle_tree.prove_inclusion(merkle_tree.get_nth_leaf_path(1).unwrap()),
+        root_hash: merkle_tree.root.hash().clone().unwrap(),
+    };
+    assert_eq!(inclusion_proof.level_widths(), vec![BATCH_SIZE, RECURSIVE_SIZE, RECURSIVE_SIZE]);
+    assert!(inclusion_proof.validate_structure().is_ok());
+
+    // truncated sibling hash
+    let mut truncated = inclusion_proof.clone();
+    truncated.merkle_proof.left_hashes[0].pop();
+    assert!(truncated.validate_structure().is_err());
+
+    // recursive levels of different widths
+    let mut uneven = inclusion_proof.clone();
+    uneven.merkle_proof.parent_hashes.as_mut().unwrap().right_hashes.pop();
+    assert!(uneven.validate_structure().is_err());
+
+    // the leaf can't be hashed
+    let mut invalid_user_hash = inclusion_proof.clone();
+    invalid_user_hash.user_hash = "not hex".to_string();
+    assert!(invalid_user_hash.validate_structure().is_err());
+}
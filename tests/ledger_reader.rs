@@ -0,0 +1,66 @@
+// The ledger json is read without a serde_json::Value of the whole document (read_ledger). The assets and accounts
+// come out sorted by name and hash whatever the order of the keys, which keeps the leaf order of the proofs.
+
+use plonky2_por::read_ledger;
+
+const LEDGER: &str = r#"{
+    "accounts": {
+        "0000000000000000000000000000000000000000000000000000000000000002": {"ETH": "20000", "BTC": 2000, "DOGE": "x"},
+        "0000000000000000000000000000000000000000000000000000000000000001": {"BTC": 1000, "ETH": 10000}
+    },
+    "exchange": "ignored",
+    "reserve_buffer": {"ETH": 5},
+    "assets": {
+        "ETH": {"usdt_decimals": 2, "balance_decimals": 4, "price": "2000.40"},
+        "BTC": {"usdt_decimals": 2, "balance_decimals": 4, "price": 6000000}
+    },
+    "timestamp": 1746488437000
+}"#;
+
+fn hash(i: u64) -> String {
+    format!("{i:064x}")
+}
+
+#[test]
+fn ledger_is_read_in_sorted_order() {
+    let ledger = read_ledger(LEDGER.as_bytes()).unwrap();
+
+    assert_eq!(ledger.asset_names, ["BTC", "ETH"]);
+    assert_eq!(ledger.asset_prices, [6000000, 200040]);
+    assert_eq!(ledger.hashes, [hash(1), hash(2)]);
+    // the balances follow the asset order, a balance of an asset outside the assets field (DOGE) is ignored
+    assert_eq!(ledger.account_balances, [vec![1000, 10000], vec![2000, 20000]]);
+    assert_eq!(ledger.reserve_buffer, [0, 5]);
+    assert_eq!(ledger.timestamp, 1746488437000);
+}
+
+#[test]
+fn missing_balance_is_reported_with_its_account() {
+    let json = LEDGER.replace(r#""BTC": 1000, "#, "");
+    let error = read_ledger(json.as_bytes()).unwrap_err().to_string();
+
+    assert!(error.contains(&format!("Account {}, asset BTC", hash(1))), "{error}");
+    assert!(error.contains("The balance is missing"), "{error}");
+}
+
+#[test]
+fn duplicate_account_is_rejected() {
+    let json = LEDGER.replace(&hash(2), &hash(1));
+    let error = read_ledger(json.as_bytes()).unwrap_err().to_string();
+
+    assert!(error.contains("Duplicate account"), "{error}");
+}
+
+#[test]
+fn malformed_ledger_is_rejected() {
+    for json in [
+        LEDGER.replace(r#""timestamp": 1746488437000"#, r#""timestamp": null"#),
+        LEDGER.replace(r#""reserve_buffer": {"ETH": 5}"#, r#""reserve_buffer": {"ETH": 0.5}"#),
+        LEDGER.replace(r#""reserve_buffer": {"ETH": 5}"#, r#""reserve_buffer": {"ETH": "five"}"#),
+        LEDGER.replace(r#""reserve_buffer": {"ETH": 5}"#, r#""reserve_buffer": 5"#),
+        LEDGER.replace("\"assets\"", "\"asset_list\""),
+        LEDGER.replace("1746488437000\n}", "1746488437000\n} {}"),
+    ] {
+        assert!(read_ledger(json.as_bytes()).is_err(), "{json}");
+    }
+}
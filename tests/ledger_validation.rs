@@ -0,0 +1,132 @@
+// Every account must have exactly one balance per asset before proving (Ledger::validate_accounts).
+
+mod common;
+
+use common::ledger_with_balances;
+
+#[test]
+fn consistent_accounts_are_accepted() {
+    assert!(ledger_with_balances(vec![vec![1, 2], vec![3, 4]]).validate_accounts().is_ok());
+}
+
+#[test]
+fn account_with_wrong_balance_count_is_reported() {
+    for balances in [vec![1], vec![1, 2, 3]] {
+        let ledger = ledger_with_balances(vec![vec![1, 2], balances]);
+        let error = ledger.validate_accounts().unwrap_err().to_string();
+
+        // the offending user hash is in the error
+        assert!(error.contains(&ledger.hashes[1]), "{error}");
+    }
+}
+
+#[test]
+fn non_hex_user_hash_is_rejected_before_hashing() {
+    let mut ledger = ledger_with_balances(vec![vec![1, 2], vec![3, 4]]);
+    ledger.hashes[1] = "user-1".to_string();
+
+    let error = ledger.validate_accounts().unwrap_err().to_string();
+    assert!(error.contains("user-1"), "{error}");
+}
+
+#[test]
+fn reserved_leaf_hash_is_rejected_as_user_hash() {
+    // the reserve buffer (f...) and dust aggregate (e...) hashes, of any length
+    for reserved in ["f".repeat(64), "e".repeat(64), "ff".to_string()] {
+        let mut ledger = ledger_with_balances(vec![vec![1, 2], vec![3, 4]]);
+        ledger.hashes[1] = reserved.clone();
+
+        let error = ledger.validate_accounts().unwrap_err().to_string();
+        assert!(error.contains("reserved"), "{error}");
+    }
+}
+
+#[test]
+fn uppercase_user_hash_is_rejected() {
+    // "AB.." and "ab.." would be two users with the same leaf encoding
+    let mut ledger = ledger_with_balances(vec![vec![1, 2], vec![3, 4]]);
+    ledger.hashes[1] = ledger.hashes[1].replace('0', "A");
+
+    let error = ledger.validate_accounts().unwrap_err().to_string();
+    assert!(error.contains("lowercase"), "{error}");
+}
+
+#[test]
+#[cfg(feature = "prover")]
+fn empty_ledger_is_rejected_before_proving() {
+    use plonky2_por::core::prover::prove_global_with_config;
+    use plonky2_por::ProverConfig;
+
+    let mut ledger = ledger_with_balances(Vec::new());
+    ledger.reserve_buffer = vec![1, 2];
+
+    let error = prove_global_with_config(ledger, &ProverConfig::testing()).unwrap_err().to_string();
+    assert!(error.contains("no accounts"), "{error}");
+}
+
+#[test]
+fn reserve_buffer_with_wrong_balance_count_is_rejected() {
+    let mut ledger = ledger_with_balances(vec![vec![1, 2]]);
+    ledger.reserve_buffer = vec![1];
+    assert!(ledger.validate_accounts().is_err());
+}
+
+#[test]
+fn ledger_timestamp_must_be_present_and_plausible() {
+    use plonky2_por::utils::util::parse_ledger_timestamp;
+    use serde_json::json;
+
+    assert_eq!(parse_ledger_timestamp(&json!(1746488437000u64)).unwrap(), 1746488437000);
+
+    // missing, not an integer, zero, in seconds and far in the future
+    for timestamp in [json!(null), json!("1746488437000"), json!(0), json!(1746488437), json!(u64::MAX)] {
+        assert!(parse_ledger_timestamp(&timestamp).is_err(), "{timestamp}");
+    }
+}
+
+#[test]
+#[cfg(feature = "prover")]
+fn mismatched_hashes_and_balances_are_rejected_before_proving() {
+    use plonky2_por::core::prover::prove_global_with_config;
+    use plonky2_por::utils::util::pad_accounts;
+    use plonky2_por::ProverConfig;
+
+    // a user hash without balances (e.g. a partially malformed ledger file)
+    let mut ledger = ledger_with_balances(vec![vec![1, 2], vec![3, 4]]);
+    ledger.hashes.push(format!("{:064x}", 2));
+
+    let error = prove_global_with_config(ledger.clone(), &ProverConfig::testing()).unwrap_err().to_string();
+    assert!(error.contains("3 user hashes but 2 accounts"), "{error}");
+
+    let error = pad_accounts(&mut ledger.account_balances, &mut ledger.hashes, 2, 4).unwrap_err().to_string();
+    assert!(error.contains("3 user hashes but 2 accounts"), "{error}");
+    assert!(pad_accounts(&mut Vec::new(), &mut Vec::new(), 2, 4).is_err());
+}
+
+#[test]
+fn ledger_balances_accept_integer_strings_in_the_supported_range() {
+    use plonky2_por::utils::util::parse_ledger_balance;
+    use plonky2_por::{MAX_ACCOUNT_BALANCE_BITS, MAX_RANGE_CHECKED_BALANCE};
+    use serde_json::json;
+
+    assert_eq!(parse_ledger_balance(&json!(-1500)).unwrap(), -1500);
+    assert_eq!(parse_ledger_balance(&json!("123456789")).unwrap(), 123456789);
+    assert_eq!(parse_ledger_balance(&json!(" -42 ")).unwrap(), -42);
+    assert_eq!(parse_ledger_balance(&json!(MAX_RANGE_CHECKED_BALANCE)).unwrap(), MAX_RANGE_CHECKED_BALANCE as i64);
+
+    // missing, decimals, floats, above i64 and outside the range check of the batch circuit
+    let above_range = (MAX_RANGE_CHECKED_BALANCE + 1).to_string();
+    let below_range = (-(1i64 << MAX_ACCOUNT_BALANCE_BITS) - 1).to_string();
+    for balance in [
+        json!(null),
+        json!("1.5"),
+        json!(1.5),
+        json!("abc"),
+        json!(u64::MAX),
+        json!("99999999999999999999"),
+        json!(above_range),
+        json!(below_range),
+    ] {
+        assert!(parse_ledger_balance(&balance).is_err(), "{balance}");
+    }
+}
@@ -0,0 +1,172 @@
+// compute_merkle_root and CompactMerkleTree must give the root (and the inclusion paths) of the tree built by
+// new_from_leafs_with_config, with the nodes hashed like the circuits (hash of the concatenated children hashes,
+// padding nodes are the root hashes of the empty proofs).
+
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::HashOut;
+use plonky2::plonk::config::GenericHashOut;
+use plonky2_por::utils::util::hash_n_subhashes;
+use plonky2_por::{compute_merkle_root, verify_tree_against_root, CompactMerkleTree, MerkleTree, Node, ProverConfig, D, F};
+use proptest::prelude::*;
+
+fn leaf_hash(seed: u64) -> Vec<u8> {
+    HashOut::<F>::from_partial(&[F::from_canonical_u64(seed)]).to_bytes()
+}
+
+// full merkle tree, populated bottom-up like rebuild_merkle_tree
+fn build_tree(leaves: &[Vec<u8>], config: &ProverConfig) -> MerkleTree {
+    let leaf_nodes = leaves.iter().map(|hash| Node::new(Some(hash.clone()))).collect();
+    let mut merkle_tree = MerkleTree::new_from_leafs_with_config(leaf_nodes, 1, true, config);
+
+    let mut padding_hash = HashOut::<F>::default().to_bytes();
+    let mut padding_arity = config.batch_size;
+    for depth in (1..merkle_tree.depth).rev() {
+        padding_hash = hash_n_subhashes::<F, D>(&vec![padding_hash; padding_arity]).to_bytes();
+        padding_arity = config.recursive_size;
+
+        for node in merkle_tree.get_nodes_from_depth(depth) {
+            let hash = match node.children() {
+                Some(children) => {
+                    let children_hashes = children
+                        .iter()
+                        .map(|child| child.hash().clone().unwrap())
+                        .collect::<Vec<_>>();
+                    hash_n_subhashes::<F, D>(&children_hashes).to_bytes()
+                }
+                None => padding_hash.clone(),
+            };
+            node.set_hash(hash);
+        }
+    }
+
+    assert!(merkle_tree.verify());
+    merkle_tree
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn streamed_root_matches_the_tree_root(
+        // 1 batch up to several recursive levels of the testing sizes (4 leafs per batch, 2 children per recursive node)
+        batch_count in 1usize..=40,
+        seed in any::<u64>(),
+    ) {
+        let config = ProverConfig::testing();
+        let leaves = (0..(batch_count * config.batch_size) as u64)
+            .map(|i| leaf_hash(seed.wrapping_add(i)))
+            .collect::<Vec<_>>();
+
+        let merkle_tree = build_tree(&leaves, &config);
+        prop_assert_eq!(
+            compute_merkle_root(&leaves, config.batch_size, config.recursive_size).unwrap(),
+            merkle_tree.root.hash().clone().unwrap()
+        );
+    }
+
+    #[test]
+    fn compact_tree_matches_the_tree(
+        batch_count in 1usize..=40,
+        seed in any::<u64>(),
+        samples in prop::collection::vec(any::<prop::sample::Index>(), 1..8),
+    ) {
+        let config = ProverConfig::testing();
+        let leaves = (0..(batch_count * config.batch_size) as u64)
+            .map(|i| leaf_hash(seed.wrapping_add(i)))
+            .collect::<Vec<_>>();
+
+        let merkle_tree = build_tree(&leaves, &config);
+        let compact_tree = CompactMerkleTree::new(&leaves, &config).unwrap();
+        prop_assert_eq!(compact_tree.root_hash(), merkle_tree.root.hash().clone().unwrap());
+        prop_assert_eq!(compact_tree.depth(), merkle_tree.depth);
+
+        // the inclusion paths are the same (MerkleProof has no PartialEq, compare the serialized proofs)
+        let mut leaf_indices = vec![0, leaves.len() - 1];
+        leaf_indices.extend(samples.iter().map(|index| index.index(leaves.len())));
+        for leaf_index in leaf_indices {
+            let expected = merkle_tree.prove_inclusion(merkle_tree.get_nth_leaf_path(leaf_index).unwrap());
+            let merkle_proof = compact_tree.merkle_proof(leaf_index).unwrap();
+            prop_assert_eq!(serde_json::to_string(&merkle_proof).unwrap(), serde_json::to_string(&expected).unwrap());
+        }
+
+        prop_assert!(compact_tree.merkle_proof(leaves.len()).is_err());
+    }
+}
+
+#[test]
+fn malformed_leafs_are_rejected() {
+    let config = ProverConfig::testing();
+    let leaves = (0..(2 * config.batch_size) as u64).map(leaf_hash).collect::<Vec<_>>();
+    let mut short_leaf = leaves.clone();
+    short_leaf[5].pop();
+
+    for (leaves, expected) in [
+        (&[][..], "without leafs"),
+        (&leaves[..leaves.len() - 1], "not whole batches"),
+        (&short_leaf[..], "Leaf 5 is not a 32 bytes hash"),
+    ] {
+        let error = compute_merkle_root(leaves, config.batch_size, config.recursive_size).unwrap_err().to_string();
+        assert!(error.contains(expected), "{error}");
+        let error = CompactMerkleTree::new(leaves, &config).err().unwrap().to_string();
+        assert!(error.contains(expected), "{error}");
+    }
+
+    // a recursive size of 1 would never reach the root
+    assert!(compute_merkle_root(&leaves, config.batch_size, 1).is_err());
+}
+
+#[test]
+fn tampered_subtree_fails_verification() {
+    let config = ProverConfig::testing();
+    let leaves = (0..(5 * config.batch_size) as u64).map(leaf_hash).collect::<Vec<_>>();
+    let mut merkle_tree = build_tree(&leaves, &config);
+    let root_hash = merkle_tree.root.hash().clone().unwrap();
+    verify_tree_against_root(&merkle_tree, &root_hash).unwrap();
+
+    // the progress callback runs once per batch (the padding batches have no leafs to verify)
+    let mut verified_batches = 0;
+    assert!(merkle_tree.verify_with_progress(|| verified_batches += 1));
+    assert_eq!((verified_batches, merkle_tree.batch_count()), (5, 5));
+
+    // another published root is rejected
+    assert!(verify_tree_against_root(&merkle_tree, &leaf_hash(u64::MAX)).is_err());
+
+    // a leaf deep in the tree no longer hashes to its batch node, even though the root still matches its children
+    let depth = merkle_tree.depth;
+    merkle_tree.get_nodes_from_depth(depth)[1].set_hash(leaf_hash(u64::MAX));
+    assert!(!merkle_tree.verify());
+    assert!(verify_tree_against_root(&merkle_tree, &root_hash).is_err());
+}
+
+#[test]
+fn streamed_verification_matches_the_tree() {
+    use plonky2_por::custom_serializer::format::to_bytes;
+    use plonky2_por::{from_reader_seed, SerializationFormat, VerifyingTreeSeed};
+
+    let config = ProverConfig::testing();
+    let leaves = (0..(5 * config.batch_size) as u64).map(leaf_hash).collect::<Vec<_>>();
+    let mut merkle_tree = build_tree(&leaves, &config);
+    let max_depth = merkle_tree.depth;
+    let seed = move || VerifyingTreeSeed { max_depth };
+
+    for format in [SerializationFormat::Json, SerializationFormat::Bincode, SerializationFormat::Msgpack] {
+        let bytes = to_bytes(&merkle_tree, format).unwrap();
+        let streamed = from_reader_seed(&bytes[..], seed()).unwrap();
+        assert_eq!(&streamed.root_hash, merkle_tree.root.hash(), "{format:?}");
+        assert_eq!(streamed.batch_count(), merkle_tree.batch_count());
+        streamed.check_depth(merkle_tree.depth).unwrap();
+        assert!(streamed.check_depth(merkle_tree.depth + 1).is_err());
+
+        // a shallower proof rejects the nodes below its depth while they are read
+        let error = from_reader_seed(&bytes[..], VerifyingTreeSeed { max_depth: 2 }).unwrap_err();
+        assert!(format!("{error:#}").contains("deeper than the maximum depth"), "{error:#}");
+    }
+
+    let depth = merkle_tree.depth;
+    merkle_tree.get_nodes_from_depth(depth)[1].set_hash(leaf_hash(u64::MAX));
+    for format in [SerializationFormat::Json, SerializationFormat::Bincode, SerializationFormat::Msgpack] {
+        let bytes = to_bytes(&merkle_tree, format).unwrap();
+        let error = from_reader_seed(&bytes[..], seed()).unwrap_err();
+        assert!(format!("{error:#}").contains("is not the hash of its children"), "{error:#}");
+    }
+}
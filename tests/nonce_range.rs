@@ -0,0 +1,26 @@
+// Leaf nonces must be canonical field elements (in [0, F::ORDER)), otherwise they would be reduced when hashed.
+
+use plonky2::field::types::Field64;
+use plonky2_por::utils::util::{check_nonce, random_nonce};
+use plonky2_por::F;
+
+#[test]
+fn canonical_nonces_are_accepted() {
+    for nonce in [0, 1, F::ORDER - 1] {
+        assert!(check_nonce(nonce).is_ok());
+    }
+}
+
+#[test]
+fn out_of_range_nonces_are_rejected() {
+    for nonce in [F::ORDER, F::ORDER + 1, u64::MAX] {
+        assert!(check_nonce(nonce).is_err());
+    }
+}
+
+#[test]
+fn random_nonces_are_canonical() {
+    for _ in 0..10_000 {
+        assert!(check_nonce(random_nonce()).is_ok());
+    }
+}
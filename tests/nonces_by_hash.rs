@@ -0,0 +1,72 @@
+// The nonces can be keyed by user hash (prove --nonces-by-hash) instead of listed by leaf position. Both give the same
+// inclusion proofs, and read_nonces tells them apart in every serialization format. It uses the tiny circuits of
+// ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::{
+    check_unique_user_hashes, nonces_by_hash, prove_inclusion_from_data, read_nonces, resolve_nonces, resolve_user_nonce, write_to_file, AccountNonces, ProverConfig,
+    SerializationFormat,
+};
+use common::ledger;
+
+#[test]
+fn keyed_and_positional_nonces_give_the_same_inclusion_proofs() {
+    let ledger = ledger(9);
+    let (final_proof, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &ProverConfig::testing()).unwrap();
+
+    let keyed_nonces = nonces_by_hash(&ledger.hashes, &nonces).unwrap();
+    assert_eq!(keyed_nonces.len(), ledger.hashes.len());
+    let user_hash = &ledger.hashes[3];
+    let keyed_proof = prove_inclusion_from_data(user_hash, &merkle_tree, &final_proof, &keyed_nonces, &ledger, None);
+    let positional_proof = prove_inclusion_from_data(user_hash, &merkle_tree, &final_proof, &nonces, &ledger, None);
+    assert_eq!(
+        serde_json::to_string(&keyed_proof.unwrap()).unwrap(),
+        serde_json::to_string(&positional_proof.unwrap()).unwrap()
+    );
+
+    // a single user nonce is read from the borrowed nonces, with the same checks as the full resolution
+    assert_eq!(resolve_user_nonce((&keyed_nonces).into(), 3, &ledger, &merkle_tree).unwrap(), nonces[3]);
+    assert_eq!(resolve_user_nonce((&nonces).into(), 3, &ledger, &merkle_tree).unwrap(), nonces[3]);
+    assert!(resolve_user_nonce((&nonces[1..]).into(), 3, &ledger, &merkle_tree).is_err());
+
+    // a user without a nonce is reported, whatever other hashes are keyed
+    assert_eq!(resolve_nonces((&keyed_nonces).into(), &ledger, &merkle_tree).unwrap(), nonces[..ledger.hashes.len()]);
+    let mut missing_nonce = keyed_nonces;
+    missing_nonce.remove(user_hash);
+    missing_nonce.insert("f".repeat(64), 1);
+    let error = resolve_nonces((&missing_nonce).into(), &ledger, &merkle_tree).unwrap_err().to_string();
+    assert!(error.contains(user_hash.as_str()), "{error}");
+    assert!(resolve_user_nonce((&missing_nonce).into(), 3, &ledger, &merkle_tree).is_err());
+}
+
+#[test]
+fn nonces_files_are_read_in_every_format() {
+    let ledger = ledger(9);
+    let nonces = (1..=12).collect::<Vec<u64>>();
+    let keyed_nonces = nonces_by_hash(&ledger.hashes, &nonces).unwrap();
+
+    for format in [SerializationFormat::Json, SerializationFormat::Bincode, SerializationFormat::Msgpack] {
+        let file = std::env::temp_dir().join(format!("por_nonces_{}.{}", std::process::id(), format.extension()));
+        let file = file.to_string_lossy().into_owned();
+
+        write_to_file(&file, &keyed_nonces, format).unwrap();
+        assert_eq!(read_nonces(&file).unwrap(), AccountNonces::ByHash(keyed_nonces.clone()));
+        write_to_file(&file, &nonces, format).unwrap();
+        assert_eq!(read_nonces(&file).unwrap(), AccountNonces::Positional(nonces.clone()));
+        let _ = std::fs::remove_file(file);
+    }
+}
+
+#[test]
+fn duplicate_user_hashes_are_reported_before_proving() {
+    let mut ledger = ledger(9);
+    assert!(check_unique_user_hashes(&ledger.hashes).is_ok());
+
+    ledger.hashes[5] = ledger.hashes[2].clone();
+    let error = check_unique_user_hashes(&ledger.hashes).unwrap_err().to_string();
+    assert!(error.contains(&ledger.hashes[2]), "{error}");
+    assert!(nonces_by_hash(&ledger.hashes, &(1..=12).collect::<Vec<u64>>()).is_err());
+}
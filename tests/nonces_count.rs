@@ -0,0 +1,49 @@
+// The nonces file has one nonce per merkle tree leaf (accounts, reserve buffer and padding), so a nonces file
+// of another ledger must be rejected when loading the inclusion inputs (check_nonces_count).
+#![cfg(feature = "prover")]
+
+use plonky2_por::core::prover::check_nonces_count;
+use plonky2_por::{Ledger, LedgerDecimals, MerkleTree, Node, ProverConfig};
+
+fn ledger(account_count: usize) -> Ledger {
+    Ledger {
+        asset_names: vec!["BTC".to_string()],
+        hashes: (1..=account_count).map(|i| format!("{i:064x}")).collect(),
+        account_balances: vec![vec![1]; account_count],
+        asset_prices: vec![6000000],
+        asset_decimals: vec![LedgerDecimals { usdt_decimals: 2, balance_decimals: 4 }],
+        reserve_buffer: Vec::new(),
+        dust: None,
+        timestamp: 1746488437000,
+    }
+}
+
+// a tree with the testing circuit sizes (4 leafs per batch)
+fn tree(leaf_count: usize) -> MerkleTree {
+    let leaf_nodes = (0..leaf_count).map(|_| Node::new(None)).collect();
+    MerkleTree::new_from_leafs_with_config(leaf_nodes, 1, true, &ProverConfig::testing())
+}
+
+#[test]
+fn one_nonce_per_leaf_is_accepted() {
+    // 5 accounts --> padded to 2 batches of 4
+    assert!(check_nonces_count(&[0; 8], &ledger(5), &tree(8)).is_ok());
+}
+
+#[test]
+fn nonces_of_another_ledger_are_rejected() {
+    for nonces_len in [5, 4, 12] {
+        let error = check_nonces_count(&vec![0; nonces_len], &ledger(5), &tree(8)).unwrap_err().to_string();
+        assert!(error.contains("Nonces length mismatch"), "{error}");
+    }
+}
+
+#[test]
+fn reserve_buffer_leaf_has_a_nonce() {
+    // 8 accounts + the reserve buffer --> 3 batches of 4
+    let mut ledger = ledger(8);
+    ledger.reserve_buffer = vec![100];
+
+    assert!(check_nonces_count(&[0; 8], &ledger, &tree(12)).is_err());
+    assert!(check_nonces_count(&[0; 12], &ledger, &tree(12)).is_ok());
+}
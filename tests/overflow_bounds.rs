@@ -0,0 +1,118 @@
+// The batch circuit range checks every balance with MAX_ACCOUNT_BALANCE_BITS bits so the sum of a batch
+// can't overflow into the negative half of the field (or underflow into the positive half with negative balances).
+// These tests check the bounds and their rounding.
+
+use plonky2::field::types::{Field, Field64, PrimeField64};
+use plonky2_por::{
+    BATCH_SIZE, F, MAX_ACCOUNT_BALANCE, MAX_ACCOUNT_BALANCE_BITS, MAX_BATCH_TOTAL_BITS,
+    MAX_RANGE_CHECKED_BALANCE,
+};
+
+// largest positive value, field elements above it represent negative numbers
+const MAX_POSITIVE: u64 = (F::ORDER - 1) / 2;
+
+#[test]
+fn max_balance_bits_are_rounded_down() {
+    // every value accepted by the range check is within MAX_ACCOUNT_BALANCE
+    assert_eq!(MAX_RANGE_CHECKED_BALANCE, (1u64 << MAX_ACCOUNT_BALANCE_BITS) - 1);
+    const { assert!(MAX_RANGE_CHECKED_BALANCE <= MAX_ACCOUNT_BALANCE) };
+
+    // and it is the tightest power of two bound (one more bit would accept balances above MAX_ACCOUNT_BALANCE)
+    const { assert!((1u64 << (MAX_ACCOUNT_BALANCE_BITS + 1)) - 1 > MAX_ACCOUNT_BALANCE) };
+}
+
+#[test]
+fn max_account_balance_matches_field_order_and_batch_size() {
+    assert_eq!(MAX_ACCOUNT_BALANCE, MAX_POSITIVE / BATCH_SIZE as u64);
+    assert!(MAX_ACCOUNT_BALANCE as u128 * BATCH_SIZE as u128 <= MAX_POSITIVE as u128);
+}
+
+#[test]
+fn batch_of_max_balances_does_not_overflow() {
+    // sum BATCH_SIZE max allowed balances in the field (as the batch circuit does)
+    let sum = (0..BATCH_SIZE).fold(F::ZERO, |acc, _| {
+        acc + F::from_canonical_u64(MAX_RANGE_CHECKED_BALANCE)
+    });
+
+    let expected = MAX_RANGE_CHECKED_BALANCE as u128 * BATCH_SIZE as u128;
+    assert_eq!(sum.to_canonical_u64() as u128, expected);
+
+    // the sum is still a positive number
+    assert!(sum.to_canonical_u64() <= MAX_POSITIVE);
+}
+
+#[test]
+fn balance_above_range_check_would_overflow_a_batch() {
+    // one bit more per balance is enough to wrap a full batch into the negative half of the field
+    let balance = 1u64 << (MAX_ACCOUNT_BALANCE_BITS + 1);
+    let sum = (0..BATCH_SIZE).fold(F::ZERO, |acc, _| acc + F::from_canonical_u64(balance));
+
+    assert!(balance as u128 * BATCH_SIZE as u128 > MAX_POSITIVE as u128);
+    assert!(sum.to_canonical_u64() > MAX_POSITIVE);
+}
+
+#[test]
+fn batch_of_min_balances_does_not_underflow() {
+    // negative balances are range checked in [-2^bits, 2^bits), sum BATCH_SIZE of the most negative one
+    let min_balance = -(1i64 << MAX_ACCOUNT_BALANCE_BITS);
+    let sum = (0..BATCH_SIZE).fold(F::ZERO, |acc, _| acc + F::from_noncanonical_i64(min_balance));
+
+    // the sum is still a negative number (it didn't wrap into the positive half of the field)
+    let expected = min_balance as i128 * BATCH_SIZE as i128;
+    assert!(sum.to_canonical_u64() > MAX_POSITIVE);
+    assert_eq!(-((F::ORDER - sum.to_canonical_u64()) as i128), expected);
+
+    // and it is within the batch total range check
+    assert!(expected >= -(1i128 << MAX_BATCH_TOTAL_BITS));
+}
+
+#[test]
+fn balance_below_range_check_would_underflow_a_batch() {
+    // one bit more per negative balance is enough to wrap a full batch into the positive half of the field
+    let balance = -(1i64 << (MAX_ACCOUNT_BALANCE_BITS + 1));
+    let sum = (0..BATCH_SIZE).fold(F::ZERO, |acc, _| acc + F::from_noncanonical_i64(balance));
+
+    assert!(sum.to_canonical_u64() <= MAX_POSITIVE);
+}
+
+#[test]
+fn batch_total_bits_cover_a_full_batch() {
+    let max_total = MAX_RANGE_CHECKED_BALANCE as u128 * BATCH_SIZE as u128;
+    let min_total = (1u128 << MAX_ACCOUNT_BALANCE_BITS) * BATCH_SIZE as u128;
+
+    assert!(max_total < 1u128 << MAX_BATCH_TOTAL_BITS);
+    assert!(min_total <= 1u128 << MAX_BATCH_TOTAL_BITS);
+    const { assert!(1u64 << MAX_BATCH_TOTAL_BITS <= MAX_POSITIVE) };
+}
+
+#[test]
+fn equity_overflowing_the_field_is_rejected() {
+    use plonky2_por::{Ledger, LedgerDecimals, MAX_ACCOUNT_EQUITY};
+
+    // both prices are within the default bounds (u32::MAX), but the equity is above the largest positive value
+    let price = u32::MAX as u64;
+    let balance = MAX_RANGE_CHECKED_BALANCE as i64;
+    assert!(balance as u128 * price as u128 > MAX_ACCOUNT_EQUITY as u128);
+    let mut ledger = Ledger {
+        asset_names: vec!["BTC".to_string(), "ETH".to_string()],
+        hashes: vec![format!("{:064x}", 1), format!("{:064x}", 2)],
+        account_balances: vec![vec![1, 2], vec![balance, 0]],
+        asset_prices: vec![price, price],
+        asset_decimals: vec![
+            LedgerDecimals { usdt_decimals: 2, balance_decimals: 4 },
+            LedgerDecimals { usdt_decimals: 2, balance_decimals: 4 },
+        ],
+        reserve_buffer: Vec::new(),
+        dust: None,
+        timestamp: 1746488437000,
+    };
+
+    let error = ledger.check_equity_range().unwrap_err().to_string();
+    assert!(error.contains(&ledger.hashes[1]), "{error}");
+
+    // a large negative equity wraps around as well, an opposite balance of another asset compensates it exactly
+    ledger.account_balances[1] = vec![-balance, 0];
+    assert!(ledger.check_equity_range().is_err());
+    ledger.account_balances[1] = vec![-balance, balance];
+    assert!(ledger.check_equity_range().is_ok());
+}
@@ -0,0 +1,44 @@
+// The padding report (ProverConfig::padding_report) must match the merkle trees built by the prover.
+
+use plonky2_por::{MerkleTree, Node, ProverConfig};
+
+fn build_tree(leaf_count: usize, config: &ProverConfig) -> MerkleTree {
+    let padded_count = leaf_count.div_ceil(config.batch_size) * config.batch_size;
+    let leaf_nodes = (0..padded_count).map(|_| Node::new(None)).collect();
+    MerkleTree::new_from_leafs_with_config(leaf_nodes, 1, true, config)
+}
+
+#[test]
+fn report_matches_the_built_trees() {
+    let config = ProverConfig { batch_size: 4, recursive_size: 2 };
+
+    for leaf_count in 1..=70 {
+        let report = config.padding_report(leaf_count).unwrap();
+        let merkle_tree = build_tree(leaf_count, &config);
+
+        assert_eq!(report.tree_depth, merkle_tree.depth, "{leaf_count}");
+        assert_eq!(report.padded_leaf_count, merkle_tree.leaf_count(), "{leaf_count}");
+        assert_eq!(report.batch_count * 4, leaf_count + report.padding_accounts);
+
+        // removing the reported accounts drops a level, removing one less doesn't
+        if let Some(accounts) = report.accounts_to_drop_level {
+            assert_eq!(config.padding_report(leaf_count - accounts).unwrap().tree_depth, report.tree_depth - 1);
+            assert_eq!(config.padding_report(leaf_count - accounts + 1).unwrap().tree_depth, report.tree_depth);
+        }
+    }
+}
+
+#[test]
+fn ledger_just_over_a_boundary_is_mostly_padding() {
+    let report = ProverConfig::default().padding_report(4097).unwrap();
+    assert_eq!((report.batch_count, report.padding_accounts, report.padding_batches), (9, 511, 55));
+    assert_eq!((report.padded_leaf_count, report.tree_depth, report.accounts_to_drop_level), (32768, 4, Some(1)));
+    assert!(report.padding_percentage() > 87.0);
+
+    let report = ProverConfig::default().padding_report(4096).unwrap();
+    assert_eq!((report.padding_accounts, report.padding_batches, report.accounts_to_drop_level), (0, 0, None));
+    assert_eq!(report.padding_percentage(), 0.0);
+
+    assert!(ProverConfig::default().padding_report(0).is_err());
+    assert!(ProverConfig { batch_size: 0, recursive_size: 2 }.padding_report(10).is_err());
+}
@@ -0,0 +1,59 @@
+// The inclusion server keeps the serialized proofs of the most recently requested users in a bounded LRU cache.
+#![cfg(all(feature = "prover", target_family = "unix"))]
+
+use plonky2_por::core::server::ProofCache;
+use std::sync::Arc;
+
+fn proof(json: &str) -> Arc<String> {
+    Arc::new(json.to_string())
+}
+
+#[test]
+fn repeated_requests_are_served_from_the_cache() {
+    let cache = ProofCache::new(2);
+
+    assert!(cache.get("aa").is_none());
+    cache.insert("aa".to_string(), proof("{\"a\":1}"));
+    assert_eq!(cache.get("aa").unwrap().as_str(), "{\"a\":1}");
+    assert_eq!(cache.get("aa").unwrap().as_str(), "{\"a\":1}");
+
+    assert_eq!(cache.hits(), 2);
+    assert_eq!(cache.misses(), 1);
+}
+
+#[test]
+fn least_recently_used_proof_is_evicted() {
+    let cache = ProofCache::new(2);
+    cache.insert("aa".to_string(), proof("a"));
+    cache.insert("bb".to_string(), proof("b"));
+
+    // "aa" is used again, so "bb" is the least recently used one
+    assert!(cache.get("aa").is_some());
+    cache.insert("cc".to_string(), proof("c"));
+
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get("bb").is_none());
+    assert!(cache.get("aa").is_some());
+    assert!(cache.get("cc").is_some());
+}
+
+#[test]
+fn reinserting_a_proof_does_not_grow_the_cache() {
+    let cache = ProofCache::new(2);
+    cache.insert("aa".to_string(), proof("a"));
+    cache.insert("aa".to_string(), proof("a"));
+    cache.insert("bb".to_string(), proof("b"));
+
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get("aa").is_some());
+}
+
+#[test]
+fn zero_capacity_disables_the_cache() {
+    let cache = ProofCache::new(0);
+    cache.insert("aa".to_string(), proof("a"));
+
+    assert!(cache.is_empty());
+    assert!(cache.get("aa").is_none());
+    assert_eq!(cache.misses(), 1);
+}
@@ -0,0 +1,72 @@
+// A final proof that doesn't fit its embedded verifier data (public inputs, fri query rounds, asset count) is reported
+// as such by verify_global_proof, before the plonky2 verification. It uses the tiny circuits of ProverConfig::testing()
+// (batch 4, recursive 2). A proof file whose fields don't match its asset count is rejected by check_final_proof_shape
+// before anything is printed or indexed.
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::core::verifier::verify_proof_only;
+use plonky2_por::{
+    check_final_proof_shape, verify_global_proof, write_to_file, FinalProof, ProverConfig, SerializationFormat,
+    VerifyOptions,
+};
+use common::ledger;
+
+// edits a field of a valid final proof
+type Malform = fn(&mut FinalProof);
+
+#[test]
+fn proof_not_matching_its_verifier_data_is_reported() {
+    let (final_proof, _, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+    assert_eq!(verify_global_proof(&final_proof).unwrap(), final_proof.root_hash());
+
+    let mut mismatched_proof = final_proof.clone();
+    mismatched_proof.proof.public_inputs.pop();
+    let error = verify_global_proof(&mismatched_proof).unwrap_err().to_string();
+    assert!(error.contains("Proof/verifier data mismatch") && error.contains("public inputs"), "{error}");
+
+    let mut mismatched_proof = final_proof.clone();
+    mismatched_proof.proof.proof.opening_proof.query_round_proofs.pop();
+    let error = verify_global_proof(&mismatched_proof).unwrap_err().to_string();
+    assert!(error.contains("fri query rounds"), "{error}");
+
+    let mut mismatched_proof = final_proof.clone();
+    mismatched_proof.asset_names.push("SOL".to_string());
+    let error = verify_global_proof(&mismatched_proof).unwrap_err().to_string();
+    assert!(error.contains("3 assets need 10"), "{error}");
+}
+
+#[test]
+fn malformed_proof_files_are_rejected_before_printing() {
+    let (final_proof, _, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+    check_final_proof_shape(&final_proof).unwrap();
+
+    let malformed: Vec<(Malform, &str)> = vec![
+        (|proof| { proof.asset_prices.pop(); }, "prices"),
+        (|proof| { proof.asset_decimals.clear(); }, "decimals"),
+        (|proof| proof.reserve_buffer = vec![1], "reserve buffer"),
+        (|proof| { proof.proof.public_inputs.pop(); }, "public inputs"),
+        (|proof| proof.timestamp = u64::MAX, "timestamp"),
+    ];
+    for (malform, expected) in malformed {
+        let mut malformed_proof = final_proof.clone();
+        malform(&mut malformed_proof);
+        let error = check_final_proof_shape(&malformed_proof).unwrap_err().to_string();
+        assert!(error.contains(expected), "{error}");
+        // an error, not a panic while printing the global information
+        assert!(verify_proof_only(&malformed_proof, &VerifyOptions::default()).is_err());
+    }
+
+    // the files are checked when read, before the commitment (info, publish-root) or anything else is computed
+    let mut malformed_proof = final_proof.clone();
+    malformed_proof.asset_names.push("SOL".to_string());
+    let dir = std::env::temp_dir().join(format!("por_proof_shape_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("final_proof.json").to_string_lossy().into_owned();
+    write_to_file(&file, &malformed_proof, SerializationFormat::Json).unwrap();
+    let error = format!("{:#}", FinalProof::read(&file).unwrap_err());
+    assert!(error.contains("3 assets but 2 prices"), "{error}");
+    let _ = std::fs::remove_dir_all(dir);
+}
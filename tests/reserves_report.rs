@@ -0,0 +1,93 @@
+// The reserves reports of a verified proof: the summary, the reserves CSV (verify --reserves-csv) and the coverage
+// ratios (verify --liabilities). They are computed from the proven prices and reserves of the public inputs, with the
+// assets sorted by name. It uses the tiny circuits of ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::core::verifier::write_reserves_csv;
+use plonky2_por::{bytes_to_hex, coverage_ratios, AssetCoverage, ProofSummary, ProverConfig};
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+use std::str::FromStr;
+use common::ledger;
+
+#[test]
+fn reports_use_the_proven_prices() {
+    let (final_proof, _, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+
+    // BTC: 45000 * 10^-4 BTC at US$ 60000, ETH: 900000 * 10^-4 at US$ 2000
+    let summary = final_proof.summary();
+    assert_eq!(summary.root_hash, bytes_to_hex(&final_proof.root_hash()));
+    assert_eq!(summary.assets.iter().map(|asset| asset.reserve_raw).collect::<Vec<_>>(), [45000, 900000]);
+    assert_eq!((summary.assets[0].price.as_str(), summary.assets[0].reserve_decimal.as_str()), ("60000.00", "4.5000"));
+    assert_eq!(summary.total_usd_value, "450000.000000");
+    let json = serde_json::to_string(&summary).unwrap();
+    assert_eq!(serde_json::from_str::<ProofSummary>(&json).unwrap(), summary);
+
+    // the summary and the reserves CSV use the proven prices, not the asset_prices field (only checked by verify step 3)
+    let mut repriced_proof = final_proof.clone();
+    repriced_proof.asset_prices[0] *= 2;
+    assert_eq!(repriced_proof.summary(), summary);
+    assert_eq!(repriced_proof.asset_valuations(), final_proof.asset_valuations());
+    let csv_path = std::env::temp_dir().join(format!("por_report_repriced_{}.csv", std::process::id()));
+    let csv_path = csv_path.to_str().unwrap();
+    write_reserves_csv(&repriced_proof, csv_path, None).unwrap();
+    let csv = std::fs::read_to_string(csv_path).unwrap();
+    assert!(csv.contains(",4.5000,60000.00,270000.000000\n"), "{csv}");
+    std::fs::remove_file(csv_path).unwrap();
+}
+
+#[test]
+fn a_subset_of_the_assets_is_exported_in_name_order() {
+    let (final_proof, _, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+
+    let mut renamed_proof = final_proof.clone();
+    renamed_proof.asset_names = vec!["ZEC".to_string(), "ADA".to_string()];
+    assert_eq!(renamed_proof.canonical_asset_order(), vec![1, 0]);
+
+    // an unknown asset is rejected
+    assert_eq!(renamed_proof.selected_asset_order(&["ZEC".to_string()]).unwrap(), vec![0]);
+    assert!(renamed_proof.selected_asset_order(&["BTC".to_string()]).is_err());
+
+    let csv_path = std::env::temp_dir().join(format!("por_report_reserves_{}.csv", std::process::id()));
+    let csv_path = csv_path.to_str().unwrap();
+    // the rows are sorted by name like the printed reserves, not in the proof order
+    write_reserves_csv(&renamed_proof, csv_path, None).unwrap();
+    let csv = std::fs::read_to_string(csv_path).unwrap();
+    let rows = csv.lines().skip(1).map(|row| row.split(',').next().unwrap()).collect::<Vec<_>>();
+    assert_eq!(rows, ["ADA", "ZEC"], "{csv}");
+    write_reserves_csv(&renamed_proof, csv_path, Some(&["ADA".to_string()])).unwrap();
+    let csv = std::fs::read_to_string(csv_path).unwrap();
+    assert_eq!(csv.lines().count(), 2, "{csv}");
+    assert!(csv.lines().nth(1).unwrap().starts_with("ADA,"), "{csv}");
+    std::fs::remove_file(csv_path).unwrap();
+}
+
+#[test]
+fn coverage_ratios_compare_the_declared_liabilities() {
+    let (final_proof, _, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+
+    // twice the BTC liability and half the ETH one
+    let declared = HashMap::from([("BTC".to_string(), 90000), ("ETH".to_string(), 450000)]);
+    let ratios = coverage_ratios(&final_proof, &declared).unwrap();
+    assert_eq!(ratios[0], AssetCoverage { asset: "BTC".to_string(), ratio: Some(BigDecimal::from(2)) });
+    assert_eq!(ratios[1], AssetCoverage { asset: "ETH".to_string(), ratio: Some(BigDecimal::from_str("0.5").unwrap()) });
+
+    // no ratio once the users owe nothing, an unknown asset is rejected
+    let mut covered_proof = final_proof.clone();
+    covered_proof.reserve_buffer = final_proof.asset_reserves();
+    assert!(coverage_ratios(&covered_proof, &declared).unwrap().iter().all(|coverage| coverage.ratio.is_none()));
+    assert!(coverage_ratios(&final_proof, &HashMap::from([("SOL".to_string(), 1)])).is_err());
+
+    // the reserve buffer is copied from the proof file: a short or overflowing buffer is an error, not a panic
+    let mut short_buffer_proof = final_proof.clone();
+    short_buffer_proof.reserve_buffer = vec![1];
+    assert!(short_buffer_proof.user_liabilities().is_err());
+    assert!(coverage_ratios(&short_buffer_proof, &declared).is_err());
+    let mut overflowing_proof = final_proof.clone();
+    overflowing_proof.reserve_buffer = vec![i64::MIN, 0];
+    let error = overflowing_proof.user_liabilities().unwrap_err().to_string();
+    assert!(error.contains("BTC reserve buffer overflows"), "{error}");
+}
@@ -0,0 +1,37 @@
+// The inclusion servers (daemon and HTTP) are given the nonces of the users. The positional nonces file (one nonce per
+// leaf) and the nonces keyed by user hash (prove --nonces-by-hash) must resolve to the same user nonces, so both can be
+// served (the servers themselves are tested in daemon_server.rs and http_server.rs). It uses the tiny circuits of
+// ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::{check_user_nonces_count, nonces_by_hash, resolve_nonces, ProverConfig};
+use common::ledger;
+
+#[test]
+fn keyed_and_positional_nonces_resolve_to_the_same_user_nonces() {
+    let ledger = ledger(6);
+    let (_, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &ProverConfig::testing()).unwrap();
+    assert_eq!(nonces.len(), 8);
+
+    let keyed_nonces = nonces_by_hash(&ledger.hashes, &nonces).unwrap();
+    let from_keyed = resolve_nonces((&keyed_nonces).into(), &ledger, &merkle_tree).unwrap();
+    let from_positional = resolve_nonces((&nonces).into(), &ledger, &merkle_tree).unwrap();
+    assert_eq!(from_keyed, from_positional);
+    assert_eq!(from_keyed, nonces[..ledger.hashes.len()]);
+
+    // the servers take exactly the user nonces
+    assert!(check_user_nonces_count(&from_keyed, &ledger).is_ok());
+    assert!(check_user_nonces_count(&nonces, &ledger).is_err());
+}
+
+#[test]
+fn duplicate_user_hashes_cannot_be_keyed() {
+    let mut hashes = ledger(6).hashes;
+    hashes[4] = hashes[1].clone();
+
+    let error = nonces_by_hash(&hashes, &[1, 2, 3, 4, 5, 6]).unwrap_err().to_string();
+    assert!(error.contains("Duplicate user hash"), "{error}");
+}
@@ -0,0 +1,106 @@
+// Ledger and proof locations are local paths by default, s3://bucket/key locations go through the object store.
+
+use plonky2_por::custom_serializer::storage::{
+    join_location, location_exists, parse_s3_location, read_location, read_location_decompressed, write_location,
+};
+use std::io::Read;
+
+#[test]
+fn local_paths_are_not_s3_locations() {
+    assert_eq!(parse_s3_location("private_ledger.json").unwrap(), None);
+    assert_eq!(parse_s3_location("/tmp/s3/private_ledger.json").unwrap(), None);
+}
+
+#[test]
+fn s3_locations_are_split_into_bucket_and_key() {
+    assert_eq!(
+        parse_s3_location("s3://bucket/ledgers/private_ledger.json").unwrap(),
+        Some(("bucket", "ledgers/private_ledger.json"))
+    );
+
+    for location in ["s3://bucket", "s3://bucket/", "s3:///key"] {
+        assert!(parse_s3_location(location).is_err(), "{location}");
+    }
+}
+
+#[test]
+fn file_names_are_joined_to_local_and_s3_directories() {
+    assert_eq!(join_location("s3://bucket/proofs/", "final_proof.json"), "s3://bucket/proofs/final_proof.json");
+    assert_eq!(join_location("s3://bucket/proofs", "final_proof.json"), "s3://bucket/proofs/final_proof.json");
+    assert_eq!(
+        join_location("proofs", "final_proof.json"),
+        std::path::Path::new("proofs").join("final_proof.json").to_string_lossy()
+    );
+}
+
+#[test]
+fn local_location_round_trips() {
+    let path = std::env::temp_dir().join(format!("por_storage_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    write_location(path, b"{\"accounts\": {}}").unwrap();
+
+    let mut contents = String::new();
+    read_location(path).unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "{\"accounts\": {}}");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn existing_outputs_are_detected() {
+    let path = std::env::temp_dir().join(format!("por_storage_exists_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    assert!(!location_exists(path).unwrap());
+    write_location(path, b"{}").unwrap();
+    assert!(location_exists(path).unwrap());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn zstd_locations_are_decompressed_while_read() {
+    let path = std::env::temp_dir().join(format!("por_storage_{}.json.zst", std::process::id()));
+    let path = path.to_str().unwrap();
+    let ledger = b"{\"accounts\": {}}";
+
+    // compressed and plain input, whatever the extension
+    for bytes in [zstd::encode_all(&ledger[..], 3).unwrap(), ledger.to_vec()] {
+        write_location(path, &bytes).unwrap();
+
+        let mut contents = Vec::new();
+        read_location_decompressed(path).unwrap().read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, ledger);
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(not(feature = "s3"))]
+#[test]
+fn s3_location_requires_the_feature() {
+    let error = read_location("s3://bucket/private_ledger.json").err().unwrap().to_string();
+    assert!(error.contains("s3 feature"), "{error}");
+}
+
+#[test]
+fn artifacts_are_streamed_in_every_format() {
+    use plonky2_por::custom_serializer::format::to_bytes;
+    use plonky2_por::custom_serializer::storage::write_artifact;
+    use plonky2_por::{read_from_file, SerializationFormat};
+
+    let nonces: Vec<u64> = (0..1000).collect();
+    let path = std::env::temp_dir().join(format!("por_artifact_{}", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    for format in [SerializationFormat::Json, SerializationFormat::Bincode, SerializationFormat::Msgpack] {
+        write_artifact(path, &nonces, format).unwrap();
+
+        // the streamed file is the same as the buffered serialization
+        assert_eq!(std::fs::read(path).unwrap(), to_bytes(&nonces, format).unwrap());
+        assert_eq!(read_from_file::<Vec<u64>>(path).unwrap(), nonces);
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
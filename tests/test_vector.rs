@@ -0,0 +1,58 @@
+// The committed test vector pins the outputs of the leaf hashing, the merkle tree construction and the serializers, so a
+// change that alters them fails here. Only regenerate it (cargo run --features fixtures -- gen-fixture) for an intended
+// change, and bump the format version if the serialized artifacts changed.
+// The generator is behind the fixtures feature: cargo test --features fixtures --test test_vector
+#![cfg(feature = "fixtures")]
+
+use plonky2_por::utils::util::check_nonce;
+use plonky2_por::{
+    generate_test_vector, seeded_nonces, test_vector_ledger, ProverConfig, TestVector, TEST_VECTOR_FILE, TEST_VECTOR_NONCE_SEED,
+};
+
+fn committed_test_vector() -> TestVector {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(TEST_VECTOR_FILE);
+    let json = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!("Failed to read {}: {e} (generate it with cargo run --features fixtures -- gen-fixture)", path.display())
+    });
+    serde_json::from_str(&json).unwrap()
+}
+
+#[test]
+fn seeded_nonces_are_reproducible_and_canonical() {
+    // splitmix64 reference values, the test vector depends on them
+    assert_eq!(seeded_nonces(0, 3), vec![16294208416658607535, 7960286522194355700, 487617019471545679]);
+    assert_eq!(seeded_nonces(7, 4), seeded_nonces(7, 16)[..4]);
+    assert_ne!(seeded_nonces(7, 16), seeded_nonces(8, 16));
+
+    for nonce in seeded_nonces(7, 10_000) {
+        assert!(check_nonce(nonce).is_ok());
+    }
+}
+
+#[test]
+fn committed_test_vector_is_reproduced() {
+    let expected = committed_test_vector();
+    let actual = generate_test_vector(expected.ledger(), expected.nonce_seed, &expected.config()).unwrap();
+
+    // compared one by one first, so a failure names what changed
+    assert_eq!(actual.nonces, expected.nonces, "the seeded nonces changed");
+    assert_eq!(actual.sample_leaf_hash, expected.sample_leaf_hash, "the leaf hash changed");
+    assert_eq!(actual.root_hash, expected.root_hash, "the merkle root changed");
+    assert_eq!(actual.inclusion_proof, expected.inclusion_proof, "the JSON inclusion proof changed");
+    assert_eq!(actual.inclusion_proof_bincode, expected.inclusion_proof_bincode, "the bincode inclusion proof changed");
+    assert_eq!(actual.inclusion_proof_msgpack, expected.inclusion_proof_msgpack, "the msgpack inclusion proof changed");
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn committed_test_vector_has_the_fixed_inputs() {
+    let expected = committed_test_vector();
+    let ledger = test_vector_ledger();
+
+    assert_eq!(expected.nonce_seed, TEST_VECTOR_NONCE_SEED);
+    assert_eq!(expected.config(), ProverConfig::testing());
+    assert_eq!(expected.asset_names, ledger.asset_names);
+    assert_eq!(expected.user_hashes, ledger.hashes);
+    assert_eq!(expected.account_balances, ledger.account_balances);
+    assert_eq!(expected.sample_user_hash, ledger.hashes[0]);
+}
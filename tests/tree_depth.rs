@@ -0,0 +1,110 @@
+// A final proof and a merkle tree of a different shape must be rejected before rebuilding the circuit
+// (verify_root checks the proof tree_depth with MerkleTree::check_depth).
+
+use plonky2_por::{MerkleTree, Node, BATCH_SIZE, RECURSIVE_SIZE};
+
+fn build_tree(account_count: usize) -> MerkleTree {
+    let padded_count = account_count.div_ceil(BATCH_SIZE) * BATCH_SIZE;
+    let leaf_nodes = (0..padded_count).map(|_| Node::new(None)).collect();
+    MerkleTree::new_from_leafs(leaf_nodes, 1, true)
+}
+
+#[test]
+fn matching_depth_is_accepted() {
+    let merkle_tree = build_tree(BATCH_SIZE);
+    assert!(merkle_tree.check_depth(merkle_tree.depth).is_ok());
+}
+
+#[test]
+fn mismatched_proof_and_tree_are_rejected() {
+    // a tree with one more recursive level than the proof
+    let small_tree = build_tree(BATCH_SIZE);
+    let big_tree = build_tree(BATCH_SIZE * RECURSIVE_SIZE * RECURSIVE_SIZE);
+    assert!(big_tree.depth > small_tree.depth);
+
+    assert!(big_tree.check_depth(small_tree.depth).is_err());
+    assert!(small_tree.check_depth(big_tree.depth).is_err());
+}
+
+#[test]
+fn tampered_depth_is_rejected() {
+    let mut merkle_tree = build_tree(BATCH_SIZE);
+    merkle_tree.depth += 1;
+
+    // even if it matches the proof tree depth, it doesn't match the tree structure
+    assert!(merkle_tree.check_depth(merkle_tree.depth).is_err());
+}
+
+#[test]
+fn leaf_counts_separate_the_padding_batches() {
+    // 2 batches of leafs with a hash, padded with empty batch nodes up to RECURSIVE_SIZE
+    let leaf_nodes = (0..2 * BATCH_SIZE).map(|_| Node::new(Some(vec![1; 32]))).collect();
+    let merkle_tree = MerkleTree::new_from_leafs(leaf_nodes, 1, true);
+
+    assert_eq!(merkle_tree.leaf_count(), BATCH_SIZE * RECURSIVE_SIZE);
+    assert_eq!(merkle_tree.real_leaf_count(), 2 * BATCH_SIZE);
+    assert_eq!(merkle_tree.padding_leaf_count(), (RECURSIVE_SIZE - 2) * BATCH_SIZE);
+    assert_eq!(merkle_tree.leaf_hashes().len(), 2 * BATCH_SIZE);
+}
+
+#[test]
+fn recorded_real_leaf_count_does_not_depend_on_the_hashes() {
+    // 3 real leafs (the second one with the zero hash) and the padding accounts of the batch
+    let mut leaf_nodes = vec![Node::new(Some(vec![1; 32])), Node::new(Some(vec![0; 32])), Node::new(Some(vec![2; 32]))];
+    leaf_nodes.extend((3..BATCH_SIZE).map(|_| Node::new(Some(vec![3; 32]))));
+    let merkle_tree = MerkleTree::new_from_leafs(leaf_nodes, 1, true).with_real_leaf_count(3);
+
+    assert_eq!(merkle_tree.real_leaf_count(), 3);
+    assert_eq!(merkle_tree.padding_leaf_count(), BATCH_SIZE * RECURSIVE_SIZE - 3);
+    assert_eq!(merkle_tree.leaf_hashes(), vec![vec![1; 32], vec![0; 32], vec![2; 32]]);
+
+    // the count is serialized with the tree
+    let json = serde_json::to_string(&merkle_tree).unwrap();
+    let deserialized: MerkleTree = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized.real_leaf_count, Some(3));
+}
+
+#[test]
+fn dot_graph_is_truncated_to_max_nodes() {
+    let merkle_tree = build_tree(BATCH_SIZE);
+
+    // root + 1 batch node + BATCH_SIZE leafs + RECURSIVE_SIZE - 1 padding batch nodes
+    let full = merkle_tree.to_dot(usize::MAX);
+    assert_eq!(full.matches(" [label=").count(), 1 + RECURSIVE_SIZE + BATCH_SIZE);
+    assert!(!full.contains("truncated"));
+
+    // 10 nodes and the node summarizing the rest
+    let truncated = merkle_tree.to_dot(10);
+    assert_eq!(truncated.matches(" [label=").count(), 11);
+    assert!(truncated.contains(&format!("{} more nodes", BATCH_SIZE - 1)));
+}
+
+#[test]
+fn absurd_proof_tree_depth_is_rejected_before_rebuilding_circuits() {
+    use plonky2_por::{check_tree_depth, VerifyOptions, DEFAULT_MAX_TREE_DEPTH};
+
+    let max_tree_depth = VerifyOptions::default().max_tree_depth;
+    assert_eq!(max_tree_depth, DEFAULT_MAX_TREE_DEPTH);
+
+    for tree_depth in [3, 10, max_tree_depth] {
+        assert!(check_tree_depth(tree_depth, max_tree_depth).is_ok(), "{tree_depth}");
+    }
+    for tree_depth in [0, 2, max_tree_depth + 1, usize::MAX] {
+        assert!(check_tree_depth(tree_depth, max_tree_depth).is_err(), "{tree_depth}");
+    }
+
+    // the cap is configurable
+    assert!(check_tree_depth(10, 8).is_err());
+}
+
+#[test]
+fn absurd_proof_circuit_sizes_are_rejected_before_rebuilding_circuits() {
+    use plonky2_por::{ProverConfig, MAX_RECURSIVE_SIZE};
+
+    for (batch_size, recursive_size) in [(1, 2), (BATCH_SIZE, MAX_RECURSIVE_SIZE)] {
+        assert!(ProverConfig { batch_size, recursive_size }.validate().is_ok(), "{batch_size} {recursive_size}");
+    }
+    for (batch_size, recursive_size) in [(0, 8), (BATCH_SIZE + 1, 8), (4, 1), (4, MAX_RECURSIVE_SIZE + 1), (4, usize::MAX)] {
+        assert!(ProverConfig { batch_size, recursive_size }.validate().is_err(), "{batch_size} {recursive_size}");
+    }
+}
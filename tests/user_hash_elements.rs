@@ -0,0 +1,71 @@
+// Test vectors of the user hash to field elements scheme (user_hash_elements), so other verifiers can reproduce it.
+
+use plonky2::field::types::PrimeField64;
+use plonky2_por::utils::util::{user_hash_elements, user_hash_elements_with_chunk_len};
+use plonky2_por::USER_HASH_CHUNK_HEX_LEN;
+
+fn elements(user_hash: &str) -> Vec<u64> {
+    user_hash_elements(user_hash).unwrap().iter().map(|element| element.to_canonical_u64()).collect()
+}
+
+#[test]
+fn sha256_user_hash() {
+    assert_eq!(
+        elements("6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b"),
+        [0x40, 0x6b86b273ff34fce, 0x19d6b804eff5a3f, 0x5747ada4eaa22f1, 0xd49c01e52ddb787, 0x5b4b00000000000]
+    );
+}
+
+#[test]
+fn ethereum_address_last_chunk_is_zero_extended() {
+    // 40 hex characters
+    let expected = [0x28, 0x529084000985278, 0x86e0f7030069857, 0xd2e4169ee700000];
+    assert_eq!(elements("52908400098527886e0f7030069857d2e4169ee7"), expected);
+
+    // a checksummed (mixed case) address would be another user with the same leaf, it must be lowercased first
+    assert!(user_hash_elements("52908400098527886E0F7030069857D2E4169EE7").is_err());
+}
+
+#[test]
+fn shorter_chunks_are_configurable() {
+    assert_eq!(
+        user_hash_elements_with_chunk_len("52908400098527886e0f7030069857d2e4169ee7", 8)
+            .unwrap()
+            .iter()
+            .map(|element| element.to_canonical_u64())
+            .collect::<Vec<_>>(),
+        [0x28, 0x52908400, 0x09852788, 0x6e0f7030, 0x069857d2, 0xe4169ee7]
+    );
+    assert_eq!(
+        user_hash_elements_with_chunk_len("ab", USER_HASH_CHUNK_HEX_LEN).unwrap(),
+        user_hash_elements("ab").unwrap()
+    );
+
+    // a 16 character chunk could be reduced modulo the field order
+    for chunk_hex_len in [0, USER_HASH_CHUNK_HEX_LEN + 1] {
+        assert!(user_hash_elements_with_chunk_len("ab", chunk_hex_len).is_err(), "{chunk_hex_len}");
+    }
+}
+
+#[test]
+fn uuid_and_short_user_hashes() {
+    assert_eq!(elements("550e8400e29b41d4a716446655440000"), [0x20, 0x550e8400e29b41d, 0x4a7164466554400, 0x0]);
+    assert_eq!(elements("ab"), [0x2, 0xab0000000000000]);
+
+    // the length prefix keeps a short hash apart from its zero-extended chunk
+    assert_ne!(elements("ab"), elements("ab0"));
+}
+
+#[test]
+fn chunks_are_never_reduced() {
+    // with 64-bit chunks "ffffffffffffffff" would be reduced modulo the field order (and collide with "00000000fffffffe")
+    assert_eq!(elements("ffffffffffffffff"), [0x10, 0xfffffffffffffff, 0xf00000000000000]);
+    assert_ne!(elements("ffffffffffffffff"), elements("00000000fffffffe"));
+}
+
+#[test]
+fn non_hex_user_hash_is_rejected() {
+    for user_hash in ["", "0x6b86", "zz86b273ff34fce1", "6b86-b273", "6B86"] {
+        assert!(user_hash_elements(user_hash).is_err(), "{user_hash}");
+    }
+}
@@ -0,0 +1,29 @@
+// The verification benchmark (verify --bench): the circuit is rebuilt once, then every run times the proof checks and
+// the merkle tree checks. It uses the tiny circuits of ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2_por::core::prover::prove_global_with_config;
+use plonky2_por::{bench_verify, duration_stats, ProverConfig};
+use std::time::Duration;
+use common::ledger;
+
+#[test]
+fn every_run_is_timed() {
+    let (final_proof, merkle_tree, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+
+    let benchmark = bench_verify(&final_proof, &merkle_tree, 2).unwrap();
+    assert_eq!((benchmark.proof_checks.len(), benchmark.merkle_checks.len()), (2, 2));
+    assert!(bench_verify(&final_proof, &merkle_tree, 0).is_err());
+}
+
+#[test]
+fn duration_stats_are_min_median_max() {
+    let durations = [5, 1, 3].map(Duration::from_millis);
+    assert_eq!(
+        duration_stats(&durations),
+        Some((Duration::from_millis(1), Duration::from_millis(3), Duration::from_millis(5)))
+    );
+    assert_eq!(duration_stats(&[]), None);
+}
@@ -0,0 +1,83 @@
+// The partial verifications: without the merkle tree (verify --proof-only), with the merkle tree streamed from its file
+// and the deep check of the batch reserves (verify --deep-check). The proof, prices and decimals are checked by each of
+// them. It uses the tiny circuits of ProverConfig::testing() (batch 4, recursive 2).
+#![cfg(feature = "prover")]
+
+mod common;
+
+use plonky2_por::core::prover::{prove_global_with_config, rebuild_merkle_tree};
+use plonky2_por::core::verifier::verify_root;
+use plonky2_por::{
+    check_batch_reserves, verify_proof_only, verify_root_streaming, write_to_file, ProverConfig, SerializationFormat,
+    VerifyOptions,
+};
+use common::ledger;
+
+#[test]
+fn proof_is_verified_without_the_merkle_tree() {
+    let (final_proof, _, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+    let options = VerifyOptions::default();
+
+    verify_proof_only(&final_proof, &options).unwrap();
+    let mut wrong_price = final_proof.clone();
+    wrong_price.asset_prices[1] += 1;
+    let error = verify_proof_only(&wrong_price, &options).unwrap_err().to_string();
+    assert!(error.contains("Asset price for ETH does not match"), "{error}");
+
+    // the circuit sizes of the (untrusted) file are bounded before any circuit is built
+    let mut absurd_arity = final_proof.clone();
+    absurd_arity.recursive_size = 1 << 20;
+    let error = verify_proof_only(&absurd_arity, &options).unwrap_err().to_string();
+    assert!(error.contains("Invalid recursive size"), "{error}");
+
+    // a subset of the assets can be displayed, an unknown asset is rejected
+    let filtered = VerifyOptions { assets: Some(vec!["ETH".to_string()]), ..options.clone() };
+    verify_proof_only(&final_proof, &filtered).unwrap();
+    let unknown = VerifyOptions { assets: Some(vec!["SOL".to_string()]), ..options };
+    assert!(verify_proof_only(&final_proof, &unknown).is_err());
+}
+
+#[test]
+fn merkle_tree_is_verified_while_it_is_read() {
+    let (final_proof, merkle_tree, _) = prove_global_with_config(ledger(9), &ProverConfig::testing()).unwrap();
+    let options = VerifyOptions::default();
+
+    let tree_file = std::env::temp_dir().join(format!("por_streamed_tree_{}.bin", std::process::id()));
+    let tree_file = tree_file.to_string_lossy().into_owned();
+    write_to_file(&tree_file, &merkle_tree, SerializationFormat::Bincode).unwrap();
+    verify_root_streaming(&final_proof, &tree_file, &options).unwrap();
+
+    let mut wrong_price = final_proof.clone();
+    wrong_price.asset_prices[1] += 1;
+    let error = verify_root_streaming(&wrong_price, &tree_file, &options).unwrap_err().to_string();
+    assert!(error.contains("Asset price for ETH does not match"), "{error}");
+    let _ = std::fs::remove_file(&tree_file);
+}
+
+#[test]
+fn deep_check_compares_the_batch_reserves() {
+    let config = ProverConfig::testing();
+    let ledger = ledger(9);
+    let (final_proof, merkle_tree, nonces) = prove_global_with_config(ledger.clone(), &config).unwrap();
+
+    // the batch reserves add up to the proven reserves, and are the ones of the rebuilt tree
+    let deep = VerifyOptions {
+        deep_check: true,
+        ..VerifyOptions::default()
+    };
+    verify_root(final_proof.clone(), merkle_tree.clone(), &deep);
+    assert_eq!(merkle_tree.batch_reserves.len(), 3);
+    let rebuilt_tree = rebuild_merkle_tree(ledger, &nonces, &config).unwrap();
+    assert_eq!(rebuilt_tree.batch_reserves, merkle_tree.batch_reserves);
+
+    let mut tampered_tree = merkle_tree.clone();
+    tampered_tree.batch_reserves[1][0] += 1;
+    assert!(check_batch_reserves(&final_proof, &tampered_tree).unwrap_err().to_string().contains("BTC"));
+
+    // the mismatched assets are reported sorted by name, whatever their order in the proof
+    tampered_tree.batch_reserves[1][1] += 1;
+    let mut renamed_proof = final_proof.clone();
+    renamed_proof.asset_names = vec!["ZEC".to_string(), "ADA".to_string()];
+    let error = check_batch_reserves(&renamed_proof, &tampered_tree).unwrap_err().to_string();
+    assert!(error.find("ADA").unwrap() < error.find("ZEC").unwrap(), "{error}");
+}
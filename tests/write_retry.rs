@@ -0,0 +1,55 @@
+// Inclusion proof files are written with write_with_retry: transient errors are retried, permanent ones are returned.
+
+use plonky2_por::custom_serializer::format::{is_storage_full, write_atomic_with_retry, write_with_retry};
+use std::time::{Duration, Instant};
+
+#[test]
+fn write_succeeds_without_retries() {
+    let path = std::env::temp_dir().join(format!("por_write_retry_{}.bin", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    write_with_retry(path, b"proof", 3).unwrap();
+    assert_eq!(std::fs::read(path).unwrap(), b"proof");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn permanent_error_is_returned_without_retrying() {
+    let timer = Instant::now();
+
+    // a missing directory is not a transient error
+    let result = write_with_retry("/nonexistent_por_dir/inclusion_proof.json", b"proof", 5);
+
+    assert!(result.is_err());
+    assert!(timer.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn atomic_write_leaves_no_temporary_file() {
+    let path = std::env::temp_dir().join(format!("por_write_atomic_{}.zst", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    write_atomic_with_retry(path, b"bundle", 3).unwrap();
+    write_atomic_with_retry(path, b"bundle 2", 3).unwrap();
+    assert_eq!(std::fs::read(path).unwrap(), b"bundle 2");
+    assert!(!std::path::Path::new(&format!("{path}.tmp")).exists());
+
+    std::fs::remove_file(path).unwrap();
+    assert!(write_atomic_with_retry("/nonexistent_por_dir/bundle.zst", b"bundle", 0).is_err());
+}
+
+#[test]
+fn full_disk_is_detected_through_the_error_context() {
+    use anyhow::Context;
+
+    let error = Err::<(), _>(std::io::Error::from_raw_os_error(28)) // ENOSPC
+        .context("Failed to write inclusion_proofs/abc.json.zst")
+        .unwrap_err();
+    assert!(is_storage_full(&error));
+
+    let error = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+        .context("Failed to write inclusion_proofs/abc.json.zst")
+        .unwrap_err();
+    assert!(!is_storage_full(&error));
+}